@@ -0,0 +1,476 @@
+use std::fmt;
+use ethereum_types::{H160, H256, U256};
+use serde::Deserialize;
+use thiserror::Error;
+use crate::helper::Helper;
+use crate::hex;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AbiError {
+    #[error("Invalid ABI JSON: {0}")]
+    Json(String),
+    #[error("Unsupported type {0}")]
+    InvalidType(String),
+    #[error("Data too short or malformed for the types")]
+    InvalidData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamType {
+    Address,
+    Bool,
+    Uint(usize),
+    Int(usize),
+    FixedBytes(usize),
+    Bytes,
+    String,
+    Array(Box<ParamType>),
+    FixedArray(Box<ParamType>, usize),
+    Tuple(Vec<ParamType>),
+}
+
+impl ParamType {
+    // Parses a Solidity type as written in an ABI, `components` giving the members of tuples
+    pub fn parse(kind: &str, components: &[Param]) -> Result<ParamType, AbiError> {
+        let invalid = || AbiError::InvalidType(kind.to_string());
+        if let Some(inner) = kind.strip_suffix("[]") {
+            return Ok(ParamType::Array(Box::new(ParamType::parse(inner, components)?)));
+        }
+        if let Some(rest) = kind.strip_suffix(']') {
+            let open = rest.rfind('[').ok_or_else(invalid)?;
+            let size = rest[open + 1..].parse().map_err(|_| invalid())?;
+            return Ok(ParamType::FixedArray(Box::new(ParamType::parse(&rest[..open], components)?), size));
+        }
+        let bits = |digits: &str| -> Result<usize, AbiError> {
+            let bits = if digits.is_empty() { 256 } else { digits.parse().map_err(|_| invalid())? };
+            if bits == 0 || bits > 256 || bits % 8 != 0 {
+                return Err(invalid());
+            }
+            Ok(bits)
+        };
+        match kind {
+            "address" => Ok(ParamType::Address),
+            "bool" => Ok(ParamType::Bool),
+            "bytes" => Ok(ParamType::Bytes),
+            "string" => Ok(ParamType::String),
+            "function" => Ok(ParamType::FixedBytes(24)),
+            "tuple" => Ok(ParamType::Tuple(components.iter().map(|param| param.kind.clone()).collect())),
+            _ if kind.starts_with("uint") => Ok(ParamType::Uint(bits(&kind[4..])?)),
+            _ if kind.starts_with("int") => Ok(ParamType::Int(bits(&kind[3..])?)),
+            _ if kind.starts_with("bytes") => {
+                let size: usize = kind[5..].parse().map_err(|_| invalid())?;
+                if size == 0 || size > 32 {
+                    return Err(invalid());
+                }
+                Ok(ParamType::FixedBytes(size))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    // The type as it appears in signatures
+    pub fn canonical(&self) -> String {
+        match self {
+            ParamType::Address => "address".to_string(),
+            ParamType::Bool => "bool".to_string(),
+            ParamType::Uint(bits) => format!("uint{}", bits),
+            ParamType::Int(bits) => format!("int{}", bits),
+            ParamType::FixedBytes(size) => format!("bytes{}", size),
+            ParamType::Bytes => "bytes".to_string(),
+            ParamType::String => "string".to_string(),
+            ParamType::Array(inner) => format!("{}[]", inner.canonical()),
+            ParamType::FixedArray(inner, size) => format!("{}[{}]", inner.canonical(), size),
+            ParamType::Tuple(members) => format!("({})", members.iter().map(ParamType::canonical).collect::<Vec<_>>().join(",")),
+        }
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        match self {
+            ParamType::Bytes | ParamType::String | ParamType::Array(_) => true,
+            ParamType::FixedArray(inner, _) => inner.is_dynamic(),
+            ParamType::Tuple(members) => members.iter().any(ParamType::is_dynamic),
+            _ => false,
+        }
+    }
+
+    // Bytes taken in the head of the enclosing tuple
+    fn head_size(&self) -> usize {
+        match self {
+            _ if self.is_dynamic() => 32,
+            ParamType::FixedArray(inner, size) => inner.head_size() * size,
+            ParamType::Tuple(members) => members.iter().map(ParamType::head_size).sum(),
+            _ => 32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AbiValue {
+    Address(H160),
+    Bool(bool),
+    Uint(U256),
+    // Two's complement
+    Int(U256),
+    FixedBytes(Vec<u8>),
+    Bytes(Vec<u8>),
+    String(String),
+    Array(Vec<AbiValue>),
+    Tuple(Vec<AbiValue>),
+}
+
+impl AbiValue {
+    // Formats the value, writing addresses with `address`
+    pub fn format_with(&self, address: &dyn Fn(&H160) -> String) -> String {
+        let list = |values: &[AbiValue]| values.iter().map(|value| value.format_with(address)).collect::<Vec<_>>().join(", ");
+        match self {
+            AbiValue::Address(value) => address(value),
+            AbiValue::Bool(value) => value.to_string(),
+            AbiValue::Uint(value) => value.to_string(),
+            AbiValue::Int(value) if value.bit(255) => format!("-{}", (!*value).overflowing_add(U256::one()).0),
+            AbiValue::Int(value) => value.to_string(),
+            AbiValue::FixedBytes(bytes) | AbiValue::Bytes(bytes) => hex::encode(bytes),
+            AbiValue::String(value) => format!("{:?}", value),
+            AbiValue::Array(values) => format!("[{}]", list(values)),
+            AbiValue::Tuple(values) => format!("({})", list(values)),
+        }
+    }
+}
+
+impl fmt::Display for AbiValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format_with(&hex::checksum))
+    }
+}
+
+// Decodes `data` as the ABI encoding of a tuple of `types`
+pub fn decode(types: &[ParamType], data: &[u8]) -> Result<Vec<AbiValue>, AbiError> {
+    let mut values = Vec::with_capacity(types.len());
+    let mut head = 0;
+    for kind in types {
+        let value = if kind.is_dynamic() {
+            let offset = read_usize(data, head)?;
+            decode_value(kind, data.get(offset..).ok_or(AbiError::InvalidData)?)?
+        } else {
+            decode_value(kind, data.get(head..).ok_or(AbiError::InvalidData)?)?
+        };
+        values.push(value);
+        head += kind.head_size();
+    }
+    Ok(values)
+}
+
+fn decode_value(kind: &ParamType, data: &[u8]) -> Result<AbiValue, AbiError> {
+    let word = data.get(..32).ok_or(AbiError::InvalidData);
+    match kind {
+        ParamType::Address => Ok(AbiValue::Address(H160::from_slice(&word?[12..]))),
+        ParamType::Bool => Ok(AbiValue::Bool(word?.iter().any(|byte| *byte != 0))),
+        ParamType::Uint(_) => Ok(AbiValue::Uint(U256::from_big_endian(word?))),
+        ParamType::Int(_) => Ok(AbiValue::Int(U256::from_big_endian(word?))),
+        ParamType::FixedBytes(size) => Ok(AbiValue::FixedBytes(word?[..*size].to_vec())),
+        ParamType::Bytes | ParamType::String => {
+            let length = read_usize(data, 0)?;
+            let bytes = data.get(32..32usize.checked_add(length).ok_or(AbiError::InvalidData)?).ok_or(AbiError::InvalidData)?;
+            match kind {
+                ParamType::String => Ok(AbiValue::String(String::from_utf8_lossy(bytes).into_owned())),
+                _ => Ok(AbiValue::Bytes(bytes.to_vec())),
+            }
+        }
+        ParamType::Array(inner) => {
+            let length = read_usize(data, 0)?;
+            // Every element takes at least a word, which bounds the length by the data
+            if length > data.len() / 32 {
+                return Err(AbiError::InvalidData);
+            }
+            Ok(AbiValue::Array(decode(&vec![(**inner).clone(); length], &data[32..])?))
+        }
+        ParamType::FixedArray(inner, size) => {
+            if *size > data.len() / 32 {
+                return Err(AbiError::InvalidData);
+            }
+            Ok(AbiValue::Array(decode(&vec![(**inner).clone(); *size], data)?))
+        }
+        ParamType::Tuple(members) => Ok(AbiValue::Tuple(decode(members, data)?)),
+    }
+}
+
+fn read_usize(data: &[u8], offset: usize) -> Result<usize, AbiError> {
+    let word = data.get(offset..offset + 32).ok_or(AbiError::InvalidData)?;
+    let value = U256::from_big_endian(word);
+    if value > U256::from(u32::MAX) {
+        return Err(AbiError::InvalidData);
+    }
+    Ok(value.as_usize())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    pub kind: ParamType,
+    pub indexed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    pub inputs: Vec<Param>,
+    pub outputs: Vec<Param>,
+}
+
+// Anonymous events have no signature topic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub name: String,
+    pub inputs: Vec<Param>,
+    pub anonymous: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomError {
+    pub name: String,
+    pub inputs: Vec<Param>,
+}
+
+fn signature(name: &str, params: &[Param]) -> String {
+    format!("{}({})", name, params.iter().map(|param| param.kind.canonical()).collect::<Vec<_>>().join(","))
+}
+
+fn selector(signature: &str) -> [u8; 4] {
+    let hash = Helper::keccak256(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+fn types(params: &[Param]) -> Vec<ParamType> {
+    params.iter().map(|param| param.kind.clone()).collect()
+}
+
+impl Function {
+    pub fn signature(&self) -> String {
+        signature(&self.name, &self.inputs)
+    }
+
+    pub fn selector(&self) -> [u8; 4] {
+        selector(&self.signature())
+    }
+
+    // Decodes calldata without the selector
+    pub fn decode_input(&self, data: &[u8]) -> Result<Vec<AbiValue>, AbiError> {
+        decode(&types(&self.inputs), data)
+    }
+
+    pub fn decode_output(&self, data: &[u8]) -> Result<Vec<AbiValue>, AbiError> {
+        decode(&types(&self.outputs), data)
+    }
+}
+
+impl Event {
+    pub fn signature(&self) -> String {
+        signature(&self.name, &self.inputs)
+    }
+
+    pub fn topic(&self) -> H256 {
+        H256(Helper::keccak256(self.signature().as_bytes()))
+    }
+
+    // Indexed parameters come from the topics after the first, dynamic ones only as their hash
+    pub fn decode_log(&self, topics: &[H256], data: &[u8]) -> Result<Vec<AbiValue>, AbiError> {
+        let non_indexed: Vec<ParamType> = self.inputs.iter().filter(|param| !param.indexed).map(|param| param.kind.clone()).collect();
+        let mut data_values = decode(&non_indexed, data)?.into_iter();
+        let mut topics = topics.iter().skip(if self.anonymous { 0 } else { 1 });
+        let mut values = Vec::with_capacity(self.inputs.len());
+        for param in &self.inputs {
+            if param.indexed {
+                let topic = topics.next().ok_or(AbiError::InvalidData)?;
+                let value = if param.kind.is_dynamic() {
+                    AbiValue::FixedBytes(topic.as_bytes().to_vec())
+                } else {
+                    decode_value(&param.kind, topic.as_bytes())?
+                };
+                values.push(value);
+            } else {
+                values.push(data_values.next().ok_or(AbiError::InvalidData)?);
+            }
+        }
+        Ok(values)
+    }
+}
+
+impl CustomError {
+    pub fn signature(&self) -> String {
+        signature(&self.name, &self.inputs)
+    }
+
+    pub fn selector(&self) -> [u8; 4] {
+        selector(&self.signature())
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<AbiValue>, AbiError> {
+        decode(&types(&self.inputs), data)
+    }
+}
+
+// The functions, events and errors of a contract's JSON ABI
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Abi {
+    pub functions: Vec<Function>,
+    pub events: Vec<Event>,
+    pub errors: Vec<CustomError>,
+}
+
+#[derive(Deserialize)]
+struct JsonItem {
+    #[serde(rename = "type", default = "default_item_type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    inputs: Vec<JsonParam>,
+    #[serde(default)]
+    outputs: Vec<JsonParam>,
+    #[serde(default)]
+    anonymous: bool,
+}
+
+fn default_item_type() -> String {
+    "function".to_string()
+}
+
+#[derive(Deserialize)]
+struct JsonParam {
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    indexed: bool,
+    #[serde(default)]
+    components: Vec<JsonParam>,
+}
+
+impl JsonParam {
+    fn to_param(&self) -> Result<Param, AbiError> {
+        let components = self.components.iter().map(JsonParam::to_param).collect::<Result<Vec<_>, _>>()?;
+        Ok(Param { name: self.name.clone(), kind: ParamType::parse(&self.kind, &components)?, indexed: self.indexed })
+    }
+}
+
+fn params(params: &[JsonParam]) -> Result<Vec<Param>, AbiError> {
+    params.iter().map(JsonParam::to_param).collect()
+}
+
+impl Abi {
+    // Reads the array solc emits with --abi. Constructors, fallbacks and receive functions are skipped
+    pub fn from_json(json: &str) -> Result<Abi, AbiError> {
+        let items: Vec<JsonItem> = serde_json::from_str(json).map_err(|e| AbiError::Json(e.to_string()))?;
+        let mut abi = Abi::default();
+        for item in items {
+            match item.kind.as_str() {
+                "function" => abi.functions.push(Function { name: item.name, inputs: params(&item.inputs)?, outputs: params(&item.outputs)? }),
+                "event" => abi.events.push(Event { name: item.name, inputs: params(&item.inputs)?, anonymous: item.anonymous }),
+                "error" => abi.errors.push(CustomError { name: item.name, inputs: params(&item.inputs)? }),
+                _ => {}
+            }
+        }
+        Ok(abi)
+    }
+
+    pub fn function_by_selector(&self, selector: &[u8]) -> Option<&Function> {
+        self.functions.iter().find(|function| function.selector()[..] == *selector)
+    }
+
+    pub fn event_by_topic(&self, topic: &H256) -> Option<&Event> {
+        self.events.iter().find(|event| !event.anonymous && event.topic() == *topic)
+    }
+
+    pub fn error_by_selector(&self, selector: &[u8]) -> Option<&CustomError> {
+        self.errors.iter().find(|error| error.selector()[..] == *selector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ERC20: &str = r#"[
+        {"type":"function","name":"transfer","inputs":[{"name":"to","type":"address"},{"name":"amount","type":"uint256"}],"outputs":[{"name":"","type":"bool"}]},
+        {"type":"event","name":"Transfer","inputs":[{"name":"from","type":"address","indexed":true},{"name":"to","type":"address","indexed":true},{"name":"value","type":"uint256","indexed":false}],"anonymous":false},
+        {"type":"error","name":"Error","inputs":[{"name":"message","type":"string"}]},
+        {"type":"constructor","inputs":[]}
+    ]"#;
+
+    fn words(words: &[&str]) -> Vec<u8> {
+        words.iter().flat_map(|word| hex::decode(&format!("{:0>64}", word)).unwrap()).collect()
+    }
+
+    #[test]
+    fn selectors_and_topics() {
+        let abi = Abi::from_json(ERC20).unwrap();
+        assert_eq!(abi.functions[0].signature(), "transfer(address,uint256)");
+        assert_eq!(abi.functions[0].selector(), [0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(hex::encode(abi.events[0].topic().as_bytes()), "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
+        assert_eq!(abi.error_by_selector(&[0x08, 0xc3, 0x79, 0xa0]).unwrap().name, "Error");
+        assert!(abi.function_by_selector(&[0, 0, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn parses_types() {
+        let kind = ParamType::parse("uint8[2][]", &[]).unwrap();
+        assert_eq!(kind, ParamType::Array(Box::new(ParamType::FixedArray(Box::new(ParamType::Uint(8)), 2))));
+        assert_eq!(kind.canonical(), "uint8[2][]");
+        for kind in ["uint7", "uint264", "int0", "bytes0", "bytes33", "uint[x]", "fixed128x18"] {
+            assert_eq!(ParamType::parse(kind, &[]), Err(AbiError::InvalidType(kind.to_string())));
+        }
+        assert!(matches!(Abi::from_json("{}"), Err(AbiError::Json(_))));
+    }
+
+    #[test]
+    fn decodes_calls_events_and_errors() {
+        let abi = Abi::from_json(ERC20).unwrap();
+        let to = H160::repeat_byte(0x11);
+        let input = words(&["1111111111111111111111111111111111111111", "64"]);
+        assert_eq!(abi.functions[0].decode_input(&input).unwrap(), [AbiValue::Address(to), AbiValue::Uint(100.into())]);
+
+        let from = H256::from(H160::repeat_byte(0x22));
+        let values = abi.events[0].decode_log(&[abi.events[0].topic(), from, H256::from(to)], &words(&["2a"])).unwrap();
+        assert_eq!(values, [AbiValue::Address(H160::repeat_byte(0x22)), AbiValue::Address(to), AbiValue::Uint(42.into())]);
+        assert_eq!(abi.events[0].decode_log(&[abi.events[0].topic(), from], &words(&["2a"])), Err(AbiError::InvalidData));
+
+        // Error("Not enough") as solc reverts with it
+        let reason = [words(&["20", "a"]), b"Not enough".to_vec(), vec![0; 22]].concat();
+        assert_eq!(abi.errors[0].decode(&reason).unwrap(), [AbiValue::String("Not enough".to_string())]);
+    }
+
+    #[test]
+    fn decodes_nested_dynamic_values() {
+        // (uint256[], bytes) with the two values in reverse order in the tail
+        let data = words(&["40", "a0", "2", "1", "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff", "3", "abcdef0000000000000000000000000000000000000000000000000000000000"]);
+        let values = decode(&[ParamType::parse("int256[]", &[]).unwrap(), ParamType::Bytes], &data).unwrap();
+        assert_eq!(values[0].to_string(), "[1, -1]");
+        assert_eq!(values[1], AbiValue::Bytes(vec![0xab, 0xcd, 0xef]));
+
+        let tuple = ParamType::Tuple(vec![ParamType::Bool, ParamType::FixedBytes(2)]);
+        let values = decode(&[ParamType::FixedArray(Box::new(tuple), 2)], &words(&["1", &format!("{:0<64}", "1234"), "0", &format!("{:0<64}", "5678")])).unwrap();
+        assert_eq!(values[0].to_string(), "[(true, 0x1234), (false, 0x5678)]");
+    }
+
+    #[test]
+    fn rejects_malformed_data() {
+        let bytes = [ParamType::Bytes];
+        let array = [ParamType::Array(Box::new(ParamType::Uint(256)))];
+        let cases: [(&[ParamType], Vec<u8>); 8] = [
+            // A word cut short
+            (&[ParamType::Uint(256)], vec![0; 31]),
+            (&[ParamType::Address, ParamType::Uint(256)], words(&["1"])),
+            // Offsets past the end, or too large to be an offset at all
+            (&bytes, words(&["40", "0"])),
+            (&bytes, words(&["ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe0"])),
+            // Lengths running past the data
+            (&bytes, words(&["20", "21", "0"])),
+            (&bytes, words(&["20", "ffffffff"])),
+            (&array, words(&["20", "3", "1", "2"])),
+            (&[ParamType::FixedArray(Box::new(ParamType::Uint(256)), 1 << 40)], words(&["1"])),
+        ];
+        for (types, data) in cases {
+            assert_eq!(decode(types, &data), Err(AbiError::InvalidData), "{}", hex::encode(&data));
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::{BTreeMap, BTreeSet};
+use ethereum_types::{H160, H256, U256};
+use thiserror::Error;
+
+mod cache;
+mod file;
+mod memory;
+mod remote;
+pub use cache::CacheDb;
+pub use file::FileDb;
+pub use memory::InMemoryDb;
+pub use remote::RemoteDb;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseError {
+    #[error("Code not found for hash {0:?}")]
+    MissingCode(H256),
+    #[error("Database does not accept changes")]
+    ReadOnly,
+    #[error("I/O error: {0}")]
+    Io(String),
+    #[error("Corrupt database file: {0}")]
+    Corrupt(String),
+    #[error("JSON-RPC error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("Invalid JSON-RPC response: {0}")]
+    InvalidResponse(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+// Account fields stored alongside the trie, code is looked up by its hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountInfo {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code_hash: H256,
+}
+
+// Final values of everything a transaction (or several) changed, written back with
+// `Database::commit`. Destroyed accounts are removed with all their storage
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeSet {
+    pub accounts: BTreeMap<H160, AccountInfo>,
+    pub destroyed: BTreeSet<H160>,
+    pub code: BTreeMap<H256, Vec<u8>>,
+    pub storage: BTreeMap<(H160, U256), U256>,
+    pub block_hashes: BTreeMap<u64, H256>,
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+            && self.destroyed.is_empty()
+            && self.code.is_empty()
+            && self.storage.is_empty()
+            && self.block_hashes.is_empty()
+    }
+}
+
+// Backend the state loads accounts and slots from the first time they are used
+pub trait Database {
+    // None if the account does not exist
+    fn basic(&mut self, address: H160) -> Result<Option<AccountInfo>, DatabaseError>;
+
+    fn code_by_hash(&mut self, code_hash: H256) -> Result<Vec<u8>, DatabaseError>;
+
+    fn storage(&mut self, address: H160, key: U256) -> Result<U256, DatabaseError>;
+
+    fn block_hash(&mut self, number: u64) -> Result<H256, DatabaseError>;
+
+    // Applies all of `changes` or, on error, none of them
+    fn commit(&mut self, _changes: &ChangeSet) -> Result<(), DatabaseError> {
+        Err(DatabaseError::ReadOnly)
+    }
+}
+
+impl<D: Database + ?Sized> Database for Box<D> {
+    fn basic(&mut self, address: H160) -> Result<Option<AccountInfo>, DatabaseError> {
+        (**self).basic(address)
+    }
+
+    fn code_by_hash(&mut self, code_hash: H256) -> Result<Vec<u8>, DatabaseError> {
+        (**self).code_by_hash(code_hash)
+    }
+
+    fn storage(&mut self, address: H160, key: U256) -> Result<U256, DatabaseError> {
+        (**self).storage(address, key)
+    }
+
+    fn block_hash(&mut self, number: u64) -> Result<H256, DatabaseError> {
+        (**self).block_hash(number)
+    }
+
+    fn commit(&mut self, changes: &ChangeSet) -> Result<(), DatabaseError> {
+        (**self).commit(changes)
+    }
+}
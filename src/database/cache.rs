@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use ethereum_types::{H160, H256, U256};
+use super::{AccountInfo, ChangeSet, Database, DatabaseError};
+
+// Remembers every answer of `inner`, so each account, code, slot and block hash is fetched
+// at most once. Errors are not cached
+#[derive(Debug, Clone, Default)]
+pub struct CacheDb<D> {
+    inner: D,
+    accounts: HashMap<H160, Option<AccountInfo>>,
+    code: HashMap<H256, Vec<u8>>,
+    storage: HashMap<(H160, U256), U256>,
+    block_hashes: HashMap<u64, H256>,
+}
+
+impl<D: Database> CacheDb<D> {
+    pub fn new(inner: D) -> CacheDb<D> {
+        CacheDb {
+            inner,
+            accounts: HashMap::new(),
+            code: HashMap::new(),
+            storage: HashMap::new(),
+            block_hashes: HashMap::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    pub fn clear(&mut self) {
+        self.accounts.clear();
+        self.code.clear();
+        self.storage.clear();
+        self.block_hashes.clear();
+    }
+}
+
+impl<D: Database> Database for CacheDb<D> {
+    fn basic(&mut self, address: H160) -> Result<Option<AccountInfo>, DatabaseError> {
+        if let Some(info) = self.accounts.get(&address) {
+            return Ok(info.clone());
+        }
+        let info = self.inner.basic(address)?;
+        self.accounts.insert(address, info.clone());
+        Ok(info)
+    }
+
+    fn code_by_hash(&mut self, code_hash: H256) -> Result<Vec<u8>, DatabaseError> {
+        if let Some(code) = self.code.get(&code_hash) {
+            return Ok(code.clone());
+        }
+        let code = self.inner.code_by_hash(code_hash)?;
+        self.code.insert(code_hash, code.clone());
+        Ok(code)
+    }
+
+    fn storage(&mut self, address: H160, key: U256) -> Result<U256, DatabaseError> {
+        if let Some(value) = self.storage.get(&(address, key)) {
+            return Ok(*value);
+        }
+        let value = self.inner.storage(address, key)?;
+        self.storage.insert((address, key), value);
+        Ok(value)
+    }
+
+    fn block_hash(&mut self, number: u64) -> Result<H256, DatabaseError> {
+        if let Some(hash) = self.block_hashes.get(&number) {
+            return Ok(*hash);
+        }
+        let hash = self.inner.block_hash(number)?;
+        self.block_hashes.insert(number, hash);
+        Ok(hash)
+    }
+
+    // The cache is only updated once `inner` has taken the changes
+    fn commit(&mut self, changes: &ChangeSet) -> Result<(), DatabaseError> {
+        self.inner.commit(changes)?;
+        self.accounts.extend(changes.destroyed.iter().map(|address| (*address, None)));
+        self.storage.retain(|(address, _), _| !changes.destroyed.contains(address));
+        self.accounts.extend(changes.accounts.iter().map(|(address, info)| (*address, Some(info.clone()))));
+        self.code.extend(changes.code.iter().map(|(hash, code)| (*hash, code.clone())));
+        self.storage.extend(changes.storage.iter().map(|(slot, value)| (*slot, *value)));
+        self.block_hashes.extend(changes.block_hashes.iter().map(|(number, hash)| (*number, *hash)));
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use ethereum_types::{H160, H256, U256, U64};
+use serde::{Deserialize, Serialize};
+use crate::hex::Bytes;
+use crate::state::EMPTY_CODE_HASH;
+use super::{AccountInfo, ChangeSet, Database, DatabaseError};
+
+// Database kept in a JSON file. The whole file is rewritten on every commit: the new contents
+// go to a temporary file that then replaces the old one, so a crash leaves either the old or
+// the new state on disk, never a mix. The directory is synced after the rename so the new file
+// survives a crash once the commit returns
+#[derive(Debug)]
+pub struct FileDb {
+    path: PathBuf,
+    contents: Contents,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Contents {
+    accounts: BTreeMap<H160, StoredAccount>,
+    code: BTreeMap<H256, Bytes>,
+    storage: BTreeMap<H160, BTreeMap<U256, U256>>,
+    block_hashes: BTreeMap<u64, H256>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredAccount {
+    balance: U256,
+    nonce: U64,
+    code_hash: H256,
+}
+
+impl FileDb {
+    // Opens the database at `path`, starting empty if the file does not exist yet
+    pub fn open(path: impl AsRef<Path>) -> Result<FileDb, DatabaseError> {
+        let path = path.as_ref().to_path_buf();
+        let contents = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|error| DatabaseError::Corrupt(error.to_string()))?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Contents::default(),
+            Err(error) => return Err(DatabaseError::Io(error.to_string())),
+        };
+        Ok(FileDb { path, contents })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Replaces the file with `contents`, leaving it as it was on error
+    fn write(&self, contents: &Contents) -> Result<(), DatabaseError> {
+        let data = serde_json::to_vec_pretty(contents).map_err(|error| DatabaseError::Io(error.to_string()))?;
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+
+        let result = (|| {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+            fs::rename(&tmp_path, &self.path)
+        })();
+        result.map_err(|error| {
+            let _ = fs::remove_file(&tmp_path);
+            DatabaseError::Io(error.to_string())
+        })
+    }
+}
+
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> std::io::Result<()> {
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::File::open(parent)?.sync_all()
+}
+
+// Directories cannot be opened to be synced elsewhere
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+impl Database for FileDb {
+    fn basic(&mut self, address: H160) -> Result<Option<AccountInfo>, DatabaseError> {
+        Ok(self.contents.accounts.get(&address).map(|account| AccountInfo {
+            balance: account.balance,
+            nonce: account.nonce.as_u64(),
+            code_hash: account.code_hash,
+        }))
+    }
+
+    fn code_by_hash(&mut self, code_hash: H256) -> Result<Vec<u8>, DatabaseError> {
+        if code_hash == EMPTY_CODE_HASH {
+            return Ok(Vec::new());
+        }
+        self.contents.code.get(&code_hash).map(|code| code.0.clone()).ok_or(DatabaseError::MissingCode(code_hash))
+    }
+
+    fn storage(&mut self, address: H160, key: U256) -> Result<U256, DatabaseError> {
+        Ok(self.contents.storage.get(&address).and_then(|slots| slots.get(&key)).copied().unwrap_or_default())
+    }
+
+    fn block_hash(&mut self, number: u64) -> Result<H256, DatabaseError> {
+        Ok(self.contents.block_hashes.get(&number).copied().unwrap_or_default())
+    }
+
+    // The file is written first, memory is only updated once it has replaced the old one. If only
+    // syncing the directory fails the new file is already in place, so the changes are kept and
+    // the error says they may not survive a crash
+    fn commit(&mut self, changes: &ChangeSet) -> Result<(), DatabaseError> {
+        let mut contents = self.contents.clone();
+        for address in &changes.destroyed {
+            contents.accounts.remove(address);
+            contents.storage.remove(address);
+        }
+        for (address, info) in &changes.accounts {
+            let account = StoredAccount { balance: info.balance, nonce: U64::from(info.nonce), code_hash: info.code_hash };
+            contents.accounts.insert(*address, account);
+        }
+        for (hash, code) in &changes.code {
+            contents.code.insert(*hash, Bytes(code.clone()));
+        }
+        for ((address, key), value) in &changes.storage {
+            let slots = contents.storage.entry(*address).or_default();
+            if value.is_zero() {
+                slots.remove(key);
+            } else {
+                slots.insert(*key, *value);
+            }
+        }
+        contents.storage.retain(|_, slots| !slots.is_empty());
+        contents.block_hashes.extend(&changes.block_hashes);
+
+        self.write(&contents)?;
+        self.contents = contents;
+        sync_parent(&self.path).map_err(|error| DatabaseError::Io(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rtevm-file-db-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("state.json")
+    }
+
+    fn changes() -> ChangeSet {
+        let code = vec![0x60, 0x00, 0x00];
+        let code_hash = H256(crate::helper::Helper::keccak256(&code));
+        let mut changes = ChangeSet::default();
+        changes.accounts.insert(H160([1; 20]), AccountInfo { balance: U256::from(100), nonce: 2, code_hash: EMPTY_CODE_HASH });
+        changes.accounts.insert(H160([2; 20]), AccountInfo { balance: U256::zero(), nonce: 1, code_hash });
+        changes.code.insert(code_hash, code);
+        changes.storage.insert((H160([2; 20]), U256::one()), U256::from(7));
+        changes.storage.insert((H160([2; 20]), U256::MAX), U256::MAX);
+        changes.block_hashes.insert(5, H256::repeat_byte(5));
+        changes
+    }
+
+    #[test]
+    fn commits_survive_reopening() {
+        let path = temp_path("reopen");
+        let mut db = FileDb::open(&path).unwrap();
+        db.commit(&changes()).unwrap();
+
+        let mut db = FileDb::open(&path).unwrap();
+        let account = db.basic(H160([2; 20])).unwrap().unwrap();
+        assert_eq!(account.nonce, 1);
+        assert_eq!(db.code_by_hash(account.code_hash).unwrap(), [0x60, 0x00, 0x00]);
+        assert_eq!(db.basic(H160([1; 20])).unwrap().unwrap().balance, U256::from(100));
+        assert_eq!(db.storage(H160([2; 20]), U256::one()).unwrap(), U256::from(7));
+        assert_eq!(db.storage(H160([2; 20]), U256::MAX).unwrap(), U256::MAX);
+        assert_eq!(db.block_hash(5).unwrap(), H256::repeat_byte(5));
+        assert_eq!(db.basic(H160([3; 20])).unwrap(), None);
+
+        // Cleared slots and destroyed accounts are gone after the next reopen
+        let mut more = ChangeSet::default();
+        more.storage.insert((H160([2; 20]), U256::one()), U256::zero());
+        more.destroyed.insert(H160([1; 20]));
+        db.commit(&more).unwrap();
+        let mut db = FileDb::open(&path).unwrap();
+        assert_eq!(db.storage(H160([2; 20]), U256::one()).unwrap(), U256::zero());
+        assert_eq!(db.storage(H160([2; 20]), U256::MAX).unwrap(), U256::MAX);
+        assert_eq!(db.basic(H160([1; 20])).unwrap(), None);
+        assert!(!path.with_extension("json.tmp").exists());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn failed_writes_leave_the_previous_file() {
+        let path = temp_path("failed");
+        let mut db = FileDb::open(&path).unwrap();
+        db.commit(&changes()).unwrap();
+        let before = fs::read(&path).unwrap();
+
+        // A directory in the way of the temporary file makes the write fail
+        fs::create_dir(path.with_extension("json.tmp")).unwrap();
+        let mut more = ChangeSet::default();
+        more.destroyed.insert(H160([1; 20]));
+        assert!(matches!(db.commit(&more), Err(DatabaseError::Io(_))));
+        assert_eq!(fs::read(&path).unwrap(), before);
+        assert!(db.basic(H160([1; 20])).unwrap().is_some());
+        assert!(FileDb::open(&path).unwrap().basic(H160([1; 20])).unwrap().is_some());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn corrupt_files_are_reported() {
+        let path = temp_path("corrupt");
+        fs::write(&path, b"{ not json").unwrap();
+        assert!(matches!(FileDb::open(&path), Err(DatabaseError::Corrupt(_))));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use ethereum_types::{H160, H256, U256};
+use crate::helper::Helper;
+use crate::state::EMPTY_CODE_HASH;
+use super::{AccountInfo, ChangeSet, Database, DatabaseError};
+
+// Database held in memory, filled up front
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryDb {
+    accounts: HashMap<H160, AccountInfo>,
+    code: HashMap<H256, Vec<u8>>,
+    storage: HashMap<(H160, U256), U256>,
+    block_hashes: HashMap<u64, H256>,
+}
+
+impl InMemoryDb {
+    pub fn new() -> InMemoryDb {
+        InMemoryDb::default()
+    }
+
+    pub fn insert_account(&mut self, address: H160, balance: U256, nonce: u64, code: Vec<u8>) {
+        let code_hash = if code.is_empty() { EMPTY_CODE_HASH } else { H256(Helper::keccak256(&code)) };
+        self.code.insert(code_hash, code);
+        self.accounts.insert(address, AccountInfo { balance, nonce, code_hash });
+    }
+
+    pub fn insert_storage(&mut self, address: H160, key: U256, value: U256) {
+        self.storage.insert((address, key), value);
+    }
+
+    pub fn insert_block_hash(&mut self, number: u64, hash: H256) {
+        self.block_hashes.insert(number, hash);
+    }
+}
+
+impl Database for InMemoryDb {
+    fn basic(&mut self, address: H160) -> Result<Option<AccountInfo>, DatabaseError> {
+        Ok(self.accounts.get(&address).cloned())
+    }
+
+    fn code_by_hash(&mut self, code_hash: H256) -> Result<Vec<u8>, DatabaseError> {
+        if code_hash == EMPTY_CODE_HASH {
+            return Ok(Vec::new());
+        }
+        self.code.get(&code_hash).cloned().ok_or(DatabaseError::MissingCode(code_hash))
+    }
+
+    fn storage(&mut self, address: H160, key: U256) -> Result<U256, DatabaseError> {
+        Ok(self.storage.get(&(address, key)).copied().unwrap_or_default())
+    }
+
+    fn block_hash(&mut self, number: u64) -> Result<H256, DatabaseError> {
+        Ok(self.block_hashes.get(&number).copied().unwrap_or_default())
+    }
+
+    fn commit(&mut self, changes: &ChangeSet) -> Result<(), DatabaseError> {
+        for address in &changes.destroyed {
+            self.accounts.remove(address);
+        }
+        self.storage.retain(|(address, _), _| !changes.destroyed.contains(address));
+        self.accounts.extend(changes.accounts.iter().map(|(address, info)| (*address, info.clone())));
+        self.code.extend(changes.code.iter().map(|(hash, code)| (*hash, code.clone())));
+        self.storage.extend(changes.storage.iter().map(|(slot, value)| (*slot, *value)));
+        self.block_hashes.extend(changes.block_hashes.iter().map(|(number, hash)| (*number, *hash)));
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use ethereum_types::{H160, H256, U256, U64};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use crate::helper::Helper;
+use crate::hex::Bytes;
+use crate::state::EMPTY_CODE_HASH;
+use super::{AccountInfo, CacheDb, Database, DatabaseError};
+
+// Reads state from a JSON-RPC node as of a pinned block, like a forked chain. Every lookup is
+// a request, wrap it in a `CacheDb` (see `cached`) to make each one only once
+#[derive(Debug)]
+pub struct RemoteDb {
+    url: String,
+    block: String,
+    agent: ureq::Agent,
+    next_id: u64,
+    code: HashMap<H256, Vec<u8>>, // fetched along with the accounts, served by code_by_hash
+}
+
+impl RemoteDb {
+    pub fn new(url: impl Into<String>, block_number: u64) -> RemoteDb {
+        RemoteDb {
+            url: url.into(),
+            block: format!("{:#x}", block_number),
+            agent: ureq::Agent::new(),
+            next_id: 1,
+            code: HashMap::new(),
+        }
+    }
+
+    pub fn cached(self) -> CacheDb<RemoteDb> {
+        CacheDb::new(self)
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn request<T: DeserializeOwned>(&mut self, method: &str, params: Value) -> Result<T, DatabaseError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let body = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+
+        let response: Value = self
+            .agent
+            .post(&self.url)
+            .send_json(body)
+            .map_err(|error| DatabaseError::Io(error.to_string()))?
+            .into_json()
+            .map_err(|error| DatabaseError::InvalidResponse(error.to_string()))?;
+
+        if let Some(error) = response.get("error") {
+            return Err(DatabaseError::Rpc {
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+            });
+        }
+        let result = response.get("result").cloned().unwrap_or(Value::Null);
+        serde_json::from_value(result).map_err(|error| DatabaseError::InvalidResponse(format!("{}: {}", method, error)))
+    }
+}
+
+impl Database for RemoteDb {
+    // JSON-RPC has no notion of a missing account, an empty one is reported as missing
+    fn basic(&mut self, address: H160) -> Result<Option<AccountInfo>, DatabaseError> {
+        let block = self.block.clone();
+        let balance: U256 = self.request("eth_getBalance", json!([address, block]))?;
+        let nonce: U64 = self.request("eth_getTransactionCount", json!([address, block]))?;
+        let code: Bytes = self.request("eth_getCode", json!([address, block]))?;
+
+        if balance.is_zero() && nonce.is_zero() && code.is_empty() {
+            return Ok(None);
+        }
+        let code_hash = if code.is_empty() { EMPTY_CODE_HASH } else { H256(Helper::keccak256(&code)) };
+        self.code.insert(code_hash, code.0);
+        Ok(Some(AccountInfo { balance, nonce: nonce.as_u64(), code_hash }))
+    }
+
+    fn code_by_hash(&mut self, code_hash: H256) -> Result<Vec<u8>, DatabaseError> {
+        if code_hash == EMPTY_CODE_HASH {
+            return Ok(Vec::new());
+        }
+        self.code.get(&code_hash).cloned().ok_or(DatabaseError::MissingCode(code_hash))
+    }
+
+    fn storage(&mut self, address: H160, key: U256) -> Result<U256, DatabaseError> {
+        let block = self.block.clone();
+        let mut slot = [0u8; 32];
+        key.to_big_endian(&mut slot);
+        let value: H256 = self.request("eth_getStorageAt", json!([address, H256(slot), block]))?;
+        Ok(U256::from_big_endian(value.as_bytes()))
+    }
+
+    fn block_hash(&mut self, number: u64) -> Result<H256, DatabaseError> {
+        let block: Option<Value> = self.request("eth_getBlockByNumber", json!([format!("{:#x}", number), false]))?;
+        match block {
+            Some(block) => serde_json::from_value(block["hash"].clone()).map_err(|error| DatabaseError::InvalidResponse(error.to_string())),
+            None => Ok(H256::zero()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    const ACCOUNT: H160 = H160([0xaa; 20]);
+
+    // Method and params of each request, in order
+    type Requests = Arc<Mutex<Vec<(String, Value)>>>;
+
+    // A JSON-RPC node on localhost answering from fixtures
+    fn serve() -> (String, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Requests::default();
+        let log = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = BufReader::new(stream.unwrap());
+                // Connections are kept alive, answer until the client hangs up
+                loop {
+                    let mut length = None;
+                    let mut line = String::new();
+                    loop {
+                        line.clear();
+                        if stream.read_line(&mut line).unwrap_or(0) == 0 {
+                            break;
+                        }
+                        if line == "\r\n" {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                length = value.trim().parse().ok();
+                            }
+                        }
+                    }
+                    let Some(length) = length else {
+                        break;
+                    };
+                    let mut body = vec![0; length];
+                    stream.read_exact(&mut body).unwrap();
+                    let request: Value = serde_json::from_slice(&body).unwrap();
+                    let method = request["method"].as_str().unwrap().to_string();
+                    let response = match fixture(&method, &request["params"]) {
+                        Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+                        Err(message) => json!({ "jsonrpc": "2.0", "id": request["id"], "error": { "code": -32000, "message": message } }),
+                    };
+                    log.lock().unwrap().push((method, request["params"].clone()));
+                    let body = response.to_string();
+                    let head = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n", body.len());
+                    stream.get_mut().write_all(head.as_bytes()).unwrap();
+                    stream.get_mut().write_all(body.as_bytes()).unwrap();
+                }
+            }
+        });
+        (url, requests)
+    }
+
+    fn fixture(method: &str, params: &Value) -> Result<Value, &'static str> {
+        let known = params[0] == json!(ACCOUNT);
+        match method {
+            "eth_getBalance" if known => Ok(json!("0xde0b6b3a7640000")),
+            "eth_getTransactionCount" if known => Ok(json!("0x5")),
+            "eth_getCode" if known => Ok(json!("0x6001600055")),
+            "eth_getBalance" | "eth_getTransactionCount" => Ok(json!("0x0")),
+            "eth_getCode" => Ok(json!("0x")),
+            "eth_getStorageAt" if params[1] == json!(H256::from_low_u64_be(1)) => {
+                Ok(json!("0x000000000000000000000000000000000000000000000000000000000000002a"))
+            }
+            "eth_getStorageAt" => Err("storage unavailable"),
+            _ => Err("method not found"),
+        }
+    }
+
+    #[test]
+    fn reads_accounts_and_storage_at_the_pinned_block() {
+        let (url, requests) = serve();
+        let mut db = RemoteDb::new(url, 0x112a880);
+
+        let info = db.basic(ACCOUNT).unwrap().unwrap();
+        assert_eq!(info.balance, U256::exp10(18));
+        assert_eq!(info.nonce, 5);
+        assert_eq!(db.code_by_hash(info.code_hash).unwrap(), vec![0x60, 0x01, 0x60, 0x00, 0x55]);
+        assert_eq!(db.basic(H160::repeat_byte(0xbb)).unwrap(), None);
+        assert_eq!(db.storage(ACCOUNT, U256::one()).unwrap(), U256::from(42));
+        assert_eq!(
+            db.storage(ACCOUNT, U256::from(2)),
+            Err(DatabaseError::Rpc { code: -32000, message: "storage unavailable".to_string() })
+        );
+
+        let requests = requests.lock().unwrap();
+        let methods: Vec<&str> = requests.iter().map(|(method, _)| method.as_str()).collect();
+        assert_eq!(methods[..4], ["eth_getBalance", "eth_getTransactionCount", "eth_getCode", "eth_getBalance"]);
+        assert_eq!(requests.len(), 8);
+        for (_, params) in requests.iter() {
+            assert_eq!(params.as_array().unwrap().last().unwrap(), "0x112a880");
+        }
+    }
+
+    #[test]
+    fn cached_makes_each_request_once() {
+        let (url, requests) = serve();
+        let mut db = RemoteDb::new(url, 1).cached();
+        for _ in 0..3 {
+            let info = db.basic(ACCOUNT).unwrap().unwrap();
+            db.code_by_hash(info.code_hash).unwrap();
+            assert_eq!(db.storage(ACCOUNT, U256::one()).unwrap(), U256::from(42));
+        }
+        assert_eq!(requests.lock().unwrap().len(), 4);
+
+        // Errors are asked again
+        assert!(db.storage(ACCOUNT, U256::from(2)).is_err());
+        assert!(db.storage(ACCOUNT, U256::from(2)).is_err());
+        assert_eq!(requests.lock().unwrap().len(), 6);
+    }
+}
@@ -0,0 +1,273 @@
+use std::fmt;
+use ethereum_types::H160;
+use crate::hex;
+use crate::inspector::{CallInputs, CallKind, CallOutcome, CreateInputs, CreateKind, Inspector};
+use crate::opcodes::Opcode;
+use crate::sourcemap::{SourceLocation, SourceMap};
+use crate::tracer::op_name;
+use crate::EVM;
+
+mod history;
+mod navigator;
+mod repl;
+mod tui;
+pub use history::{Cursor, FrameState, History, MemoryChange, StackChange, StepRecord, StorageChange, TransientChange};
+pub use navigator::Navigator;
+pub use repl::Repl;
+pub use tui::Tui;
+
+// Where execution pauses, checked before every opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    Pc(usize),
+    Opcode(u8),
+    StorageWrite,
+    Log,
+}
+
+impl Breakpoint {
+    // Whether execution pauses before `op` at `pc`
+    pub fn matches(&self, pc: usize, op: u8) -> bool {
+        match self {
+            Breakpoint::Pc(breakpoint) => pc == *breakpoint,
+            Breakpoint::Opcode(breakpoint) => op == *breakpoint,
+            Breakpoint::StorageWrite => op == Opcode::SSTORE as u8,
+            Breakpoint::Log => (Opcode::LOG0 as u8..=Opcode::LOG4 as u8).contains(&op),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Pc(pc) => write!(f, "pc {:#x}", pc),
+            Breakpoint::Opcode(op) => write!(f, "opcode {}", op_name(*op)),
+            Breakpoint::StorageWrite => f.write_str("storage write"),
+            Breakpoint::Log => f.write_str("log"),
+        }
+    }
+}
+
+// The instruction at `pc` with its PUSH data, say `PUSH2 0x0102`
+pub fn instruction_at(code: &[u8], pc: usize) -> String {
+    let op = code.get(pc).copied().unwrap_or(Opcode::STOP as u8);
+    match op {
+        0x60..=0x7f => {
+            let end = (pc + 1 + (op - 0x5f) as usize).min(code.len());
+            let data = code.get(pc + 1..end).unwrap_or_default();
+            format!("{} {}", op_name(op), hex::encode(data))
+        }
+        _ => op_name(op),
+    }
+}
+
+// How the frontend lets execution go on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    // Pause before the next opcode, in whichever frame it runs
+    Step,
+    // Pause before the next opcode at the given depth or less, running calls through
+    StepOver(usize),
+    // Pause before the next opcode at less than the given depth, once its frame has returned
+    StepOut(usize),
+    // Pause at the next breakpoint
+    Continue,
+    // Run to the end without pausing again
+    Detach,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    Step,
+    Breakpoint(usize),
+}
+
+// A frame on the call stack, the outermost first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: &'static str,
+    pub address: H160,
+    pub code_address: H160,
+    pub depth: usize,
+}
+
+// What the frontend sees and may change while execution is paused
+#[derive(Debug, Default)]
+pub struct Session {
+    breakpoints: Vec<Breakpoint>,
+    frames: Vec<Frame>,
+    reason: Option<PauseReason>,
+    history: History,
+    source_maps: Vec<SourceMap>,
+}
+
+impl Session {
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    // Returns the index of the breakpoint, an existing one being reused
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        match self.breakpoints.iter().position(|existing| *existing == breakpoint) {
+            Some(index) => index,
+            None => {
+                self.breakpoints.push(breakpoint);
+                self.breakpoints.len() - 1
+            }
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        (index < self.breakpoints.len()).then(|| self.breakpoints.remove(index))
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    // Number of opcodes executed before the one about to run
+    pub fn step(&self) -> usize {
+        self.history.len().saturating_sub(1)
+    }
+
+    // Every step so far, the last being the one about to run
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn reason(&self) -> Option<PauseReason> {
+        self.reason
+    }
+
+    pub fn has_source_maps(&self) -> bool {
+        !self.source_maps.is_empty()
+    }
+
+    // The source map made for `code`
+    pub fn source_map(&self, code: &[u8]) -> Option<&SourceMap> {
+        self.source_maps.iter().find(|map| map.code() == code)
+    }
+
+    // The Solidity line a step ran, when its code has a source map
+    pub fn source_location(&self, record: &StepRecord) -> Option<SourceLocation> {
+        self.source_map(self.history.code(record))?.location(record.pc)
+    }
+}
+
+// A user interface for the debugger
+pub trait Frontend {
+    // Execution is paused before `evm`'s current opcode
+    fn paused(&mut self, evm: &EVM, session: &mut Session) -> Resume;
+
+    // The outermost call or creation has returned
+    fn finished(&mut self, _outcome: &CallOutcome, _session: &Session) {}
+}
+
+// Pauses execution, in any frame, whenever stepping or a breakpoint asks for it and hands control
+// to the frontend. It is an inspector, so it debugs both `EVM::run` and transactions:
+//
+//     let mut debugger = Debugger::new(Repl::new(stdin.lock(), stdout));
+//     inspect_transaction(&tx, &block, &mut state, &mut precompiles, &mut debugger)?;
+pub struct Debugger<F: Frontend> {
+    frontend: F,
+    session: Session,
+    resume: Resume,
+}
+
+impl<F: Frontend> Debugger<F> {
+    pub fn new(frontend: F) -> Self {
+        Debugger { frontend, session: Session::default(), resume: Resume::Step }
+    }
+
+    pub fn with_breakpoint(mut self, breakpoint: Breakpoint) -> Self {
+        self.session.add_breakpoint(breakpoint);
+        self
+    }
+
+    // Shows Solidity lines for frames running the map's code, wherever it is deployed
+    pub fn with_source_map(mut self, map: SourceMap) -> Self {
+        self.session.source_maps.push(map);
+        self
+    }
+
+    // Starts without pausing until a breakpoint is hit
+    pub fn continue_to_breakpoint(mut self) -> Self {
+        self.resume = Resume::Continue;
+        self
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub fn frontend(&mut self) -> &mut F {
+        &mut self.frontend
+    }
+
+    pub fn into_frontend(self) -> F {
+        self.frontend
+    }
+
+    fn enter(&mut self, frame: Frame) {
+        self.session.frames.push(frame);
+    }
+
+    fn exit(&mut self, depth: usize, outcome: &CallOutcome) {
+        self.session.history.frame_end(depth, outcome);
+        self.session.frames.pop();
+        if depth == 0 {
+            self.frontend.finished(outcome, &self.session);
+        }
+    }
+}
+
+impl<F: Frontend> Inspector for Debugger<F> {
+    fn step(&mut self, evm: &EVM) {
+        self.session.history.step(evm);
+        let stepped = match self.resume {
+            Resume::Step => true,
+            Resume::StepOver(depth) => evm.depth() <= depth,
+            Resume::StepOut(depth) => evm.depth() < depth,
+            Resume::Continue | Resume::Detach => false,
+        };
+        let hit = match self.resume {
+            Resume::Detach => None,
+            _ => self.session.breakpoints.iter().position(|breakpoint| breakpoint.matches(evm.pc(), evm.opcode())),
+        };
+        let reason = hit.map(PauseReason::Breakpoint).or(stepped.then_some(PauseReason::Step));
+        if reason.is_some() {
+            self.session.reason = reason;
+            self.resume = self.frontend.paused(evm, &mut self.session);
+        }
+    }
+
+    fn step_end(&mut self, evm: &EVM) {
+        self.session.history.step_end(evm);
+    }
+
+    fn call(&mut self, inputs: &CallInputs) {
+        let kind = match inputs.kind {
+            CallKind::Call => "CALL",
+            CallKind::CallCode => "CALLCODE",
+            CallKind::DelegateCall => "DELEGATECALL",
+            CallKind::StaticCall => "STATICCALL",
+        };
+        self.enter(Frame { kind, address: inputs.target, code_address: inputs.code_address, depth: inputs.depth });
+    }
+
+    fn call_end(&mut self, inputs: &CallInputs, outcome: &CallOutcome) {
+        self.exit(inputs.depth, outcome);
+    }
+
+    fn create(&mut self, inputs: &CreateInputs) {
+        let kind = match inputs.kind {
+            CreateKind::Create => "CREATE",
+            CreateKind::Create2 => "CREATE2",
+        };
+        self.enter(Frame { kind, address: inputs.address, code_address: inputs.address, depth: inputs.depth });
+    }
+
+    fn create_end(&mut self, inputs: &CreateInputs, outcome: &CallOutcome) {
+        self.exit(inputs.depth, outcome);
+    }
+}
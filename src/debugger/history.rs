@@ -0,0 +1,608 @@
+use std::collections::{BTreeMap, HashMap};
+use ethereum_types::{H160, U256};
+use crate::inspector::CallOutcome;
+use crate::opcodes::Opcode;
+use crate::state::JournalEntry;
+use crate::{Transient, State, EVM};
+
+// The top of the stack an opcode replaced, bottom first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StackChange {
+    pub popped: Vec<U256>,
+    pub pushed: Vec<U256>,
+}
+
+// Bytes an opcode wrote at `offset`, and how it grew memory. `before` only covers what existed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryChange {
+    pub offset: usize,
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+    pub len_before: usize,
+    pub len_after: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageChange {
+    pub address: H160,
+    pub key: U256,
+    pub from: U256,
+    pub to: U256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransientChange {
+    pub address: H160,
+    pub key: U256,
+    pub from: U256,
+    pub to: U256,
+}
+
+// One executed opcode: where it ran and everything needed to redo or undo it. The last step of a
+// failed frame also carries the storage and transient writes the frame rolled back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepRecord {
+    pub pc: usize,
+    pub op: u8,
+    pub gas: usize,
+    pub depth: usize,
+    pub address: H160,
+    // Index into `History::code`
+    pub code: usize,
+    pub stack: StackChange,
+    pub memory: Option<MemoryChange>,
+    pub storage: Vec<StorageChange>,
+    pub transient: Vec<TransientChange>,
+    pub return_data: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+// The machine state before a step that is only known once it has run
+#[derive(Debug)]
+struct Pending {
+    index: usize,
+    stack: Vec<U256>,
+    region: Option<(usize, usize)>,
+    memory: Vec<u8>,
+    memory_len: usize,
+    journal_len: usize,
+    transient: Option<(U256, U256)>,
+    return_data: Option<Vec<u8>>,
+}
+
+// Every step of an execution, recorded by the debugger as it runs. The last record belongs to
+// the opcode execution is paused at, whose changes are not known yet
+#[derive(Debug, Default)]
+pub struct History {
+    records: Vec<StepRecord>,
+    codes: Vec<Vec<u8>>,
+    // Code and first record of every running frame, the outermost first
+    frames: Vec<(usize, usize)>,
+    pending: Vec<Pending>,
+    initial_storage: HashMap<(H160, U256), U256>,
+    initial_transient: HashMap<(H160, U256), U256>,
+}
+
+// Memory an opcode may write to, from its stack arguments
+fn memory_region(op: u8, stack: &[U256]) -> Option<(usize, usize)> {
+    let argument = |position: usize| {
+        let value = *stack.get(stack.len().checked_sub(position + 1)?)?;
+        (value <= U256::from(u32::MAX)).then(|| value.as_usize())
+    };
+    let (offset, size) = match Opcode::try_from_u8(op)? {
+        Opcode::MSTORE => (argument(0)?, 32),
+        Opcode::MSTORE8 => (argument(0)?, 1),
+        Opcode::CALLDATACOPY | Opcode::CODECOPY | Opcode::RETURNDATACOPY | Opcode::MCOPY => (argument(0)?, argument(2)?),
+        Opcode::EXTCODECOPY => (argument(1)?, argument(3)?),
+        Opcode::CALL | Opcode::CALLCODE => (argument(5)?, argument(6)?),
+        Opcode::DELEGATECALL | Opcode::STATICCALL => (argument(4)?, argument(5)?),
+        _ => return None,
+    };
+    (size > 0).then_some((offset, size))
+}
+
+fn changes_return_data(op: u8) -> bool {
+    matches!(
+        Opcode::try_from_u8(op),
+        Some(
+            Opcode::CALL
+                | Opcode::CALLCODE
+                | Opcode::DELEGATECALL
+                | Opcode::STATICCALL
+                | Opcode::CREATE
+                | Opcode::CREATE2
+        )
+    )
+}
+
+fn clip(data: &[u8], offset: usize, size: usize) -> &[u8] {
+    &data[offset.min(data.len())..offset.saturating_add(size).min(data.len())]
+}
+
+impl History {
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn records(&self) -> &[StepRecord] {
+        &self.records
+    }
+
+    pub fn get(&self, step: usize) -> Option<&StepRecord> {
+        self.records.get(step)
+    }
+
+    pub fn code(&self, record: &StepRecord) -> &[u8] {
+        &self.codes[record.code]
+    }
+
+    pub(super) fn step(&mut self, evm: &EVM) {
+        let index = self.records.len();
+        self.frames.truncate(evm.depth() + 1);
+        if self.frames.len() < evm.depth() + 1 {
+            self.codes.push(evm.code().to_vec());
+            self.frames.push((self.codes.len() - 1, index));
+        }
+        let stack = evm.stack().data();
+        let region = memory_region(evm.opcode(), stack);
+        let memory = region.map(|(offset, size)| clip(evm.memory().data(), offset, size).to_vec()).unwrap_or_default();
+        let transient = (evm.opcode() == Opcode::TSTORE as u8)
+            .then(|| stack.last().copied())
+            .flatten()
+            .map(|key| (key, evm.transient().load(evm.address(), key)));
+        self.pending.push(Pending {
+            index,
+            stack: stack.to_vec(),
+            region,
+            memory,
+            memory_len: evm.memory().len(),
+            journal_len: evm.state().journal().len(),
+            transient,
+            return_data: changes_return_data(evm.opcode()).then(|| evm.return_data().to_vec()),
+        });
+        self.records.push(StepRecord {
+            pc: evm.pc(),
+            op: evm.opcode(),
+            gas: evm.gas_left(),
+            depth: evm.depth(),
+            address: evm.address(),
+            code: self.frames[evm.depth()].0,
+            stack: StackChange::default(),
+            memory: None,
+            storage: Vec::new(),
+            transient: Vec::new(),
+            return_data: None,
+        });
+    }
+
+    pub(super) fn step_end(&mut self, evm: &EVM) {
+        let Some(pending) = self.pending.pop() else {
+            return;
+        };
+
+        let after = evm.stack().data();
+        let common = pending.stack.iter().zip(after).take_while(|(a, b)| a == b).count();
+        let stack = StackChange { popped: pending.stack[common..].to_vec(), pushed: after[common..].to_vec() };
+
+        let memory_len = evm.memory().len();
+        let memory = match pending.region {
+            Some((offset, size)) => Some(MemoryChange {
+                offset,
+                before: pending.memory,
+                after: clip(evm.memory().data(), offset, size).to_vec(),
+                len_before: pending.memory_len,
+                len_after: memory_len,
+            }),
+            None if memory_len != pending.memory_len => Some(MemoryChange {
+                offset: pending.memory_len,
+                before: Vec::new(),
+                after: Vec::new(),
+                len_before: pending.memory_len,
+                len_after: memory_len,
+            }),
+            None => None,
+        };
+
+        // Writes made by calls are recorded in their own frames
+        let mut storage = Vec::new();
+        if evm.opcode() == Opcode::SSTORE as u8 {
+            for entry in evm.state().journal().get(pending.journal_len..).unwrap_or_default() {
+                if let JournalEntry::StorageChanged { address, key, previous } = entry {
+                    let to = evm.state().cached_account(address).map(|account| account.storage.get(*key)).unwrap_or_default();
+                    storage.push(StorageChange { address: *address, key: *key, from: *previous, to });
+                }
+            }
+        }
+        let transient = pending
+            .transient
+            .map(|(key, from)| TransientChange { address: evm.address(), key, from, to: evm.transient().load(evm.address(), key) })
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        for change in &storage {
+            self.initial_storage.entry((change.address, change.key)).or_insert(change.from);
+        }
+        for change in &transient {
+            self.initial_transient.entry((change.address, change.key)).or_insert(change.from);
+        }
+        let record = &mut self.records[pending.index];
+        record.stack = stack;
+        record.memory = memory;
+        record.storage.extend(storage);
+        record.transient.extend(transient);
+        record.return_data = pending.return_data.map(|before| (before, evm.return_data().to_vec()));
+    }
+
+    // The state rolls back what a failed frame at `depth` wrote to storage and transient storage,
+    // which its last step records as writes of its own
+    pub(super) fn frame_end(&mut self, depth: usize, outcome: &CallOutcome) {
+        if outcome.success || self.frames.len() != depth + 1 {
+            return;
+        }
+        let start = self.frames[depth].1;
+        let rollback: Vec<StorageChange> = self.records[start..]
+            .iter()
+            .filter(|record| record.depth >= depth)
+            .flat_map(|record| record.storage.iter())
+            .rev()
+            .map(|change| StorageChange { from: change.to, to: change.from, ..*change })
+            .collect();
+        let transient_rollback: Vec<TransientChange> = self.records[start..]
+            .iter()
+            .filter(|record| record.depth >= depth)
+            .flat_map(|record| record.transient.iter())
+            .rev()
+            .map(|change| TransientChange { from: change.to, to: change.from, ..*change })
+            .collect();
+        if let Some(last) = self.records.last_mut() {
+            last.storage.extend(rollback);
+            last.transient.extend(transient_rollback);
+        }
+    }
+}
+
+// A frame as it was at some step
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameState {
+    pub address: H160,
+    pub depth: usize,
+    pub stack: Vec<U256>,
+    pub memory: Vec<u8>,
+    pub return_data: Vec<u8>,
+}
+
+// A position in the history, with the state of the machine before that step. It moves a step at a
+// time by redoing or undoing records, the frames of finished calls being kept to step back into
+#[derive(Debug, Default)]
+pub struct Cursor {
+    position: usize,
+    frames: Vec<FrameState>,
+    exited: Vec<FrameState>,
+    storage: HashMap<(H160, U256), U256>,
+    transient: HashMap<(H160, U256), U256>,
+    // Steps passed while they were still running, their changes not known yet
+    unsettled: Vec<usize>,
+}
+
+impl Cursor {
+    pub fn new() -> Self {
+        Cursor::default()
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn record<'h>(&self, history: &'h History) -> Option<&'h StepRecord> {
+        history.get(self.position)
+    }
+
+    // Running frames, the outermost first
+    pub fn frames(&self) -> &[FrameState] {
+        &self.frames
+    }
+
+    pub fn frame(&self) -> Option<&FrameState> {
+        self.frames.last()
+    }
+
+    // Moves to `position`, at most the last step of the history
+    pub fn seek(&mut self, history: &History, position: usize) {
+        self.sync(history);
+        let target = position.min(history.len().saturating_sub(1));
+        while self.position < target && self.forward(history) {}
+        while self.position > target && self.back(history) {}
+    }
+
+    // Redoes the current step, false at the last step of the history
+    pub fn forward(&mut self, history: &History) -> bool {
+        self.sync(history);
+        if self.position + 1 >= history.len() {
+            return false;
+        }
+        let record = &history.records[self.position];
+        if let Some(frame) = self.frames.last_mut() {
+            frame.stack.truncate(frame.stack.len() - record.stack.popped.len());
+            frame.stack.extend_from_slice(&record.stack.pushed);
+            if let Some(change) = &record.memory {
+                frame.memory.resize(change.len_after, 0);
+                if !change.after.is_empty() {
+                    frame.memory[change.offset..change.offset + change.after.len()].copy_from_slice(&change.after);
+                }
+            }
+            if let Some((_, after)) = &record.return_data {
+                frame.return_data = after.clone();
+            }
+        }
+        for change in &record.storage {
+            self.storage.insert((change.address, change.key), change.to);
+        }
+        for change in &record.transient {
+            self.transient.insert((change.address, change.key), change.to);
+        }
+        if history.pending.iter().any(|pending| pending.index == self.position) {
+            self.unsettled.push(self.position);
+        }
+
+        self.position += 1;
+        let next = &history.records[self.position];
+        while self.frames.len() > next.depth + 1 {
+            self.exited.push(self.frames.pop().expect("frames are not empty"));
+        }
+        while self.frames.len() < next.depth + 1 {
+            self.frames.push(FrameState { address: next.address, depth: self.frames.len(), ..Default::default() });
+        }
+        true
+    }
+
+    // Undoes the previous step, false at the first
+    pub fn back(&mut self, history: &History) -> bool {
+        self.sync(history);
+        if self.position == 0 {
+            return false;
+        }
+        self.position -= 1;
+        self.unsettled.retain(|step| *step < self.position);
+        let record = &history.records[self.position];
+        // Frames entered by the step were fresh, frames it returned from are the last exited
+        while self.frames.len() > record.depth + 1 {
+            self.frames.pop();
+        }
+        while self.frames.len() < record.depth + 1 {
+            self.frames.push(self.exited.pop().expect("returned frames are kept"));
+        }
+
+        if let Some(frame) = self.frames.last_mut() {
+            if let Some(change) = &record.memory {
+                if !change.before.is_empty() {
+                    frame.memory[change.offset..change.offset + change.before.len()].copy_from_slice(&change.before);
+                }
+                frame.memory.truncate(change.len_before);
+            }
+            frame.stack.truncate(frame.stack.len() - record.stack.pushed.len());
+            frame.stack.extend_from_slice(&record.stack.popped);
+            if let Some((before, _)) = &record.return_data {
+                frame.return_data = before.clone();
+            }
+        }
+        for change in record.storage.iter().rev() {
+            self.storage.insert((change.address, change.key), change.from);
+        }
+        for change in record.transient.iter().rev() {
+            self.transient.insert((change.address, change.key), change.from);
+        }
+        true
+    }
+
+    // Sets up the frames of the first step, and replays the history once steps passed while
+    // running have finished
+    fn sync(&mut self, history: &History) {
+        if self.unsettled.iter().any(|step| history.pending.iter().all(|pending| pending.index != *step)) {
+            let position = self.position;
+            *self = Cursor::default();
+            while self.position < position && self.forward(history) {}
+            return;
+        }
+        if self.frames.is_empty() {
+            if let Some(first) = history.records.first() {
+                for depth in 0..=first.depth {
+                    self.frames.push(FrameState { address: first.address, depth, ..Default::default() });
+                }
+            }
+        }
+    }
+
+    // A slot as it was at this step. Slots the history never wrote are as they are now
+    pub fn storage_at(&self, history: &History, state: &State, address: H160, key: U256) -> U256 {
+        self.storage
+            .get(&(address, key))
+            .or_else(|| history.initial_storage.get(&(address, key)))
+            .copied()
+            .unwrap_or_else(|| state.cached_account(&address).map(|account| account.storage.get(key)).unwrap_or_default())
+    }
+
+    // The non-zero slots of `address` at this step
+    pub fn storage(&self, history: &History, state: &State, address: H160) -> BTreeMap<U256, U256> {
+        let mut keys: Vec<U256> = state.cached_account(&address).map(|account| account.storage.iter().map(|(key, _)| *key).collect()).unwrap_or_default();
+        keys.extend(history.initial_storage.keys().filter(|(owner, _)| *owner == address).map(|(_, key)| *key));
+        keys.into_iter()
+            .map(|key| (key, self.storage_at(history, state, address, key)))
+            .filter(|(_, value)| !value.is_zero())
+            .collect()
+    }
+
+    // Slots written up to this step that differ from before the execution, by address and key
+    pub fn storage_changes(&self, history: &History) -> Vec<StorageChange> {
+        let mut changes: Vec<StorageChange> = self
+            .storage
+            .iter()
+            .filter_map(|(&(address, key), &to)| {
+                let from = history.initial_storage.get(&(address, key)).copied().unwrap_or_default();
+                (from != to).then_some(StorageChange { address, key, from, to })
+            })
+            .collect();
+        changes.sort_by_key(|change| (change.address, change.key));
+        changes
+    }
+
+    // The non-zero transient slots of `address` at this step
+    pub fn transient(&self, history: &History, transient: &Transient, address: H160) -> BTreeMap<U256, U256> {
+        let mut keys: Vec<U256> = transient.iter().filter(|((owner, _), _)| *owner == address).map(|((_, key), _)| *key).collect();
+        keys.extend(history.initial_transient.keys().filter(|(owner, _)| *owner == address).map(|(_, key)| *key));
+        keys.into_iter()
+            .map(|key| {
+                let value = self.transient.get(&(address, key)).or_else(|| history.initial_transient.get(&(address, key))).copied();
+                (key, value.unwrap_or_else(|| transient.load(address, key)))
+            })
+            .filter(|(_, value)| !value.is_zero())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inspector::{CallInputs, CreateInputs, Inspector};
+    use crate::transaction::{inspect_transaction, BlockEnv, Transaction};
+    use crate::{hex, Account, Precompiles};
+
+    const CALLER: H160 = H160([0x11; 20]);
+    const A: H160 = H160([0xaa; 20]);
+    const B: H160 = H160([0xbb; 20]);
+    const C: H160 = H160([0xcc; 20]);
+
+    // The machine as it was before a step, taken live
+    #[derive(Debug, PartialEq)]
+    struct Snapshot {
+        frame: FrameState,
+        storage: Vec<U256>,
+        transient: Vec<U256>,
+    }
+
+    // Records the history the way the debugger does, with a snapshot of every step
+    #[derive(Default)]
+    struct Recorder {
+        history: History,
+        snapshots: Vec<Snapshot>,
+    }
+
+    impl Inspector for Recorder {
+        fn step(&mut self, evm: &EVM) {
+            self.history.step(evm);
+            let frame = FrameState {
+                address: evm.address(),
+                depth: evm.depth(),
+                stack: evm.stack().data().to_vec(),
+                memory: evm.memory().data().to_vec(),
+                return_data: evm.return_data().to_vec(),
+            };
+            let storage = [A, B, C]
+                .iter()
+                .map(|address| evm.state().cached_account(address).map(|account| account.storage.get(U256::zero())).unwrap_or_default())
+                .collect();
+            let transient = [A, B, C].iter().map(|address| evm.transient().load(*address, U256::one())).collect();
+            self.snapshots.push(Snapshot { frame, storage, transient });
+        }
+
+        fn step_end(&mut self, evm: &EVM) {
+            self.history.step_end(evm);
+        }
+
+        fn call_end(&mut self, inputs: &CallInputs, outcome: &CallOutcome) {
+            self.history.frame_end(inputs.depth, outcome);
+        }
+
+        fn create_end(&mut self, inputs: &CreateInputs, outcome: &CallOutcome) {
+            self.history.frame_end(inputs.depth, outcome);
+        }
+    }
+
+    fn call(address: H160, ret_offset: u8, ret_size: u8) -> String {
+        format!("60{:02x}60{:02x}600060006000 73{:x} 61ffff f1 50", ret_size, ret_offset, address)
+    }
+
+    // A writes storage, transient storage and memory, then calls B, which writes its own and
+    // returns 32 bytes, and C, which writes its own and reverts with 1 byte
+    fn run() -> (Recorder, State) {
+        let a = ["600560015d 6001600055 60aa600052".to_string(), call(B, 0x20, 0x20), call(C, 0x40, 0x01), "6009600055 00".to_string()].concat();
+        let b = "6002600055 600760015d 6042600052 60206000f3";
+        let c = "6003600055 600860015d 60ee600053 60016000fd";
+        let mut state = State::new();
+        state.insert_account(CALLER, Account::new(U256::from(10).pow(U256::from(18))));
+        for (address, code) in [(A, a.as_str()), (B, b), (C, c)] {
+            state.insert_account(address, Account::default().with_code(hex::decode(&code.replace(' ', "")).unwrap()));
+        }
+        let tx = Transaction { caller: CALLER, to: Some(A), gas_limit: 300_000, gas_price: U256::one(), ..Default::default() };
+        let mut recorder = Recorder::default();
+        assert!(inspect_transaction(&tx, &BlockEnv::default(), &mut state, &mut Precompiles::default(), &mut recorder).unwrap().status);
+        (recorder, state)
+    }
+
+    fn assert_at(cursor: &Cursor, recorder: &Recorder, state: &State) {
+        let history = &recorder.history;
+        let step = cursor.position();
+        let expected = &recorder.snapshots[step];
+        let frame = cursor.frame().unwrap();
+        assert_eq!(frame, &expected.frame, "frame at step {}", step);
+        assert_eq!(cursor.frames().len(), expected.frame.depth + 1, "depth at step {}", step);
+        let storage: Vec<U256> = [A, B, C].iter().map(|address| cursor.storage_at(history, state, *address, U256::zero())).collect();
+        assert_eq!(storage, expected.storage, "storage at step {}", step);
+        // The transaction has cleared transient storage by now, the history still knows it
+        let transient: Vec<U256> = [A, B, C]
+            .iter()
+            .map(|address| cursor.transient(history, &Transient::new(), *address).get(&U256::one()).copied().unwrap_or_default())
+            .collect();
+        assert_eq!(transient, expected.transient, "transient storage at step {}", step);
+    }
+
+    #[test]
+    fn stepping_back_restores_every_step_seen_going_forward() {
+        let (recorder, mut state) = run();
+        let history = &recorder.history;
+        let last = history.len() - 1;
+        assert_eq!(recorder.snapshots.len(), history.len());
+        // Both calls ran, the revert undid C's writes
+        assert!(history.records().iter().any(|record| record.depth == 1 && record.address == C));
+        assert_eq!(state.storage(&A, U256::zero()), U256::from(9));
+        assert_eq!(state.storage(&B, U256::zero()), U256::from(2));
+        assert_eq!(state.storage(&C, U256::zero()), U256::zero());
+
+        let mut cursor = Cursor::new();
+        cursor.seek(history, 0);
+        assert_at(&cursor, &recorder, &state);
+        for step in 1..=last {
+            assert!(cursor.forward(history));
+            assert_eq!(cursor.position(), step);
+            assert_at(&cursor, &recorder, &state);
+        }
+        assert!(!cursor.forward(history));
+        for step in (0..last).rev() {
+            assert!(cursor.back(history));
+            assert_eq!(cursor.position(), step);
+            assert_at(&cursor, &recorder, &state);
+        }
+        assert!(!cursor.back(history));
+    }
+
+    #[test]
+    fn seeking_in_and_out_of_finished_frames() {
+        let (recorder, state) = run();
+        let history = &recorder.history;
+        let in_c = history.records().iter().rposition(|record| record.address == C).unwrap();
+        let in_b = history.records().iter().position(|record| record.address == B).unwrap();
+        let mut cursor = Cursor::new();
+        for step in [history.len() - 1, in_c, in_b, 0, in_c + 1, in_b + 2, history.len() - 1] {
+            cursor.seek(history, step);
+            assert_eq!(cursor.position(), step);
+            assert_at(&cursor, &recorder, &state);
+        }
+        // C's last step, its REVERT, also undoes its storage and transient writes
+        let revert = &history.records()[in_c];
+        assert_eq!(revert.op, Opcode::REVERT as u8);
+        assert!(revert.storage.iter().any(|change| change.address == C && change.to.is_zero()));
+        assert!(revert.transient.iter().any(|change| change.address == C && change.to.is_zero()));
+    }
+}
@@ -0,0 +1,158 @@
+use super::{Cursor, PauseReason, Resume, Session, StepRecord};
+
+// Moves a frontend's cursor through the steps run so far, by opcodes or Solidity lines. Each
+// motion either stays within the history, returning `None`, or returns how execution has to
+// resume to get where it was asked
+#[derive(Debug, Default)]
+pub struct Navigator {
+    cursor: Cursor,
+    // Steps left of a motion that went past the live step
+    steps: usize,
+    // A line motion that went past the live step
+    line: Option<LineMotion>,
+}
+
+// A move to the next step on another Solidity line, running through frames deeper than `depth`
+#[derive(Debug, Clone)]
+struct LineMotion {
+    from: Option<(String, usize)>,
+    depth: Option<usize>,
+}
+
+impl LineMotion {
+    fn accepts(&self, session: &Session, record: &StepRecord) -> bool {
+        self.depth.is_none_or(|depth| record.depth <= depth) && line_of(session, record).is_some_and(|line| Some(&line) != self.from.as_ref())
+    }
+
+    fn resume(&self) -> Resume {
+        match self.depth {
+            Some(depth) => Resume::StepOver(depth),
+            None => Resume::Step,
+        }
+    }
+}
+
+fn line_of(session: &Session, record: &StepRecord) -> Option<(String, usize)> {
+    session.source_location(record).map(|location| (location.file, location.line))
+}
+
+impl Navigator {
+    pub fn new() -> Self {
+        Navigator::default()
+    }
+
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+
+    // To be called first whenever execution pauses. While a motion past the live step has steps
+    // left it returns how to run on, otherwise the cursor moves to the live step
+    pub fn paused(&mut self, session: &Session) -> Option<Resume> {
+        if self.steps > 0 && session.reason() == Some(PauseReason::Step) {
+            self.steps -= 1;
+            return Some(Resume::Step);
+        }
+        if let Some(motion) = self.line.take() {
+            let reached = session.history().get(session.step()).is_some_and(|record| motion.accepts(session, record));
+            if session.reason() == Some(PauseReason::Step) && !reached {
+                let resume = motion.resume();
+                self.line = Some(motion);
+                return Some(resume);
+            }
+        }
+        self.steps = 0;
+        self.cursor.seek(session.history(), session.step());
+        None
+    }
+
+    pub fn is_live(&self, session: &Session) -> bool {
+        self.cursor.position() == session.step()
+    }
+
+    pub fn step(&mut self, session: &Session, count: usize) -> Option<Resume> {
+        self.goto(session, self.cursor.position() + count)
+    }
+
+    pub fn goto(&mut self, session: &Session, step: usize) -> Option<Resume> {
+        self.cursor.seek(session.history(), step);
+        match step.checked_sub(session.step()) {
+            Some(remaining) if remaining > 0 => {
+                self.steps = remaining - 1;
+                Some(Resume::Step)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn next(&mut self, session: &Session) -> Option<Resume> {
+        let depth = self.depth(session);
+        self.forward_to(session, |record| record.depth <= depth, Resume::StepOver(depth))
+    }
+
+    pub fn out(&mut self, session: &Session) -> Option<Resume> {
+        let depth = self.depth(session);
+        self.forward_to(session, |record| record.depth < depth, Resume::StepOut(depth))
+    }
+
+    pub fn continue_to_breakpoint(&mut self, session: &Session) -> Option<Resume> {
+        self.forward_to(session, |record| at_breakpoint(session, record), Resume::Continue)
+    }
+
+    // To the next step on another Solidity line, entering calls
+    pub fn step_line(&mut self, session: &Session) -> Option<Resume> {
+        self.line_motion(session, None)
+    }
+
+    // To the next step on another Solidity line of this frame or its callers, over calls
+    pub fn next_line(&mut self, session: &Session) -> Option<Resume> {
+        let depth = self.depth(session);
+        self.line_motion(session, Some(depth))
+    }
+
+    fn line_motion(&mut self, session: &Session, depth: Option<usize>) -> Option<Resume> {
+        let from = self.cursor.record(session.history()).and_then(|record| line_of(session, record));
+        let motion = LineMotion { from, depth };
+        let resume = self.forward_to(session, |record| motion.accepts(session, record), motion.resume())?;
+        self.line = Some(motion);
+        Some(resume)
+    }
+
+    pub fn back(&mut self, session: &Session, count: usize) {
+        self.cursor.seek(session.history(), self.cursor.position().saturating_sub(count));
+    }
+
+    // Back to the previous step at a breakpoint, or the first step
+    pub fn reverse_continue(&mut self, session: &Session) {
+        let history = session.history();
+        let step = (0..self.cursor.position())
+            .rev()
+            .find(|step| history.get(*step).is_some_and(|record| at_breakpoint(session, record)))
+            .unwrap_or(0);
+        self.cursor.seek(history, step);
+    }
+
+    fn depth(&self, session: &Session) -> usize {
+        self.cursor.record(session.history()).map(|record| record.depth).unwrap_or_default()
+    }
+
+    // Moves to the first step after the cursor, up to the live one, that `target` accepts.
+    // Without one the cursor goes live and execution resumes as `otherwise`
+    fn forward_to(&mut self, session: &Session, target: impl Fn(&StepRecord) -> bool, otherwise: Resume) -> Option<Resume> {
+        let history = session.history();
+        let live = session.step();
+        match (self.cursor.position() + 1..=live).find(|step| history.get(*step).is_some_and(&target)) {
+            Some(step) => {
+                self.cursor.seek(history, step);
+                None
+            }
+            None => {
+                self.cursor.seek(history, live);
+                Some(otherwise)
+            }
+        }
+    }
+}
+
+fn at_breakpoint(session: &Session, record: &StepRecord) -> bool {
+    session.breakpoints().iter().any(|breakpoint| breakpoint.matches(record.pc, record.op))
+}
@@ -0,0 +1,463 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+use crate::hex;
+use crate::inspector::CallOutcome;
+use crate::opcodes::Opcode;
+use crate::tracer::op_name;
+use crate::EVM;
+use super::{instruction_at, Breakpoint, Cursor, Frontend, Navigator, Resume, Session};
+
+const HELP: &str = "commands:
+  s, step [n]          run n opcodes (1 by default), entering calls
+  n, next              run to the next opcode of this frame, over calls
+  o, out               run until the current frame returns
+  c, continue          run to the next breakpoint
+  sl, stepline         run to the next Solidity line, entering calls
+  nl, nextline         run to the next Solidity line of this function, over calls
+  bs, back [n]         step back n opcodes (1 by default)
+  rc, rcontinue        go back to the previous breakpoint, or the first step
+  g, goto <step>       go to any step, back or forward
+  b, break pc <pc>     pause before the opcode at pc
+  b, break op <name>   pause before every such opcode, say SSTORE or 0x55
+  b, break sstore      pause before storage writes
+  b, break log         pause before LOG0-LOG4
+  d, delete <n>        remove breakpoint n
+  bl, breakpoints      list breakpoints
+  st, stack            stack, top first
+  m, memory [offset] [length]  hex dump of memory
+  storage              storage of the current account
+  transient            transient storage
+  rd, returndata       data returned by the last call
+  w, where             current position and call stack
+  l, list [n]          Solidity source around the current line, n lines either side
+  q, quit              run to the end without pausing
+  an empty line repeats the last command";
+
+enum Command {
+    Step(usize),
+    Next,
+    Out,
+    Continue,
+    StepLine,
+    NextLine,
+    Back(usize),
+    ReverseContinue,
+    Goto(usize),
+    Quit,
+    Break(Breakpoint),
+    Delete(usize),
+    Breakpoints,
+    Stack,
+    Memory(usize, Option<usize>),
+    Storage,
+    Transient,
+    ReturnData,
+    Where,
+    List(usize),
+    Help,
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(digits) => usize::from_str_radix(digits, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number {}", text))
+}
+
+fn parse_opcode(text: &str) -> Result<u8, String> {
+    if text.starts_with("0x") {
+        return u8::try_from(parse_number(text)?).map_err(|_| format!("invalid opcode {}", text));
+    }
+    (0..=u8::MAX)
+        .find(|op| Opcode::try_from_u8(*op).is_some() && op_name(*op).eq_ignore_ascii_case(text))
+        .ok_or(format!("unknown opcode {}", text))
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let argument = |index: usize| words.get(index).copied().ok_or("missing argument, see help".to_string());
+    let command = match words[0] {
+        "s" | "step" => Command::Step(words.get(1).map(|count| parse_number(count)).transpose()?.unwrap_or(1)),
+        "n" | "next" => Command::Next,
+        "o" | "out" => Command::Out,
+        "c" | "continue" => Command::Continue,
+        "sl" | "stepline" => Command::StepLine,
+        "nl" | "nextline" => Command::NextLine,
+        "bs" | "back" => Command::Back(words.get(1).map(|count| parse_number(count)).transpose()?.unwrap_or(1)),
+        "rc" | "rcontinue" => Command::ReverseContinue,
+        "g" | "goto" => Command::Goto(parse_number(argument(1)?)?),
+        "q" | "quit" => Command::Quit,
+        "b" | "break" => match argument(1)? {
+            "pc" => Command::Break(Breakpoint::Pc(parse_number(argument(2)?)?)),
+            "op" => Command::Break(Breakpoint::Opcode(parse_opcode(argument(2)?)?)),
+            "sstore" => Command::Break(Breakpoint::StorageWrite),
+            "log" => Command::Break(Breakpoint::Log),
+            other => return Err(format!("unknown breakpoint {}, expected pc, op, sstore or log", other)),
+        },
+        "d" | "delete" => Command::Delete(parse_number(argument(1)?)?),
+        "bl" | "breakpoints" => Command::Breakpoints,
+        "st" | "stack" => Command::Stack,
+        "m" | "memory" => {
+            let offset = words.get(1).map(|offset| parse_number(offset)).transpose()?.unwrap_or(0);
+            Command::Memory(offset, words.get(2).map(|length| parse_number(length)).transpose()?)
+        }
+        "storage" => Command::Storage,
+        "transient" => Command::Transient,
+        "rd" | "returndata" => Command::ReturnData,
+        "w" | "where" => Command::Where,
+        "l" | "list" => Command::List(words.get(1).map(|context| parse_number(context)).transpose()?.unwrap_or(5)),
+        "h" | "help" => Command::Help,
+        other => return Err(format!("unknown command {}, try help", other)),
+    };
+    Ok(command)
+}
+
+// Rows of 32 bytes with their offset and printable characters
+fn hexdump(bytes: &[u8], start: usize) -> String {
+    let mut out = String::new();
+    for (row, chunk) in bytes.chunks(32).enumerate() {
+        let digits: String = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text: String = chunk.iter().map(|byte| if byte.is_ascii_graphic() { *byte as char } else { '.' }).collect();
+        let _ = writeln!(out, "{:#06x}  {:<64}  {}", start + row * 32, digits, text);
+    }
+    out
+}
+
+// What a command line asks for
+enum Reply {
+    Print(String),
+    Resume(Resume),
+}
+
+// A line-oriented debugger reading commands from `input`, gdb style. Besides stepping forward it
+// moves back and forth through the steps already run, showing the machine state as it was then
+pub struct Repl<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    last: String,
+    navigator: Navigator,
+}
+
+impl<R: BufRead, W: Write> Repl<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Repl { input, output, last: String::new(), navigator: Navigator::new() }
+    }
+
+    // Write errors detach the debugger
+    fn print(&mut self, text: &str) -> bool {
+        self.output.write_all(text.as_bytes()).and_then(|_| self.output.flush()).is_ok()
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim().to_string()),
+        }
+    }
+
+    pub fn into_output(self) -> W {
+        self.output
+    }
+
+    // Runs one command line, an empty one repeating the last. Apart from the reading and
+    // printing, so the commands do not depend on a terminal
+    fn execute(&mut self, evm: &EVM, session: &mut Session, line: &str) -> Reply {
+        let line = if line.is_empty() { self.last.clone() } else { line.to_string() };
+        if line.is_empty() {
+            return Reply::Print(String::new());
+        }
+        self.last = line.clone();
+        let out = match parse_command(&line) {
+            Ok(command) => match self.motion(session, &command) {
+                Some(Some(resume)) => return Reply::Resume(resume),
+                Some(None) => location(session, self.navigator.cursor()),
+                None => run_command(evm, session, self.navigator.cursor(), command),
+            },
+            Err(message) => format!("{}\n", message),
+        };
+        Reply::Print(out)
+    }
+
+    // `None` for commands that only show something, `Some(None)` when the cursor moved within the
+    // history and `Some(Some(resume))` when execution has to run on
+    fn motion(&mut self, session: &Session, command: &Command) -> Option<Option<Resume>> {
+        let navigator = &mut self.navigator;
+        let resume = match command {
+            Command::Step(count) => navigator.step(session, *count),
+            Command::Goto(step) => navigator.goto(session, *step),
+            Command::Next => navigator.next(session),
+            Command::Out => navigator.out(session),
+            Command::Continue => navigator.continue_to_breakpoint(session),
+            Command::StepLine => navigator.step_line(session),
+            Command::NextLine => navigator.next_line(session),
+            Command::Back(count) => {
+                navigator.back(session, *count);
+                None
+            }
+            Command::ReverseContinue => {
+                navigator.reverse_continue(session);
+                None
+            }
+            Command::Quit => Some(Resume::Detach),
+            _ => return None,
+        };
+        Some(resume)
+    }
+}
+
+fn location(session: &Session, cursor: &Cursor) -> String {
+    let mut out = String::new();
+    let history = session.history();
+    let Some(record) = cursor.record(history) else {
+        return out;
+    };
+    if let Some(index) = session.breakpoints().iter().position(|breakpoint| breakpoint.matches(record.pc, record.op)) {
+        let _ = writeln!(out, "breakpoint {}: {}", index, session.breakpoints()[index]);
+    }
+    let _ = write!(
+        out,
+        "step {} depth {} {} pc {:#06x} {} gas {}",
+        cursor.position(),
+        record.depth,
+        hex::checksum(&record.address),
+        record.pc,
+        instruction_at(history.code(record), record.pc),
+        record.gas
+    );
+    if cursor.position() < session.step() {
+        let _ = write!(out, " (history, running at step {})", session.step());
+    }
+    let _ = writeln!(out);
+    if let Some(location) = session.source_location(record) {
+        let _ = writeln!(out, "  at {}: {}", location, location.text);
+    }
+    out
+}
+
+fn run_command(evm: &EVM, session: &mut Session, cursor: &Cursor, command: Command) -> String {
+    let mut out = String::new();
+    let history = session.history();
+    let frame = cursor.frame().cloned().unwrap_or_default();
+    let address = cursor.record(history).map(|record| record.address).unwrap_or_default();
+    match command {
+        Command::Break(breakpoint) => {
+            let index = session.add_breakpoint(breakpoint);
+            let _ = writeln!(out, "breakpoint {}: {}", index, breakpoint);
+        }
+        Command::Delete(index) => match session.remove_breakpoint(index) {
+            Some(breakpoint) => {
+                let _ = writeln!(out, "deleted breakpoint {}: {}", index, breakpoint);
+            }
+            None => {
+                let _ = writeln!(out, "no breakpoint {}", index);
+            }
+        },
+        Command::Breakpoints if session.breakpoints().is_empty() => out.push_str("no breakpoints\n"),
+        Command::Breakpoints => {
+            for (index, breakpoint) in session.breakpoints().iter().enumerate() {
+                let _ = writeln!(out, "{}: {}", index, breakpoint);
+            }
+        }
+        Command::Stack if frame.stack.is_empty() => out.push_str("(empty)\n"),
+        Command::Stack => {
+            for (position, value) in frame.stack.iter().rev().enumerate() {
+                let _ = writeln!(out, "{:>4}: {:#x}", position, value);
+            }
+        }
+        Command::Memory(offset, length) => {
+            let data = &frame.memory;
+            let start = offset.min(data.len());
+            let end = length.map(|length| start.saturating_add(length)).unwrap_or(data.len()).min(data.len());
+            let _ = writeln!(out, "memory size {:#x}", data.len());
+            out.push_str(&hexdump(&data[start..end], start));
+        }
+        Command::Storage => {
+            let slots = cursor.storage(history, evm.state(), address);
+            if slots.is_empty() {
+                out.push_str("(empty)\n");
+            }
+            for (key, value) in slots {
+                let _ = writeln!(out, "{:#x}: {:#x}", key, value);
+            }
+        }
+        Command::Transient => {
+            let slots = cursor.transient(history, evm.transient(), address);
+            if slots.is_empty() {
+                out.push_str("(empty)\n");
+            }
+            for (key, value) in slots {
+                let _ = writeln!(out, "{:#x}: {:#x}", key, value);
+            }
+        }
+        Command::ReturnData => {
+            let _ = writeln!(out, "{}", hex::encode(&frame.return_data));
+        }
+        Command::Where => {
+            out.push_str(&location(session, cursor));
+            if cursor.position() == session.step() {
+                for frame in session.frames().iter().rev() {
+                    let _ = writeln!(out, "  #{} {} {} (code at {})", frame.depth, frame.kind, hex::checksum(&frame.address), hex::checksum(&frame.code_address));
+                }
+            } else {
+                for frame in cursor.frames().iter().rev() {
+                    let _ = writeln!(out, "  #{} {}", frame.depth, hex::checksum(&frame.address));
+                }
+            }
+        }
+        Command::List(context) => {
+            let listing = cursor.record(history).and_then(|record| session.source_map(history.code(record))?.listing(record.pc, context));
+            out.push_str(listing.as_deref().unwrap_or("no source for this pc\n"));
+        }
+        Command::Help => {
+            let _ = writeln!(out, "{}", HELP);
+        }
+        _ => {}
+    }
+    out
+}
+
+impl<R: BufRead, W: Write> Frontend for Repl<R, W> {
+    fn paused(&mut self, evm: &EVM, session: &mut Session) -> Resume {
+        if let Some(resume) = self.navigator.paused(session) {
+            return resume;
+        }
+        if !self.print(&location(session, self.navigator.cursor())) {
+            return Resume::Detach;
+        }
+        loop {
+            if !self.print("(rtevm) ") {
+                return Resume::Detach;
+            }
+            let Some(line) = self.read_line() else {
+                return Resume::Detach;
+            };
+            match self.execute(evm, session, &line) {
+                Reply::Resume(resume) => return resume,
+                Reply::Print(out) => {
+                    if !self.print(&out) {
+                        return Resume::Detach;
+                    }
+                }
+            }
+        }
+    }
+
+    fn finished(&mut self, outcome: &CallOutcome, session: &Session) {
+        let status = match &outcome.error {
+            Some(error) => format!("failed: {}", error),
+            None if outcome.is_revert() => "reverted".to_string(),
+            None => "succeeded".to_string(),
+        };
+        let text = format!(
+            "execution {} after {} steps, gas left {}, output {}\n",
+            status,
+            session.history().len(),
+            outcome.gas_left,
+            hex::encode(&outcome.output)
+        );
+        self.print(&text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethereum_types::{H160, U256};
+    use super::*;
+    use crate::debugger::Debugger;
+    use crate::transaction::{inspect_transaction, BlockEnv, Transaction};
+    use crate::{Account, Precompiles, State};
+
+    const CALLER: H160 = H160([0x11; 20]);
+    const CONTRACT: H160 = H160([0x22; 20]);
+    const CALLEE: H160 = H160([0x33; 20]);
+
+    // CONTRACT calls CALLEE, which stores 42 at slot 0, then pops the result at 0x23 and stores
+    // 7 at slot 1. 17 steps, 4 of them in CALLEE
+    fn state() -> State {
+        let code = format!("6000600060006000600073{:x}61fffff150600760015500", CALLEE);
+        let mut state = State::new();
+        state.insert_account(CALLER, Account::new(U256::from(10).pow(U256::from(18))));
+        state.insert_account(CONTRACT, Account::default().with_code(hex::decode(&code).unwrap()));
+        state.insert_account(CALLEE, Account::default().with_code(hex::decode("602a60005500").unwrap()));
+        state
+    }
+
+    // Runs the transaction with the commands of `script`, one per line, and returns what was printed
+    fn debug(script: &str) -> String {
+        let tx = Transaction { caller: CALLER, to: Some(CONTRACT), gas_limit: 200_000, gas_price: U256::one(), ..Default::default() };
+        let mut debugger = Debugger::new(Repl::new(script.as_bytes(), Vec::new()));
+        let mut state = state();
+        inspect_transaction(&tx, &BlockEnv::default(), &mut state, &mut Precompiles::default(), &mut debugger).unwrap();
+        assert_eq!(state.storage(&CONTRACT, U256::one()), U256::from(7));
+        String::from_utf8(debugger.into_frontend().into_output()).unwrap()
+    }
+
+    fn assert_in_order(out: &str, expected: &[&str]) {
+        let mut rest = out;
+        for text in expected {
+            let Some(index) = rest.find(text) else {
+                panic!("{:?} missing after the earlier lines in:\n{}", text, out);
+            };
+            rest = &rest[index + text.len()..];
+        }
+    }
+
+    #[test]
+    fn pauses_at_pc_opcode_and_storage_write_breakpoints() {
+        let out = debug("b pc 0x22\nb op pop\nb sstore\nbl\nc\nc\nc\nc\nc\n");
+        assert_in_order(
+            &out,
+            &[
+                "step 0 depth 0",
+                "breakpoint 0: pc 0x22\n",
+                "breakpoint 1: opcode POP\n",
+                "breakpoint 2: storage write\n",
+                "0: pc 0x22\n1: opcode POP\n2: storage write\n",
+                "breakpoint 0: pc 0x22\nstep 7 depth 0",
+                "pc 0x0022 CALL",
+                "breakpoint 2: storage write\nstep 10 depth 1",
+                "pc 0x0004 SSTORE",
+                "breakpoint 1: opcode POP\nstep 12 depth 0",
+                "breakpoint 2: storage write\nstep 15 depth 0",
+                "pc 0x0028 SSTORE",
+                "execution succeeded after 17 steps",
+            ],
+        );
+        assert!(!out.contains("step 16"));
+    }
+
+    #[test]
+    fn next_steps_over_calls_and_an_empty_line_repeats_it() {
+        let out = debug("s 7\nn\nstack\nn\n\nstorage\nq\n");
+        assert_in_order(
+            &out,
+            &[
+                "step 7 depth 0",
+                "pc 0x0022 CALL",
+                "step 12 depth 0",
+                "pc 0x0023 POP",
+                "   0: 0x1\n",
+                "step 13 depth 0",
+                "step 14 depth 0",
+                "(empty)\n",
+                "execution succeeded after 17 steps",
+            ],
+        );
+        assert!(!out.contains("depth 1"));
+    }
+
+    #[test]
+    fn continue_without_breakpoints_runs_to_the_end() {
+        let out = debug("c\n");
+        assert_in_order(&out, &["step 0 depth 0", "(rtevm) ", "execution succeeded after 17 steps, gas left"]);
+        assert!(!out.contains("step 1 "));
+
+        // So does the end of the input
+        assert!(debug("s\n").contains("step 1 depth 0"));
+        let out = debug("");
+        assert!(out.starts_with("step 0 depth 0"), "{}", out);
+        assert!(out.ends_with("(rtevm) execution succeeded after 17 steps, gas left 132165, output 0x\n"), "{}", out);
+    }
+}
@@ -0,0 +1,537 @@
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use crate::hex;
+use crate::inspector::CallOutcome;
+use crate::EVM;
+use super::{instruction_at, Breakpoint, Frontend, Navigator, Resume, Session};
+
+const KEYS: &str = "s/→ step  n next  o out  c continue  l/L line  b/← back  r rcontinue  g goto  x breakpoint  ↑↓ memory  q quit";
+
+// Bytes of memory per row
+const MEMORY_ROW: usize = 16;
+
+// Smaller terminals are drawn as if they had this size, every pane keeping at least a row
+const MIN_ROWS: usize = 12;
+const MIN_COLUMNS: usize = 40;
+
+// Pane sizes: a title row, then the body with the code on the left and the stack, call stack
+// and storage on the right, then a memory header, the memory rows and a status row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    body: usize,
+    left: usize,
+    right: usize,
+    memory: usize,
+}
+
+impl Layout {
+    fn new(rows: usize, columns: usize) -> Layout {
+        let (rows, columns) = (rows.max(MIN_ROWS), columns.max(MIN_COLUMNS));
+        let memory = (rows / 4).max(2);
+        let left = columns / 2;
+        Layout { body: rows - memory - 3, left, right: columns - left - 1, memory }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Enter,
+    Backspace,
+    Other,
+}
+
+// A full-screen terminal debugger drawn with ANSI escapes: the Solidity source and disassembly
+// around the current pc, the stack, the call stack, storage changes and memory, driven by single
+// keys. `input` must be a terminal in raw mode, as `rtevm debug --tui` sets up. Like the REPL it
+// moves back through the steps already run as well as forward
+pub struct Tui<R: Read, W: Write> {
+    input: R,
+    output: W,
+    rows: usize,
+    columns: usize,
+    navigator: Navigator,
+    // First memory row shown
+    memory_scroll: usize,
+    message: String,
+    // Whether the alternate screen is showing
+    started: bool,
+}
+
+impl<R: Read, W: Write> Tui<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Tui { input, output, rows: 24, columns: 80, navigator: Navigator::new(), memory_scroll: 0, message: String::new(), started: false }
+    }
+
+    // Terminal size, 24 rows of 80 columns by default
+    pub fn with_size(mut self, rows: usize, columns: usize) -> Self {
+        self.rows = rows.max(MIN_ROWS);
+        self.columns = columns.max(MIN_COLUMNS);
+        self
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        let mut byte = [0];
+        match self.input.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+
+    fn read_key(&mut self) -> Option<Key> {
+        let key = match self.read_byte()? {
+            0x1b => match (self.read_byte()?, self.read_byte()?) {
+                (b'[', b'A') => Key::Up,
+                (b'[', b'B') => Key::Down,
+                (b'[', b'C') => Key::Right,
+                (b'[', b'D') => Key::Left,
+                (b'[', page @ (b'5' | b'6')) => match (page, self.read_byte()?) {
+                    (b'5', b'~') => Key::PageUp,
+                    (_, b'~') => Key::PageDown,
+                    _ => Key::Other,
+                },
+                _ => Key::Other,
+            },
+            b'\r' | b'\n' => Key::Enter,
+            0x7f | 0x08 => Key::Backspace,
+            // Ctrl-C, as raw mode delivers it
+            0x03 => Key::Char('q'),
+            byte if byte.is_ascii_graphic() => Key::Char(byte as char),
+            _ => Key::Other,
+        };
+        Some(key)
+    }
+
+    // Write errors detach the debugger
+    fn draw(&mut self, session: &Session) -> bool {
+        let mut screen = String::new();
+        if !self.started {
+            // Alternate screen, cursor hidden
+            screen.push_str("\x1b[?1049h\x1b[?25l");
+            self.started = true;
+        }
+        screen.push_str("\x1b[H");
+        screen.push_str(&self.render(session));
+        self.output.write_all(screen.as_bytes()).and_then(|_| self.output.flush()).is_ok()
+    }
+
+    fn leave(&mut self) {
+        if self.started {
+            let _ = self.output.write_all(b"\x1b[?25h\x1b[?1049l");
+            let _ = self.output.flush();
+            self.started = false;
+        }
+    }
+
+    // Reads a step number into the status line, `None` if cancelled
+    fn prompt(&mut self, session: &Session) -> Option<usize> {
+        let mut digits = String::new();
+        loop {
+            self.message = format!("goto step: {}_  (enter to go, any other key to cancel)", digits);
+            if !self.draw(session) {
+                return None;
+            }
+            match self.read_key()? {
+                Key::Char(digit) if digit.is_ascii_digit() => digits.push(digit),
+                Key::Backspace => {
+                    digits.pop();
+                }
+                Key::Enter => {
+                    self.message.clear();
+                    return digits.parse().ok();
+                }
+                _ => {
+                    self.message.clear();
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn toggle_breakpoint(&mut self, session: &mut Session) {
+        let Some(record) = self.navigator.cursor().record(session.history()) else {
+            return;
+        };
+        let breakpoint = Breakpoint::Pc(record.pc);
+        match session.breakpoints().iter().position(|existing| *existing == breakpoint) {
+            Some(index) => {
+                session.remove_breakpoint(index);
+                self.message = format!("deleted breakpoint {}", breakpoint);
+            }
+            None => {
+                let index = session.add_breakpoint(breakpoint);
+                self.message = format!("breakpoint {}: {}", index, breakpoint);
+            }
+        }
+    }
+
+    fn render(&mut self, session: &Session) -> String {
+        let history = session.history();
+        let cursor = self.navigator.cursor();
+        let Some(record) = cursor.record(history) else {
+            return String::new();
+        };
+        let frame = cursor.frame().cloned().unwrap_or_default();
+        let Layout { body, left, right, memory: memory_rows } = Layout::new(self.rows, self.columns);
+        let mut lines = Vec::new();
+
+        let running = if self.navigator.is_live(session) { String::new() } else { format!(" (running at {})", session.step()) };
+        let title = format!(
+            " rtevm  step {}{}  depth {}  pc {:#06x}  gas {}  {}",
+            cursor.position(),
+            running,
+            record.depth,
+            record.pc,
+            record.gas,
+            hex::checksum(&record.address)
+        );
+        lines.push(highlight(&fit(&title, self.columns)));
+
+        // The Solidity source above the disassembly when the code has a source map, the current
+        // line highlighted
+        let code = history.code(record);
+        let mut left_panel = Vec::new();
+        if let Some((source, range)) = session.source_map(code).and_then(|map| map.source_range(record.pc)) {
+            let (line, _) = source.line_column(range.offset);
+            let header = session.source_location(record).map(|location| format!(" Source  {}", location)).unwrap_or_default();
+            left_panel.push((fit(&header, left), false));
+            let height = (body - 1) / 2 - 1;
+            let first = line.saturating_sub(height / 2).max(1).min(source.line_count().saturating_sub(height).max(1));
+            for number in (first..=source.line_count()).take(height) {
+                let text = format!("{:>5}  {}", number, source.line(number).unwrap_or_default());
+                left_panel.push((fit(&text, left), number == line));
+            }
+        }
+
+        // Disassembly, the current instruction highlighted and breakpoints marked
+        let mut instructions = Vec::new();
+        let mut pc = 0;
+        while pc < code.len() {
+            instructions.push(pc);
+            pc += match code[pc] {
+                op @ 0x60..=0x7f => (op - 0x5e) as usize,
+                _ => 1,
+            };
+        }
+        let current = instructions.iter().rposition(|pc| *pc <= record.pc).unwrap_or_default();
+        let height = body - 1 - left_panel.len();
+        let start = current.saturating_sub(height / 3).min(instructions.len().saturating_sub(height));
+        left_panel.push((fit(" Disassembly", left), false));
+        for &pc in instructions.iter().skip(start).take(height) {
+            let marker = if session.breakpoints().contains(&Breakpoint::Pc(pc)) { "●" } else { " " };
+            let text = format!("{}{:#06x}  {}", marker, pc, instruction_at(code, pc));
+            left_panel.push((fit(&text, left), pc == record.pc));
+        }
+
+        // Stack top first, then the call stack and storage changes
+        let mut panel = vec![format!(" Stack ({})", frame.stack.len())];
+        let stack_rows = body / 2 - 1;
+        for (position, value) in frame.stack.iter().rev().enumerate().take(stack_rows) {
+            panel.push(format!("{:>4}: {:#x}", position, value));
+        }
+        if frame.stack.len() > stack_rows {
+            panel.push(format!("      … {} more", frame.stack.len() - stack_rows));
+        }
+        panel.push(" Call stack".to_string());
+        if self.navigator.is_live(session) {
+            for frame in session.frames().iter().rev() {
+                panel.push(format!("  #{} {} {}", frame.depth, frame.kind, hex::checksum(&frame.address)));
+            }
+        } else {
+            for frame in cursor.frames().iter().rev() {
+                panel.push(format!("  #{} {}", frame.depth, hex::checksum(&frame.address)));
+            }
+        }
+        panel.push(" Storage changes".to_string());
+        let changes = cursor.storage_changes(history);
+        if changes.is_empty() {
+            panel.push("  (none)".to_string());
+        }
+        let mut owner = None;
+        for change in changes {
+            if owner != Some(change.address) {
+                panel.push(format!("  {}", hex::checksum(&change.address)));
+                owner = Some(change.address);
+            }
+            panel.push(format!("    {:#x}: {:#x} → {:#x}", change.key, change.from, change.to));
+        }
+
+        for row in 0..body {
+            let (text, current) = left_panel.get(row).cloned().unwrap_or_else(|| (fit("", left), false));
+            let text = if current { highlight(&text) } else { text };
+            lines.push(format!("{}│{}", text, fit(panel.get(row).map(String::as_str).unwrap_or_default(), right)));
+        }
+
+        // Memory, scrolled to stay within it
+        let memory_total = frame.memory.len().div_ceil(MEMORY_ROW);
+        self.memory_scroll = self.memory_scroll.min(memory_total.saturating_sub(memory_rows));
+        let header = format!(" Memory ({:#x} bytes)", frame.memory.len());
+        lines.push(highlight(&fit(&header, self.columns)));
+        for row in 0..memory_rows {
+            let offset = (self.memory_scroll + row) * MEMORY_ROW;
+            let text = match frame.memory.get(offset..(offset + MEMORY_ROW).min(frame.memory.len())) {
+                Some(chunk) if !chunk.is_empty() => {
+                    let digits: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+                    let ascii: String = chunk.iter().map(|byte| if byte.is_ascii_graphic() { *byte as char } else { '.' }).collect();
+                    format!("{:#06x}  {:<47}  {}", offset, digits.join(" "), ascii)
+                }
+                _ => String::new(),
+            };
+            lines.push(fit(&text, self.columns));
+        }
+
+        let status = if self.message.is_empty() {
+            match session.breakpoints().iter().position(|breakpoint| breakpoint.matches(record.pc, record.op)) {
+                Some(index) => format!("breakpoint {}: {}  |  {}", index, session.breakpoints()[index], KEYS),
+                None => KEYS.to_string(),
+            }
+        } else {
+            self.message.clone()
+        };
+        lines.push(fit(&status, self.columns));
+        lines.iter().map(|line| format!("{}\x1b[K", line)).collect::<Vec<_>>().join("\r\n")
+    }
+}
+
+// Cuts or pads `text` to exactly `width` characters
+fn fit(text: &str, width: usize) -> String {
+    let mut out: String = text.chars().take(width).collect();
+    let count = out.chars().count();
+    let _ = write!(out, "{:1$}", "", width - count);
+    out
+}
+
+fn highlight(text: &str) -> String {
+    format!("\x1b[7m{}\x1b[0m", text)
+}
+
+impl<R: Read, W: Write> Frontend for Tui<R, W> {
+    fn paused(&mut self, _evm: &EVM, session: &mut Session) -> Resume {
+        if let Some(resume) = self.navigator.paused(session) {
+            return resume;
+        }
+        loop {
+            if !self.draw(session) {
+                return Resume::Detach;
+            }
+            let Some(key) = self.read_key() else {
+                return Resume::Detach;
+            };
+            self.message.clear();
+            let memory_rows = Layout::new(self.rows, self.columns).memory;
+            let resume = match key {
+                Key::Char('s') | Key::Right => self.navigator.step(session, 1),
+                Key::Char('n') => self.navigator.next(session),
+                Key::Char('o') => self.navigator.out(session),
+                Key::Char('c') => self.navigator.continue_to_breakpoint(session),
+                Key::Char('l') => self.navigator.step_line(session),
+                Key::Char('L') => self.navigator.next_line(session),
+                Key::Char('b') | Key::Left => {
+                    self.navigator.back(session, 1);
+                    None
+                }
+                Key::Char('r') => {
+                    self.navigator.reverse_continue(session);
+                    None
+                }
+                Key::Char('g') => self.prompt(session).and_then(|step| self.navigator.goto(session, step)),
+                Key::Char('x') => {
+                    self.toggle_breakpoint(session);
+                    None
+                }
+                Key::Up => {
+                    self.memory_scroll = self.memory_scroll.saturating_sub(1);
+                    None
+                }
+                Key::Down => {
+                    self.memory_scroll += 1;
+                    None
+                }
+                Key::PageUp => {
+                    self.memory_scroll = self.memory_scroll.saturating_sub(memory_rows);
+                    None
+                }
+                Key::PageDown => {
+                    self.memory_scroll += memory_rows;
+                    None
+                }
+                Key::Char('q') => Some(Resume::Detach),
+                _ => None,
+            };
+            if let Some(resume) = resume {
+                return resume;
+            }
+        }
+    }
+
+    fn finished(&mut self, outcome: &CallOutcome, session: &Session) {
+        self.leave();
+        let status = match &outcome.error {
+            Some(error) => format!("failed: {}", error),
+            None if outcome.is_revert() => "reverted".to_string(),
+            None => "succeeded".to_string(),
+        };
+        let text = format!(
+            "execution {} after {} steps, gas left {}, output {}\r\n",
+            status,
+            session.history().len(),
+            outcome.gas_left,
+            hex::encode(&outcome.output)
+        );
+        let _ = self.output.write_all(text.as_bytes()).and_then(|_| self.output.flush());
+    }
+}
+
+impl<R: Read, W: Write> Drop for Tui<R, W> {
+    fn drop(&mut self) {
+        self.leave();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethereum_types::{H160, U256};
+    use super::*;
+    use crate::debugger::Debugger;
+    use crate::transaction::{inspect_transaction, BlockEnv, Transaction};
+    use crate::sourcemap::SourceMap;
+    use crate::{Account, Precompiles, State};
+
+    const CALLER: H160 = H160([0x11; 20]);
+    const CONTRACT: H160 = H160([0x22; 20]);
+
+    // Pushes 1 to 12, stores 0xff at memory 0 and stops at pc 0x1d, 16 steps in all
+    fn code() -> Vec<u8> {
+        let pushes: String = (1..=12).map(|value| format!("60{:02x}", value)).collect();
+        hex::decode(&format!("{}60ff60005200", pushes)).unwrap()
+    }
+
+    // A line of source for every instruction
+    fn source_map() -> SourceMap {
+        let source: String = (0..16).map(|line| format!("x{:02};\n", line)).collect();
+        let srcmap: Vec<String> = (0..16).map(|line| format!("{}:4:0", line * 5)).collect();
+        SourceMap::new(&code(), &srcmap.join(";")).unwrap().with_source(0, "Steps.sol", source)
+    }
+
+    // Runs the code pressing `keys`, and returns every screen drawn, raw
+    fn screens(keys: &str, rows: usize, columns: usize, source: bool) -> Vec<String> {
+        let mut state = State::new();
+        state.insert_account(CALLER, Account::new(U256::from(10).pow(U256::from(18))));
+        state.insert_account(CONTRACT, Account::default().with_code(code()));
+        let tx = Transaction { caller: CALLER, to: Some(CONTRACT), gas_limit: 100_000, gas_price: U256::one(), ..Default::default() };
+        let mut output = Vec::new();
+        let tui = Tui::new(keys.as_bytes(), &mut output).with_size(rows, columns);
+        let mut debugger = Debugger::new(tui);
+        if source {
+            debugger = debugger.with_source_map(source_map());
+        }
+        inspect_transaction(&tx, &BlockEnv::default(), &mut state, &mut Precompiles::default(), &mut debugger).unwrap();
+        drop(debugger);
+        let output = String::from_utf8(output).unwrap();
+        let (drawn, finished) = output.split_once("\x1b[?25h\x1b[?1049l").expect("the screen is restored");
+        assert!(finished.starts_with("execution succeeded after 16 steps"), "{:?}", finished);
+        let drawn = drawn.strip_prefix("\x1b[?1049h\x1b[?25l").expect("the alternate screen is entered first");
+        drawn.split("\x1b[H").skip(1).map(str::to_string).collect()
+    }
+
+    // The screen as the terminal shows it, without escapes
+    fn visible(screen: &str) -> Vec<String> {
+        let mut text = String::new();
+        let mut chars = screen.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|c| c.is_ascii_alphabetic());
+            } else {
+                text.push(c);
+            }
+        }
+        text.split("\r\n").map(str::to_string).collect()
+    }
+
+    #[test]
+    fn panes_fill_the_terminal() {
+        for (rows, columns) in [(12, 40), (13, 41), (24, 80), (60, 200)] {
+            let layout = Layout::new(rows, columns);
+            assert_eq!(1 + layout.body + 1 + layout.memory + 1, rows, "{}x{}", rows, columns);
+            assert_eq!(layout.left + 1 + layout.right, columns, "{}x{}", rows, columns);
+            assert!(layout.body >= 6 && layout.memory >= 2);
+        }
+        assert_eq!(Layout::new(3, 5), Layout::new(MIN_ROWS, MIN_COLUMNS));
+    }
+
+    #[test]
+    fn every_screen_is_exactly_the_terminal_size() {
+        for (rows, columns, drawn_rows, drawn_columns) in [(24, 80, 24, 80), (12, 40, 12, 40), (5, 10, 12, 40), (40, 120, 40, 120)] {
+            for source in [false, true] {
+                let screens = screens("sssssssssssssssbbg7\rxq", rows, columns, source);
+                assert_eq!(screens.len(), 22);
+                for screen in &screens {
+                    let lines = visible(screen);
+                    assert_eq!(lines.len(), drawn_rows, "{}x{}:\n{}", rows, columns, lines.join("\n"));
+                    for line in &lines {
+                        assert_eq!(line.chars().count(), drawn_columns, "{}x{}: {:?}", rows, columns, line);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn draws_the_code_stack_memory_and_status() {
+        // Go to the last step, mark a breakpoint there, step back once, then quit
+        let screens = screens("g15\rxbq", 24, 80, false);
+        let first = visible(&screens[0]);
+        assert!(first[0].starts_with(" rtevm  step 0  depth 0  pc 0x0000  gas 79000"), "{}", first[0]);
+        assert!(first[1].starts_with(" Disassembly"));
+        assert!(first[1].contains("│ Stack (0)"));
+        assert!(first.iter().any(|line| line.contains("│ Storage changes")));
+        assert!(first.iter().any(|line| line.starts_with(" Memory (0x0 bytes)")));
+        assert!(screens[0].contains("\x1b[7m 0x0000  PUSH1 0x01"));
+        assert!(first[23].starts_with("s/→ step"));
+
+        let last_step = visible(&screens[4]);
+        assert!(last_step[0].starts_with(" rtevm  step 15  depth 0  pc 0x001d"));
+        assert!(screens[4].contains("\x1b[7m 0x001d  STOP"));
+        // 6 of the 12 values fit, the top first
+        assert!(last_step[1].contains("│ Stack (12)"));
+        assert!(last_step[2].ends_with("│   0: 0xc                              "), "{:?}", last_step[2]);
+        assert!(last_step.iter().any(|line| line.contains("│      … 6 more")));
+        assert!(last_step.iter().any(|line| line.starts_with(" Memory (0x20 bytes)")));
+        assert!(last_step.iter().any(|line| line.starts_with("0x0010  00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 ff")));
+
+        let marked = visible(&screens[5]);
+        assert!(marked.iter().any(|line| line.starts_with("●0x001d  STOP")));
+        assert!(marked[23].starts_with("breakpoint 0: pc 0x1d"));
+
+        let back = visible(&screens[6]);
+        assert!(back[0].starts_with(" rtevm  step 14 (running at 15)  depth 0  pc 0x001c"));
+        assert!(back[1].contains("│ Stack (14)"));
+        assert!(back.iter().any(|line| line.starts_with(" Memory (0x0 bytes)")));
+    }
+
+    #[test]
+    fn shows_the_source_line_above_the_disassembly() {
+        let large = screens("g12\rq", 24, 80, true);
+        let lines = visible(&large[4]);
+        assert!(lines[1].starts_with(" Source  Steps.sol:13:1 "), "{}", lines.join("\n"));
+        assert!(large[4].contains("\x1b[7m   13  x12;"));
+        // Half the body for the source, the rest for the disassembly
+        let disassembly = lines.iter().position(|line| line.starts_with(" Disassembly")).unwrap();
+        assert_eq!(disassembly, 8);
+        assert!(large[4].contains("\x1b[7m 0x0018  PUSH1 0xff"));
+
+        let small = screens("g12\rq", 12, 40, true);
+        let lines = visible(&small[4]);
+        // The header is cut at the edge of the pane
+        assert!(lines[1].starts_with(" Source  Steps.sol:1│ Stack (12)"), "{}", lines.join("\n"));
+        assert!(lines[2].starts_with("   13  x12;"));
+        assert!(lines[3].starts_with(" Disassembly"));
+        assert!(small[4].contains("\x1b[7m 0x0018  PUSH1 0xff"));
+    }
+}
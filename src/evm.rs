@@ -1,94 +1,369 @@
-use ethereum_types::{H160, U256};
-use crate::{Storage, Memory, Stack, Transient};
+use ethereum_types::{H160, H256, U256};
+use thiserror::Error;
+use crate::{Memory, Stack, State, Transient, Spec, Precompiles};
+use crate::precompiles::{Precompile, PrecompileError};
+use crate::inspector::{CallOutcome, Inspector};
 use crate::opcodes::Opcode;
+use crate::stack;
 
 mod opcode_instructions;
 
+// Calls nest at most this deep
+const MAX_CALL_DEPTH: usize = 1024;
 
-#[derive(Debug)]
-struct LogEntry {
-    topics: Vec<U256>,
-    data: Vec<u8>,
+// Exceptional halts. Unlike REVERT they consume all the gas of the frame. A call that cannot
+// start reports why without consuming any
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EvmError {
+    #[error("Not enough gas")]
+    OutOfGas,
+    #[error("State modification in a static call")]
+    StateChangeDuringStaticCall,
+    #[error("Max call depth exceeded")]
+    CallDepthExceeded,
+    #[error("Insufficient balance for transfer")]
+    InsufficientBalance,
+    #[error("Precompile failed: {0}")]
+    Precompile(PrecompileError),
+    #[error("Max code size exceeded")]
+    CodeSizeExceeded,
+    #[error("Deployed code starts with 0xef")]
+    InvalidCodePrefix,
+    #[error("Return data out of bounds")]
+    ReturnDataOutOfBounds,
+    #[error("Contract address collision")]
+    CreateCollision,
+    #[error("Stack underflow: {len} items, {required} required")]
+    StackUnderflow { len: usize, required: usize },
+    #[error("Stack overflow: {len} items, at most {limit} allowed")]
+    StackOverflow { len: usize, limit: usize },
+    #[error("Invalid jump destination")]
+    InvalidJump,
+    #[error("Max initcode size exceeded")]
+    InitCodeSizeExceeded,
+    #[error("Nonce overflow")]
+    NonceOverflow,
+}
+
+// A log emitted by LOG0-LOG4 from the account at `address`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub address: H160,
+    pub topics: Vec<H256>,
+    pub data: Vec<u8>,
 }
 
 #[derive(Debug)]
-pub struct EVM {
+pub struct EVM<'a> {
     pc: usize,
     stack: Stack,
     memory: Memory,
-    storage: Storage,
+    state: State,
     transient: Transient,
-    sender: H160, //address 20-bytes
+    sender: H160, //address 20-bytes, caller of this frame
+    origin: H160, // account that signed the transaction
+    address: H160, // account whose code is running
     program: Vec<u8>,
     gas: usize,
-    value: usize,
+    value: U256,
     call_data: Vec<u8>,
     stop_flag: bool,
     revert_flag: bool,
-    return_data: Vec<u8>,
+    return_data: Vec<u8>, // of the last call this frame made
+    output: Vec<u8>, // given to RETURN or REVERT
     logs: Vec<LogEntry>,
+    spec: Spec,
+    precompiles: Precompiles,
+    gas_price: U256,
+    blob_hashes: Vec<H256>, // EIP-4844 versioned hashes of the transaction
+    block_number: u64,
+    coinbase: H160, // beneficiary of the block's fees
+    depth: usize,
+    is_static: bool,
+    error: Option<EvmError>,
+    inspector: Option<&'a mut dyn Inspector>,
+}
+
+// A message call into a new frame
+struct CallParams {
+    address: H160,
+    caller: H160,
+    value: U256,
+    code: Vec<u8>,
+    input: Vec<u8>,
+    gas: usize,
+    is_static: bool,
 }
 
-impl EVM {
+impl<'a> EVM<'a> {
     pub fn new(sender: H160, gas: usize, value: usize, program: Vec<u8>, call_data: Vec<u8>) -> Self {
         EVM {
             pc: 0,
             stack: Stack::new(),
             memory: Memory::new(),
-            storage: Storage::new(),
+            state: State::new(),
             transient: Transient::new(),
             sender,
+            origin: sender,
+            address: H160::zero(),
             program,
             gas,
-            value,
+            value: U256::from(value),
             call_data,
             stop_flag: false,
             revert_flag: false,
             return_data: Vec::new(),
+            output: Vec::new(),
             logs: Vec::new(),
+            spec: Spec::default(),
+            precompiles: Precompiles::new(Spec::default()),
+            gas_price: U256::zero(),
+            blob_hashes: Vec::new(),
+            block_number: 0,
+            coinbase: H160::zero(),
+            depth: 0,
+            is_static: false,
+            error: None,
+            inspector: None,
         }
     }
 
+    pub fn with_state(mut self, state: State) -> Self {
+        self.state = state;
+        self
+    }
+
+    // Account the program runs as, zero unless set
+    pub fn with_address(mut self, address: H160) -> Self {
+        self.address = address;
+        self
+    }
+
+    pub fn with_origin(mut self, origin: H160) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    pub fn with_value(mut self, value: U256) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn with_gas_price(mut self, gas_price: U256) -> Self {
+        self.gas_price = gas_price;
+        self
+    }
+
+    pub fn with_blob_hashes(mut self, blob_hashes: Vec<H256>) -> Self {
+        self.blob_hashes = blob_hashes;
+        self
+    }
+
+    pub fn with_block_number(mut self, block_number: u64) -> Self {
+        self.block_number = block_number;
+        self
+    }
+
+    pub fn with_coinbase(mut self, coinbase: H160) -> Self {
+        self.coinbase = coinbase;
+        self
+    }
+
+    pub fn with_precompiles(mut self, precompiles: Precompiles) -> Self {
+        self.spec = precompiles.spec();
+        self.precompiles = precompiles;
+        self
+    }
+
+    pub fn with_spec(mut self, spec: Spec) -> Self {
+        self.spec = spec;
+        self.precompiles.set_spec(spec);
+        self
+    }
+
+    pub fn with_inspector(mut self, inspector: &'a mut dyn Inspector) -> Self {
+        self.inspector = Some(inspector);
+        self
+    }
+
+    // Makes CALL, CALLCODE, DELEGATECALL and STATICCALL to `address` run `precompile`
+    pub fn register_precompile(&mut self, address: H160, precompile: impl Precompile + 'static) {
+        self.precompiles.register(address, precompile);
+    }
+
+    pub fn precompiles(&self) -> &Precompiles {
+        &self.precompiles
+    }
+
+    pub fn spec(&self) -> Spec {
+        self.spec
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    // The opcode at the pc, STOP past the end of the code
+    pub fn opcode(&self) -> u8 {
+        self.program.get(self.pc).copied().unwrap_or_default()
+    }
+
+    pub fn code(&self) -> &[u8] {
+        &self.program
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn transient(&self) -> &Transient {
+        &self.transient
+    }
+
+    pub fn address(&self) -> H160 {
+        self.address
+    }
+
+    pub fn caller(&self) -> H160 {
+        self.sender
+    }
+
+    pub fn call_value(&self) -> U256 {
+        self.value
+    }
+
+    pub fn call_data(&self) -> &[u8] {
+        &self.call_data
+    }
+
+    // 0 for the outermost frame
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.is_static
+    }
+
+    pub fn return_data(&self) -> &[u8] {
+        &self.return_data
+    }
+
+    // What the frame returned or reverted with, empty if it stopped any other way
+    pub fn output(&self) -> &[u8] {
+        if self.error.is_some() {
+            return &[];
+        }
+        &self.output
+    }
+
+    // Logs of this frame and the calls it made that succeeded
+    pub fn logs(&self) -> &[LogEntry] {
+        &self.logs
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+
+    // Hands back the state and precompiles, leaving empty ones behind
+    pub fn take_state(&mut self) -> (State, Precompiles) {
+        (std::mem::take(&mut self.state), std::mem::take(&mut self.precompiles))
+    }
+
+    pub fn gas_left(&self) -> usize {
+        self.gas
+    }
+
+    pub fn error(&self) -> Option<&EvmError> {
+        self.error.as_ref()
+    }
+
+    // Finished without REVERT or an exceptional halt
+    pub fn is_success(&self) -> bool {
+        !self.revert_flag && self.error.is_none()
+    }
+
     pub fn gas_decrease(&mut self, gas: usize) {
         if self.gas < gas {
-            panic!("Not enough gas");
+            self.halt(EvmError::OutOfGas);
+            return;
         }
         self.gas -= gas;
     }
 
+    // Stops execution with an exceptional halt, which consumes all remaining gas
+    pub fn halt(&mut self, error: EvmError) {
+        self.gas = 0;
+        self.error.get_or_insert(error);
+    }
+
 
     pub fn run(&mut self) {
-        while self.continue_execution() {
-            let op_u8 = self.fetch_opcode();
-            self.execute_opcode(op_u8);
-            self.step_next();
-        };
+        while self.step() {}
+    }
+
+    // Executes the opcode at the pc, calls it makes running to completion. Returns whether the
+    // frame goes on
+    pub fn step(&mut self) -> bool {
+        if !self.continue_execution() {
+            return false;
+        }
+        let op_u8 = self.fetch_opcode();
+        self.inspect(|inspector, evm| inspector.step(evm));
+        self.execute_opcode(op_u8);
+        self.inspect(|inspector, evm| inspector.step_end(evm));
+        self.step_next();
+        self.continue_execution()
     }
 
     pub fn reset(&mut self) {
         self.pc = 0;
         self.stack = Stack::new();
         self.memory = Memory::new();
-        self.storage = Storage::new();
+        self.state = State::new();
         self.transient = Transient::new();
-        self.gas = self.gas;
-        self.value = self.value;
         self.call_data = Vec::new();
         self.stop_flag = false;
         self.revert_flag = false;
         self.return_data = Vec::new();
+        self.output = Vec::new();
         self.logs = Vec::new();
+        self.error = None;
     }
 
     // Helper functions
     fn log(&mut self, topics: Vec<U256>, data: Vec<u8>) {
+        let topics = topics.into_iter().map(|topic| {
+            let mut bytes = [0u8; 32];
+            topic.to_big_endian(&mut bytes);
+            H256(bytes)
+        });
         let log = LogEntry {
-            topics,
+            address: self.address,
+            topics: topics.collect(),
             data
         };
+        self.inspect(|inspector, evm| inspector.log(evm, &log));
         self.logs.push(log);
     }
 
+    // The inspector is taken out for the duration of the hook so it can look at the whole EVM
+    fn inspect(&mut self, hook: impl FnOnce(&mut dyn Inspector, &EVM)) {
+        if let Some(inspector) = self.inspector.take() {
+            hook(&mut *inspector, self);
+            self.inspector = Some(inspector);
+        }
+    }
+
     fn step_next(&mut self) {
         if self.continue_execution() {
             self.pc += 1;
@@ -98,7 +373,59 @@ impl EVM {
     }
 
     fn continue_execution(&self) -> bool {
-        self.pc < self.program.len() && !self.stop_flag && !self.revert_flag
+        self.pc < self.program.len() && !self.stop_flag && !self.revert_flag && self.error.is_none()
+    }
+
+    // Runs `params.code` in a child frame sharing this frame's state. The child's state changes
+    // and logs are kept only if it succeeds
+    fn call_frame(&mut self, params: CallParams) -> CallOutcome {
+        let checkpoint = self.state.checkpoint();
+        let transient_checkpoint = self.transient.checkpoint();
+        let mut child = EVM {
+            pc: 0,
+            stack: Stack::new(),
+            memory: Memory::new(),
+            state: std::mem::take(&mut self.state),
+            transient: std::mem::take(&mut self.transient),
+            sender: params.caller,
+            origin: self.origin,
+            address: params.address,
+            program: params.code,
+            gas: params.gas,
+            value: params.value,
+            call_data: params.input,
+            stop_flag: false,
+            revert_flag: false,
+            return_data: Vec::new(),
+            output: Vec::new(),
+            logs: Vec::new(),
+            spec: self.spec,
+            precompiles: std::mem::take(&mut self.precompiles),
+            gas_price: self.gas_price,
+            blob_hashes: self.blob_hashes.clone(),
+            block_number: self.block_number,
+            coinbase: self.coinbase,
+            depth: self.depth + 1,
+            is_static: params.is_static,
+            error: None,
+            inspector: self.inspector.take(),
+        };
+        child.run();
+        self.inspector = child.inspector.take();
+
+        self.state = std::mem::take(&mut child.state);
+        self.transient = std::mem::take(&mut child.transient);
+        self.precompiles = std::mem::take(&mut child.precompiles);
+
+        let success = child.is_success();
+        if success {
+            self.logs.append(&mut child.logs);
+        } else {
+            self.state.revert_to(checkpoint);
+            self.transient.revert_to(transient_checkpoint);
+        }
+        let output = child.output().to_vec();
+        CallOutcome { success, gas_left: child.gas, output, error: child.error }
     }
 
     fn fetch_opcode(&self) -> u8 {
@@ -106,9 +433,22 @@ impl EVM {
     }
 
     fn execute_opcode(&mut self, opcode: u8) {
-        match Opcode::from_u8(opcode) {
+        let opcode = Opcode::from_u8(opcode);
+        let (inputs, outputs) = opcode.stack_io();
+        let len = self.stack.len();
+        if len < inputs {
+            self.halt(EvmError::StackUnderflow { len, required: inputs });
+            return;
+        }
+        let limit = stack::MAX_SIZE + inputs - outputs;
+        if len > limit {
+            self.halt(EvmError::StackOverflow { len, limit });
+            return;
+        }
+        match opcode {
             Opcode::STOP => opcode_instructions::stop(self),
             Opcode::ADD => opcode_instructions::add(self),
+            Opcode::PUSH0 => opcode_instructions::push0(self),
             Opcode::PUSH1 => opcode_instructions::push_n(self, 1),
             Opcode::PUSH2 => opcode_instructions::push_n(self, 2),
             Opcode::PUSH3 => opcode_instructions::push_n(self, 3),
@@ -170,11 +510,11 @@ impl EVM {
             Opcode::SHR => opcode_instructions::shr(self),
             Opcode::SAR => opcode_instructions::sar(self),
             Opcode::KECCAK256 => opcode_instructions::_keccak256(self),
-            Opcode::LOG0 => opcode_instructions::log0(self),
-            Opcode::LOG1 => opcode_instructions::log1(self),
-            Opcode::LOG2 => opcode_instructions::log2(self),
-            Opcode::LOG3 => opcode_instructions::log3(self),
-            Opcode::LOG4 => opcode_instructions::log4(self),
+            Opcode::LOG0 => opcode_instructions::log_n(self, 0),
+            Opcode::LOG1 => opcode_instructions::log_n(self, 1),
+            Opcode::LOG2 => opcode_instructions::log_n(self, 2),
+            Opcode::LOG3 => opcode_instructions::log_n(self, 3),
+            Opcode::LOG4 => opcode_instructions::log_n(self, 4),
             Opcode::SWAP1 => opcode_instructions::swap_n(self, 1),
             Opcode::SWAP2 => opcode_instructions::swap_n(self, 2),
             Opcode::SWAP3 => opcode_instructions::swap_n(self, 3),
@@ -230,8 +570,19 @@ impl EVM {
             Opcode::EXTCODESIZE => opcode_instructions::extcodesize(self),
             Opcode::RETURNDATACOPY => opcode_instructions::returndatacopy(self),
             Opcode::RETURNDATASIZE => opcode_instructions::returndatasize(self),
+            Opcode::EXTCODEHASH => opcode_instructions::extcodehash(self),
+            Opcode::BLOCKHASH => opcode_instructions::blockhash(self),
+            Opcode::COINBASE => opcode_instructions::coinbase(self),
+            Opcode::BLOBHASH => opcode_instructions::blobhash(self),
+            Opcode::CREATE => opcode_instructions::create(self),
+            Opcode::CREATE2 => opcode_instructions::create2(self),
+            Opcode::CALL => opcode_instructions::call(self),
+            Opcode::CALLCODE => opcode_instructions::callcode(self),
+            Opcode::DELEGATECALL => opcode_instructions::delegatecall(self),
+            Opcode::STATICCALL => opcode_instructions::staticcall(self),
             Opcode::RETURN => opcode_instructions::_return(self),
             Opcode::REVERT => opcode_instructions::revert(self),
+            Opcode::SELFDESTRUCT => opcode_instructions::selfdestruct(self),
 
             _ => {
                 // panic!("Unknown opcode: {:#?}", op_u8);
@@ -240,4 +591,92 @@ impl EVM {
         }
     }
 }
-  
\ No newline at end of file
+  
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(code: &str) -> EVM<'static> {
+        let mut evm = EVM::new(H160::zero(), 100_000, 0, crate::hex::decode(code).unwrap(), vec![]);
+        evm.run();
+        evm
+    }
+
+    #[test]
+    fn bad_jumps_halt_and_consume_all_gas() {
+        // JUMP past the end of the code, and into the data of a PUSH2 holding a JUMPDEST byte
+        for code in ["60ff56", "600356615b00"] {
+            let evm = run(code);
+            assert_eq!(evm.error(), Some(&EvmError::InvalidJump));
+            assert_eq!(evm.gas_left(), 0);
+        }
+        // A false JUMPI falls through to the next opcode, a true one needs a valid destination
+        let evm = run("6000600a5760016000");
+        assert!(evm.is_success());
+        assert_eq!(evm.stack().data(), &[U256::one(), U256::zero()]);
+        assert_eq!(run("6001600a57").error(), Some(&EvmError::InvalidJump));
+        let evm = run("600160075700005b");
+        assert!(evm.is_success());
+        assert_eq!(evm.stack().len(), 0);
+    }
+
+    #[test]
+    fn stack_misuse_halts_and_consumes_all_gas() {
+        let evm = run("600101");
+        assert_eq!(evm.error(), Some(&EvmError::StackUnderflow { len: 1, required: 2 }));
+        assert_eq!(evm.gas_left(), 0);
+        assert_eq!(run("8090").error(), Some(&EvmError::StackUnderflow { len: 0, required: 1 }));
+
+        let evm = run(&"5f".repeat(1025));
+        assert_eq!(evm.error(), Some(&EvmError::StackOverflow { len: 1024, limit: 1023 }));
+        assert_eq!(evm.gas_left(), 0);
+        // DUP on a full stack overflows, SWAP does not
+        assert!(run(&format!("{}90", "5f".repeat(1024))).is_success());
+        assert!(run(&format!("{}80", "5f".repeat(1024))).error().is_some());
+    }
+
+    #[test]
+    fn arithmetic_wraps_instead_of_panicking() {
+        let max = format!("7f{}", "ff".repeat(32));
+        let cases = [
+            (format!("6001{max}01"), Some(U256::zero())),
+            (format!("6002{max}02"), Some(U256::MAX - 1)),
+            ("6000600104".to_string(), Some(U256::zero())),
+            ("6000600106".to_string(), Some(U256::zero())),
+            (format!("{max}{max}600308"), Some(U256::zero())),
+            ("60006000600009".to_string(), Some(U256::zero())),
+            ("60016101001b".to_string(), Some(U256::zero())),
+            ("60016101001c".to_string(), Some(U256::zero())),
+            ("61abcd601f1a".to_string(), Some(U256::from(0xcd))),
+            ("61abcd60201a".to_string(), Some(U256::zero())),
+            ("6000600103".to_string(), None),
+            ("6001600003".to_string(), None),
+            (format!("6002{max}0a"), None),
+            (format!("{max}60020a"), None),
+        ];
+        for (code, top) in cases {
+            let evm = run(&code);
+            assert!(evm.is_success(), "{code}");
+            if let Some(top) = top {
+                assert_eq!(evm.stack().peek(), top, "{code}");
+            }
+        }
+    }
+
+    #[test]
+    fn log_charges_for_topics_and_data() {
+        // A topic, the size and the offset, a word of memory, then 375 + 375 per topic + 8 per byte
+        let evm = run("600060205fa1");
+        assert!(evm.is_success());
+        assert_eq!(evm.logs().len(), 1);
+        assert_eq!(evm.logs()[0].data, vec![0; 32]);
+        assert_eq!(100_000 - evm.gas_left(), 3 + 3 + 2 + 3 + 375 + 375 + 8 * 32);
+    }
+
+    #[test]
+    fn push_past_the_end_of_the_code_reads_zeros() {
+        let evm = run("61ab");
+        assert!(evm.is_success());
+        assert_eq!(evm.stack().data(), &[U256::from(0xab00)]);
+    }
+}
@@ -1,57 +1,60 @@
-use crate::evm::{EVM, LogEntry};
+use crate::evm::{CallParams, EvmError, EVM, MAX_CALL_DEPTH};
 use crate::memory::MemoryError;
-use ethereum_types::{H160, U256};
-use tiny_keccak::{Keccak, Hasher};
+use ethereum_types::{H160, H256, U256, U512};
 use crate::helper::Helper;
+use crate::inspector::{CallInputs, CallKind, CallOutcome, CreateInputs, CreateKind};
+use crate::precompiles::PrecompileContext;
+use crate::spec::Spec;
+use crate::transaction::{create2_address, create_address, deposit_code, MAX_INITCODE_SIZE};
 
 
 // ----------- ARITHMETIC -----------
 pub fn add(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
-    evm.stack.push(a + b);
+    evm.stack.push(a.overflowing_add(b).0);
     evm.gas_decrease(3);
 }
 
 pub fn mul(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
-    evm.stack.push(a * b);
+    evm.stack.push(a.overflowing_mul(b).0);
     evm.gas_decrease(5);
 }
 
 pub fn sub(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
-    evm.stack.push(b - a);
+    evm.stack.push(b.overflowing_sub(a).0);
     evm.gas_decrease(3);
 }
 
 pub fn div(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
-    evm.stack.push(b / a);
+    evm.stack.push(b.checked_div(a).unwrap_or_default());
     evm.gas_decrease(5);
 }
 
 pub fn sdiv(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
-    evm.stack.push(b / a);
+    evm.stack.push(b.checked_div(a).unwrap_or_default());
     evm.gas_decrease(5);
 }
 
 pub fn _mod(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
-    evm.stack.push(b % a);
+    evm.stack.push(b.checked_rem(a).unwrap_or_default());
     evm.gas_decrease(5);
 }
 
 pub fn smod(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
-    evm.stack.push(b % a);
+    evm.stack.push(b.checked_rem(a).unwrap_or_default());
     evm.gas_decrease(5);
 }
 
@@ -59,7 +62,8 @@ pub fn addmod(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
     let c = evm.stack.pop();
-    evm.stack.push((b + c) % a);
+    let sum = (U512::from(b) + U512::from(c)).checked_rem(U512::from(a)).unwrap_or_default();
+    evm.stack.push(U256::try_from(sum).unwrap());
     evm.gas_decrease(8);
 }
 
@@ -67,14 +71,15 @@ pub fn mulmod(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
     let c = evm.stack.pop();
-    evm.stack.push((b * c) % a);
+    let product = b.full_mul(c).checked_rem(U512::from(a)).unwrap_or_default();
+    evm.stack.push(U256::try_from(product).unwrap());
     evm.gas_decrease(8);
 }
 
 pub fn exp(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
-    evm.stack.push(b.pow(a));
+    evm.stack.push(b.overflowing_pow(a).0);
     evm.gas_decrease(10);
 }
 
@@ -158,36 +163,41 @@ pub fn not(evm: &mut EVM) {
 pub fn byte(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
-    evm.stack.push(b >> (U256::from(8) * a));
+    evm.stack.push(if a < U256::from(32) { U256::from(b.byte(31 - a.as_usize())) } else { U256::zero() });
     evm.gas_decrease(3);
 }
 
 pub fn shl(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
-    evm.stack.push(b << a);
+    evm.stack.push(if a < U256::from(256) { b << a.as_usize() } else { U256::zero() });
     evm.gas_decrease(3);
 }
 
 pub fn shr(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
-    evm.stack.push(b >> a);
+    evm.stack.push(if a < U256::from(256) { b >> a.as_usize() } else { U256::zero() });
     evm.gas_decrease(3);
 }
 
 pub fn sar(evm: &mut EVM) {
     let a = evm.stack.pop();
     let b = evm.stack.pop();
-    evm.stack.push(b >> a);
+    evm.stack.push(if a < U256::from(256) { b >> a.as_usize() } else { U256::zero() });
     evm.gas_decrease(3);
 }
 
 // ----------- PUSH -----------
+pub fn push0(evm: &mut EVM) {
+    evm.stack.push(U256::zero());
+    evm.gas_decrease(2);
+}
+
 pub fn push_n(evm: &mut EVM, n: usize) {
     let mut value_bytes = vec![0u8; 32];
     for i in 0..n {
-        value_bytes[31 - i] = evm.program[evm.pc + 1 + i];
+        value_bytes[32 - n + i] = evm.program.get(evm.pc + 1 + i).copied().unwrap_or_default(); // zero past the end
     }
 
     evm.stack.push(U256::from_big_endian(&value_bytes));
@@ -219,9 +229,11 @@ pub fn dun_n(evm: &mut EVM, n: usize) {
 pub fn mstore(evm: &mut EVM) { 
     let address = evm.stack.pop();
     let value = evm.stack.pop();
-    let address_as_u64 = address.low_u64(); // This gets the lower 64 bits
-    let mem_expansion_cost = evm.memory.store(address_as_u64 as usize, &[value]).unwrap();
-    evm.gas_decrease(3 + mem_expansion_cost);
+    let Some((offset, _)) = memory_region(evm, address, U256::from(32)) else {
+        return;
+    };
+    evm.memory.store(offset, &u256_to_bytes(vec![value]));
+    evm.gas_decrease(3);
 }
 
 // For 1 byte = 8 bits
@@ -229,14 +241,19 @@ pub fn mstore8(evm: &mut EVM) {
     let address = evm.stack.pop();
     let value = evm.stack.pop();
     let byte = (value.low_u64() & 0xFF) as u8;
-    let address_as_u64 = address.low_u64(); // This gets the lower 64 bits
-    let mem_expansion_cost = evm.memory.store(address_as_u64 as usize, &[U256::from(byte)]).unwrap();
-    evm.gas_decrease(3 + mem_expansion_cost);
+    let Some((offset, _)) = memory_region(evm, address, U256::one()) else {
+        return;
+    };
+    evm.memory.store(offset, &[byte]);
+    evm.gas_decrease(3);
 }
 
 pub fn mload(evm: &mut EVM) {
     let address = evm.stack.pop();
-    let result = evm.memory.load(address.as_usize());
+    let Some((offset, _)) = memory_region(evm, address, U256::from(32)) else {
+        return;
+    };
+    let result = evm.memory.load(offset);
     match extract_u256(result) {
         Some(value) => evm.stack.push(value),
         None => {
@@ -248,24 +265,72 @@ pub fn mload(evm: &mut EVM) {
 }
 
 // ----------- STORAGE -----------
+const COLD_SLOAD_COST: usize = 2100;
+const WARM_STORAGE_READ_COST: usize = 100;
+const SSTORE_SET_GAS: usize = 20000;
+const SSTORE_RESET_GAS: usize = 5000 - COLD_SLOAD_COST;
+const SSTORE_SENTRY_GAS: usize = 2300;
+
+// EIP-2200 net metering with the EIP-2929 cold slot surcharge, as SLOAD, priced from Berlin.
+// Refunds follow EIP-3529 from London
 pub fn sstore(evm: &mut EVM) {
-    let address = evm.stack.pop();
+    let key = evm.stack.pop();
     let value = evm.stack.pop();
-    let address_as_u64 = address.low_u64(); // This gets the lower 64 bits
-    evm.storage.store(address_as_u64 as i32, &[value]);
-    evm.gas_decrease(20);
+    if evm.is_static {
+        evm.halt(EvmError::StateChangeDuringStaticCall);
+        return;
+    }
+    // A frame with no more than the call stipend left cannot store
+    if evm.gas <= SSTORE_SENTRY_GAS {
+        evm.halt(EvmError::OutOfGas);
+        return;
+    }
+    let (warm, current) = evm.state.sload(evm.address, key);
+    let original = evm.state.original_storage(evm.address, key);
+    let access_cost = if warm { 0 } else { COLD_SLOAD_COST };
+    let store_cost = if current == value || original != current {
+        WARM_STORAGE_READ_COST
+    } else if original.is_zero() {
+        SSTORE_SET_GAS
+    } else {
+        SSTORE_RESET_GAS
+    };
+    evm.gas_decrease(access_cost + store_cost);
+    if evm.error.is_some() {
+        return;
+    }
+    sstore_refund(evm, original, current, value);
+    evm.state.sstore(evm.address, key, value);
 }
 
-pub fn sload(evm: &mut EVM) {
-    let address = evm.stack.pop();
-    let address_as_u64 = address.low_u64(); // Get the lower 64 bits
-    let (warm_access, result) = evm.storage.load(address_as_u64 as i32);
+fn sstore_refund(evm: &mut EVM, original: U256, current: U256, value: U256) {
+    let clears = if evm.spec.is_enabled(Spec::London) { 4800 } else { 15000 };
+    if current == value {
+        return;
+    }
+    if original == current {
+        if !original.is_zero() && value.is_zero() {
+            evm.state.add_refund(clears);
+        }
+        return;
+    }
+    // The slot was already written in this transaction
+    if !original.is_zero() {
+        if current.is_zero() {
+            evm.state.sub_refund(clears);
+        } else if value.is_zero() {
+            evm.state.add_refund(clears);
+        }
+    }
+    if original == value {
+        let charged = if original.is_zero() { SSTORE_SET_GAS } else { SSTORE_RESET_GAS };
+        evm.state.add_refund((charged - WARM_STORAGE_READ_COST) as u64);
+    }
+}
 
-    let value = if let Some(&val) = result.get(0) {
-        val
-    } else {
-        U256::zero()
-    };
+pub fn sload(evm: &mut EVM) {
+    let key = evm.stack.pop();
+    let (warm_access, value) = evm.state.sload(evm.address, key);
 
     evm.stack.push(value);
     let gas_cost = if warm_access { 100 } else { 2100 };
@@ -279,22 +344,18 @@ pub fn stop(evm: &mut EVM) {
 
 // ----------- TRANSIENT ----------
 pub fn tstore(evm: &mut EVM) {
-    let address = evm.stack.pop();
+    let key = evm.stack.pop();
     let value = evm.stack.pop();
-    let address_as_u64 = address.low_u64(); // This gets the lower 64 bits
-    evm.transient.store(address_as_u64 as i32, &[value]);
+    if evm.is_static {
+        evm.halt(EvmError::StateChangeDuringStaticCall);
+        return;
+    }
+    evm.transient.store(evm.address, key, value);
     evm.gas_decrease(100);
 }
 pub fn tload(evm: &mut EVM) {
-    let address = evm.stack.pop();
-    let address_as_u64 = address.low_u64(); // This gets the lower 64 bits
-    let result = evm.transient.load(address_as_u64 as i32);
-    let value = if let Some(&val) = result.get(0) {
-        val
-    } else {
-        U256::zero()
-    };
-
+    let key = evm.stack.pop();
+    let value = evm.transient.load(evm.address, key);
     evm.stack.push(value);
     evm.gas_decrease(100);
 }
@@ -303,96 +364,71 @@ pub fn tload(evm: &mut EVM) {
 pub fn _keccak256(evm: &mut EVM) {
     let offset = evm.stack.pop();
     let size = evm.stack.pop();
-    let data = evm.memory.access(offset.low_u64() as usize, size.low_u64() as usize).unwrap();
-    let bytes: Vec<u8> = data.iter().flat_map(|u| {
-     let mut buf = [0u8; 32];
-     u.to_big_endian(&mut buf);
-     buf.to_vec()
- }).collect();
-    let hash = _keccak(&bytes);
+    let Some((offset, size)) = memory_region(evm, offset, size) else {
+        return;
+    };
+    let data = evm.memory.access(offset, size).unwrap();
+    let hash = Helper::keccak256(data);
     evm.stack.push(U256::from_big_endian(&hash));
-    evm.gas_decrease(30);
+    evm.gas_decrease(30 + 6 * Helper::to_word_size(size));
  }
 
 // ----------- LOG -----------
-pub fn log0(evm: &mut EVM) {
-    let offset = evm.stack.pop();
-    let size = evm.stack.pop();
-    let data = evm.memory.access(offset.low_u64() as usize, size.low_u64() as usize).unwrap();
-    logs_handler(evm, vec![], data.to_vec());
-    evm.gas_decrease(375);
-}
-
-pub fn log1(evm: &mut EVM) {
-    let offset = evm.stack.pop();
-    let size = evm.stack.pop();
-    let topic1 = evm.stack.pop();
-    println!("Offset is: {:?} and size is: {:?}", offset, size);
-    let data = evm.memory.access(offset.low_u64() as usize, size.low_u64() as usize).unwrap();
-    logs_handler(evm, vec![topic1], data.to_vec());
-    evm.gas_decrease(750);
-}
-
-pub fn log2(evm: &mut EVM) {
+pub fn log_n(evm: &mut EVM, n: usize) {
     let offset = evm.stack.pop();
     let size = evm.stack.pop();
-    let topic1 = evm.stack.pop();
-    let topic2 = evm.stack.pop();
-    let data = evm.memory.access(offset.low_u64() as usize, size.low_u64() as usize).unwrap();
-    logs_handler(evm, vec![topic1, topic2], data.to_vec());
-    evm.gas_decrease(1125);
-}
-
-pub fn log3(evm: &mut EVM) {
-    let offset = evm.stack.pop();
-    let size = evm.stack.pop();
-    let topic1 = evm.stack.pop();
-    let topic2 = evm.stack.pop();
-    let topic3 = evm.stack.pop();
-    let data = evm.memory.access(offset.low_u64() as usize, size.low_u64() as usize).unwrap();
-    logs_handler(evm, vec![topic1, topic2, topic3], data.to_vec());
-    evm.gas_decrease(1500);
-}
-
-pub fn log4(evm: &mut EVM) {
-    let offset = evm.stack.pop();
-    let size = evm.stack.pop();
-    let topic1 = evm.stack.pop();
-    let topic2 = evm.stack.pop();
-    let topic3 = evm.stack.pop();
-    let topic4 = evm.stack.pop();
-    let data = evm.memory.access(offset.low_u64() as usize, size.low_u64() as usize).unwrap();
-    logs_handler(evm, vec![topic1, topic2, topic3, topic4], data.to_vec());
-    evm.gas_decrease(1875);
+    let topics = (0..n).map(|_| evm.stack.pop()).collect();
+    let Some((offset, size)) = memory_region(evm, offset, size) else {
+        return;
+    };
+    let data = evm.memory.access(offset, size).unwrap();
+    logs_handler(evm, topics, data.to_vec());
+    evm.gas_decrease(375 + 375 * n + 8 * size);
 }
 
 // ----------- JUMP ----------
 pub fn jump(evm: &mut EVM) {
-    let counter = evm.stack.pop().low_u64() as usize;
-    if evm.program[counter] != 0x5B {
-        panic!("Invalid jump instruction");
-    }
-    evm.pc = counter;
+    let counter = evm.stack.pop();
     evm.gas_decrease(8);
+    jump_to(evm, counter);
 }
 
 pub fn jumpi(evm: &mut EVM) {
-    let counter = evm.stack.pop().low_u64() as usize;
+    let counter = evm.stack.pop();
     let condition = evm.stack.pop();
-    if evm.program[counter] != 0x5B || condition == U256::zero() {
-        evm.pc += 1;
-    } else {
-        evm.pc = counter;
-    }
     evm.gas_decrease(10);
+    if !condition.is_zero() {
+        jump_to(evm, counter);
+    }
 }
 
 pub fn pc(evm: &mut EVM) {
     evm.stack.push(U256::from(evm.pc));
-    evm.pc += 1;
     evm.gas_decrease(2);
 }
 
+// Jumping anywhere but a JUMPDEST outside PUSH data is an exceptional halt
+fn jump_to(evm: &mut EVM, destination: U256) {
+    if evm.error.is_some() {
+        return;
+    }
+    if destination >= U256::from(evm.program.len()) || !is_jump_dest(&evm.program, destination.as_usize()) {
+        evm.halt(EvmError::InvalidJump);
+        return;
+    }
+    evm.pc = destination.as_usize();
+}
+
+fn is_jump_dest(code: &[u8], destination: usize) -> bool {
+    let mut pc = 0;
+    while pc < destination {
+        let opcode = code[pc];
+        let data = if (0x60..=0x7F).contains(&opcode) { (opcode - 0x5F) as usize } else { 0 };
+        pc += 1 + data;
+    }
+    pc == destination && code[destination] == 0x5B
+}
+
 pub fn jump_dest(evm: &mut EVM) {
     evm.gas_decrease(1);
 }
@@ -402,38 +438,36 @@ fn h160_to_u256(address: H160) -> U256 {
     U256::from_big_endian(&address.0)
 }
 pub fn address(evm: &mut EVM) {
-    evm.stack.push(h160_to_u256(evm.sender));
+    evm.stack.push(h160_to_u256(evm.address));
     evm.gas_decrease(2);
 }
 
 pub fn balance(evm: &mut EVM) {
-    let address = evm.stack.pop();
-    let balance = U256::from_big_endian(b"99999"); 
+    let address = u256_to_h160(evm.stack.pop());
+    let balance = evm.state.balance(&address);
     evm.stack.push(balance);
-    evm.gas_decrease(2600); //gas in case of cold address state
+    let gas_cost = account_access_cost(evm, address);
+    evm.gas_decrease(gas_cost);
 }
 
 pub fn origin(evm: &mut EVM) {
-    evm.stack.push(h160_to_u256(evm.sender)); // must pass address of account that initiated the txn, not same as sender in case of contracts calling other contracts - sender in an immediate caller
+    evm.stack.push(h160_to_u256(evm.origin)); // address of account that initiated the txn, not same as sender in case of contracts calling other contracts - sender in an immediate caller
     evm.gas_decrease(2);
 }
 
 pub fn caller(evm: &mut EVM) {
-    evm.stack.push(h160_to_u256(H160::random())); // caller is the one who invoked current function - random here
+    evm.stack.push(h160_to_u256(evm.sender)); // caller is the one who invoked current function
     evm.gas_decrease(2);
 }
 
 pub fn callvalue(evm: &mut EVM) {
-    evm.stack.push(U256::from(evm.value)); // ETH value sent with a call for execution
+    evm.stack.push(evm.value); // ETH value sent with a call for execution
     evm.gas_decrease(2);
 }
 
 pub fn calldataload(evm: &mut EVM) { // reads 32 byte data from calldata starting from offset and push onto stack
     let offset = evm.stack.pop();
-    let mut data = Vec::new();
-    for i in 0..32 {
-        data.push(evm.call_data[offset.low_u64() as usize + i]);
-    }
+    let data = padded(&evm.call_data, offset, 32);
     evm.stack.push(U256::from_big_endian(&data));
     evm.gas_decrease(3);
 }
@@ -447,10 +481,12 @@ pub fn calldatacopy(evm: &mut EVM) { // Copy specified part of input data of thi
     let dest_offset = evm.stack.pop();
     let offset = evm.stack.pop();
     let size = evm.stack.pop();
-    let mut data = Vec::new();
-    data = evm.call_data[offset.low_u64() as usize..offset.low_u64() as usize + size.low_u64() as usize].to_vec();
-    evm.memory.store(dest_offset.low_u64() as usize, &[U256::from_big_endian(&data)]);
-    evm.gas_decrease(3);
+    let Some((dest_offset, size)) = memory_region(evm, dest_offset, size) else {
+        return;
+    };
+    let data = padded(&evm.call_data, offset, size);
+    evm.memory.store(dest_offset, &data);
+    evm.gas_decrease(3 + Helper::to_word_size(size) * 3);
 }
 
 pub fn codesize(evm: &mut EVM) { // pushed size of currently running code
@@ -462,34 +498,39 @@ pub fn codecopy(evm: &mut EVM) { // Copy running code of this environment to mem
     let dest_offset = evm.stack.pop();
     let offset = evm.stack.pop();
     let size = evm.stack.pop();
-    let mut data = Vec::new();
-    data = evm.program[offset.low_u64() as usize..offset.low_u64() as usize + size.low_u64() as usize].to_vec();
-    let mem_expansion_cost =evm.memory.store(dest_offset.low_u64() as usize, &[U256::from_big_endian(&data)]).unwrap();
-    let dynamic_gas = Helper::to_word_size(size.low_u64() as usize) * 3 + mem_expansion_cost;
-    evm.gas_decrease(dynamic_gas);
+    let Some((dest_offset, size)) = memory_region(evm, dest_offset, size) else {
+        return;
+    };
+    let data = padded(&evm.program, offset, size);
+    evm.memory.store(dest_offset, &data);
+    evm.gas_decrease(3 + Helper::to_word_size(size) * 3);
 }
 
 pub fn gasprice(evm: &mut EVM) { // Get price of gas in current environment in Wei
-    evm.stack.push(U256::from(0x00)); // random - gas price per unit gas
+    evm.stack.push(evm.gas_price); // gas price per unit gas
     evm.gas_decrease(2);
 }
 
 pub fn extcodesize(evm: &mut EVM) { // Get size of code at given contractaddress
-    let address = evm.stack.pop();
-    let address_as_u64 = address.low_u64(); 
-    evm.stack.push(U256::from(0)); 
-    evm.gas_decrease(2600);
+    let address = u256_to_h160(evm.stack.pop());
+    evm.stack.push(U256::from(evm.state.code(&address).len()));
+    let gas_cost = account_access_cost(evm, address);
+    evm.gas_decrease(gas_cost);
 }
 
 pub fn extcodecopy(evm: &mut EVM) {
-    let address = evm.stack.pop();
+    let address = u256_to_h160(evm.stack.pop());
     let dest_offset = evm.stack.pop();
-    let offset = evm.stack.pop().as_usize();
-    let size = evm.stack.pop().as_usize();
-    let code = U256::from(0);  // no external code
-    let mem_expansion_cost = evm.memory.store(dest_offset.low_u64() as usize, &[code]);
+    let offset = evm.stack.pop();
+    let size = evm.stack.pop();
+    let Some((dest_offset, size)) = memory_region(evm, dest_offset, size) else {
+        return;
+    };
+    let code = padded(evm.state.code(&address), offset, size);
+    evm.memory.store(dest_offset, &code);
+    let gas_cost = account_access_cost(evm, address);
 
-    evm.gas_decrease(2600);
+    evm.gas_decrease(gas_cost + Helper::to_word_size(size) * 3);
 }
 
 pub fn returndatasize(evm: &mut EVM) { // Get size of return data in current environment from previous call
@@ -501,55 +542,128 @@ pub fn returndatacopy(evm: &mut EVM) {
     let dest_offset = evm.stack.pop();
     let offset = evm.stack.pop();
     let size = evm.stack.pop();
-    let mut data = Vec::new();
-    data = evm.return_data[offset.low_u64() as usize..offset.low_u64() as usize + size.low_u64() as usize].to_vec();
-    let mem_expansion_cost = evm.memory.store(dest_offset.low_u64() as usize, &[U256::from_big_endian(&data)]).unwrap(); 
-    let dynamic_gas = Helper::to_word_size(size.low_u64() as usize) * 3 + mem_expansion_cost;
-    evm.gas_decrease(dynamic_gas);
+    // EIP-211: reading past the end of the return data is an exceptional halt
+    if offset.checked_add(size).is_none_or(|end| end > U256::from(evm.return_data.len())) {
+        evm.halt(EvmError::ReturnDataOutOfBounds);
+        return;
+    }
+    let Some((dest_offset, size)) = memory_region(evm, dest_offset, size) else {
+        return;
+    };
+    let offset = offset.as_usize();
+    let data = evm.return_data[offset..offset + size].to_vec();
+    evm.memory.store(dest_offset, &data);
+    evm.gas_decrease(3 + Helper::to_word_size(size) * 3);
 }
 
 pub fn extcodehash(evm: &mut EVM) { // Get hash of code at given contractaddress
-    let _ = evm.stack.pop();
-    evm.stack.push(U256::from(0)); 
-    evm.gas_decrease(2600); // 100 if warm
+    let address = u256_to_h160(evm.stack.pop());
+    let hash = if evm.state.is_empty(&address) {
+        U256::zero()
+    } else {
+        U256::from_big_endian(&Helper::keccak256(evm.state.code(&address)))
+    };
+    evm.stack.push(hash);
+    let gas_cost = account_access_cost(evm, address);
+    evm.gas_decrease(gas_cost);
 }
 
 pub fn blockhash(evm: &mut EVM) { // Get hash of one of the 256 most recent block headers
-    let _block_no = evm.stack.pop();
-    evm.stack.push(U256::zero()); 
+    let block_no = evm.stack.pop();
+    let current = U256::from(evm.block_number);
+    let hash = if block_no < current && current - block_no <= U256::from(256) {
+        U256::from_big_endian(evm.state.block_hash(block_no.as_u64()).as_bytes())
+    } else {
+        U256::zero()
+    };
+    evm.stack.push(hash);
     evm.gas_decrease(20);
 }
 
 pub fn coinbase(evm: &mut EVM) { // Get address of miner of current block
-    evm.stack.push(h160_to_u256(H160::random())); 
+    evm.stack.push(h160_to_u256(evm.coinbase));
     evm.gas_decrease(2);
 }
 
+pub fn blobhash(evm: &mut EVM) { // Get versioned hash at index of the transaction's blobs (EIP-4844)
+    let index = evm.stack.pop();
+    let hash = if index < U256::from(evm.blob_hashes.len()) {
+        U256::from_big_endian(evm.blob_hashes[index.as_usize()].as_bytes())
+    } else {
+        U256::zero()
+    };
+    evm.stack.push(hash);
+    evm.gas_decrease(3);
+}
+
 
 // ----------- CONTRACT -----------
 pub fn _return(evm: &mut EVM) { // Return data from current environment
     let mem_dest_offset = evm.stack.pop();
     let size = evm.stack.pop();
-    evm.return_data = u256_to_bytes((*evm.memory.access(mem_dest_offset.low_u64() as usize, size.low_u64() as usize).unwrap()).to_vec()).to_vec();
+    let Some((offset, size)) = memory_region(evm, mem_dest_offset, size) else {
+        return;
+    };
+    evm.output = evm.memory.access(offset, size).unwrap().to_vec();
     evm.stop_flag = true
 }
 
 pub fn revert(evm: &mut EVM) { // Stops execution and reverts state changes
     let mem_dest_offset = evm.stack.pop();
     let size = evm.stack.pop();
-    evm.return_data = u256_to_bytes((*evm.memory.access(mem_dest_offset.low_u64() as usize, size.low_u64() as usize).unwrap()).to_vec()).to_vec();
+    let Some((offset, size)) = memory_region(evm, mem_dest_offset, size) else {
+        return;
+    };
+    evm.output = evm.memory.access(offset, size).unwrap().to_vec();
     evm.revert_flag = true
 }
 
+// EIP-6780: the balance goes to `target` but, as since Cancun, the account itself is kept
+pub fn selfdestruct(evm: &mut EVM) {
+    let target = u256_to_h160(evm.stack.pop());
+    if evm.is_static {
+        evm.halt(EvmError::StateChangeDuringStaticCall);
+        return;
+    }
+    let balance = evm.state.balance(&evm.address);
+    let access_cost = if evm.state.warm_address(target) { 0 } else { 2600 };
+    let new_account_cost = if !balance.is_zero() && evm.state.is_empty(&target) { 25000 } else { 0 };
+    evm.gas_decrease(5000 + access_cost + new_account_cost);
+    if evm.error.is_some() {
+        return;
+    }
+    evm.state.transfer(evm.address, target, balance);
+    let address = evm.address;
+    evm.inspect(|inspector, _| inspector.selfdestruct(address, target, balance));
+    evm.stop_flag = true;
+}
+
+// ----------- CALL -----------
+pub fn call(evm: &mut EVM) {
+    call_handler(evm, CallKind::Call);
+}
+
+pub fn callcode(evm: &mut EVM) {
+    call_handler(evm, CallKind::CallCode);
+}
+
+pub fn delegatecall(evm: &mut EVM) {
+    call_handler(evm, CallKind::DelegateCall);
+}
+
+pub fn staticcall(evm: &mut EVM) {
+    call_handler(evm, CallKind::StaticCall);
+}
+
 
 
 // Helper functions
-fn extract_u256(result: Result<&[U256], MemoryError>) -> Option<U256> {
+fn extract_u256(result: Result<&[u8], MemoryError>) -> Option<U256> {
     match result {
         Ok(slice) => {
             // Handle cases where the slice may be empty or has fewer elements than expected
-            if let Some(&value) = slice.get(0) {
-                Some(value)
+            if slice.len() == 32 {
+                Some(U256::from_big_endian(slice))
             } else {
                 // Handle the case where the slice is empty
                 None
@@ -562,26 +676,6 @@ fn extract_u256(result: Result<&[U256], MemoryError>) -> Option<U256> {
     }
 }
 
-fn u8_to_u32_vec(data: &[u8]) -> Vec<u32> {
-    data.chunks(4)
-        .map(|chunk| {
-            let mut array = [0u8; 4];
-            array.copy_from_slice(chunk);
-            u32::from_be_bytes(array)
-        })
-        .collect()
-}
-
-
-
-fn _keccak(input: &[u8]) -> [u8; 32] {
-    let mut hasher = Keccak::v256();
-    let mut output = [0u8; 32];
-    hasher.update(input);
-    hasher.finalize(&mut output);
-    output
-}
-
 fn u256_to_bytes(data: Vec<U256>) -> Vec<u8> {
     let mut bytes = Vec::new();
     for u in data {
@@ -592,11 +686,302 @@ fn u256_to_bytes(data: Vec<U256>) -> Vec<u8> {
     bytes
 }
 
-fn logs_handler(evm: &mut EVM, topics: Vec<U256>, data: Vec<U256>) {
-    let entry = LogEntry {
-        topics: topics,
-        data: u256_to_bytes(data),
+fn logs_handler(evm: &mut EVM, topics: Vec<U256>, data: Vec<u8>) {
+    if evm.is_static {
+        evm.halt(EvmError::StateChangeDuringStaticCall);
+        return;
+    }
+    evm.log(topics, data);
+}
+
+// EIP-2929: 2600 the first time an account is touched in a transaction, 100 after. Precompiles are always warm
+fn account_access_cost(evm: &mut EVM, address: H160) -> usize {
+    if evm.precompiles.contains(&address) || evm.state.warm_address(address) {
+        100
+    } else {
+        2600
+    }
+}
+
+// Charges for growing memory over offset..offset + size and only then grows it, so operands
+// from untrusted code never allocate more than the gas pays for. None once the frame has halted
+fn memory_region(evm: &mut EVM, offset: U256, size: U256) -> Option<(usize, usize)> {
+    if size.is_zero() {
+        return Some((0, 0));
+    }
+    let Some(cost) = evm.memory.expansion_cost(offset, size) else {
+        evm.halt(EvmError::OutOfGas);
+        return None;
     };
-    evm.logs.push(entry);
+    evm.gas_decrease(cost);
+    if evm.error.is_some() {
+        return None;
+    }
+    let (offset, size) = (offset.as_usize(), size.as_usize());
+    evm.memory.expand(offset, size);
+    Some((offset, size))
+}
+
+// `size` bytes of `data` from `offset`, zero padded past its end
+fn padded(data: &[u8], offset: U256, size: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; size];
+    if offset < U256::from(data.len()) {
+        let offset = offset.as_usize();
+        let end = data.len().min(offset + size);
+        bytes[..end - offset].copy_from_slice(&data[offset..end]);
+    }
+    bytes
 }
 
+fn u256_to_h160(value: U256) -> H160 {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    H160::from_slice(&bytes[12..])
+}
+
+// ----------- CREATE -----------
+pub fn create(evm: &mut EVM) {
+    create_handler(evm, CreateKind::Create);
+}
+
+pub fn create2(evm: &mut EVM) {
+    create_handler(evm, CreateKind::Create2);
+}
+
+// Shared by CREATE and CREATE2. The init code runs in a child frame as the new account, and the
+// code it returns is deployed there
+fn create_handler(evm: &mut EVM, kind: CreateKind) {
+    let value = evm.stack.pop();
+    let offset = evm.stack.pop();
+    let size = evm.stack.pop();
+    let salt = match kind {
+        CreateKind::Create => None,
+        CreateKind::Create2 => Some(evm.stack.pop()),
+    };
+
+    if evm.is_static {
+        evm.halt(EvmError::StateChangeDuringStaticCall);
+        return;
+    }
+    let Some((offset, size)) = memory_region(evm, offset, size) else {
+        return;
+    };
+    // EIP-3860: init code is limited in size and paid for by the word
+    let shanghai = evm.spec.is_enabled(Spec::Shanghai);
+    if shanghai && size > MAX_INITCODE_SIZE {
+        evm.halt(EvmError::InitCodeSizeExceeded);
+        return;
+    }
+    let init_code = evm.memory.access(offset, size).unwrap().to_vec();
+    let words = Helper::to_word_size(size);
+    let init_code_cost = if shanghai { 2 * words } else { 0 };
+    let hash_cost = if salt.is_some() { 6 * words } else { 0 };
+    evm.gas_decrease(32000 + init_code_cost + hash_cost);
+    if evm.error.is_some() {
+        return;
+    }
+
+    let nonce = evm.state.nonce(&evm.address);
+    let address = match salt {
+        Some(salt) => {
+            let mut bytes = [0u8; 32];
+            salt.to_big_endian(&mut bytes);
+            create2_address(evm.address, H256(bytes), &init_code)
+        }
+        None => create_address(evm.address, nonce),
+    };
+    // EIP-150: all but one 64th of the remaining gas is passed on
+    let gas = evm.gas - evm.gas / 64;
+    evm.gas_decrease(gas);
+    let inputs = CreateInputs { kind, caller: evm.address, address, value, init_code, gas, depth: evm.depth + 1 };
+    evm.inspect(|inspector, _| inspector.create(&inputs));
+
+    // The creation fails without running anything, or bumping the nonce, if it is too deep or
+    // the value cannot be paid
+    let failed = |error| CallOutcome { success: false, gas_left: gas, output: Vec::new(), error: Some(error) };
+    let outcome = if evm.depth >= MAX_CALL_DEPTH {
+        failed(EvmError::CallDepthExceeded)
+    } else if evm.state.balance(&evm.address) < value {
+        failed(EvmError::InsufficientBalance)
+    } else if nonce == u64::MAX {
+        failed(EvmError::NonceOverflow)
+    } else {
+        run_create(evm, &inputs)
+    };
+    evm.inspect(|inspector, _| inspector.create_end(&inputs, &outcome));
+
+    evm.gas += outcome.gas_left;
+    let created = if outcome.success { h160_to_u256(address) } else { U256::zero() };
+    // Only a revert leaves return data, a deployment's code is not returned
+    evm.return_data = if outcome.is_revert() { outcome.output } else { Vec::new() };
+    evm.stack.push(created);
+}
+
+// Runs an accepted creation. The creator's nonce is bumped and the new address warmed even if
+// it fails, everything else is undone
+fn run_create(evm: &mut EVM, inputs: &CreateInputs) -> CallOutcome {
+    let address = inputs.address;
+    evm.state.increment_nonce(evm.address);
+    evm.state.warm_address(address);
+    // EIP-684: never deploy over an account that already has a nonce or code
+    if evm.state.nonce(&address) != 0 || !evm.state.code(&address).is_empty() {
+        return CallOutcome { success: false, gas_left: 0, output: Vec::new(), error: Some(EvmError::CreateCollision) };
+    }
+
+    let checkpoint = evm.state.checkpoint();
+    let logs = evm.logs.len();
+    if evm.spec.is_enabled(Spec::SpuriousDragon) {
+        evm.state.set_nonce(address, 1); // EIP-161
+    }
+    let outcome = if evm.state.transfer(evm.address, address, inputs.value) {
+        let outcome = evm.call_frame(CallParams {
+            address,
+            caller: evm.address,
+            value: inputs.value,
+            code: inputs.init_code.clone(),
+            input: Vec::new(),
+            gas: inputs.gas,
+            is_static: false,
+        });
+        if !outcome.success {
+            outcome
+        } else {
+            match deposit_code(&mut evm.state, evm.spec, address, outcome.output.clone(), outcome.gas_left) {
+                Ok(gas_left) => CallOutcome { gas_left, ..outcome },
+                Err(error) => CallOutcome { success: false, gas_left: 0, output: Vec::new(), error: Some(error) },
+            }
+        }
+    } else {
+        CallOutcome { success: false, gas_left: inputs.gas, output: Vec::new(), error: Some(EvmError::InsufficientBalance) }
+    };
+    if !outcome.success {
+        evm.state.revert_to(checkpoint);
+        evm.logs.truncate(logs);
+    }
+    outcome
+}
+
+// Shared by the CALL family. Precompiles run natively, accounts with code run in a child frame
+fn call_handler(evm: &mut EVM, kind: CallKind) {
+    let gas = evm.stack.pop();
+    let address = u256_to_h160(evm.stack.pop());
+    let value = match kind {
+        CallKind::Call | CallKind::CallCode => evm.stack.pop(),
+        CallKind::DelegateCall | CallKind::StaticCall => U256::zero(),
+    };
+    let args_offset = evm.stack.pop();
+    let args_size = evm.stack.pop();
+    let ret_offset = evm.stack.pop();
+    let ret_size = evm.stack.pop();
+
+    if kind == CallKind::Call && evm.is_static && !value.is_zero() {
+        evm.halt(EvmError::StateChangeDuringStaticCall);
+        return;
+    }
+
+    let Some((args_offset, args_size)) = memory_region(evm, args_offset, args_size) else {
+        return;
+    };
+    let Some((ret_offset, ret_size)) = memory_region(evm, ret_offset, ret_size) else {
+        return;
+    };
+    let input = evm.memory.access(args_offset, args_size).unwrap().to_vec();
+
+    let mut access_cost = account_access_cost(evm, address);
+    // EIP-7702: a delegated account runs its target's code, and accessing the target is paid for too
+    let delegate = if evm.spec.is_enabled(Spec::Prague) { evm.state.delegation(&address) } else { None };
+    if let Some(target) = delegate {
+        access_cost += account_access_cost(evm, target);
+    }
+    let value_cost = if value.is_zero() { 0 } else { 9000 };
+    let new_account_cost = if kind == CallKind::Call && !value.is_zero() && evm.state.is_empty(&address) { 25000 } else { 0 };
+    evm.gas_decrease(access_cost + value_cost + new_account_cost);
+    if evm.error.is_some() {
+        return;
+    }
+
+    // EIP-150: at most all but one 64th of the remaining gas is passed on
+    let max_call_gas = evm.gas - evm.gas / 64;
+    let call_gas = if gas > U256::from(max_call_gas) { max_call_gas } else { gas.as_usize() };
+    evm.gas_decrease(call_gas);
+    let stipend = if value.is_zero() { 0 } else { 2300 };
+    let call_gas = call_gas + stipend;
+
+    let is_static = evm.is_static || kind == CallKind::StaticCall;
+    let inputs = CallInputs {
+        kind,
+        caller: evm.address,
+        target: address,
+        code_address: delegate.unwrap_or(address),
+        value,
+        input,
+        gas: call_gas,
+        is_static,
+        depth: evm.depth + 1,
+    };
+    evm.inspect(|inspector, _| inspector.call(&inputs));
+
+    // The call fails without running anything if it is too deep or the value cannot be paid
+    let checkpoint = evm.state.checkpoint();
+    let outcome = if evm.depth >= MAX_CALL_DEPTH {
+        CallOutcome { success: false, gas_left: call_gas, output: Vec::new(), error: Some(EvmError::CallDepthExceeded) }
+    } else if evm.state.balance(&evm.address) < value || (kind == CallKind::Call && !evm.state.transfer(evm.address, address, value)) {
+        CallOutcome { success: false, gas_left: call_gas, output: Vec::new(), error: Some(EvmError::InsufficientBalance) }
+    } else {
+        run_call(evm, kind, &inputs, delegate)
+    };
+    evm.inspect(|inspector, _| inspector.call_end(&inputs, &outcome));
+
+    let CallOutcome { success, gas_left, output, .. } = outcome;
+    if !success {
+        evm.state.revert_to(checkpoint);
+    }
+
+    evm.gas += gas_left;
+    let copy_size = ret_size.min(output.len());
+    evm.memory.store(ret_offset, &output[..copy_size]);
+    evm.return_data = output;
+    evm.stack.push(U256::from(success as u8));
+}
+
+// Runs an accepted call, natively for precompiles and in a child frame for accounts with code
+fn run_call(evm: &mut EVM, kind: CallKind, inputs: &CallInputs, delegate: Option<H160>) -> CallOutcome {
+    let address = inputs.target;
+    if evm.precompiles.contains(&address) {
+        let mut context = PrecompileContext {
+            caller: evm.address,
+            value: inputs.value,
+            is_static: inputs.is_static,
+            state: &mut evm.state,
+            transient: &mut evm.transient,
+        };
+        return match evm.precompiles.call(&address, &inputs.input, inputs.gas, &mut context).unwrap() {
+            Ok(result) => CallOutcome { success: true, gas_left: inputs.gas - result.gas_used, output: result.output, error: None },
+            Err(error) => CallOutcome { success: false, gas_left: 0, output: Vec::new(), error: Some(EvmError::Precompile(error)) },
+        };
+    }
+
+    let code = match delegate {
+        Some(target) if evm.precompiles.contains(&target) => Vec::new(),
+        Some(target) => evm.state.code(&target).to_vec(),
+        None => evm.state.code(&address).to_vec(),
+    };
+    if code.is_empty() {
+        return CallOutcome { success: true, gas_left: inputs.gas, output: Vec::new(), error: None };
+    }
+    // CALLCODE and DELEGATECALL run the callee's code as the current account
+    let (frame_address, caller, frame_value) = match kind {
+        CallKind::Call | CallKind::StaticCall => (address, evm.address, inputs.value),
+        CallKind::CallCode => (evm.address, evm.address, inputs.value),
+        CallKind::DelegateCall => (evm.address, evm.sender, evm.value),
+    };
+    evm.call_frame(CallParams {
+        address: frame_address,
+        caller,
+        value: frame_value,
+        code,
+        input: inputs.input.clone(),
+        gas: inputs.gas,
+        is_static: inputs.is_static,
+    })
+}
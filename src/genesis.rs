@@ -0,0 +1,516 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use ethereum_types::{BigEndianHash, H160, H256, U256};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+use crate::hex::{self, Bytes};
+use crate::transaction::BlockEnv;
+use crate::{Account, Spec, State, Storage};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum GenesisError {
+    #[error("Cannot read genesis file: {0}")]
+    Io(String),
+    #[error("Invalid genesis JSON: {0}")]
+    Json(String),
+}
+
+// geth genesis.json. Header fields that do not affect execution are ignored
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Genesis {
+    #[serde(default)]
+    pub config: ChainConfig,
+    #[serde(default, with = "quantity")]
+    pub number: u64,
+    #[serde(default, with = "quantity")]
+    pub timestamp: u64,
+    #[serde(default, with = "quantity")]
+    pub gas_limit: u64,
+    #[serde(default)]
+    pub coinbase: H160,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "optional_big_quantity")]
+    pub base_fee_per_gas: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "optional_quantity")]
+    pub excess_blob_gas: Option<u64>,
+    #[serde(default, with = "address_map")]
+    pub alloc: BTreeMap<H160, GenesisAccount>,
+}
+
+// Fork activation points, by block number up to the merge and by timestamp after it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homestead_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eip150_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eip158_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub byzantium_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constantinople_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub petersburg_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub istanbul_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub berlin_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub london_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_netsplit_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub terminal_total_difficulty_passed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shanghai_time: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancun_time: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prague_time: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisAccount {
+    #[serde(with = "big_quantity")]
+    pub balance: U256,
+    #[serde(default, skip_serializing_if = "is_zero", with = "quantity")]
+    pub nonce: u64,
+    #[serde(default, skip_serializing_if = "<[u8]>::is_empty")]
+    pub code: Bytes,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty", with = "word_map")]
+    pub storage: BTreeMap<H256, H256>,
+}
+
+impl ChainConfig {
+    // Spec in force for a block with `number` and `timestamp`
+    pub fn spec_at(&self, number: u64, timestamp: u64) -> Spec {
+        let by_time = |fork: Option<u64>| fork.is_some_and(|time| time <= timestamp);
+        let by_block = |fork: Option<u64>| fork.is_some_and(|block| block <= number);
+        let forks = [
+            (by_time(self.prague_time), Spec::Prague),
+            (by_time(self.cancun_time), Spec::Cancun),
+            (by_time(self.shanghai_time), Spec::Shanghai),
+            (by_block(self.merge_netsplit_block) || self.terminal_total_difficulty_passed, Spec::Merge),
+            (by_block(self.london_block), Spec::London),
+            (by_block(self.berlin_block), Spec::Berlin),
+            (by_block(self.istanbul_block), Spec::Istanbul),
+            (by_block(self.petersburg_block), Spec::Petersburg),
+            (by_block(self.constantinople_block), Spec::Constantinople),
+            (by_block(self.byzantium_block), Spec::Byzantium),
+            (by_block(self.eip158_block), Spec::SpuriousDragon),
+            (by_block(self.eip150_block), Spec::TangerineWhistle),
+            (by_block(self.homestead_block), Spec::Homestead),
+        ];
+        forks.iter().find(|(active, _)| *active).map(|(_, spec)| *spec).unwrap_or(Spec::Frontier)
+    }
+}
+
+impl Genesis {
+    pub fn from_json(json: &str) -> Result<Genesis, GenesisError> {
+        serde_json::from_str(json).map_err(|error| GenesisError::Json(error.to_string()))
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Genesis, GenesisError> {
+        let json = std::fs::read_to_string(path).map_err(|error| GenesisError::Io(error.to_string()))?;
+        Genesis::from_json(&json)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn spec(&self) -> Spec {
+        self.config.spec_at(self.number, self.timestamp)
+    }
+
+    // Environment of the genesis block, for running transactions on top of it
+    pub fn block_env(&self) -> BlockEnv {
+        let defaults = BlockEnv::default();
+        BlockEnv {
+            spec: self.spec(),
+            chain_id: self.config.chain_id,
+            number: self.number,
+            timestamp: self.timestamp,
+            coinbase: self.coinbase,
+            gas_limit: if self.gas_limit == 0 { defaults.gas_limit } else { self.gas_limit },
+            base_fee: self.base_fee_per_gas.unwrap_or_default(),
+            excess_blob_gas: self.excess_blob_gas.unwrap_or_default(),
+        }
+    }
+
+    // Adds the alloc accounts to `state`, replacing any already there
+    pub fn apply_alloc(&self, state: &mut State) {
+        for (address, genesis_account) in &self.alloc {
+            let mut storage = Storage::new();
+            for (key, value) in &genesis_account.storage {
+                storage.store(U256::from_big_endian(key.as_bytes()), U256::from_big_endian(value.as_bytes()));
+            }
+            let account = Account {
+                balance: genesis_account.balance,
+                nonce: genesis_account.nonce,
+                code: genesis_account.code.0.clone(),
+                storage,
+            };
+            state.insert_account(*address, account);
+        }
+    }
+
+    pub fn state(&self) -> State {
+        let mut state = State::new();
+        self.apply_alloc(&mut state);
+        state
+    }
+
+    // This genesis with its alloc replaced by the accounts of `state`, so that
+    // `Genesis::from_json(&genesis.export(&state).to_json())?.state()` gives the state back.
+    // With a database, only the accounts loaded so far are exported
+    pub fn export(&self, state: &State) -> Genesis {
+        let alloc = state
+            .accounts()
+            .map(|(address, account)| {
+                let storage = account
+                    .storage
+                    .iter()
+                    .map(|(key, value)| (H256::from_uint(key), H256::from_uint(value)))
+                    .collect();
+                let genesis_account = GenesisAccount {
+                    balance: account.balance,
+                    nonce: account.nonce,
+                    code: Bytes(account.code.clone()),
+                    storage,
+                };
+                (*address, genesis_account)
+            })
+            .collect();
+        Genesis { alloc, ..self.clone() }
+    }
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+// geth accepts numbers, decimal strings and 0x-prefixed hex strings for quantities
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawQuantity {
+    Number(u64),
+    String(String),
+}
+
+fn parse_quantity<E: serde::de::Error>(raw: RawQuantity) -> Result<U256, E> {
+    match raw {
+        RawQuantity::Number(number) => Ok(U256::from(number)),
+        RawQuantity::String(s) => match s.strip_prefix("0x") {
+            Some("") => Ok(U256::zero()),
+            Some(digits) => U256::from_str_radix(digits, 16).map_err(|_| E::custom(format!("invalid quantity {}", s))),
+            None => U256::from_dec_str(&s).map_err(|_| E::custom(format!("invalid quantity {}", s))),
+        },
+    }
+}
+
+fn parse_small_quantity<E: serde::de::Error>(raw: RawQuantity) -> Result<u64, E> {
+    let value = parse_quantity(raw)?;
+    if value > U256::from(u64::MAX) {
+        return Err(E::custom("quantity does not fit in 64 bits"));
+    }
+    Ok(value.as_u64())
+}
+
+fn parse_word<E: serde::de::Error>(s: &str) -> Result<H256, E> {
+    let bytes = hex::decode(s).map_err(E::custom)?;
+    if bytes.len() > 32 {
+        return Err(E::custom(format!("word too long: {}", s)));
+    }
+    let mut word = [0u8; 32];
+    word[32 - bytes.len()..].copy_from_slice(&bytes);
+    Ok(H256(word))
+}
+
+mod quantity {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:#x}", value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        parse_small_quantity(RawQuantity::deserialize(deserializer)?)
+    }
+}
+
+mod optional_quantity {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+        quantity::serialize(&value.unwrap_or_default(), serializer)
+    }
+
+    // geth dumps absent header fields as null
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+        Option::<RawQuantity>::deserialize(deserializer)?.map(parse_small_quantity).transpose()
+    }
+}
+
+mod big_quantity {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:#x}", value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+        parse_quantity(RawQuantity::deserialize(deserializer)?)
+    }
+}
+
+mod optional_big_quantity {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &Option<U256>, serializer: S) -> Result<S::Ok, S::Error> {
+        big_quantity::serialize(&value.unwrap_or_default(), serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<U256>, D::Error> {
+        Option::<RawQuantity>::deserialize(deserializer)?.map(parse_quantity).transpose()
+    }
+}
+
+// alloc keys may come with or without the 0x prefix
+mod address_map {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(map: &BTreeMap<H160, GenesisAccount>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(map.iter().map(|(address, account)| (hex::encode(address.as_bytes()), account)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<H160, GenesisAccount>, D::Error> {
+        let raw = BTreeMap::<String, GenesisAccount>::deserialize(deserializer)?;
+        raw.into_iter()
+            .map(|(key, account)| {
+                let bytes = hex::decode(&key).map_err(D::Error::custom)?;
+                if bytes.len() != 20 {
+                    return Err(D::Error::custom(format!("invalid address {}", key)));
+                }
+                Ok((H160::from_slice(&bytes), account))
+            })
+            .collect()
+    }
+}
+
+// Storage keys and values may be shorter than 32 bytes, they are left-padded
+mod word_map {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(map: &BTreeMap<H256, H256>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(map.iter().map(|(key, value)| (hex::encode(key.as_bytes()), hex::encode(value.as_bytes()))))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<H256, H256>, D::Error> {
+        let raw = BTreeMap::<String, String>::deserialize(deserializer)?;
+        raw.iter().map(|(key, value)| Ok((parse_word(key)?, parse_word(value)?))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Shaped like `geth dumpgenesis` output: null header fields, unused config keys,
+    // alloc keys with and without 0x, hex and decimal balances
+    const GETH_GENESIS: &str = r#"{
+        "config": {
+            "chainId": 1337,
+            "homesteadBlock": 0,
+            "eip150Block": 0,
+            "eip155Block": 0,
+            "eip158Block": 0,
+            "byzantiumBlock": 0,
+            "constantinopleBlock": 0,
+            "petersburgBlock": 0,
+            "istanbulBlock": 0,
+            "muirGlacierBlock": 0,
+            "berlinBlock": 0,
+            "londonBlock": 0,
+            "arrowGlacierBlock": 0,
+            "grayGlacierBlock": 0,
+            "mergeNetsplitBlock": 0,
+            "shanghaiTime": 0,
+            "cancunTime": 1700000000,
+            "terminalTotalDifficulty": 0,
+            "terminalTotalDifficultyPassed": true,
+            "blobSchedule": {"cancun": {"target": 3, "max": 6, "baseFeeUpdateFraction": 3338477}}
+        },
+        "nonce": "0x0",
+        "timestamp": "0x6553f0ff",
+        "extraData": "0x",
+        "gasLimit": "0x1c9c380",
+        "difficulty": "0x0",
+        "mixHash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+        "coinbase": "0x0000000000000000000000000000000000000000",
+        "alloc": {
+            "0000000000000000000000000000000000000001": {"balance": "0x1"},
+            "0x71562b71999873DB5b286dF957af199Ec94617F7": {
+                "balance": "1000000000000000000000",
+                "nonce": "0x2"
+            },
+            "0x4242424242424242424242424242424242424242": {
+                "balance": "0",
+                "nonce": "1",
+                "code": "0x60005460005260206000f3",
+                "storage": {
+                    "0x0000000000000000000000000000000000000000000000000000000000000000": "0x000000000000000000000000000000000000000000000000000000000000002a",
+                    "0x01": "0xff"
+                }
+            }
+        },
+        "number": "0x0",
+        "gasUsed": "0x0",
+        "parentHash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+        "baseFeePerGas": null,
+        "excessBlobGas": null,
+        "blobGasUsed": null
+    }"#;
+
+    fn address(s: &str) -> H160 {
+        H160::from_slice(&hex::decode(s).unwrap())
+    }
+
+    type Contents = (U256, u64, Vec<u8>, BTreeMap<U256, U256>);
+
+    fn accounts(state: &State) -> BTreeMap<H160, Contents> {
+        state
+            .accounts()
+            .map(|(address, account)| {
+                let storage = account.storage.iter().map(|(key, value)| (*key, *value)).collect();
+                (*address, (account.balance, account.nonce, account.code.clone(), storage))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_a_geth_genesis() {
+        let genesis = Genesis::from_json(GETH_GENESIS).unwrap();
+        assert_eq!(genesis.config.chain_id, 1337);
+        assert_eq!(genesis.config.london_block, Some(0));
+        assert_eq!(genesis.config.cancun_time, Some(1_700_000_000));
+        assert!(genesis.config.terminal_total_difficulty_passed);
+        assert_eq!(genesis.timestamp, 1_699_999_999);
+        assert_eq!(genesis.gas_limit, 30_000_000);
+        assert_eq!(genesis.base_fee_per_gas, None);
+        assert_eq!(genesis.excess_blob_gas, None);
+        // one second before cancunTime
+        assert_eq!(genesis.spec(), Spec::Shanghai);
+
+        let mut state = genesis.state();
+        assert_eq!(state.accounts().count(), 3);
+        assert_eq!(state.account(&H160::from_low_u64_be(1)).unwrap().balance, U256::one());
+        let funded = state.account(&address("71562b71999873db5b286df957af199ec94617f7")).unwrap();
+        assert_eq!(funded.balance, U256::exp10(21));
+        assert_eq!(funded.nonce, 2);
+        let contract = address("4242424242424242424242424242424242424242");
+        let account = state.account(&contract).unwrap();
+        assert_eq!(account.nonce, 1);
+        assert_eq!(account.code, hex::decode("60005460005260206000f3").unwrap());
+        assert_eq!(state.storage(&contract, U256::zero()), U256::from(42));
+        assert_eq!(state.storage(&contract, U256::one()), U256::from(0xff));
+
+        let block = genesis.block_env();
+        assert_eq!(block.spec, Spec::Shanghai);
+        assert_eq!(block.chain_id, 1337);
+        assert_eq!(block.gas_limit, 30_000_000);
+        assert_eq!(block.base_fee, U256::zero());
+    }
+
+    #[test]
+    fn parses_header_quantities_in_any_form() {
+        let genesis = Genesis::from_json(
+            r#"{"config": {"chainId": 1}, "number": 7, "timestamp": "1000", "gasLimit": "0x5208",
+                "baseFeePerGas": "0x3b9aca00", "excessBlobGas": "393216", "alloc": {}}"#,
+        )
+        .unwrap();
+        assert_eq!(genesis.number, 7);
+        assert_eq!(genesis.timestamp, 1000);
+        assert_eq!(genesis.gas_limit, 21000);
+        assert_eq!(genesis.base_fee_per_gas, Some(U256::from(1_000_000_000)));
+        assert_eq!(genesis.excess_blob_gas, Some(393216));
+
+        assert!(Genesis::from_json(r#"{"config": {"chainId": 1}, "gasLimit": "0x10000000000000000"}"#).is_err());
+        assert!(Genesis::from_json(r#"{"config": {"chainId": 1}, "alloc": {"0x01": {"balance": "0x0"}}}"#).is_err());
+        assert!(Genesis::from_json(r#"{"config": {"chainId": 1}, "timestamp": "soon"}"#).is_err());
+    }
+
+    #[test]
+    fn spec_changes_at_each_fork_boundary() {
+        // Mainnet activation points
+        let config = ChainConfig {
+            chain_id: 1,
+            homestead_block: Some(1_150_000),
+            eip150_block: Some(2_463_000),
+            eip158_block: Some(2_675_000),
+            byzantium_block: Some(4_370_000),
+            constantinople_block: Some(7_280_000),
+            petersburg_block: Some(7_280_000),
+            istanbul_block: Some(9_069_000),
+            berlin_block: Some(12_244_000),
+            london_block: Some(12_965_000),
+            merge_netsplit_block: Some(15_537_394),
+            terminal_total_difficulty_passed: false,
+            shanghai_time: Some(1_681_338_455),
+            cancun_time: Some(1_710_338_135),
+            prague_time: Some(1_746_612_311),
+        };
+        let blocks = [
+            (1_150_000, Spec::Frontier, Spec::Homestead),
+            (2_463_000, Spec::Homestead, Spec::TangerineWhistle),
+            (2_675_000, Spec::TangerineWhistle, Spec::SpuriousDragon),
+            (4_370_000, Spec::SpuriousDragon, Spec::Byzantium),
+            // Constantinople and Petersburg activated together
+            (7_280_000, Spec::Byzantium, Spec::Petersburg),
+            (9_069_000, Spec::Petersburg, Spec::Istanbul),
+            (12_244_000, Spec::Istanbul, Spec::Berlin),
+            (12_965_000, Spec::Berlin, Spec::London),
+            (15_537_394, Spec::London, Spec::Merge),
+        ];
+        for (block, before, after) in blocks {
+            assert_eq!(config.spec_at(block - 1, 0), before, "block {}", block - 1);
+            assert_eq!(config.spec_at(block, 0), after, "block {}", block);
+        }
+        let merged = 20_000_000;
+        let times = [
+            (1_681_338_455, Spec::Merge, Spec::Shanghai),
+            (1_710_338_135, Spec::Shanghai, Spec::Cancun),
+            (1_746_612_311, Spec::Cancun, Spec::Prague),
+        ];
+        for (time, before, after) in times {
+            assert_eq!(config.spec_at(merged, time - 1), before, "time {}", time - 1);
+            assert_eq!(config.spec_at(merged, time), after, "time {}", time);
+        }
+
+        assert_eq!(ChainConfig::default().spec_at(u64::MAX, u64::MAX), Spec::Frontier);
+        let passed = ChainConfig { terminal_total_difficulty_passed: true, ..Default::default() };
+        assert_eq!(passed.spec_at(0, 0), Spec::Merge);
+    }
+
+    #[test]
+    fn exported_genesis_loads_back_into_the_same_state() {
+        let genesis = Genesis::from_json(GETH_GENESIS).unwrap();
+        let mut state = genesis.state();
+        let contract = address("4242424242424242424242424242424242424242");
+        state.sstore(contract, U256::from(2), U256::MAX);
+        state.insert_account(H160::repeat_byte(0xaa), Account::new(U256::from(5)).with_code(vec![0x00]));
+
+        let exported = genesis.export(&state);
+        assert_eq!(exported.config, genesis.config);
+        assert_eq!(exported.timestamp, genesis.timestamp);
+        let reloaded = Genesis::from_json(&exported.to_json()).unwrap();
+        assert_eq!(reloaded, exported);
+        assert_eq!(accounts(&reloaded.state()), accounts(&state));
+        // and the export of the reloaded state is the same file
+        assert_eq!(reloaded.export(&reloaded.state()).to_json(), exported.to_json());
+    }
+}
@@ -1,10 +1,12 @@
+use tiny_keccak::{Keccak, Hasher};
+
 pub struct Helper;
 
 impl Helper {
 
 
     pub fn to_word_size(size: usize) -> usize {
-        (size + 31) / 32
+        size.div_ceil(32)
     }
 
     pub fn calc_mem_gas_cost(size: usize) -> usize {
@@ -18,4 +20,12 @@ impl Helper {
         static_gas + mem_gas
     }
 
+    pub fn keccak256(input: &[u8]) -> [u8; 32] {
+        let mut hasher = Keccak::v256();
+        let mut output = [0u8; 32];
+        hasher.update(input);
+        hasher.finalize(&mut output);
+        output
+    }
+
 }
@@ -0,0 +1,94 @@
+use std::fmt;
+use std::ops::Deref;
+use ethereum_types::H160;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+use crate::helper::Helper;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum HexError {
+    #[error("Invalid hex character {0:?}")]
+    InvalidCharacter(char),
+}
+
+// 0x-prefixed lowercase hex
+pub fn encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(2 + bytes.len() * 2);
+    out.push_str("0x");
+    for byte in bytes {
+        out.push_str(&format!("{:02x}", byte));
+    }
+    out
+}
+
+// EIP-55 mixed-case address, a letter is uppercase when the matching nibble of the hash is 8 or more
+pub fn checksum(address: &H160) -> String {
+    let digits = &encode(address.as_bytes())[2..];
+    let hash = Helper::keccak256(digits.as_bytes());
+    let mut out = String::from("0x");
+    for (i, c) in digits.chars().enumerate() {
+        let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+        out.push(if nibble >= 8 { c.to_ascii_uppercase() } else { c });
+    }
+    out
+}
+
+// Accepts an optional 0x prefix and odd lengths, which get a leading zero nibble
+pub fn decode(input: &str) -> Result<Vec<u8>, HexError> {
+    let digits = input.strip_prefix("0x").unwrap_or(input);
+    let nibbles = digits
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8).ok_or(HexError::InvalidCharacter(c)))
+        .collect::<Result<Vec<u8>, _>>()?;
+    let padded = if nibbles.len() % 2 == 1 { [&[0][..], &nibbles].concat() } else { nibbles };
+    Ok(padded.chunks(2).map(|pair| (pair[0] << 4) | pair[1]).collect())
+}
+
+// Byte string serialized as 0x-prefixed hex in JSON
+#[derive(Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Bytes(pub Vec<u8>);
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bytes(bytes)
+    }
+}
+
+impl From<&[u8]> for Bytes {
+    fn from(bytes: &[u8]) -> Self {
+        Bytes(bytes.to_vec())
+    }
+}
+
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", encode(&self.0))
+    }
+}
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", encode(&self.0))
+    }
+}
+
+impl Serialize for Bytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        decode(&s).map(Bytes).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+use ethereum_types::{H160, U256};
+use crate::evm::{EvmError, LogEntry, EVM};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Call,
+    CallCode,
+    DelegateCall,
+    StaticCall,
+}
+
+// A call about to enter a new frame. `target` is the account called, `code_address` the one whose
+// code runs, which differs for CALLCODE, DELEGATECALL and EIP-7702 delegations. For the outermost
+// frame of a transaction `gas` is the transaction's gas limit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallInputs {
+    pub kind: CallKind,
+    pub caller: H160,
+    pub target: H160,
+    pub code_address: H160,
+    pub value: U256,
+    pub input: Vec<u8>,
+    pub gas: usize,
+    pub is_static: bool,
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CreateKind {
+    Create,
+    Create2,
+}
+
+// A contract creation about to run `init_code` as `address`. A creating transaction is reported
+// as a CREATE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreateInputs {
+    pub kind: CreateKind,
+    pub caller: H160,
+    pub address: H160,
+    pub value: U256,
+    pub init_code: Vec<u8>,
+    pub gas: usize,
+    pub depth: usize,
+}
+
+// How a call or creation ended. A revert is unsuccessful without an error, for a creation
+// `output` is the deployed code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallOutcome {
+    pub success: bool,
+    pub gas_left: usize,
+    pub output: Vec<u8>,
+    pub error: Option<EvmError>,
+}
+
+impl CallOutcome {
+    pub fn is_revert(&self) -> bool {
+        !self.success && self.error.is_none()
+    }
+}
+
+// Hooks into execution. `step` runs before each opcode and `step_end` after it, before the pc moves
+// on, both seeing the frame through the EVM's accessors. Every method does nothing by default
+pub trait Inspector {
+    fn step(&mut self, _evm: &EVM) {}
+
+    fn step_end(&mut self, _evm: &EVM) {}
+
+    fn call(&mut self, _inputs: &CallInputs) {}
+
+    fn call_end(&mut self, _inputs: &CallInputs, _outcome: &CallOutcome) {}
+
+    fn create(&mut self, _inputs: &CreateInputs) {}
+
+    fn create_end(&mut self, _inputs: &CreateInputs, _outcome: &CallOutcome) {}
+
+    fn log(&mut self, _evm: &EVM, _log: &LogEntry) {}
+
+    fn selfdestruct(&mut self, _address: H160, _target: H160, _value: U256) {}
+}
+
+impl fmt::Debug for dyn Inspector + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Inspector")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopInspector;
+
+impl Inspector for NoopInspector {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcodes::Opcode;
+    use crate::transaction::{create_address, inspect_transaction, BlockEnv, Transaction};
+    use crate::{Account, Precompiles, State};
+
+    // Records the hooks, steps only for the opcodes that trigger other hooks. The opcode of a step
+    // is kept for its step_end, a PUSH has moved the pc over its data by then
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+        steps: Vec<Opcode>,
+    }
+
+    impl Inspector for Recorder {
+        fn step(&mut self, evm: &EVM) {
+            let opcode = Opcode::from_u8(evm.code()[evm.pc()]);
+            if matches!(opcode, Opcode::LOG0 | Opcode::CREATE | Opcode::CALL | Opcode::SELFDESTRUCT) {
+                self.events.push(format!("step {:?} {}", opcode, evm.depth()));
+            }
+            self.steps.push(opcode);
+        }
+
+        fn step_end(&mut self, evm: &EVM) {
+            let opcode = self.steps.pop().unwrap();
+            if matches!(opcode, Opcode::LOG0 | Opcode::CREATE | Opcode::CALL | Opcode::SELFDESTRUCT) {
+                self.events.push(format!("step_end {:?} {}", opcode, evm.depth()));
+            }
+        }
+
+        fn call(&mut self, inputs: &CallInputs) {
+            self.events.push(format!("call {}", inputs.depth));
+        }
+
+        fn call_end(&mut self, inputs: &CallInputs, outcome: &CallOutcome) {
+            self.events.push(format!("call_end {} {}", inputs.depth, outcome.success));
+        }
+
+        fn create(&mut self, inputs: &CreateInputs) {
+            self.events.push(format!("create {}", inputs.depth));
+        }
+
+        fn create_end(&mut self, inputs: &CreateInputs, outcome: &CallOutcome) {
+            self.events.push(format!("create_end {} {:02x?}", inputs.depth, outcome.output));
+        }
+
+        fn log(&mut self, evm: &EVM, _log: &LogEntry) {
+            self.events.push(format!("log {}", evm.depth()));
+        }
+
+        fn selfdestruct(&mut self, address: H160, target: H160, _value: U256) {
+            self.events.push(format!("selfdestruct {}", address == target));
+        }
+    }
+
+    #[test]
+    fn hooks_nest_around_the_steps_that_trigger_them() {
+        // Logs, creates a contract that logs and deploys ADDRESS SELFDESTRUCT, then calls it
+        let init_code = "60006000a06130ff6000526002601ef3";
+        let factory = format!("60006000a06f{init_code}600052601060106000f0600060006000600060008562fffffff100");
+        let (sender, factory_address) = (H160([0x11; 20]), H160([0x22; 20]));
+        let mut state = State::new();
+        state.insert_account(sender, Account::new(U256::from(10).pow(U256::from(18))));
+        state.insert_account(factory_address, Account::default().with_code(crate::hex::decode(&factory).unwrap()));
+        let tx = Transaction {
+            caller: sender,
+            to: Some(factory_address),
+            gas_limit: 1_000_000,
+            gas_price: U256::one(),
+            ..Default::default()
+        };
+        let mut recorder = Recorder::default();
+        let receipt = inspect_transaction(&tx, &BlockEnv::default(), &mut state, &mut Precompiles::default(), &mut recorder).unwrap();
+        assert!(receipt.status);
+        assert_eq!(state.code(&create_address(factory_address, 0)), [0x30, 0xff]);
+
+        let expected = [
+            "call 0",
+            "step LOG0 0",
+            "log 0",
+            "step_end LOG0 0",
+            "step CREATE 0",
+            "create 1",
+            "step LOG0 1",
+            "log 1",
+            "step_end LOG0 1",
+            "create_end 1 [30, ff]",
+            "step_end CREATE 0",
+            "step CALL 0",
+            "call 1",
+            "step SELFDESTRUCT 1",
+            "selfdestruct true",
+            "step_end SELFDESTRUCT 1",
+            "call_end 1 true",
+            "step_end CALL 0",
+            "call_end 0 true",
+        ];
+        assert_eq!(recorder.events, expected);
+    }
+}
@@ -0,0 +1,61 @@
+pub mod stack;
+pub use stack::Stack;
+
+pub mod memory;
+pub use memory::Memory;
+
+pub mod storage;
+pub use storage::Storage;
+
+pub mod transient;
+pub use transient::Transient;
+
+pub mod state;
+pub use state::{Account, State, StateDiff};
+
+pub mod database;
+pub use database::{CacheDb, Database, FileDb, InMemoryDb, RemoteDb};
+
+pub mod evm;
+pub use evm::{LogEntry, EVM};
+
+pub mod inspector;
+pub use inspector::{CallInputs, CallKind, CallOutcome, CreateInputs, CreateKind, Inspector, NoopInspector};
+
+pub mod tracer;
+pub use tracer::{CallTracer, Eip3155Tracer, PrestateTracer, SourceTracer, TracePrinter, TraceSummary};
+
+pub mod debugger;
+pub use debugger::{Breakpoint, Debugger, Repl, Tui};
+
+pub mod spec;
+pub use spec::Spec;
+
+pub mod precompiles;
+pub use precompiles::Precompiles;
+
+pub mod transaction;
+pub use transaction::{execute_transaction, inspect_transaction, BlockEnv, BlockExecutor, SignedTransaction, Transaction};
+
+pub mod receipt;
+pub use receipt::Receipt;
+
+pub mod rlp;
+pub mod trie;
+
+pub mod proof;
+pub use proof::{AccountProof, StorageProof};
+
+pub mod hex;
+
+pub mod abi;
+pub use abi::Abi;
+
+pub mod sourcemap;
+pub use sourcemap::SourceMap;
+
+pub mod genesis;
+pub use genesis::Genesis;
+
+pub mod opcodes;
+pub mod helper;
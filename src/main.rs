@@ -1,42 +1,384 @@
-mod stack;
-use stack::Stack;
-
-mod memory;
-use memory::Memory;
-
-mod storage;
-use storage::Storage;
-
-mod transient;
-use transient::Transient;
-
-mod evm;
-use evm::EVM;
-use ethereum_types::H160;
-
-mod opcodes;
-mod helper;
-fn main() {
- 
-    println!("--------EVM--------");
-    let program = vec![
-    0x60, 0x01,       // PUSH1 0x01
-    0x60, 0x42,       // PUSH1 0x42
-    0x5D,             // TSTORE
-    0x60, 0x01,       // PUSH1 0x01
-    // 0x5C,             // TLOAD
-    0x00              // STOP
-];
-    let call_data = vec![];
-    let sender = H160::zero();  
-    let gas = 5000;  
-    let value = 0;   
-
-    // Create an instance of EVM
-    let mut evm = EVM::new(sender, gas, value, program, call_data);
-    println!("Initial EVM state: {:#?}", evm);
+use std::env;
+use std::fs;
+use std::panic;
+use std::io::{self, Write};
+use std::process::{Command, ExitCode, Stdio};
+use ethereum_types::{H160, U256};
+use rtevm::hex;
+use rtevm::tracer::{first_divergence, read_trace, render_divergence};
+use rtevm::debugger::Frontend;
+use rtevm::{Abi, Account, CallInputs, CallKind, CallOutcome, CallTracer, Debugger, Eip3155Tracer, Inspector, Repl, SourceMap, SourceTracer, State, StateDiff, TracePrinter, TraceSummary, Tui, EVM};
 
+const USAGE: &str = "usage:
+  rtevm run [options] <code hex | --code-file <path>>
+    --input <hex>        call data
+    --value <wei>        call value
+    --gas <gas>          gas limit, 10000000 by default
+    --trace              print an EIP-3155 trace to stdout
+    --trace-memory       include memory in the trace
+    --call-trace         print the call tree with gas, events and return values
+    --abi <path>         JSON ABI of the code, used to decode the call tree
+    --label <addr>=<name>  name an address in the call tree
+    --state-diff         print the accounts and storage slots that changed, to stderr with --trace
+    --state-diff-json    print them as JSON
+    --srcmap <path>      solc's srcmap-runtime for the code, to report reverts by Solidity line
+    --source <path>      a source file of the map, repeated in the order of solc's source list
+    --source-trace       print every Solidity line run
+  rtevm debug [--tui] [--input <hex>] [--value <wei>] [--gas <gas>] [--srcmap <path> --source <path>...]
+              <code hex | --code-file <path>>
+    runs the code under an interactive debugger, type help at its prompt
+    --tui                full-screen debugger instead, driven by single keys
+  rtevm trace-diff [--context <steps>] <a.jsonl> <b.jsonl>
+    finds the first step where two EIP-3155 traces differ in pc, opcode,
+    gas, stack or memory, exiting with 1 if they do";
+
+// Account the code runs as
+const CONTRACT: u64 = 0xc0de;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("run") => run(&args[1..]),
+        Some("trace-diff") => trace_diff(&args[1..]),
+        Some("debug") => debug(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(code) => code,
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+struct RunOptions {
+    code: Vec<u8>,
+    input: Vec<u8>,
+    value: U256,
+    gas: usize,
+    trace: bool,
+    trace_memory: bool,
+    call_trace: bool,
+    abi: Option<Abi>,
+    labels: Vec<(H160, String)>,
+    state_diff: Option<DiffFormat>,
+    source_map: Option<SourceMap>,
+    source_trace: bool,
+}
+
+enum DiffFormat {
+    Text,
+    Json,
+}
+
+fn parse_run_options(args: &[String]) -> Result<RunOptions, String> {
+    let mut options = RunOptions {
+        code: Vec::new(),
+        input: Vec::new(),
+        value: U256::zero(),
+        gas: 10_000_000,
+        trace: false,
+        trace_memory: false,
+        call_trace: false,
+        abi: None,
+        labels: Vec::new(),
+        state_diff: None,
+        source_map: None,
+        source_trace: false,
+    };
+    let mut code = None;
+    let mut srcmap = None;
+    let mut sources = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--input" => options.input = hex::decode(value()?).map_err(|e| e.to_string())?,
+            "--value" => options.value = U256::from_dec_str(value()?).map_err(|e| format!("invalid value: {:?}", e))?,
+            "--gas" => options.gas = value()?.parse().map_err(|e| format!("invalid gas: {}", e))?,
+            "--code-file" => code = Some(fs::read_to_string(value()?).map_err(|e| e.to_string())?.trim().to_string()),
+            "--trace" => options.trace = true,
+            "--trace-memory" => {
+                options.trace = true;
+                options.trace_memory = true;
+            }
+            "--call-trace" => options.call_trace = true,
+            "--state-diff" => options.state_diff = Some(DiffFormat::Text),
+            "--state-diff-json" => options.state_diff = Some(DiffFormat::Json),
+            "--srcmap" => srcmap = Some(fs::read_to_string(value()?).map_err(|e| e.to_string())?),
+            "--source" => {
+                let path = value()?;
+                sources.push((path.clone(), fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?));
+            }
+            "--source-trace" => options.source_trace = true,
+            "--abi" => {
+                let json = fs::read_to_string(value()?).map_err(|e| e.to_string())?;
+                options.abi = Some(Abi::from_json(&json).map_err(|e| e.to_string())?);
+            }
+            "--label" => {
+                let label = value()?;
+                let (address, name) = label.split_once('=').ok_or(format!("invalid label {}, expected <address>=<name>", label))?;
+                let address = hex::decode(address).map_err(|e| e.to_string())?;
+                if address.len() != 20 {
+                    return Err(format!("invalid address in label {}", label));
+                }
+                options.labels.push((H160::from_slice(&address), name.to_string()));
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => code = Some(arg.clone()),
+        }
+    }
+    // The trace modes print nothing else, so options for the other outputs would be ignored
+    let mode = if options.trace { Some("--trace") } else if options.call_trace { Some("--call-trace") } else { None };
+    let outputs = [("--call-trace", options.call_trace), ("--srcmap", srcmap.is_some()), ("--source-trace", options.source_trace)];
+    if let Some(mode) = mode {
+        if let Some((option, _)) = outputs.iter().find(|(option, set)| *set && *option != mode) {
+            return Err(format!("{} cannot be combined with {}", mode, option));
+        }
+    }
+    let code = code.ok_or(USAGE.to_string())?;
+    options.code = hex::decode(&code).map_err(|e| e.to_string())?;
+    if let Some(srcmap) = srcmap {
+        let mut map = SourceMap::new(&options.code, &srcmap).map_err(|e| e.to_string())?;
+        for (index, (path, content)) in sources.into_iter().enumerate() {
+            map = map.with_source(index, path, content);
+        }
+        options.source_map = Some(map);
+    }
+    Ok(options)
+}
+
+fn run(args: &[String]) -> Result<ExitCode, String> {
+    let options = parse_run_options(args)?;
+    let inputs = top_call(&options);
+    let state = contract_state(&options);
+    let evm = EVM::new(H160::zero(), options.gas, 0, options.code, options.input)
+        .with_state(state)
+        .with_address(H160::from_low_u64_be(CONTRACT))
+        .with_value(options.value);
+
+    if options.trace {
+        let mut tracer = Eip3155Tracer::new(io::stdout().lock()).with_memory(options.trace_memory);
+        let mut evm = evm.with_inspector(&mut tracer);
+        evm.run();
+        let summary = TraceSummary::new(&evm, options.gas);
+        let diff = evm.state().diff(0);
+        drop(evm);
+        tracer.write_summary(&summary).map_err(|e| e.to_string())?;
+        // stdout is kept to the JSON lines of the trace
+        print_state_diff(options.state_diff.as_ref(), &diff, &mut io::stderr())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if options.call_trace {
+        let mut tracer = CallTracer::new().with_logs(true);
+        tracer.call(&inputs);
+        let mut evm = evm.with_inspector(&mut tracer);
+        evm.run();
+        let outcome = outcome(&evm);
+        let diff = evm.state().diff(0);
+        drop(evm);
+        tracer.call_end(&inputs, &outcome);
+
+        let mut printer = TracePrinter::new();
+        if let Some(abi) = options.abi {
+            printer = printer.with_abi(H160::from_low_u64_be(CONTRACT), abi);
+        }
+        for (address, name) in options.labels {
+            printer = printer.with_label(address, name);
+        }
+        if let Some(frame) = tracer.frame() {
+            print!("{}", printer.render(frame));
+        }
+        print_state_diff(options.state_diff.as_ref(), &diff, &mut io::stdout())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut tracer = SourceTracer::new();
+    if let Some(map) = options.source_map.clone() {
+        tracer = tracer.with_source_map(map);
+    }
+    tracer.call(&inputs);
+    let mut evm = evm.with_inspector(&mut tracer);
+    evm.run();
+    let summary = TraceSummary::new(&evm, options.gas);
+    let outcome = outcome(&evm);
+    let diff = evm.state().diff(0);
+    drop(evm);
+    tracer.call_end(&inputs, &outcome);
+
+    if options.source_trace {
+        print!("{}", tracer.render());
+    }
+    println!("output: {}", summary.output);
+    println!("gas used: {}", summary.gas_used);
+    if let Some(error) = summary.error {
+        println!("error: {}", error);
+    }
+    if options.source_map.is_some() {
+        print!("{}", tracer.report());
+    }
+    print_state_diff(options.state_diff.as_ref(), &diff, &mut io::stdout())?;
+    Ok(ExitCode::SUCCESS)
+}
+
+// The EVM runs a single frame, so inspectors are told about the outermost call by the caller
+fn top_call(options: &RunOptions) -> CallInputs {
+    CallInputs {
+        kind: CallKind::Call,
+        caller: H160::zero(),
+        target: H160::from_low_u64_be(CONTRACT),
+        code_address: H160::from_low_u64_be(CONTRACT),
+        value: options.value,
+        input: options.input.clone(),
+        gas: options.gas,
+        is_static: false,
+        depth: 0,
+    }
+}
+
+// The contract exists with its code, holding the call value as if the caller had sent it. It
+// and the caller start warm, as a transaction's sender and target do
+fn contract_state(options: &RunOptions) -> State {
+    let contract = H160::from_low_u64_be(CONTRACT);
+    let mut state = State::new();
+    state.insert_account(contract, Account::new(options.value).with_code(options.code.clone()));
+    state.warm_address(contract);
+    state.warm_address(H160::zero());
+    state.clear_journal();
+    state
+}
+
+fn outcome(evm: &EVM) -> CallOutcome {
+    CallOutcome {
+        success: evm.is_success(),
+        gas_left: evm.gas_left(),
+        output: evm.output().to_vec(),
+        error: evm.error().cloned(),
+    }
+}
+
+fn debug(args: &[String]) -> Result<ExitCode, String> {
+    let tui = args.iter().any(|arg| arg == "--tui");
+    let args: Vec<String> = args.iter().filter(|arg| *arg != "--tui").cloned().collect();
+    let options = parse_run_options(&args)?;
+    if !tui {
+        run_debugger(options, Debugger::new(Repl::new(io::stdin().lock(), io::stdout())));
+        return Ok(ExitCode::SUCCESS);
+    }
+    let (saved, rows, columns) = raw_terminal()?;
+    let _terminal = TerminalGuard::new(saved);
+    run_debugger(options, Debugger::new(Tui::new(io::stdin().lock(), io::stdout()).with_size(rows, columns)));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_debugger<F: Frontend>(options: RunOptions, mut debugger: Debugger<F>) {
+    if let Some(map) = options.source_map.clone() {
+        debugger = debugger.with_source_map(map);
+    }
+    let inputs = top_call(&options);
+    debugger.call(&inputs);
+    let state = contract_state(&options);
+    let mut evm = EVM::new(H160::zero(), options.gas, 0, options.code, options.input)
+        .with_state(state)
+        .with_address(H160::from_low_u64_be(CONTRACT))
+        .with_value(options.value)
+        .with_inspector(&mut debugger);
     evm.run();
+    let outcome = outcome(&evm);
+    drop(evm);
+    debugger.call_end(&inputs, &outcome);
+}
+
+// Runs stty on the terminal behind stdin
+fn stty(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output().map_err(|e| format!("stty: {}", e))?;
+    if !output.status.success() {
+        return Err("--tui needs a terminal".to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Switches the terminal to raw mode, returning its previous settings and its rows and columns
+fn raw_terminal() -> Result<(String, usize, usize), String> {
+    let saved = stty(&["-g"])?;
+    let size = stty(&["size"])?;
+    let (rows, columns) = size.split_once(' ').ok_or(format!("unexpected terminal size {}", size))?;
+    let rows = rows.parse().map_err(|e| format!("invalid terminal size: {}", e))?;
+    let columns = columns.parse().map_err(|e| format!("invalid terminal size: {}", e))?;
+    stty(&["raw", "-echo"])?;
+    Ok((saved, rows, columns))
+}
+
+// Puts the terminal back as `raw_terminal` found it when dropped, including while a panic unwinds
+struct TerminalGuard {
+    saved: String,
+}
+
+impl TerminalGuard {
+    fn new(saved: String) -> Self {
+        // A panic message printed on the alternate screen would vanish with it, so restore first
+        let hook = panic::take_hook();
+        let settings = saved.clone();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal(&settings);
+            hook(info);
+        }));
+        TerminalGuard { saved }
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal(&self.saved);
+    }
+}
+
+// Leaves the alternate screen with the cursor shown, and restores the stty settings
+fn restore_terminal(saved: &str) {
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(b"\x1b[?25h\x1b[?1049l").and_then(|_| stdout.flush());
+    let _ = stty(&[saved]);
+}
+
+fn print_state_diff(format: Option<&DiffFormat>, diff: &StateDiff, out: &mut dyn Write) -> Result<(), String> {
+    let result = match format {
+        Some(DiffFormat::Text) if diff.is_empty() => writeln!(out, "state diff: no changes"),
+        Some(DiffFormat::Text) => write!(out, "state diff:\n{}", diff),
+        Some(DiffFormat::Json) => writeln!(out, "{}", serde_json::to_string_pretty(diff).map_err(|e| e.to_string())?),
+        None => Ok(()),
+    };
+    result.map_err(|e| e.to_string())
+}
 
-    println!("Final EVM state: {:#?}", evm);
-}
\ No newline at end of file
+fn trace_diff(args: &[String]) -> Result<ExitCode, String> {
+    let mut context = 5;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => {
+                let value = args.next().ok_or("missing value for --context")?;
+                context = value.parse().map_err(|e| format!("invalid context: {}", e))?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => paths.push(arg),
+        }
+    }
+    let [path_a, path_b] = paths[..] else {
+        return Err(USAGE.to_string());
+    };
+    let a = read_trace(path_a).map_err(|e| format!("{}: {}", path_a, e))?;
+    let b = read_trace(path_b).map_err(|e| format!("{}: {}", path_b, e))?;
+    match first_divergence(&a, &b) {
+        Some(divergence) => {
+            print!("{}", render_divergence(&a, &b, &divergence, context));
+            Ok(ExitCode::FAILURE)
+        }
+        None => {
+            println!("traces match ({} steps)", a.len());
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use thiserror::Error;
 use ethereum_types::U256;
+use crate::helper::Helper;
 
 #[derive(Error, Debug)]
 pub enum MemoryError {
@@ -9,7 +10,7 @@ pub enum MemoryError {
 
 #[derive(Debug)]
 pub struct Memory {
-    data: Vec<U256>, // 32 bytes
+    data: Vec<u8>, // byte addressed, grows in 32-byte words
 }
 
 impl Memory {
@@ -17,9 +18,35 @@ impl Memory {
         Memory { data: Vec::new() }
     }
 
+    // Gas to grow memory over offset..offset + size, with the operands as they come off the
+    // stack. None if the range is too large for any amount of gas to pay for
+    pub fn expansion_cost(&self, offset: U256, size: U256) -> Option<usize> {
+        if size.is_zero() {
+            return Some(0);
+        }
+        let end = offset.checked_add(size).filter(|end| end.bits() <= 64)?.low_u64();
+        let cost = |words: u128| 3 * words + words * words / 512;
+        let words = end.div_ceil(32) as u128;
+        let current = (self.len() / 32) as u128;
+        usize::try_from(cost(words).saturating_sub(cost(current))).ok()
+    }
+
+    // Grows memory to cover offset..offset + size. The growth has to be paid for first, see
+    // `expansion_cost`
+    pub fn expand(&mut self, offset: usize, size: usize) {
+        if size == 0 {
+            return;
+        }
+        let new_len = Helper::to_word_size(offset + size) * 32;
+        if new_len > self.len() {
+            self.data.resize(new_len, 0);
+        }
+    }
 
-    // Updated for read
-    pub fn access(&self, offset: usize, size: usize) -> Result<&[U256], MemoryError> {
+    pub fn access(&self, offset: usize, size: usize) -> Result<&[u8], MemoryError> {
+        if size == 0 {
+            return Ok(&[]);
+        }
         if self.len() < offset + size {
             return Err(MemoryError::ErrOutOfBounds);
         }
@@ -27,37 +54,16 @@ impl Memory {
         Ok(&self.data[offset..offset + size])
     }
 
-    pub fn load(&mut self, offset: usize) -> Result<&[U256], MemoryError> {
-        self.access(offset, 1)
+    pub fn load(&mut self, offset: usize) -> Result<&[u8], MemoryError> {
+        self.access(offset, 32)
     }
 
-    pub fn store(&mut self, offset: usize, value: &[U256]) -> Result<usize, MemoryError> {
-        let expansion_cost;
-        if self.len() <= offset + value.len() {
-            let mut expansion_size = 0;
-
-            if self.len() == 0 {
-                expansion_size = 32;
-                self.data = vec![U256::zero(); 32]; //initialize memory with 32 zeros if it is empty
-            }
-
-            if self.len() < offset + value.len() { // extend more memory if needed
-                expansion_size = offset + value.len() - self.len();
-                let mut n_mem = vec![U256::zero(); expansion_size];
-                n_mem[..self.len()].copy_from_slice(&self.data);
-                self.data = n_mem;
-                expansion_cost = (expansion_size.pow(2) as f64).sqrt() as usize;
-            } else {
-                expansion_cost = 0;
-            }
-        } else {
-            expansion_cost = 0;
-        }
+    pub fn store(&mut self, offset: usize, value: &[u8]) {
+        self.expand(offset, value.len());
         self.data[offset..offset + value.len()].copy_from_slice(value);
-        Ok(expansion_cost)
     }
 
-    pub fn data(&self) -> &[U256] {
+    pub fn data(&self) -> &[u8] {
         &self.data
     }
 
@@ -65,8 +71,48 @@ impl Memory {
         self.data.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
     pub fn cap(&self) -> usize {
         self.data.capacity()
     }
 
-}
\ No newline at end of file
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expansion_cost_is_charged_per_word_and_only_for_growth() {
+        let mut memory = Memory::new();
+        assert_eq!(memory.expansion_cost(U256::zero(), U256::zero()), Some(0));
+        assert_eq!(memory.expansion_cost(U256::zero(), U256::from(32)), Some(3));
+        // 1024 words: 3 * 1024 + 1024^2 / 512
+        assert_eq!(memory.expansion_cost(U256::zero(), U256::from(32 * 1024)), Some(5120));
+
+        memory.expand(0, 33);
+        assert_eq!(memory.len(), 64);
+        assert_eq!(memory.expansion_cost(U256::from(10), U256::from(20)), Some(0));
+        assert_eq!(memory.expansion_cost(U256::from(64), U256::one()), Some(3));
+    }
+
+    #[test]
+    fn expansion_cost_rejects_ranges_no_gas_can_pay_for() {
+        let memory = Memory::new();
+        assert_eq!(memory.expansion_cost(U256::MAX, U256::one()), None);
+        assert_eq!(memory.expansion_cost(U256::from(u64::MAX), U256::from(32)), None);
+        // Fits in u64 but costs more than any usize
+        assert_eq!(memory.expansion_cost(U256::from(u64::MAX - 64), U256::from(32)), None);
+        // An empty range costs nothing wherever it is
+        assert_eq!(memory.expansion_cost(U256::MAX, U256::zero()), Some(0));
+    }
+}
@@ -1,3 +1,4 @@
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Opcode {
     // Stop
@@ -65,6 +66,8 @@ pub enum Opcode {
     CHAINID = 0x46,
     SELFBALANCE = 0x47,
     BASEFEE = 0x48,
+    BLOBHASH = 0x49,
+    BLOBBASEFEE = 0x4A,
 
     // Stack Pop
     POP = 0x50,
@@ -82,13 +85,17 @@ pub enum Opcode {
     JUMP = 0x56,
     JUMPI = 0x57,
     PC = 0x58,
+    MSIZE = 0x59,
+    GAS = 0x5A,
     JUMPDEST = 0x5B,
 
     // Transient Storage
     TLOAD = 0x5C,
     TSTORE = 0x5D,
+    MCOPY = 0x5E,
 
     // Push
+    PUSH0 = 0x5F,
     PUSH1 = 0x60,
     PUSH2 = 0x61,
     PUSH3 = 0x62,
@@ -179,141 +186,200 @@ pub enum Opcode {
 }
 
 impl Opcode {
+    // Opcodes the interpreter does not know execute as INVALID
     pub fn from_u8(value: u8) -> Opcode {
+        Self::try_from_u8(value).unwrap_or(Opcode::INVALID)
+    }
+
+    // Items the opcode takes from the stack and items it leaves in their place
+    pub fn stack_io(&self) -> (usize, usize) {
+        use Opcode::*;
+        let byte = *self as u8 as usize;
+        match self {
+            _ if (0x5F..=0x7F).contains(&byte) => (0, 1), // PUSH0-PUSH32
+            _ if (0x80..=0x8F).contains(&byte) => (byte - 0x7F, byte - 0x7F + 1), // DUP1-DUP16
+            _ if (0x90..=0x9F).contains(&byte) => (byte - 0x8F + 1, byte - 0x8F + 1), // SWAP1-SWAP16
+            _ if (0xA0..=0xA4).contains(&byte) => (byte - 0xA0 + 2, 0), // LOG0-LOG4
+            STOP | JUMPDEST | INVALID => (0, 0),
+            ADD | MUL | SUB | DIV | SDIV | MOD | SMOD | EXP | SIGNEXTEND | LT | GT | SLT | SGT | EQ | AND | OR | XOR
+            | BYTE | SHL | SHR | SAR | KECCAK256 => (2, 1),
+            ADDMOD | MULMOD => (3, 1),
+            ISZERO | NOT | BALANCE | CALLDATALOAD | EXTCODESIZE | EXTCODEHASH | BLOCKHASH | BLOBHASH | MLOAD | SLOAD
+            | TLOAD => (1, 1),
+            ADDRESS | ORIGIN | CALLER | CALLVALUE | CALLDATASIZE | CODESIZE | GASPRICE | RETURNDATASIZE | COINBASE
+            | TIMESTAMP | NUMBER | DIFFICULTY | GASLIMIT | CHAINID | SELFBALANCE | BASEFEE | BLOBBASEFEE | PC | MSIZE
+            | GAS => (0, 1),
+            POP | JUMP | SELFDESTRUCT => (1, 0),
+            MSTORE | MSTORE8 | SSTORE | TSTORE | JUMPI | RETURN | REVERT => (2, 0),
+            CALLDATACOPY | CODECOPY | RETURNDATACOPY | MCOPY => (3, 0),
+            EXTCODECOPY => (4, 0),
+            CREATE => (3, 1),
+            CREATE2 => (4, 1),
+            CALL | CALLCODE => (7, 1),
+            DELEGATECALL | STATICCALL => (6, 1),
+            _ => unreachable!("every opcode is covered above"),
+        }
+    }
+
+    pub fn try_from_u8(value: u8) -> Option<Opcode> {
         match value {
-            0x00 => Opcode::STOP,
-            0x01 => Opcode::ADD,
-            0x02 => Opcode::MUL,
-            0x03 => Opcode::SUB,
-            0x04 => Opcode::DIV,
-            0x05 => Opcode::SDIV,
-            0x06 => Opcode::MOD,
-            0x07 => Opcode::SMOD,
-            0x08 => Opcode::ADDMOD,
-            0x09 => Opcode::MULMOD,
-            0x0A => Opcode::EXP,
-            0x0B => Opcode::SIGNEXTEND,
-            0x10 => Opcode::LT,
-            0x11 => Opcode::GT,
-            0x12 => Opcode::SLT,
-            0x13 => Opcode::SGT,
-            0x14 => Opcode::EQ,
-            0x15 => Opcode::ISZERO,
-            0x16 => Opcode::AND,
-            0x17 => Opcode::OR,
-            0x18 => Opcode::XOR,
-            0x19 => Opcode::NOT,
-            0x1A => Opcode::BYTE,
-            0x1B => Opcode::SHL,
-            0x1C => Opcode::SHR,
-            0x1D => Opcode::SAR,
-            0x20 => Opcode::KECCAK256,
-            0xA0 => Opcode::LOG0,
-            0xA1 => Opcode::LOG1,
-            0xA2 => Opcode::LOG2,
-            0xA3 => Opcode::LOG3,
-            0xA4 => Opcode::LOG4,
+            0x00 => Some(Opcode::STOP),
+            0x01 => Some(Opcode::ADD),
+            0x02 => Some(Opcode::MUL),
+            0x03 => Some(Opcode::SUB),
+            0x04 => Some(Opcode::DIV),
+            0x05 => Some(Opcode::SDIV),
+            0x06 => Some(Opcode::MOD),
+            0x07 => Some(Opcode::SMOD),
+            0x08 => Some(Opcode::ADDMOD),
+            0x09 => Some(Opcode::MULMOD),
+            0x0A => Some(Opcode::EXP),
+            0x0B => Some(Opcode::SIGNEXTEND),
+            0x10 => Some(Opcode::LT),
+            0x11 => Some(Opcode::GT),
+            0x12 => Some(Opcode::SLT),
+            0x13 => Some(Opcode::SGT),
+            0x14 => Some(Opcode::EQ),
+            0x15 => Some(Opcode::ISZERO),
+            0x16 => Some(Opcode::AND),
+            0x17 => Some(Opcode::OR),
+            0x18 => Some(Opcode::XOR),
+            0x19 => Some(Opcode::NOT),
+            0x1A => Some(Opcode::BYTE),
+            0x1B => Some(Opcode::SHL),
+            0x1C => Some(Opcode::SHR),
+            0x1D => Some(Opcode::SAR),
+            0x20 => Some(Opcode::KECCAK256),
+            0xA0 => Some(Opcode::LOG0),
+            0xA1 => Some(Opcode::LOG1),
+            0xA2 => Some(Opcode::LOG2),
+            0xA3 => Some(Opcode::LOG3),
+            0xA4 => Some(Opcode::LOG4),
 
-            0x30 => Opcode::ADDRESS,
-            0x31 => Opcode::BALANCE,
-            0x32 => Opcode::ORIGIN,
-            0x33 => Opcode::CALLER,
-            0x34 => Opcode::CALLVALUE,
-            0x35 => Opcode::CALLDATALOAD,
-            0x36 => Opcode::CALLDATASIZE,
-            0x37 => Opcode::CALLDATACOPY,
-            0x38 => Opcode::CODESIZE,
-            0x39 => Opcode::CODECOPY,
-            0x3A => Opcode::GASPRICE,
-            0x3B => Opcode::EXTCODESIZE,
-            0x3C => Opcode::EXTCODECOPY,
-            0x3D => Opcode::RETURNDATASIZE,
-            0x3E => Opcode::RETURNDATACOPY,
+            0x30 => Some(Opcode::ADDRESS),
+            0x31 => Some(Opcode::BALANCE),
+            0x32 => Some(Opcode::ORIGIN),
+            0x33 => Some(Opcode::CALLER),
+            0x34 => Some(Opcode::CALLVALUE),
+            0x35 => Some(Opcode::CALLDATALOAD),
+            0x36 => Some(Opcode::CALLDATASIZE),
+            0x37 => Some(Opcode::CALLDATACOPY),
+            0x38 => Some(Opcode::CODESIZE),
+            0x39 => Some(Opcode::CODECOPY),
+            0x3A => Some(Opcode::GASPRICE),
+            0x3B => Some(Opcode::EXTCODESIZE),
+            0x3C => Some(Opcode::EXTCODECOPY),
+            0x3D => Some(Opcode::RETURNDATASIZE),
+            0x3E => Some(Opcode::RETURNDATACOPY),
+            0x3F => Some(Opcode::EXTCODEHASH),
+            0x40 => Some(Opcode::BLOCKHASH),
+            0x41 => Some(Opcode::COINBASE),
+            0x42 => Some(Opcode::TIMESTAMP),
+            0x43 => Some(Opcode::NUMBER),
+            0x44 => Some(Opcode::DIFFICULTY),
+            0x45 => Some(Opcode::GASLIMIT),
+            0x46 => Some(Opcode::CHAINID),
+            0x47 => Some(Opcode::SELFBALANCE),
+            0x48 => Some(Opcode::BASEFEE),
+            0x49 => Some(Opcode::BLOBHASH),
+            0x4A => Some(Opcode::BLOBBASEFEE),
 
-            0x50 => Opcode::POP,
-            0x51 => Opcode::MLOAD,
-            0x52 => Opcode::MSTORE,
-            0x53 => Opcode::MSTORE8,
-            0x54 => Opcode::SLOAD,
-            0x55 => Opcode::SSTORE,
-            0x5C => Opcode::TLOAD,
-            0x5D => Opcode::TSTORE,
-            0x60 => Opcode::PUSH1,
-            0x61 => Opcode::PUSH2,
-            0x62 => Opcode::PUSH3,
-            0x63 => Opcode::PUSH4,
-            0x64 => Opcode::PUSH5,
-            0x65 => Opcode::PUSH6,
-            0x66 => Opcode::PUSH7,
-            0x67 => Opcode::PUSH8,
-            0x68 => Opcode::PUSH9,
-            0x69 => Opcode::PUSH10,
-            0x6A => Opcode::PUSH11,
-            0x6B => Opcode::PUSH12,
-            0x6C => Opcode::PUSH13,
-            0x6D => Opcode::PUSH14,
-            0x6E => Opcode::PUSH15,
-            0x6F => Opcode::PUSH16,
-            0x70 => Opcode::PUSH17,
-            0x71 => Opcode::PUSH18,
-            0x72 => Opcode::PUSH19,
-            0x73 => Opcode::PUSH20,
-            0x74 => Opcode::PUSH21,
-            0x75 => Opcode::PUSH22,
-            0x76 => Opcode::PUSH23,
-            0x77 => Opcode::PUSH24,
-            0x78 => Opcode::PUSH25,
-            0x79 => Opcode::PUSH26,
-            0x7A => Opcode::PUSH27,
-            0x7B => Opcode::PUSH28,
-            0x7C => Opcode::PUSH29,
-            0x7D => Opcode::PUSH30,
-            0x7E => Opcode::PUSH31,
-            0x7F => Opcode::PUSH32,
+            0x50 => Some(Opcode::POP),
+            0x51 => Some(Opcode::MLOAD),
+            0x52 => Some(Opcode::MSTORE),
+            0x53 => Some(Opcode::MSTORE8),
+            0x54 => Some(Opcode::SLOAD),
+            0x55 => Some(Opcode::SSTORE),
+            0x5C => Some(Opcode::TLOAD),
+            0x5D => Some(Opcode::TSTORE),
+            0x5E => Some(Opcode::MCOPY),
+            0x5F => Some(Opcode::PUSH0),
+            0x60 => Some(Opcode::PUSH1),
+            0x61 => Some(Opcode::PUSH2),
+            0x62 => Some(Opcode::PUSH3),
+            0x63 => Some(Opcode::PUSH4),
+            0x64 => Some(Opcode::PUSH5),
+            0x65 => Some(Opcode::PUSH6),
+            0x66 => Some(Opcode::PUSH7),
+            0x67 => Some(Opcode::PUSH8),
+            0x68 => Some(Opcode::PUSH9),
+            0x69 => Some(Opcode::PUSH10),
+            0x6A => Some(Opcode::PUSH11),
+            0x6B => Some(Opcode::PUSH12),
+            0x6C => Some(Opcode::PUSH13),
+            0x6D => Some(Opcode::PUSH14),
+            0x6E => Some(Opcode::PUSH15),
+            0x6F => Some(Opcode::PUSH16),
+            0x70 => Some(Opcode::PUSH17),
+            0x71 => Some(Opcode::PUSH18),
+            0x72 => Some(Opcode::PUSH19),
+            0x73 => Some(Opcode::PUSH20),
+            0x74 => Some(Opcode::PUSH21),
+            0x75 => Some(Opcode::PUSH22),
+            0x76 => Some(Opcode::PUSH23),
+            0x77 => Some(Opcode::PUSH24),
+            0x78 => Some(Opcode::PUSH25),
+            0x79 => Some(Opcode::PUSH26),
+            0x7A => Some(Opcode::PUSH27),
+            0x7B => Some(Opcode::PUSH28),
+            0x7C => Some(Opcode::PUSH29),
+            0x7D => Some(Opcode::PUSH30),
+            0x7E => Some(Opcode::PUSH31),
+            0x7F => Some(Opcode::PUSH32),
 
-            0x80 => Opcode::DUP1,
-            0x81 => Opcode::DUP2,
-            0x82 => Opcode::DUP3,
-            0x83 => Opcode::DUP4,
-            0x84 => Opcode::DUP5,
-            0x85 => Opcode::DUP6,
-            0x86 => Opcode::DUP7,
-            0x87 => Opcode::DUP8,
-            0x88 => Opcode::DUP9,
-            0x89 => Opcode::DUP10,
-            0x8A => Opcode::DUP11,
-            0x8B => Opcode::DUP12,
-            0x8C => Opcode::DUP13,
-            0x8D => Opcode::DUP14,
-            0x8E => Opcode::DUP15,
-            0x8F => Opcode::DUP16,
+            0x80 => Some(Opcode::DUP1),
+            0x81 => Some(Opcode::DUP2),
+            0x82 => Some(Opcode::DUP3),
+            0x83 => Some(Opcode::DUP4),
+            0x84 => Some(Opcode::DUP5),
+            0x85 => Some(Opcode::DUP6),
+            0x86 => Some(Opcode::DUP7),
+            0x87 => Some(Opcode::DUP8),
+            0x88 => Some(Opcode::DUP9),
+            0x89 => Some(Opcode::DUP10),
+            0x8A => Some(Opcode::DUP11),
+            0x8B => Some(Opcode::DUP12),
+            0x8C => Some(Opcode::DUP13),
+            0x8D => Some(Opcode::DUP14),
+            0x8E => Some(Opcode::DUP15),
+            0x8F => Some(Opcode::DUP16),
 
-            0x90 => Opcode::SWAP1,
-            0x91 => Opcode::SWAP2,
-            0x92 => Opcode::SWAP3,
-            0x93 => Opcode::SWAP4,
-            0x94 => Opcode::SWAP5,
-            0x95 => Opcode::SWAP6,
-            0x96 => Opcode::SWAP7,
-            0x97 => Opcode::SWAP8,
-            0x98 => Opcode::SWAP9,
-            0x99 => Opcode::SWAP10,
-            0x9A => Opcode::SWAP11,
-            0x9B => Opcode::SWAP12,
-            0x9C => Opcode::SWAP13,
-            0x9D => Opcode::SWAP14,
-            0x9E => Opcode::SWAP15,
-            0x9F => Opcode::SWAP16,
-            0x56 => Opcode::JUMP,
-            0x57 => Opcode::JUMPI,
-            0x58 => Opcode::PC,
-            0x5B => Opcode::JUMPDEST,
+            0x90 => Some(Opcode::SWAP1),
+            0x91 => Some(Opcode::SWAP2),
+            0x92 => Some(Opcode::SWAP3),
+            0x93 => Some(Opcode::SWAP4),
+            0x94 => Some(Opcode::SWAP5),
+            0x95 => Some(Opcode::SWAP6),
+            0x96 => Some(Opcode::SWAP7),
+            0x97 => Some(Opcode::SWAP8),
+            0x98 => Some(Opcode::SWAP9),
+            0x99 => Some(Opcode::SWAP10),
+            0x9A => Some(Opcode::SWAP11),
+            0x9B => Some(Opcode::SWAP12),
+            0x9C => Some(Opcode::SWAP13),
+            0x9D => Some(Opcode::SWAP14),
+            0x9E => Some(Opcode::SWAP15),
+            0x9F => Some(Opcode::SWAP16),
+            0x56 => Some(Opcode::JUMP),
+            0x57 => Some(Opcode::JUMPI),
+            0x58 => Some(Opcode::PC),
+            0x59 => Some(Opcode::MSIZE),
+            0x5A => Some(Opcode::GAS),
+            0x5B => Some(Opcode::JUMPDEST),
 
-            0xF3 => Opcode::RETURN,
-            0xFD => Opcode::REVERT,
+            0xF0 => Some(Opcode::CREATE),
+            0xF1 => Some(Opcode::CALL),
+            0xF2 => Some(Opcode::CALLCODE),
+            0xF3 => Some(Opcode::RETURN),
+            0xF4 => Some(Opcode::DELEGATECALL),
+            0xF5 => Some(Opcode::CREATE2),
+            0xFA => Some(Opcode::STATICCALL),
+            0xFD => Some(Opcode::REVERT),
+            0xFE => Some(Opcode::INVALID),
+            0xFF => Some(Opcode::SELFDESTRUCT),
 
     
-            _ => Opcode::INVALID // temporarily
+            _ => None
         }
     }
 }
\ No newline at end of file
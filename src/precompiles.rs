@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fmt;
+use ethereum_types::{H160, U256};
+use thiserror::Error;
+use crate::helper::Helper;
+use crate::spec::Spec;
+use crate::{State, Transient};
+
+mod blake2f;
+mod bls12_381;
+mod bn254;
+mod ecrecover;
+mod hash;
+mod identity;
+mod kzg;
+mod modexp;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PrecompileError {
+    #[error("Out of gas")]
+    OutOfGas,
+    #[error("Invalid input length")]
+    InvalidInputLength,
+    #[error("Invalid field element")]
+    InvalidFieldElement,
+    #[error("Field element is not correctly padded")]
+    InvalidPadding,
+    #[error("Point is not on the curve")]
+    InvalidPoint,
+    #[error("Point is not in the correct subgroup")]
+    NotInSubgroup,
+    #[error("Invalid final block flag")]
+    InvalidFinalFlag,
+    #[error("Versioned hash does not match the commitment")]
+    InvalidVersionedHash,
+    #[error("Invalid KZG proof")]
+    InvalidKzgProof,
+    #[error("State modification in a static call")]
+    StateChangeDuringStaticCall,
+    #[error("{0}")]
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrecompileOutput {
+    pub gas_used: usize,
+    pub output: Vec<u8>,
+}
+
+impl PrecompileOutput {
+    pub fn new(gas_used: usize, output: Vec<u8>) -> Self {
+        PrecompileOutput { gas_used, output }
+    }
+}
+
+pub type PrecompileResult = Result<PrecompileOutput, PrecompileError>;
+
+// Takes the call input and the gas available to the call
+pub type PrecompileFn = fn(&[u8], usize) -> PrecompileResult;
+
+// What a precompile can see of the call that invoked it and of the world state
+pub struct PrecompileContext<'a> {
+    pub caller: H160,
+    pub value: U256,
+    pub is_static: bool,
+    pub state: &'a mut State,
+    pub transient: &'a mut Transient,
+}
+
+// Native code at a fixed address. Stateless precompiles can ignore the context, plain
+// functions and closures taking (input, gas_limit) implement this trait directly
+pub trait Precompile {
+    fn run(&self, input: &[u8], gas_limit: usize, context: &mut PrecompileContext) -> PrecompileResult;
+}
+
+impl<F> Precompile for F
+where
+    F: Fn(&[u8], usize) -> PrecompileResult,
+{
+    fn run(&self, input: &[u8], gas_limit: usize, _context: &mut PrecompileContext) -> PrecompileResult {
+        self(input, gas_limit)
+    }
+}
+
+pub struct Precompiles {
+    spec: Spec,
+    fns: HashMap<H160, PrecompileFn>,
+    custom: HashMap<H160, Box<dyn Precompile>>,
+}
+
+impl Precompiles {
+    // Builds the set of precompiles active under `spec`, priced for that fork
+    pub fn new(spec: Spec) -> Self {
+        let mut precompiles = Precompiles {
+            spec,
+            fns: HashMap::new(),
+            custom: HashMap::new(),
+        };
+
+        precompiles.insert(1, ecrecover::ecrecover);
+        precompiles.insert(2, hash::sha256);
+        precompiles.insert(3, hash::ripemd160);
+        precompiles.insert(4, identity::identity);
+
+        if spec.is_enabled(Spec::Byzantium) {
+            if spec.is_enabled(Spec::Berlin) {
+                precompiles.insert(5, modexp::berlin);
+            } else {
+                precompiles.insert(5, modexp::byzantium);
+            }
+            if spec.is_enabled(Spec::Istanbul) {
+                precompiles.insert(6, bn254::add_istanbul);
+                precompiles.insert(7, bn254::mul_istanbul);
+                precompiles.insert(8, bn254::pairing_istanbul);
+            } else {
+                precompiles.insert(6, bn254::add_byzantium);
+                precompiles.insert(7, bn254::mul_byzantium);
+                precompiles.insert(8, bn254::pairing_byzantium);
+            }
+        }
+
+        if spec.is_enabled(Spec::Istanbul) {
+            precompiles.insert(9, blake2f::blake2f);
+        }
+
+        if spec.is_enabled(Spec::Cancun) {
+            precompiles.insert(10, kzg::point_evaluation);
+        }
+
+        // EIP-2537
+        if spec.is_enabled(Spec::Prague) {
+            precompiles.insert(11, bls12_381::g1_add);
+            precompiles.insert(12, bls12_381::g1_msm);
+            precompiles.insert(13, bls12_381::g2_add);
+            precompiles.insert(14, bls12_381::g2_msm);
+            precompiles.insert(15, bls12_381::pairing);
+            precompiles.insert(16, bls12_381::map_fp_to_g1);
+            precompiles.insert(17, bls12_381::map_fp2_to_g2);
+        }
+
+        precompiles
+    }
+
+    pub fn spec(&self) -> Spec {
+        self.spec
+    }
+
+    // Switches the built-in set to `spec`, keeping registered precompiles
+    pub fn set_spec(&mut self, spec: Spec) {
+        if spec == self.spec {
+            return;
+        }
+        let custom = std::mem::take(&mut self.custom);
+        *self = Precompiles::new(spec);
+        self.custom = custom;
+    }
+
+    // Registers a precompile at any address. It takes precedence over a built-in at the same address
+    pub fn register(&mut self, address: H160, precompile: impl Precompile + 'static) {
+        self.custom.insert(address, Box::new(precompile));
+    }
+
+    pub fn unregister(&mut self, address: &H160) -> bool {
+        self.custom.remove(address).is_some()
+    }
+
+    pub fn contains(&self, address: &H160) -> bool {
+        self.custom.contains_key(address) || self.fns.contains_key(address)
+    }
+
+    pub fn addresses(&self) -> impl Iterator<Item = &H160> {
+        self.custom.keys().chain(self.fns.keys().filter(|address| !self.custom.contains_key(address)))
+    }
+
+    // None if there is no precompile at `address`. A result using more than `gas_limit` is out
+    // of gas, whatever the precompile says
+    pub fn call(&self, address: &H160, input: &[u8], gas_limit: usize, context: &mut PrecompileContext) -> Option<PrecompileResult> {
+        let result = match self.custom.get(address) {
+            Some(precompile) => precompile.run(input, gas_limit, context),
+            None => self.fns.get(address)?(input, gas_limit),
+        };
+        Some(result.and_then(|output| if output.gas_used > gas_limit { Err(PrecompileError::OutOfGas) } else { Ok(output) }))
+    }
+
+    fn insert(&mut self, index: u64, precompile: PrecompileFn) {
+        self.fns.insert(H160::from_low_u64_be(index), precompile);
+    }
+}
+
+// The built-ins of the default spec
+impl Default for Precompiles {
+    fn default() -> Self {
+        Precompiles::new(Spec::default())
+    }
+}
+
+impl fmt::Debug for Precompiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut addresses: Vec<&H160> = self.addresses().collect();
+        addresses.sort();
+        f.debug_struct("Precompiles")
+            .field("spec", &self.spec)
+            .field("addresses", &addresses)
+            .finish()
+    }
+}
+
+// Gas for precompiles priced as a base cost plus a cost per 32-byte word of input
+fn linear_cost(input_len: usize, base: usize, word: usize) -> usize {
+    base + word * Helper::to_word_size(input_len)
+}
+
+// Returns input[offset..offset + len], zero padded on the right past the end of input
+fn padded_slice(input: &[u8], offset: usize, len: usize) -> Vec<u8> {
+    let mut out = vec![0u8; len];
+    if offset < input.len() {
+        let end = input.len().min(offset + len);
+        out[..end - offset].copy_from_slice(&input[offset..end]);
+    }
+    out
+}
+
+// Left pads `data` to a 32-byte word
+fn left_pad_word(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0u8; 32];
+    out[32 - data.len()..].copy_from_slice(data);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_using_more_than_the_gas_limit_are_out_of_gas() {
+        let address = H160::from_low_u64_be(0x100);
+        let mut precompiles = Precompiles::new(Spec::Cancun);
+        precompiles.register(address, |_: &[u8], gas_limit: usize| Ok(PrecompileOutput::new(gas_limit + 1, Vec::new())));
+        let (mut state, mut transient) = (State::new(), Transient::new());
+        let mut context = PrecompileContext { caller: H160::zero(), value: U256::zero(), is_static: false, state: &mut state, transient: &mut transient };
+        assert_eq!(precompiles.call(&address, &[], 100, &mut context), Some(Err(PrecompileError::OutOfGas)));
+        assert_eq!(precompiles.call(&H160::from_low_u64_be(4), b"abc", 18, &mut context), Some(Ok(PrecompileOutput::new(18, b"abc".to_vec()))));
+        assert_eq!(precompiles.call(&H160::from_low_u64_be(0x200), &[], 100, &mut context), None);
+    }
+
+    #[test]
+    fn default_set_has_the_built_ins_of_the_default_spec() {
+        let precompiles = Precompiles::default();
+        assert_eq!(precompiles.spec(), Spec::default());
+        assert_eq!(precompiles.addresses().count(), Precompiles::new(Spec::default()).addresses().count());
+        assert!(precompiles.contains(&H160::from_low_u64_be(10)));
+    }
+}
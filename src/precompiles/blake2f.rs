@@ -0,0 +1,140 @@
+use super::{PrecompileError, PrecompileOutput, PrecompileResult};
+
+const INPUT_LENGTH: usize = 213;
+
+const IV: [u64; 8] = [
+    0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+    0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179,
+];
+
+const SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+// 0x09 (EIP-152): input is rounds (u32 BE) || h (8 x u64 LE) || m (16 x u64 LE) || t (2 x u64 LE) || f
+pub fn blake2f(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    if input.len() != INPUT_LENGTH {
+        return Err(PrecompileError::InvalidInputLength);
+    }
+
+    let rounds = u32::from_be_bytes(input[..4].try_into().unwrap()) as usize;
+    if rounds > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+
+    let final_block = match input[212] {
+        0 => false,
+        1 => true,
+        _ => return Err(PrecompileError::InvalidFinalFlag),
+    };
+
+    let mut h = [0u64; 8];
+    let mut m = [0u64; 16];
+    for (i, word) in h.iter_mut().enumerate() {
+        *word = read_u64_le(input, 4 + i * 8);
+    }
+    for (i, word) in m.iter_mut().enumerate() {
+        *word = read_u64_le(input, 68 + i * 8);
+    }
+    let t = [read_u64_le(input, 196), read_u64_le(input, 204)];
+
+    compress(rounds, &mut h, &m, t, final_block);
+
+    let output = h.iter().flat_map(|word| word.to_le_bytes()).collect();
+    Ok(PrecompileOutput::new(rounds, output))
+}
+
+fn read_u64_le(input: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(input[offset..offset + 8].try_into().unwrap())
+}
+
+// BLAKE2b compression function F with a configurable number of rounds
+fn compress(rounds: usize, h: &mut [u64; 8], m: &[u64; 16], t: [u64; 2], final_block: bool) {
+    let mut v = [0u64; 16];
+    v[..8].copy_from_slice(h);
+    v[8..].copy_from_slice(&IV);
+    v[12] ^= t[0];
+    v[13] ^= t[1];
+    if final_block {
+        v[14] = !v[14];
+    }
+
+    for round in 0..rounds {
+        let s = &SIGMA[round % 10];
+        mix(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+        mix(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+        mix(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+        mix(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+        mix(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+        mix(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+        mix(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+        mix(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+    }
+
+    for i in 0..8 {
+        h[i] ^= v[i] ^ v[i + 8];
+    }
+}
+
+fn mix(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    // EIP-152's vectors: BLAKE2b-512 state for "abc", as the final block of one
+    fn input(rounds: u32, final_flag: u8) -> Vec<u8> {
+        let mut input = rounds.to_be_bytes().to_vec();
+        let mut h = IV;
+        h[0] ^= 0x01010040; // 64-byte digest, no key
+        input.extend(h.iter().flat_map(|word| word.to_le_bytes()));
+        let mut m = [0u8; 128];
+        m[..3].copy_from_slice(b"abc");
+        input.extend_from_slice(&m);
+        input.extend_from_slice(&3u64.to_le_bytes());
+        input.extend_from_slice(&0u64.to_le_bytes());
+        input.push(final_flag);
+        input
+    }
+
+    #[test]
+    fn eip152_vectors() {
+        let result = blake2f(&input(12, 1), 12).unwrap();
+        assert_eq!(result.gas_used, 12);
+        assert_eq!(
+            result.output,
+            hex::decode("ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923").unwrap()
+        );
+        assert_eq!(
+            blake2f(&input(0, 1), 0).unwrap().output,
+            hex::decode("08c9bcf367e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d282e6ad7f520e511f6c3e2b8c68059b9442be0454267ce079217e1319cde05b").unwrap()
+        );
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        assert_eq!(blake2f(&[], 0), Err(PrecompileError::InvalidInputLength));
+        assert_eq!(blake2f(&input(12, 1)[1..], 12), Err(PrecompileError::InvalidInputLength));
+        assert_eq!(blake2f(&input(12, 2), 12), Err(PrecompileError::InvalidFinalFlag));
+        assert_eq!(blake2f(&input(12, 1), 11), Err(PrecompileError::OutOfGas));
+    }
+}
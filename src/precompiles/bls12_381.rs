@@ -0,0 +1,449 @@
+use blst::{
+    blst_bendian_from_fp, blst_final_exp, blst_fp, blst_fp12, blst_fp12_is_one, blst_fp12_mul,
+    blst_fp2, blst_fp_from_bendian, blst_map_to_g1, blst_map_to_g2, blst_miller_loop, blst_p1,
+    blst_p1_add_or_double, blst_p1_affine, blst_p1_affine_in_g1, blst_p1_affine_is_inf,
+    blst_p1_affine_on_curve, blst_p1_from_affine, blst_p1_mult, blst_p1_to_affine, blst_p2,
+    blst_p2_add_or_double, blst_p2_affine, blst_p2_affine_in_g2, blst_p2_affine_is_inf,
+    blst_p2_affine_on_curve, blst_p2_from_affine, blst_p2_mult, blst_p2_to_affine,
+};
+use super::{PrecompileError, PrecompileOutput, PrecompileResult};
+
+// EIP-2537 encodings: a field element is 64 bytes (16 zero bytes then 48 bytes big endian),
+// a G1 point is x || y, a G2 point is x.c0 || x.c1 || y.c0 || y.c1 and a scalar is 32 bytes
+const FP_LENGTH: usize = 64;
+const FP_PAD: usize = 16;
+const G1_LENGTH: usize = 2 * FP_LENGTH;
+const G2_LENGTH: usize = 4 * FP_LENGTH;
+const SCALAR_LENGTH: usize = 32;
+
+const G1_ADD_GAS: usize = 375;
+const G2_ADD_GAS: usize = 600;
+const G1_MUL_GAS: usize = 12000;
+const G2_MUL_GAS: usize = 22500;
+const PAIRING_BASE_GAS: usize = 37700;
+const PAIRING_PER_PAIR_GAS: usize = 32600;
+const MAP_FP_TO_G1_GAS: usize = 5500;
+const MAP_FP2_TO_G2_GAS: usize = 23800;
+
+// Base field modulus p
+const MODULUS: [u8; 48] = [
+    0x1a, 0x01, 0x11, 0xea, 0x39, 0x7f, 0xe6, 0x9a, 0x4b, 0x1b, 0xa7, 0xb6, 0x43, 0x4b, 0xac, 0xd7,
+    0x64, 0x77, 0x4b, 0x84, 0xf3, 0x85, 0x12, 0xbf, 0x67, 0x30, 0xd2, 0xa0, 0xf6, 0xb0, 0xf6, 0x24,
+    0x1e, 0xab, 0xff, 0xfe, 0xb1, 0x53, 0xff, 0xff, 0xb9, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xaa, 0xab,
+];
+
+// MSM discounts per number of pairs, in thousandths. Beyond the table the last entry applies
+const G1_MSM_DISCOUNT: [usize; 128] = [
+    1000, 949, 848, 797, 764, 750, 738, 728, 719, 712, 705, 698, 692, 687, 682, 677, 673, 669,
+    665, 661, 658, 654, 651, 648, 645, 642, 640, 637, 635, 632, 630, 627, 625, 623, 621, 619, 617,
+    615, 613, 611, 609, 608, 606, 604, 603, 601, 599, 598, 596, 595, 593, 592, 591, 589, 588, 586,
+    585, 584, 582, 581, 580, 579, 577, 576, 575, 574, 573, 572, 570, 569, 568, 567, 566, 565, 564,
+    563, 562, 561, 560, 559, 558, 557, 556, 555, 554, 553, 552, 551, 550, 549, 548, 547, 547, 546,
+    545, 544, 543, 542, 541, 540, 540, 539, 538, 537, 536, 536, 535, 534, 533, 532, 532, 531, 530,
+    529, 528, 528, 527, 526, 525, 525, 524, 523, 522, 522, 521, 520, 520, 519,
+];
+
+const G2_MSM_DISCOUNT: [usize; 128] = [
+    1000, 1000, 923, 884, 855, 832, 812, 796, 782, 770, 759, 749, 740, 732, 724, 717, 711, 704,
+    699, 693, 688, 683, 679, 674, 670, 666, 663, 659, 655, 652, 649, 646, 643, 640, 637, 634, 632,
+    629, 627, 624, 622, 620, 618, 615, 613, 611, 609, 607, 606, 604, 602, 600, 598, 597, 595, 593,
+    592, 590, 589, 587, 586, 584, 583, 582, 580, 579, 578, 576, 575, 574, 573, 571, 570, 569, 568,
+    567, 566, 565, 563, 562, 561, 560, 559, 558, 557, 556, 555, 554, 553, 552, 552, 551, 550, 549,
+    548, 547, 546, 545, 545, 544, 543, 542, 541, 541, 540, 539, 538, 537, 537, 536, 535, 535, 534,
+    533, 532, 532, 531, 530, 530, 529, 528, 528, 527, 526, 526, 525, 524, 524,
+];
+
+// 0x0b
+pub fn g1_add(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    if G1_ADD_GAS > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    if input.len() != 2 * G1_LENGTH {
+        return Err(PrecompileError::InvalidInputLength);
+    }
+    // No subgroup check for addition
+    let a = read_g1(&input[..G1_LENGTH], false)?;
+    let b = read_g1(&input[G1_LENGTH..], false)?;
+
+    let mut result = blst_p1::default();
+    unsafe {
+        let mut a_jacobian = blst_p1::default();
+        let mut b_jacobian = blst_p1::default();
+        blst_p1_from_affine(&mut a_jacobian, &a);
+        blst_p1_from_affine(&mut b_jacobian, &b);
+        blst_p1_add_or_double(&mut result, &a_jacobian, &b_jacobian);
+    }
+    Ok(PrecompileOutput::new(G1_ADD_GAS, encode_g1(&result)))
+}
+
+// 0x0c
+pub fn g1_msm(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    let pair_length = G1_LENGTH + SCALAR_LENGTH;
+    if input.is_empty() || !input.len().is_multiple_of(pair_length) {
+        return Err(PrecompileError::InvalidInputLength);
+    }
+    let k = input.len() / pair_length;
+    let gas_used = msm_gas(k, G1_MUL_GAS, &G1_MSM_DISCOUNT);
+    if gas_used > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+
+    let mut result = blst_p1::default();
+    for chunk in input.chunks(pair_length) {
+        let point = read_g1(&chunk[..G1_LENGTH], true)?;
+        let scalar = read_scalar(&chunk[G1_LENGTH..]);
+        unsafe {
+            let mut jacobian = blst_p1::default();
+            let mut product = blst_p1::default();
+            blst_p1_from_affine(&mut jacobian, &point);
+            blst_p1_mult(&mut product, &jacobian, scalar.as_ptr(), 256);
+            let sum = result;
+            blst_p1_add_or_double(&mut result, &sum, &product);
+        }
+    }
+    Ok(PrecompileOutput::new(gas_used, encode_g1(&result)))
+}
+
+// 0x0d
+pub fn g2_add(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    if G2_ADD_GAS > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    if input.len() != 2 * G2_LENGTH {
+        return Err(PrecompileError::InvalidInputLength);
+    }
+    let a = read_g2(&input[..G2_LENGTH], false)?;
+    let b = read_g2(&input[G2_LENGTH..], false)?;
+
+    let mut result = blst_p2::default();
+    unsafe {
+        let mut a_jacobian = blst_p2::default();
+        let mut b_jacobian = blst_p2::default();
+        blst_p2_from_affine(&mut a_jacobian, &a);
+        blst_p2_from_affine(&mut b_jacobian, &b);
+        blst_p2_add_or_double(&mut result, &a_jacobian, &b_jacobian);
+    }
+    Ok(PrecompileOutput::new(G2_ADD_GAS, encode_g2(&result)))
+}
+
+// 0x0e
+pub fn g2_msm(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    let pair_length = G2_LENGTH + SCALAR_LENGTH;
+    if input.is_empty() || !input.len().is_multiple_of(pair_length) {
+        return Err(PrecompileError::InvalidInputLength);
+    }
+    let k = input.len() / pair_length;
+    let gas_used = msm_gas(k, G2_MUL_GAS, &G2_MSM_DISCOUNT);
+    if gas_used > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+
+    let mut result = blst_p2::default();
+    for chunk in input.chunks(pair_length) {
+        let point = read_g2(&chunk[..G2_LENGTH], true)?;
+        let scalar = read_scalar(&chunk[G2_LENGTH..]);
+        unsafe {
+            let mut jacobian = blst_p2::default();
+            let mut product = blst_p2::default();
+            blst_p2_from_affine(&mut jacobian, &point);
+            blst_p2_mult(&mut product, &jacobian, scalar.as_ptr(), 256);
+            let sum = result;
+            blst_p2_add_or_double(&mut result, &sum, &product);
+        }
+    }
+    Ok(PrecompileOutput::new(gas_used, encode_g2(&result)))
+}
+
+// 0x0f: returns 1 if the product of the pairings is the identity, 0 otherwise
+pub fn pairing(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    let pair_length = G1_LENGTH + G2_LENGTH;
+    if input.is_empty() || !input.len().is_multiple_of(pair_length) {
+        return Err(PrecompileError::InvalidInputLength);
+    }
+    let k = input.len() / pair_length;
+    let gas_used = PAIRING_BASE_GAS + PAIRING_PER_PAIR_GAS * k;
+    if gas_used > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+
+    let mut pairs = Vec::with_capacity(k);
+    for chunk in input.chunks(pair_length) {
+        let g1 = read_g1(&chunk[..G1_LENGTH], true)?;
+        let g2 = read_g2(&chunk[G1_LENGTH..], true)?;
+        pairs.push((g1, g2));
+    }
+
+    let mut success = true;
+    unsafe {
+        let mut accumulator: Option<blst_fp12> = None;
+        for (g1, g2) in &pairs {
+            // A pair with the point at infinity contributes the identity
+            if blst_p1_affine_is_inf(g1) || blst_p2_affine_is_inf(g2) {
+                continue;
+            }
+            let mut miller = blst_fp12::default();
+            blst_miller_loop(&mut miller, g2, g1);
+            accumulator = Some(match accumulator {
+                Some(previous) => {
+                    let mut product = blst_fp12::default();
+                    blst_fp12_mul(&mut product, &previous, &miller);
+                    product
+                }
+                None => miller,
+            });
+        }
+        if let Some(accumulator) = accumulator {
+            let mut result = blst_fp12::default();
+            blst_final_exp(&mut result, &accumulator);
+            success = blst_fp12_is_one(&result);
+        }
+    }
+
+    let mut output = vec![0u8; 32];
+    output[31] = success as u8;
+    Ok(PrecompileOutput::new(gas_used, output))
+}
+
+// 0x10
+pub fn map_fp_to_g1(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    if MAP_FP_TO_G1_GAS > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    if input.len() != FP_LENGTH {
+        return Err(PrecompileError::InvalidInputLength);
+    }
+    let fp = read_fp(input)?;
+
+    let mut result = blst_p1::default();
+    unsafe {
+        blst_map_to_g1(&mut result, &fp, std::ptr::null());
+    }
+    Ok(PrecompileOutput::new(MAP_FP_TO_G1_GAS, encode_g1(&result)))
+}
+
+// 0x11
+pub fn map_fp2_to_g2(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    if MAP_FP2_TO_G2_GAS > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    if input.len() != 2 * FP_LENGTH {
+        return Err(PrecompileError::InvalidInputLength);
+    }
+    let fp2 = read_fp2(input)?;
+
+    let mut result = blst_p2::default();
+    unsafe {
+        blst_map_to_g2(&mut result, &fp2, std::ptr::null());
+    }
+    Ok(PrecompileOutput::new(MAP_FP2_TO_G2_GAS, encode_g2(&result)))
+}
+
+fn msm_gas(k: usize, multiplication_gas: usize, discounts: &[usize; 128]) -> usize {
+    let discount = discounts[k.min(discounts.len()) - 1];
+    k * multiplication_gas * discount / 1000
+}
+
+fn read_fp(bytes: &[u8]) -> Result<blst_fp, PrecompileError> {
+    if bytes[..FP_PAD].iter().any(|&b| b != 0) {
+        return Err(PrecompileError::InvalidPadding);
+    }
+    let value = &bytes[FP_PAD..FP_LENGTH];
+    // Big endian byte strings of equal length compare like the numbers they encode
+    if value >= &MODULUS[..] {
+        return Err(PrecompileError::InvalidFieldElement);
+    }
+    let mut fp = blst_fp::default();
+    unsafe {
+        blst_fp_from_bendian(&mut fp, value.as_ptr());
+    }
+    Ok(fp)
+}
+
+fn read_fp2(bytes: &[u8]) -> Result<blst_fp2, PrecompileError> {
+    Ok(blst_fp2 {
+        fp: [read_fp(&bytes[..FP_LENGTH])?, read_fp(&bytes[FP_LENGTH..2 * FP_LENGTH])?],
+    })
+}
+
+// All zeros encodes the point at infinity, which blst represents the same way
+fn read_g1(bytes: &[u8], subgroup_check: bool) -> Result<blst_p1_affine, PrecompileError> {
+    let point = blst_p1_affine {
+        x: read_fp(&bytes[..FP_LENGTH])?,
+        y: read_fp(&bytes[FP_LENGTH..G1_LENGTH])?,
+    };
+    unsafe {
+        if !blst_p1_affine_on_curve(&point) {
+            return Err(PrecompileError::InvalidPoint);
+        }
+        if subgroup_check && !blst_p1_affine_is_inf(&point) && !blst_p1_affine_in_g1(&point) {
+            return Err(PrecompileError::NotInSubgroup);
+        }
+    }
+    Ok(point)
+}
+
+fn read_g2(bytes: &[u8], subgroup_check: bool) -> Result<blst_p2_affine, PrecompileError> {
+    let point = blst_p2_affine {
+        x: read_fp2(&bytes[..2 * FP_LENGTH])?,
+        y: read_fp2(&bytes[2 * FP_LENGTH..G2_LENGTH])?,
+    };
+    unsafe {
+        if !blst_p2_affine_on_curve(&point) {
+            return Err(PrecompileError::InvalidPoint);
+        }
+        if subgroup_check && !blst_p2_affine_is_inf(&point) && !blst_p2_affine_in_g2(&point) {
+            return Err(PrecompileError::NotInSubgroup);
+        }
+    }
+    Ok(point)
+}
+
+// blst takes scalars as little endian bytes. Scalars are not reduced modulo the group order
+fn read_scalar(bytes: &[u8]) -> [u8; SCALAR_LENGTH] {
+    let mut scalar = [0u8; SCALAR_LENGTH];
+    for (i, byte) in bytes.iter().rev().enumerate() {
+        scalar[i] = *byte;
+    }
+    scalar
+}
+
+fn encode_fp(fp: &blst_fp, out: &mut [u8]) {
+    unsafe {
+        blst_bendian_from_fp(out[FP_PAD..FP_LENGTH].as_mut_ptr(), fp);
+    }
+}
+
+fn encode_g1(point: &blst_p1) -> Vec<u8> {
+    let mut affine = blst_p1_affine::default();
+    unsafe {
+        blst_p1_to_affine(&mut affine, point);
+    }
+    let mut output = vec![0u8; G1_LENGTH];
+    encode_fp(&affine.x, &mut output[..FP_LENGTH]);
+    encode_fp(&affine.y, &mut output[FP_LENGTH..]);
+    output
+}
+
+fn encode_g2(point: &blst_p2) -> Vec<u8> {
+    let mut affine = blst_p2_affine::default();
+    unsafe {
+        blst_p2_to_affine(&mut affine, point);
+    }
+    let mut output = vec![0u8; G2_LENGTH];
+    encode_fp(&affine.x.fp[0], &mut output[..FP_LENGTH]);
+    encode_fp(&affine.x.fp[1], &mut output[FP_LENGTH..2 * FP_LENGTH]);
+    encode_fp(&affine.y.fp[0], &mut output[2 * FP_LENGTH..3 * FP_LENGTH]);
+    encode_fp(&affine.y.fp[1], &mut output[3 * FP_LENGTH..]);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    // Generators and their multiples, each coordinate padded to 64 bytes
+    const G1: [&str; 2] = [
+        "17f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb",
+        "08b3f481e3aaa0f1a09e30ed741d8ae4fcf5e095d5d00af600db18cb2c04b3edd03cc744a2888ae40caa232946c5e7e1",
+    ];
+    const NEG_G1: [&str; 2] = [
+        "17f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb",
+        "114d1d6855d545a8aa7d76c8cf2e21f267816aef1db507c96655b9d5caac42364e6f38ba0ecb751bad54dcd6b939c2ca",
+    ];
+    const DOUBLE_G1: [&str; 2] = [
+        "0572cbea904d67468808c8eb50a9450c9721db309128012543902d0ac358a62ae28f75bb8f1c7c42c39a8c5529bf0f4e",
+        "166a9d8cabc673a322fda673779d8e3822ba3ecb8670e461f73bb9021d5fd76a4c56d9d4cd16bd1bba86881979749d28",
+    ];
+    const G2: [&str; 4] = [
+        "024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8",
+        "13e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e",
+        "0ce5d527727d6e118cc9cdc6da2e351aadfd9baa8cbdd3a76d429a695160d12c923ac9cc3baca289e193548608b82801",
+        "0606c4a02ea734cc32acd2b02bc28b99cb3e287e85a763af267492ab572e99ab3f370d275cec1da1aaa9075ff05f79be",
+    ];
+    const NEG_G2: [&str; 4] = [
+        "024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8",
+        "13e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e",
+        "0d1b3cc2c7027888be51d9ef691d77bcb679afda66c73f17f9ee3837a55024f78c71363275a75d75d86bab79f74782aa",
+        "13fa4d4a0ad8b1ce186ed5061789213d993923066dddaf1040bc3ff59f825c78df74f2d75467e25e0f55f8a00fa030ed",
+    ];
+
+    fn point(coordinates: &[&str]) -> Vec<u8> {
+        coordinates.iter().flat_map(|fp| hex::decode(&format!("{:0>128}", fp)).unwrap()).collect()
+    }
+
+    fn scalar(value: u64) -> Vec<u8> {
+        hex::decode(&format!("{:064x}", value)).unwrap()
+    }
+
+    fn concat(parts: &[Vec<u8>]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn g1_add_and_msm() {
+        let double = point(&DOUBLE_G1);
+        assert_eq!(g1_add(&concat(&[point(&G1), point(&G1)]), 375), Ok(PrecompileOutput::new(375, double.clone())));
+        assert_eq!(g1_add(&concat(&[point(&G1), point(&NEG_G1)]), 375).unwrap().output, vec![0; G1_LENGTH]);
+        assert_eq!(g1_msm(&concat(&[point(&G1), scalar(2)]), 12000), Ok(PrecompileOutput::new(12000, double.clone())));
+        // 3G - G, two pairs at a discount of 949 thousandths
+        let input = concat(&[point(&G1), scalar(3), point(&NEG_G1), scalar(1)]);
+        assert_eq!(g1_msm(&input, 22776), Ok(PrecompileOutput::new(22776, double)));
+        assert_eq!(g1_msm(&input, 22775), Err(PrecompileError::OutOfGas));
+    }
+
+    #[test]
+    fn g2_add_and_msm() {
+        let double = g2_add(&concat(&[point(&G2), point(&G2)]), 600).unwrap();
+        assert_eq!(double.gas_used, 600);
+        assert_eq!(g2_msm(&concat(&[point(&G2), scalar(2)]), 22500).unwrap().output, double.output);
+        assert_eq!(g2_add(&concat(&[point(&G2), point(&NEG_G2)]), 600).unwrap().output, vec![0; G2_LENGTH]);
+    }
+
+    #[test]
+    fn pairing_checks() {
+        let mut one = vec![0; 32];
+        one[31] = 1;
+        // e(G1, G2) * e(-G1, G2) = 1
+        let input = concat(&[point(&G1), point(&G2), point(&NEG_G1), point(&G2)]);
+        assert_eq!(pairing(&input, 102900), Ok(PrecompileOutput::new(102900, one.clone())));
+        assert_eq!(pairing(&input, 102899), Err(PrecompileError::OutOfGas));
+        let input = concat(&[point(&G1), point(&G2)]);
+        assert_eq!(pairing(&input, 70300).unwrap().output, vec![0; 32]);
+        // Pairs with the point at infinity drop out
+        let input = concat(&[vec![0; G1_LENGTH], point(&G2)]);
+        assert_eq!(pairing(&input, 70300).unwrap().output, one);
+        assert_eq!(pairing(&[], 37700), Err(PrecompileError::InvalidInputLength));
+    }
+
+    #[test]
+    fn mapped_points_are_in_the_subgroups() {
+        let fp = point(&["01"]);
+        let g1 = map_fp_to_g1(&fp, 5500).unwrap();
+        assert_eq!(g1.gas_used, 5500);
+        assert_eq!(g1_msm(&concat(&[g1.output.clone(), scalar(1)]), 12000).unwrap().output, g1.output);
+
+        let g2 = map_fp2_to_g2(&concat(&[fp.clone(), fp]), 23800).unwrap();
+        assert_eq!(g2.gas_used, 23800);
+        assert_eq!(g2_msm(&concat(&[g2.output.clone(), scalar(1)]), 22500).unwrap().output, g2.output);
+    }
+
+    #[test]
+    fn malformed_points_are_rejected() {
+        // (0, 2) is on the curve but outside the prime order subgroup
+        let outside = point(&["00", "02"]);
+        assert_eq!(g1_add(&concat(&[outside.clone(), vec![0; G1_LENGTH]]), 375).unwrap().output, outside);
+        assert_eq!(g1_msm(&concat(&[outside, scalar(1)]), 12000), Err(PrecompileError::NotInSubgroup));
+
+        let off_curve = point(&["01", "01"]);
+        assert_eq!(g1_add(&concat(&[off_curve.clone(), off_curve]), 375), Err(PrecompileError::InvalidPoint));
+
+        let mut padded = point(&G1);
+        padded[0] = 1;
+        assert_eq!(g1_add(&concat(&[padded, point(&G1)]), 375), Err(PrecompileError::InvalidPadding));
+
+        let modulus = [vec![0; FP_PAD], MODULUS.to_vec()].concat();
+        assert_eq!(map_fp_to_g1(&modulus, 5500), Err(PrecompileError::InvalidFieldElement));
+        assert_eq!(map_fp_to_g1(&modulus[1..], 5500), Err(PrecompileError::InvalidInputLength));
+    }
+}
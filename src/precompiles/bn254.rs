@@ -0,0 +1,160 @@
+use substrate_bn::{pairing_batch, AffineG1, AffineG2, Fq, Fq2, Fr, Group, Gt, G1, G2};
+use super::{padded_slice, PrecompileError, PrecompileOutput, PrecompileResult};
+
+const PAIR_LENGTH: usize = 192;
+
+// 0x06, 0x07 and 0x08 were repriced by EIP-1108 in Istanbul
+pub fn add_byzantium(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    add(input, gas_limit, 500)
+}
+
+pub fn add_istanbul(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    add(input, gas_limit, 150)
+}
+
+pub fn mul_byzantium(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    mul(input, gas_limit, 40000)
+}
+
+pub fn mul_istanbul(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    mul(input, gas_limit, 6000)
+}
+
+pub fn pairing_byzantium(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    pairing(input, gas_limit, 100000, 80000)
+}
+
+pub fn pairing_istanbul(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    pairing(input, gas_limit, 45000, 34000)
+}
+
+fn add(input: &[u8], gas_limit: usize, gas_used: usize) -> PrecompileResult {
+    if gas_used > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    let input = padded_slice(input, 0, 128);
+    let p1 = read_g1(&input[..64])?;
+    let p2 = read_g1(&input[64..128])?;
+    Ok(PrecompileOutput::new(gas_used, encode_g1(p1 + p2)))
+}
+
+fn mul(input: &[u8], gas_limit: usize, gas_used: usize) -> PrecompileResult {
+    if gas_used > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    let input = padded_slice(input, 0, 96);
+    let p = read_g1(&input[..64])?;
+    // The scalar is not required to be reduced modulo the group order
+    let scalar = Fr::from_slice(&input[64..96]).map_err(|_| PrecompileError::InvalidFieldElement)?;
+    Ok(PrecompileOutput::new(gas_used, encode_g1(p * scalar)))
+}
+
+fn pairing(input: &[u8], gas_limit: usize, base: usize, per_pair: usize) -> PrecompileResult {
+    let gas_used = base + per_pair * (input.len() / PAIR_LENGTH);
+    if gas_used > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    if !input.len().is_multiple_of(PAIR_LENGTH) {
+        return Err(PrecompileError::InvalidInputLength);
+    }
+
+    let mut pairs = Vec::with_capacity(input.len() / PAIR_LENGTH);
+    for chunk in input.chunks(PAIR_LENGTH) {
+        let g1 = read_g1(&chunk[..64])?;
+        let g2 = read_g2(&chunk[64..])?;
+        pairs.push((g1, g2));
+    }
+
+    let success = pairs.is_empty() || pairing_batch(&pairs) == Gt::one();
+    let mut output = vec![0u8; 32];
+    output[31] = success as u8;
+    Ok(PrecompileOutput::new(gas_used, output))
+}
+
+fn read_fq(bytes: &[u8]) -> Result<Fq, PrecompileError> {
+    Fq::from_slice(bytes).map_err(|_| PrecompileError::InvalidFieldElement)
+}
+
+// (0, 0) encodes the point at infinity
+fn read_g1(bytes: &[u8]) -> Result<G1, PrecompileError> {
+    let x = read_fq(&bytes[..32])?;
+    let y = read_fq(&bytes[32..64])?;
+    if x.is_zero() && y.is_zero() {
+        return Ok(G1::zero());
+    }
+    AffineG1::new(x, y)
+        .map(Into::into)
+        .map_err(|_| PrecompileError::InvalidPoint)
+}
+
+// Fq2 coordinates are encoded with the imaginary part first
+fn read_g2(bytes: &[u8]) -> Result<G2, PrecompileError> {
+    let x = Fq2::new(read_fq(&bytes[32..64])?, read_fq(&bytes[..32])?);
+    let y = Fq2::new(read_fq(&bytes[96..128])?, read_fq(&bytes[64..96])?);
+    if x.is_zero() && y.is_zero() {
+        return Ok(G2::zero());
+    }
+    AffineG2::new(x, y)
+        .map(Into::into)
+        .map_err(|_| PrecompileError::InvalidPoint)
+}
+
+fn encode_g1(point: G1) -> Vec<u8> {
+    let mut output = vec![0u8; 64];
+    if let Some(affine) = AffineG1::from_jacobian(point) {
+        affine.x().to_big_endian(&mut output[..32]).unwrap();
+        affine.y().to_big_endian(&mut output[32..]).unwrap();
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    const G1: &str = "00000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000002";
+    // -G1, y negated modulo the field prime
+    const NEG_G1: &str = "000000000000000000000000000000000000000000000000000000000000000130644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd45";
+    const DOUBLE_G1: &str = "030644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd315ed738c0e0a7c92e7845f96b2ae9c0a68a6a449e3538fc7ff3ebf7a5a18a2c4";
+    const G2: &str = "198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c21800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa";
+
+    fn decode(parts: &[&str]) -> Vec<u8> {
+        hex::decode(&parts.concat()).unwrap()
+    }
+
+    #[test]
+    fn add_and_mul() {
+        assert_eq!(add_istanbul(&decode(&[G1, G1]), 150), Ok(PrecompileOutput::new(150, decode(&[DOUBLE_G1]))));
+        assert_eq!(add_byzantium(&decode(&[G1, G1]), 500).unwrap().gas_used, 500);
+        // Missing input is the point at infinity, the identity
+        assert_eq!(add_istanbul(&decode(&[G1]), 150).unwrap().output, decode(&[G1]));
+        assert_eq!(add_istanbul(&[], 150).unwrap().output, vec![0; 64]);
+        assert_eq!(add_istanbul(&decode(&[G1, NEG_G1]), 150).unwrap().output, vec![0; 64]);
+
+        let two = format!("{:064x}", 2);
+        assert_eq!(mul_istanbul(&decode(&[G1, &two]), 6000), Ok(PrecompileOutput::new(6000, decode(&[DOUBLE_G1]))));
+        assert_eq!(mul_byzantium(&decode(&[G1, &two]), 39999), Err(PrecompileError::OutOfGas));
+    }
+
+    #[test]
+    fn invalid_points_are_rejected() {
+        let off_curve = format!("{:064x}{:064x}", 1, 3);
+        assert_eq!(add_istanbul(&decode(&[G1, &off_curve]), 150), Err(PrecompileError::InvalidPoint));
+        // A coordinate equal to the field prime is not reduced
+        let unreduced = format!("{:064x}30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd47", 1);
+        assert_eq!(mul_istanbul(&decode(&[&unreduced]), 6000), Err(PrecompileError::InvalidFieldElement));
+    }
+
+    #[test]
+    fn pairing_checks() {
+        let mut one = vec![0; 32];
+        one[31] = 1;
+        // e(G1, G2) * e(-G1, G2) = 1
+        assert_eq!(pairing_istanbul(&decode(&[G1, G2, NEG_G1, G2]), 113000), Ok(PrecompileOutput::new(113000, one.clone())));
+        assert_eq!(pairing_istanbul(&decode(&[G1, G2]), 79000).unwrap().output, vec![0; 32]);
+        assert_eq!(pairing_istanbul(&[], 45000).unwrap().output, one);
+        assert_eq!(pairing_byzantium(&decode(&[G1, G2]), 179999), Err(PrecompileError::OutOfGas));
+        assert_eq!(pairing_istanbul(&decode(&[G1]), 45000), Err(PrecompileError::InvalidInputLength));
+    }
+}
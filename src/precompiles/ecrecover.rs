@@ -0,0 +1,74 @@
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use crate::helper::Helper;
+use super::{left_pad_word, padded_slice, PrecompileError, PrecompileOutput, PrecompileResult};
+
+const ECRECOVER_GAS: usize = 3000;
+
+// 0x01: input is hash || v || r || s, each a 32-byte word. An invalid signature is not an
+// error, the call succeeds with empty output
+pub fn ecrecover(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    if ECRECOVER_GAS > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    let input = padded_slice(input, 0, 128);
+    let output = match recover_address(&input) {
+        Some(address) => left_pad_word(&address),
+        None => Vec::new(),
+    };
+    Ok(PrecompileOutput::new(ECRECOVER_GAS, output))
+}
+
+fn recover_address(input: &[u8]) -> Option<[u8; 20]> {
+    // v must be a 32-byte word holding 27 or 28
+    if input[32..63].iter().any(|&b| b != 0) || !(27..=28).contains(&input[63]) {
+        return None;
+    }
+    let mut recovery_id = RecoveryId::from_byte(input[63] - 27)?;
+    let mut signature = Signature::from_slice(&input[64..128]).ok()?;
+
+    // Unlike transactions, the precompile accepts high-s signatures. k256 only recovers
+    // from normalized ones, so flip s and the recovery parity together
+    if let Some(normalized) = signature.normalize_s() {
+        signature = normalized;
+        recovery_id = RecoveryId::new(!recovery_id.is_y_odd(), recovery_id.is_x_reduced());
+    }
+
+    let key = VerifyingKey::recover_from_prehash(&input[..32], &signature, recovery_id).ok()?;
+    let public_key = key.to_encoded_point(false);
+    let hash = Helper::keccak256(&public_key.as_bytes()[1..]);
+
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..]);
+    Some(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    // From go-ethereum's precompile tests
+    const INPUT: &str = "38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e000000000000000000000000000000000000000000000000000000000000001b38d18acb67d25c8bb9942764b62f18e17054f66a817bd4295423adf9ed98873e789d1dd423d25f0772d2748d60f7e4b81bb14d086eba8e8e8efb6dcff8a4ae02";
+
+    #[test]
+    fn recovers_the_signer() {
+        let result = ecrecover(&hex::decode(INPUT).unwrap(), 3000).unwrap();
+        assert_eq!(result.gas_used, 3000);
+        assert_eq!(result.output, hex::decode("000000000000000000000000ceaccac640adf55b2028469bd36ba501f28b699d").unwrap());
+        assert_eq!(ecrecover(&hex::decode(INPUT).unwrap(), 2999), Err(PrecompileError::OutOfGas));
+    }
+
+    #[test]
+    fn invalid_signatures_return_nothing() {
+        let input = hex::decode(INPUT).unwrap();
+        let mut wrong_v = input.clone();
+        wrong_v[63] = 29;
+        let mut dirty_v = input.clone();
+        dirty_v[32] = 1;
+        let mut zero_r = input;
+        zero_r[64..96].fill(0);
+        for input in [wrong_v, dirty_v, zero_r, Vec::new()] {
+            assert_eq!(ecrecover(&input, 3000), Ok(PrecompileOutput::new(3000, Vec::new())));
+        }
+    }
+}
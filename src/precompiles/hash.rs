@@ -0,0 +1,50 @@
+use sha2::{Digest, Sha256};
+use ripemd::Ripemd160;
+use super::{left_pad_word, linear_cost, PrecompileError, PrecompileOutput, PrecompileResult};
+
+// 0x02
+pub fn sha256(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    let gas_used = linear_cost(input.len(), 60, 12);
+    if gas_used > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    let output = Sha256::digest(input).to_vec();
+    Ok(PrecompileOutput::new(gas_used, output))
+}
+
+// 0x03: the 20-byte digest is returned left padded to 32 bytes
+pub fn ripemd160(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    let gas_used = linear_cost(input.len(), 600, 120);
+    if gas_used > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    let output = left_pad_word(&Ripemd160::digest(input));
+    Ok(PrecompileOutput::new(gas_used, output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    #[test]
+    fn sha256_digests() {
+        let result = sha256(&[], 60).unwrap();
+        assert_eq!(result.output, hex::decode("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855").unwrap());
+        assert_eq!(result.gas_used, 60);
+        assert_eq!(sha256(&[0; 33], 84).unwrap().gas_used, 60 + 2 * 12);
+        assert_eq!(sha256(&[0; 33], 83), Err(PrecompileError::OutOfGas));
+    }
+
+    #[test]
+    fn ripemd160_digests_are_left_padded() {
+        let result = ripemd160(b"abc", 720).unwrap();
+        assert_eq!(result.output, hex::decode("0000000000000000000000008eb208f7e05d987a9b044a8e98c6b087f15a0bfc").unwrap());
+        assert_eq!(result.gas_used, 720);
+        assert_eq!(
+            ripemd160(&[], 600).unwrap().output,
+            hex::decode("0000000000000000000000009c1185a5c5e9fc54612808977ee8f548b2258d31").unwrap()
+        );
+        assert_eq!(ripemd160(b"abc", 719), Err(PrecompileError::OutOfGas));
+    }
+}
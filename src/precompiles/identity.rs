@@ -0,0 +1,10 @@
+use super::{linear_cost, PrecompileError, PrecompileOutput, PrecompileResult};
+
+// 0x04: returns the input unchanged
+pub fn identity(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    let gas_used = linear_cost(input.len(), 15, 3);
+    if gas_used > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    Ok(PrecompileOutput::new(gas_used, input.to_vec()))
+}
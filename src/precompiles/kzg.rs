@@ -0,0 +1,102 @@
+use c_kzg::{ethereum_kzg_settings, Bytes32, Bytes48};
+use sha2::{Digest, Sha256};
+use super::{PrecompileError, PrecompileOutput, PrecompileResult};
+
+const POINT_EVALUATION_GAS: usize = 50000;
+const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+
+// FIELD_ELEMENTS_PER_BLOB || BLS_MODULUS, both as 32-byte big endian words
+const RETURN_VALUE: [u8; 64] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+    0x73, 0xed, 0xa7, 0x53, 0x29, 0x9d, 0x7d, 0x48, 0x33, 0x39, 0xd8, 0x08, 0x09, 0xa1, 0xd8, 0x05,
+    0x53, 0xbd, 0xa4, 0x02, 0xff, 0xfe, 0x5b, 0xfe, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01,
+];
+
+// 0x0a (EIP-4844): input is versioned_hash || z || y || commitment || proof
+pub fn point_evaluation(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    if POINT_EVALUATION_GAS > gas_limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    if input.len() != 192 {
+        return Err(PrecompileError::InvalidInputLength);
+    }
+
+    let versioned_hash = &input[..32];
+    let z = &input[32..64];
+    let y = &input[64..96];
+    let commitment = &input[96..144];
+    let proof = &input[144..192];
+
+    if kzg_to_versioned_hash(commitment) != versioned_hash {
+        return Err(PrecompileError::InvalidVersionedHash);
+    }
+
+    if !verify_kzg_proof(commitment, z, y, proof) {
+        return Err(PrecompileError::InvalidKzgProof);
+    }
+
+    Ok(PrecompileOutput::new(POINT_EVALUATION_GAS, RETURN_VALUE.to_vec()))
+}
+
+pub fn kzg_to_versioned_hash(commitment: &[u8]) -> [u8; 32] {
+    let mut hash: [u8; 32] = Sha256::digest(commitment).into();
+    hash[0] = VERSIONED_HASH_VERSION_KZG;
+    hash
+}
+
+fn verify_kzg_proof(commitment: &[u8], z: &[u8], y: &[u8], proof: &[u8]) -> bool {
+    let (Ok(commitment), Ok(z), Ok(y), Ok(proof)) = (
+        Bytes48::from_bytes(commitment),
+        Bytes32::from_bytes(z),
+        Bytes32::from_bytes(y),
+        Bytes48::from_bytes(proof),
+    ) else {
+        return false;
+    };
+    ethereum_kzg_settings(0)
+        .verify_kzg_proof(&commitment, &z, &y, &proof)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The zero polynomial: its commitment and every proof are the point at infinity, and it
+    // evaluates to zero everywhere
+    fn input(y: u8) -> Vec<u8> {
+        let mut infinity = [0u8; 48];
+        infinity[0] = 0xc0;
+        let mut input = kzg_to_versioned_hash(&infinity).to_vec();
+        input.extend_from_slice(&[0x11; 32]); // z
+        let mut value = [0u8; 32];
+        value[31] = y;
+        input.extend_from_slice(&value);
+        input.extend_from_slice(&infinity);
+        input.extend_from_slice(&infinity);
+        input
+    }
+
+    #[test]
+    fn verifies_a_proof() {
+        let result = point_evaluation(&input(0), 50000).unwrap();
+        assert_eq!(result.gas_used, 50000);
+        // FIELD_ELEMENTS_PER_BLOB and BLS_MODULUS
+        let expected = crate::hex::decode(
+            "0000000000000000000000000000000000000000000000000000000000001000\
+             73eda753299d7d483339d80809a1d80553bda402fffe5bfeffffffff00000001",
+        );
+        assert_eq!(result.output, expected.unwrap());
+    }
+
+    #[test]
+    fn rejects_wrong_evaluations_and_hashes() {
+        assert_eq!(point_evaluation(&input(1), 50000), Err(PrecompileError::InvalidKzgProof));
+        let mut wrong_hash = input(0);
+        wrong_hash[0] = 0x02;
+        assert_eq!(point_evaluation(&wrong_hash, 50000), Err(PrecompileError::InvalidVersionedHash));
+        assert_eq!(point_evaluation(&input(0)[1..], 50000), Err(PrecompileError::InvalidInputLength));
+        assert_eq!(point_evaluation(&input(0), 49999), Err(PrecompileError::OutOfGas));
+    }
+}
@@ -0,0 +1,129 @@
+use ethereum_types::U256;
+use super::{padded_slice, PrecompileError, PrecompileOutput, PrecompileResult};
+
+// 0x05 priced as in EIP-198
+pub fn byzantium(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    run(input, gas_limit, byzantium_gas)
+}
+
+// 0x05 priced as in EIP-2565
+pub fn berlin(input: &[u8], gas_limit: usize) -> PrecompileResult {
+    run(input, gas_limit, berlin_gas)
+}
+
+// input is base_len || exp_len || mod_len (32-byte words) followed by base, exp and mod
+fn run(input: &[u8], gas_limit: usize, gas_fn: fn(u128, u128, u128, &[u8]) -> u128) -> PrecompileResult {
+    let base_len = U256::from_big_endian(&padded_slice(input, 0, 32));
+    let exp_len = U256::from_big_endian(&padded_slice(input, 32, 32));
+    let mod_len = U256::from_big_endian(&padded_slice(input, 64, 32));
+
+    // Lengths this large can never be paid for
+    let limit = U256::from(u32::MAX);
+    if base_len > limit || exp_len > limit || mod_len > limit {
+        return Err(PrecompileError::OutOfGas);
+    }
+    let base_len = base_len.as_usize();
+    let exp_len = exp_len.as_usize();
+    let mod_len = mod_len.as_usize();
+
+    // The first 32 bytes of the exponent feed into the gas formula
+    let exp_head = padded_slice(input, 96 + base_len, exp_len.min(32));
+    let gas_used = gas_fn(base_len as u128, exp_len as u128, mod_len as u128, &exp_head);
+    if gas_used > gas_limit as u128 {
+        return Err(PrecompileError::OutOfGas);
+    }
+    let gas_used = gas_used as usize;
+
+    if mod_len == 0 {
+        return Ok(PrecompileOutput::new(gas_used, Vec::new()));
+    }
+
+    let base = padded_slice(input, 96, base_len);
+    let exp = padded_slice(input, 96 + base_len, exp_len);
+    let modulus = padded_slice(input, 96 + base_len + exp_len, mod_len);
+
+    let result = aurora_engine_modexp::modexp(&base, &exp, &modulus);
+
+    // The result is left padded to the modulus length
+    let mut output = vec![0u8; mod_len];
+    let result = &result[result.len().saturating_sub(mod_len)..];
+    output[mod_len - result.len()..].copy_from_slice(result);
+    Ok(PrecompileOutput::new(gas_used, output))
+}
+
+// Effective exponent length used for pricing: roughly the bit length of the exponent
+fn adjusted_exp_len(exp_len: u128, exp_head: &[u8]) -> u128 {
+    let head = U256::from_big_endian(exp_head);
+    let head_bits = if head.is_zero() { 0 } else { head.bits() as u128 - 1 };
+    if exp_len <= 32 {
+        head_bits
+    } else {
+        8 * (exp_len - 32) + head_bits
+    }
+}
+
+fn byzantium_gas(base_len: u128, exp_len: u128, mod_len: u128, exp_head: &[u8]) -> u128 {
+    let x = base_len.max(mod_len);
+    let complexity = if x <= 64 {
+        x * x
+    } else if x <= 1024 {
+        x * x / 4 + 96 * x - 3072
+    } else {
+        x * x / 16 + 480 * x - 199680
+    };
+    let iterations = adjusted_exp_len(exp_len, exp_head).max(1);
+    complexity.saturating_mul(iterations) / 20
+}
+
+fn berlin_gas(base_len: u128, exp_len: u128, mod_len: u128, exp_head: &[u8]) -> u128 {
+    let words = base_len.max(mod_len).div_ceil(8);
+    let complexity = words * words;
+    let iterations = adjusted_exp_len(exp_len, exp_head).max(1);
+    (complexity.saturating_mul(iterations) / 3).max(200)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    // EIP-198's examples: 3^(p-1) mod p and 0^(p-1) mod p for the secp256k1 field prime
+    const FERMAT: &str = "0000000000000000000000000000000000000000000000000000000000000001\
+        0000000000000000000000000000000000000000000000000000000000000020\
+        0000000000000000000000000000000000000000000000000000000000000020\
+        03\
+        fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e\
+        fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f";
+    const ZERO_BASE: &str = "0000000000000000000000000000000000000000000000000000000000000000\
+        0000000000000000000000000000000000000000000000000000000000000020\
+        0000000000000000000000000000000000000000000000000000000000000020\
+        fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e\
+        fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f";
+
+    #[test]
+    fn eip198_examples() {
+        let input = hex::decode(FERMAT).unwrap();
+        let mut one = vec![0; 32];
+        one[31] = 1;
+        // 32 * 32 squared, over 20, times the exponent's 255 bits
+        assert_eq!(byzantium(&input, 13056), Ok(PrecompileOutput::new(13056, one.clone())));
+        assert_eq!(byzantium(&input, 13055), Err(PrecompileError::OutOfGas));
+        // EIP-2565: 4 words squared, times 255, over 3
+        assert_eq!(berlin(&input, 1360), Ok(PrecompileOutput::new(1360, one)));
+
+        let input = hex::decode(ZERO_BASE).unwrap();
+        assert_eq!(berlin(&input, 1360), Ok(PrecompileOutput::new(1360, vec![0; 32])));
+    }
+
+    #[test]
+    fn short_input_reads_as_zero_padded() {
+        // base_len 1, exp_len 1, mod_len 1, base 2, exp 5, the modulus byte missing: x mod 0 is 0
+        let input = hex::decode(&format!("{:064x}{:064x}{:064x}0205", 1, 1, 1)).unwrap();
+        assert_eq!(berlin(&input, 200).unwrap().output, vec![0]);
+        let input = hex::decode(&format!("{:064x}{:064x}{:064x}02050d", 1, 1, 1)).unwrap();
+        assert_eq!(berlin(&input, 200), Ok(PrecompileOutput::new(200, vec![6])));
+        // Lengths too large to pay for fail without allocating
+        let input = hex::decode(&format!("{:064x}{:064x}{:064x}", 1, 1, u64::MAX)).unwrap();
+        assert_eq!(berlin(&input, usize::MAX), Err(PrecompileError::OutOfGas));
+    }
+}
@@ -0,0 +1,150 @@
+use ethereum_types::{H160, H256, U256, U64};
+use serde::{Deserialize, Serialize};
+use crate::hex::Bytes;
+use crate::rlp;
+use crate::trie::{secure_key, verify_proof, ProofError, EMPTY_ROOT};
+use crate::state::EMPTY_CODE_HASH;
+use crate::State;
+
+// Response of eth_getProof: the account with its proof against the state root, and each
+// requested slot with its proof against the account's storage root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountProof {
+    pub address: H160,
+    pub account_proof: Vec<Bytes>,
+    pub balance: U256,
+    pub code_hash: H256,
+    pub nonce: U64,
+    pub storage_hash: H256,
+    pub storage_proof: Vec<StorageProof>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageProof {
+    pub key: U256,
+    pub value: U256,
+    pub proof: Vec<Bytes>,
+}
+
+impl AccountProof {
+    // Checks the account against `state_root` and every slot against the account's storage hash
+    pub fn verify(&self, state_root: H256) -> Result<(), ProofError> {
+        let account_proof: Vec<Vec<u8>> = self.account_proof.iter().map(|node| node.0.clone()).collect();
+        let proven = verify_proof(state_root, &secure_key(self.address.as_bytes()), &account_proof)?;
+        let expected = rlp::encode_list(&[
+            rlp::encode_u64(self.nonce.as_u64()),
+            rlp::encode_u256(self.balance),
+            rlp::encode_h256(&self.storage_hash),
+            rlp::encode_h256(&self.code_hash),
+        ]);
+        let matches = match proven {
+            Some(value) => value == expected,
+            // An absent account can only be claimed empty
+            None => self.nonce.is_zero() && self.balance.is_zero() && self.storage_hash == EMPTY_ROOT && self.code_hash == EMPTY_CODE_HASH,
+        };
+        if !matches {
+            return Err(ProofError::ValueMismatch);
+        }
+
+        for storage in &self.storage_proof {
+            let proof: Vec<Vec<u8>> = storage.proof.iter().map(|node| node.0.clone()).collect();
+            let proven = verify_proof(self.storage_hash, &secure_key(&u256_bytes(storage.key)), &proof)?;
+            let value = match proven {
+                Some(value) => rlp::Rlp::decode(&value)?.as_u256()?,
+                None => U256::zero(),
+            };
+            if value != storage.value {
+                return Err(ProofError::ValueMismatch);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl State {
+    // eth_getProof for `address` and `slots` against the current state root. `None` with a
+    // database, as the tries would only hold what has been loaded from it
+    pub fn proof(&mut self, address: H160, slots: &[U256]) -> Option<AccountProof> {
+        let state_trie = self.state_trie()?;
+        let account = self.account(&address).cloned().unwrap_or_default();
+        let account_proof = state_trie.proof(&secure_key(address.as_bytes()));
+        let storage_trie = account.storage.trie();
+
+        let storage_proof = slots
+            .iter()
+            .map(|&key| StorageProof {
+                key,
+                value: account.storage.get(key),
+                proof: storage_trie.proof(&secure_key(&u256_bytes(key))).into_iter().map(Bytes).collect(),
+            })
+            .collect();
+
+        Some(AccountProof {
+            address,
+            account_proof: account_proof.into_iter().map(Bytes).collect(),
+            balance: account.balance,
+            code_hash: account.code_hash(),
+            nonce: U64::from(account.nonce),
+            storage_hash: storage_trie.root(),
+            storage_proof,
+        })
+    }
+}
+
+fn u256_bytes(value: U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Account, InMemoryDb};
+
+    fn state() -> State {
+        let mut state = State::new();
+        for byte in 1..=20u8 {
+            let mut account = Account::new(U256::from(byte) * 1000).with_code(vec![byte]);
+            for key in 0..u64::from(byte) {
+                account.storage.store(U256::from(key), U256::from(key + 1));
+            }
+            state.insert_account(H160::repeat_byte(byte), account);
+        }
+        state
+    }
+
+    #[test]
+    fn proofs_verify_against_the_state_root() {
+        let mut state = state();
+        let root = state.state_root().unwrap();
+        let slots = [U256::zero(), U256::from(4), U256::from(1000)];
+        let proof = state.proof(H160::repeat_byte(5), &slots).unwrap();
+        assert_eq!(proof.storage_proof.iter().map(|slot| slot.value).collect::<Vec<_>>(), [U256::one(), U256::from(5), U256::zero()]);
+        proof.verify(root).unwrap();
+
+        // An absent account is proven empty
+        let absent = state.proof(H160::repeat_byte(0xee), &[U256::one()]).unwrap();
+        assert_eq!(absent.storage_hash, EMPTY_ROOT);
+        absent.verify(root).unwrap();
+
+        // Over another root, or with a value changed, the proof fails
+        assert!(proof.verify(H256::repeat_byte(1)).is_err());
+        let mut forged = proof.clone();
+        forged.storage_proof[1].value = U256::from(6);
+        assert_eq!(forged.verify(root), Err(ProofError::ValueMismatch));
+        let mut forged = proof;
+        forged.balance += U256::one();
+        assert_eq!(forged.verify(root), Err(ProofError::ValueMismatch));
+    }
+
+    #[test]
+    fn no_proof_or_root_over_a_database() {
+        let mut database = InMemoryDb::new();
+        database.insert_account(H160::repeat_byte(1), U256::one(), 0, Vec::new());
+        let mut state = State::with_database(database);
+        assert_eq!(state.state_root(), None);
+        assert_eq!(state.proof(H160::repeat_byte(1), &[]), None);
+    }
+}
@@ -0,0 +1,174 @@
+use ethereum_types::{Bloom, BloomInput, H160, H256, U256};
+use crate::evm::LogEntry;
+use crate::rlp;
+use crate::transaction::TxType;
+use crate::trie::ordered_trie_root;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    pub tx_type: TxType,
+    pub status: bool,
+    pub gas_used: u64,
+    pub cumulative_gas_used: u64, // gas used in the block up to and including this transaction
+    pub effective_gas_price: U256,
+    pub priority_fee: U256, // paid to the coinbase
+    pub burnt_fee: U256, // gas_used * base_fee, removed from circulation
+    pub blob_gas_used: u64,
+    pub blob_gas_price: U256, // the blob fee is burnt as well
+    pub contract_address: Option<H160>,
+    pub output: Vec<u8>,
+    pub logs: Vec<LogEntry>,
+    pub logs_bloom: Bloom,
+}
+
+impl Receipt {
+    // Consensus encoding: rlp([status, cumulative_gas_used, logs_bloom, logs]), prefixed with
+    // the transaction type for typed transactions (EIP-2718)
+    pub fn encode(&self) -> Vec<u8> {
+        let logs: Vec<Vec<u8>> = self.logs.iter().map(encode_log).collect();
+        let payload = rlp::encode_list(&[
+            rlp::encode_u64(self.status as u64),
+            rlp::encode_u64(self.cumulative_gas_used),
+            rlp::encode_bytes(self.logs_bloom.as_bytes()),
+            rlp::encode_list(&logs),
+        ]);
+        if self.tx_type == TxType::Legacy {
+            return payload;
+        }
+        let mut out = vec![self.tx_type.as_u8()];
+        out.extend(payload);
+        out
+    }
+}
+
+// 2048-bit bloom filter over the addresses and topics of `logs`
+pub fn logs_bloom(logs: &[LogEntry]) -> Bloom {
+    let mut bloom = Bloom::zero();
+    for log in logs {
+        bloom.accrue(BloomInput::Raw(log.address.as_bytes()));
+        for topic in &log.topics {
+            bloom.accrue(BloomInput::Raw(topic.as_bytes()));
+        }
+    }
+    bloom
+}
+
+pub fn receipts_root(receipts: &[Receipt]) -> H256 {
+    ordered_trie_root(&receipts.iter().map(Receipt::encode).collect::<Vec<_>>())
+}
+
+fn encode_log(log: &LogEntry) -> Vec<u8> {
+    let topics: Vec<Vec<u8>> = log.topics.iter().map(rlp::encode_h256).collect();
+    rlp::encode_list(&[rlp::encode_address(&log.address), rlp::encode_list(&topics), rlp::encode_bytes(&log.data)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+    use crate::transaction::{BlockEnv, BlockExecutor, Transaction};
+    use crate::{Account, State};
+
+    // The log of ethbloom's bloom test vector, with a word of data
+    fn log() -> LogEntry {
+        LogEntry {
+            address: H160::from_slice(&hex::decode("ef2d6d194084c2de36e0dabfce45d046b37d1106").unwrap()),
+            topics: vec![H256::from_slice(&hex::decode("02c69be41d0b7e40352fc85be1cd65eb03d40ef8427a0ca4596b1ead9a00e9fc").unwrap())],
+            data: hex::decode("0000000000000000000000000000000000000000000000000de0b6b3a7640000").unwrap(),
+        }
+    }
+
+    fn receipt(tx_type: TxType, status: bool, cumulative_gas_used: u64, logs: Vec<LogEntry>) -> Receipt {
+        Receipt {
+            tx_type,
+            status,
+            gas_used: 0,
+            cumulative_gas_used,
+            effective_gas_price: U256::zero(),
+            priority_fee: U256::zero(),
+            burnt_fee: U256::zero(),
+            blob_gas_used: 0,
+            blob_gas_price: U256::zero(),
+            contract_address: None,
+            output: Vec::new(),
+            logs_bloom: logs_bloom(&logs),
+            logs,
+        }
+    }
+
+    const LOG_BLOOM: &str = "b9010000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002020000000000000000000000000000000000000000000008000000001000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000";
+    const LOG: &str = "f85af85894ef2d6d194084c2de36e0dabfce45d046b37d1106e1a002c69be41d0b7e40352fc85be1cd65eb03d40ef8427a0ca4596b1ead9a00e9fca00000000000000000000000000000000000000000000000000de0b6b3a7640000";
+
+    #[test]
+    fn bloom_matches_ethbloom_vector() {
+        let expected = concat!(
+            "00000000000000000000000000000000",
+            "00000000100000000000000000000000",
+            "00000000000000000000000000000000",
+            "00000000000000000000000000000000",
+            "00000000000000000000000000000000",
+            "00000000000000000000000000000000",
+            "00000002020000000000000000000000",
+            "00000000000000000000000800000000",
+            "10000000000000000000000000000000",
+            "00000000000000000000001000000000",
+            "00000000000000000000000000000000",
+            "00000000000000000000000000000000",
+            "00000000000000000000000000000000",
+            "00000000000000000000000000000000",
+            "00000000000000000000000000000000",
+            "00000000000000000000000000000000",
+        );
+        assert_eq!(hex::encode(logs_bloom(&[log()]).as_bytes()), format!("0x{expected}"));
+        assert_eq!(logs_bloom(&[]), Bloom::zero());
+    }
+
+    // Expected encodings and roots were worked out with a separate keccak, RLP and trie
+    // implementation, checked against the standard trie vectors
+    #[test]
+    fn encodes_each_transaction_type() {
+        let zero_bloom = format!("b90100{}", "00".repeat(256));
+        let cases = [
+            (receipt(TxType::Legacy, true, 21000, vec![]), format!("f9010801825208{zero_bloom}c0")),
+            (receipt(TxType::AccessList, true, 21000, vec![]), format!("01f9010801825208{zero_bloom}c0")),
+            (receipt(TxType::DynamicFee, true, 74160, vec![log()]), format!("02f9016401830121b0{LOG_BLOOM}{LOG}")),
+            (receipt(TxType::Blob, false, 100000, vec![]), format!("03f9010980830186a0{zero_bloom}c0")),
+            (receipt(TxType::SetCode, true, 21000, vec![log()]), format!("04f9016301825208{LOG_BLOOM}{LOG}")),
+        ];
+        for (receipt, expected) in cases {
+            assert_eq!(hex::encode(&receipt.encode()), format!("0x{expected}"), "{:?}", receipt.tx_type);
+        }
+    }
+
+    #[test]
+    fn receipts_root_of_mixed_types() {
+        let empty = "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421";
+        assert_eq!(hex::encode(receipts_root(&[]).as_bytes()), empty);
+        let receipts = [
+            receipt(TxType::Legacy, true, 21000, vec![]),
+            receipt(TxType::DynamicFee, true, 74160, vec![log()]),
+            receipt(TxType::Blob, false, 100000, vec![]),
+        ];
+        let expected = "0x4262e787ed80ad79145a7e668d64e32fbbe00bfc25bc760c4397e4502f4c489f";
+        assert_eq!(hex::encode(receipts_root(&receipts).as_bytes()), expected);
+    }
+
+    #[test]
+    fn cumulative_gas_adds_up_over_a_block() {
+        let sender = H160([0x11; 20]);
+        let mut state = State::new();
+        state.insert_account(sender, Account::new(U256::from(10).pow(U256::from(18))));
+        let mut executor = BlockExecutor::new(BlockEnv { gas_limit: 70_000, ..BlockEnv::default() });
+        for nonce in 0..3 {
+            let tx = Transaction { caller: sender, to: Some(H160([0x22; 20])), nonce, gas_limit: 21000, gas_price: U256::one(), ..Default::default() };
+            assert_eq!(executor.execute(&tx, &mut state).unwrap().cumulative_gas_used, 21000 * (nonce + 1));
+        }
+        // Only 7000 gas is left in the block
+        let tx = Transaction { caller: sender, to: Some(H160([0x22; 20])), nonce: 3, gas_limit: 21000, gas_price: U256::one(), ..Default::default() };
+        assert!(executor.execute(&tx, &mut state).is_err());
+        assert_eq!(executor.cumulative_gas_used(), 63000);
+        assert_eq!(executor.receipts().len(), 3);
+        let expected = "0x251f2cb798e965c5d9b11c882f37c69fd2c42b314fabe64d2b4998c76eb93ae8";
+        assert_eq!(hex::encode(executor.receipts_root().as_bytes()), expected);
+    }
+}
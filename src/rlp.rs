@@ -0,0 +1,320 @@
+use ethereum_types::{H160, H256, U256};
+use thiserror::Error;
+
+// Lists nest at most this deep. Nothing Ethereum encodes comes close, and the limit keeps crafted
+// input from exhausting the stack
+const MAX_DEPTH: usize = 128;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RlpError {
+    #[error("Unexpected end of input")]
+    UnexpectedEnd,
+    #[error("Trailing bytes after the item")]
+    TrailingBytes,
+    #[error("Length is not minimally encoded")]
+    NonCanonicalSize,
+    #[error("Integer has leading zero bytes")]
+    LeadingZero,
+    #[error("Integer is too large")]
+    IntegerOverflow,
+    #[error("Expected a list")]
+    ExpectedList,
+    #[error("Expected a byte string")]
+    ExpectedBytes,
+    #[error("Expected {expected} bytes, got {got}")]
+    InvalidLength { expected: usize, got: usize },
+    #[error("Lists nested more than {MAX_DEPTH} deep")]
+    TooDeep,
+}
+
+// A decoded RLP item: a byte string or a list of items
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rlp {
+    Bytes(Vec<u8>),
+    List(Vec<Rlp>),
+}
+
+impl Rlp {
+    // Decodes exactly one item spanning all of `input`
+    pub fn decode(input: &[u8]) -> Result<Rlp, RlpError> {
+        let (item, rest) = decode_item(input, 0)?;
+        if !rest.is_empty() {
+            return Err(RlpError::TrailingBytes);
+        }
+        Ok(item)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Rlp::Bytes(bytes) => encode_bytes(bytes),
+            Rlp::List(items) => encode_list(&items.iter().map(Rlp::encode).collect::<Vec<_>>()),
+        }
+    }
+
+    pub fn as_bytes(&self) -> Result<&[u8], RlpError> {
+        match self {
+            Rlp::Bytes(bytes) => Ok(bytes),
+            Rlp::List(_) => Err(RlpError::ExpectedBytes),
+        }
+    }
+
+    pub fn as_list(&self) -> Result<&[Rlp], RlpError> {
+        match self {
+            Rlp::List(items) => Ok(items),
+            Rlp::Bytes(_) => Err(RlpError::ExpectedList),
+        }
+    }
+
+    pub fn as_u64(&self) -> Result<u64, RlpError> {
+        let bytes = integer_bytes(self.as_bytes()?, 8)?;
+        Ok(bytes.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64))
+    }
+
+    pub fn as_u256(&self) -> Result<U256, RlpError> {
+        let bytes = integer_bytes(self.as_bytes()?, 32)?;
+        Ok(U256::from_big_endian(bytes))
+    }
+
+    pub fn as_address(&self) -> Result<H160, RlpError> {
+        let bytes = self.as_bytes()?;
+        if bytes.len() != 20 {
+            return Err(RlpError::InvalidLength { expected: 20, got: bytes.len() });
+        }
+        Ok(H160::from_slice(bytes))
+    }
+
+    // An empty string stands for no address, as in the `to` of a contract creation
+    pub fn as_optional_address(&self) -> Result<Option<H160>, RlpError> {
+        if self.as_bytes()?.is_empty() {
+            return Ok(None);
+        }
+        self.as_address().map(Some)
+    }
+
+    pub fn as_h256(&self) -> Result<H256, RlpError> {
+        let bytes = self.as_bytes()?;
+        if bytes.len() != 32 {
+            return Err(RlpError::InvalidLength { expected: 32, got: bytes.len() });
+        }
+        Ok(H256::from_slice(bytes))
+    }
+}
+
+pub fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut out = encode_length(bytes.len(), 0x80);
+    out.extend_from_slice(bytes);
+    out
+}
+
+// `items` are already encoded
+pub fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload_len = items.iter().map(Vec::len).sum();
+    let mut out = encode_length(payload_len, 0xc0);
+    for item in items {
+        out.extend_from_slice(item);
+    }
+    out
+}
+
+pub fn encode_u64(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let first = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    encode_bytes(&bytes[first..])
+}
+
+pub fn encode_u256(value: U256) -> Vec<u8> {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    let first = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    encode_bytes(&bytes[first..])
+}
+
+pub fn encode_address(address: &H160) -> Vec<u8> {
+    encode_bytes(address.as_bytes())
+}
+
+pub fn encode_optional_address(address: &Option<H160>) -> Vec<u8> {
+    match address {
+        Some(address) => encode_address(address),
+        None => encode_bytes(&[]),
+    }
+}
+
+pub fn encode_h256(hash: &H256) -> Vec<u8> {
+    encode_bytes(hash.as_bytes())
+}
+
+fn encode_length(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let len_bytes = len.to_be_bytes();
+    let first = len_bytes.iter().position(|&b| b != 0).unwrap();
+    let mut out = vec![offset + 55 + (len_bytes.len() - first) as u8];
+    out.extend_from_slice(&len_bytes[first..]);
+    out
+}
+
+// Splits the first item off `input`, found `depth` lists deep
+fn decode_item(input: &[u8], depth: usize) -> Result<(Rlp, &[u8]), RlpError> {
+    let prefix = *input.first().ok_or(RlpError::UnexpectedEnd)?;
+    match prefix {
+        0x00..=0x7f => Ok((Rlp::Bytes(vec![prefix]), &input[1..])),
+        0x80..=0xbf => {
+            let (payload, rest) = split_payload(input, 0x80)?;
+            if payload.len() == 1 && payload[0] < 0x80 {
+                return Err(RlpError::NonCanonicalSize); // should have been a single byte
+            }
+            Ok((Rlp::Bytes(payload.to_vec()), rest))
+        }
+        0xc0..=0xff => {
+            if depth == MAX_DEPTH {
+                return Err(RlpError::TooDeep);
+            }
+            let (mut payload, rest) = split_payload(input, 0xc0)?;
+            let mut items = Vec::new();
+            while !payload.is_empty() {
+                let (item, remaining) = decode_item(payload, depth + 1)?;
+                items.push(item);
+                payload = remaining;
+            }
+            Ok((Rlp::List(items), rest))
+        }
+    }
+}
+
+// Reads the length prefix at the start of `input` and returns the payload and what follows it
+fn split_payload(input: &[u8], offset: u8) -> Result<(&[u8], &[u8]), RlpError> {
+    let prefix = input[0] - offset;
+    let (header_len, payload_len) = if prefix < 56 {
+        (1, prefix as usize)
+    } else {
+        let len_of_len = (prefix - 55) as usize;
+        let len_bytes = input.get(1..1 + len_of_len).ok_or(RlpError::UnexpectedEnd)?;
+        if len_bytes[0] == 0 || len_of_len > 8 {
+            return Err(RlpError::NonCanonicalSize);
+        }
+        let payload_len = len_bytes.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        if payload_len < 56 {
+            return Err(RlpError::NonCanonicalSize);
+        }
+        (1 + len_of_len, payload_len)
+    };
+    let end = header_len.checked_add(payload_len).ok_or(RlpError::UnexpectedEnd)?;
+    if input.len() < end {
+        return Err(RlpError::UnexpectedEnd);
+    }
+    Ok((&input[header_len..end], &input[end..]))
+}
+
+fn integer_bytes(bytes: &[u8], max_len: usize) -> Result<&[u8], RlpError> {
+    if bytes.len() > max_len {
+        return Err(RlpError::IntegerOverflow);
+    }
+    if bytes.first() == Some(&0) {
+        return Err(RlpError::LeadingZero);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::hex;
+
+    fn decode_hex(input: &str) -> Result<Rlp, RlpError> {
+        Rlp::decode(&hex::decode(input).unwrap())
+    }
+
+    #[test]
+    fn known_encodings_round_trip() {
+        let lorem = b"Lorem ipsum dolor sit amet, consectetur adipisicing elit".to_vec();
+        let set = |items: Vec<Rlp>| Rlp::List(items);
+        let cases = [
+            (Rlp::Bytes(b"dog".to_vec()), "83646f67".to_string()),
+            (set(vec![Rlp::Bytes(b"cat".to_vec()), Rlp::Bytes(b"dog".to_vec())]), "c88363617483646f67".to_string()),
+            (Rlp::Bytes(Vec::new()), "80".to_string()),
+            (set(Vec::new()), "c0".to_string()),
+            (Rlp::Bytes(vec![0x00]), "00".to_string()),
+            (Rlp::Bytes(vec![0x0f]), "0f".to_string()),
+            (Rlp::Bytes(vec![0x80]), "8180".to_string()),
+            (Rlp::Bytes(vec![0x04, 0x00]), "820400".to_string()),
+            // The set theoretical representation of three
+            (
+                set(vec![set(vec![]), set(vec![set(vec![])]), set(vec![set(vec![]), set(vec![set(vec![])])])]),
+                "c7c0c1c0c3c0c1c0".to_string(),
+            ),
+            (Rlp::Bytes(lorem.clone()), format!("b838{}", hex::encode(&lorem).trim_start_matches("0x"))),
+            (set(vec![Rlp::Bytes(lorem.clone())]), format!("f83ab838{}", hex::encode(&lorem).trim_start_matches("0x"))),
+        ];
+        for (item, encoded) in cases {
+            assert_eq!(hex::encode(&item.encode()), format!("0x{}", encoded));
+            assert_eq!(decode_hex(&encoded), Ok(item));
+        }
+
+        let long = vec![0xab; 1024];
+        assert_eq!(encode_bytes(&long)[..3], [0xb9, 0x04, 0x00]);
+        assert_eq!(Rlp::decode(&encode_bytes(&long)), Ok(Rlp::Bytes(long)));
+    }
+
+    #[test]
+    fn integers_encode_minimally() {
+        assert_eq!(encode_u64(0), vec![0x80]);
+        assert_eq!(encode_u64(127), vec![0x7f]);
+        assert_eq!(encode_u64(128), vec![0x81, 0x80]);
+        assert_eq!(encode_u64(1024), vec![0x82, 0x04, 0x00]);
+        assert_eq!(encode_u256(U256::MAX)[..2], [0xa0, 0xff]);
+        for value in [0, 1, 127, 128, 255, 256, u64::MAX] {
+            assert_eq!(Rlp::decode(&encode_u64(value)).unwrap().as_u64(), Ok(value));
+        }
+        assert_eq!(Rlp::decode(&encode_u256(U256::MAX)).unwrap().as_u256(), Ok(U256::MAX));
+    }
+
+    #[test]
+    fn rejects_non_canonical_and_malformed_input() {
+        // A single byte below 0x80 is its own encoding
+        assert_eq!(decode_hex("8105"), Err(RlpError::NonCanonicalSize));
+        // Long form for a short string, and a length with a leading zero
+        assert_eq!(decode_hex("b803646f67"), Err(RlpError::NonCanonicalSize));
+        assert_eq!(decode_hex(&format!("b90038{}", "00".repeat(56))), Err(RlpError::NonCanonicalSize));
+        assert_eq!(decode_hex("f803c0c0c0"), Err(RlpError::NonCanonicalSize));
+        // Lengths past the end of the input, even absurd ones
+        assert_eq!(decode_hex("83646f"), Err(RlpError::UnexpectedEnd));
+        assert_eq!(decode_hex("c88363617483646f"), Err(RlpError::UnexpectedEnd));
+        assert_eq!(decode_hex("bfffffffffffffffff"), Err(RlpError::UnexpectedEnd));
+        assert_eq!(decode_hex("b9"), Err(RlpError::UnexpectedEnd));
+        assert_eq!(decode_hex(""), Err(RlpError::UnexpectedEnd));
+        assert_eq!(decode_hex("8000"), Err(RlpError::TrailingBytes));
+
+        // Integers with leading zeros or past their width
+        assert_eq!(decode_hex("820001").unwrap().as_u64(), Err(RlpError::LeadingZero));
+        assert_eq!(decode_hex("00").unwrap().as_u64(), Err(RlpError::LeadingZero));
+        assert_eq!(decode_hex("89010000000000000000").unwrap().as_u64(), Err(RlpError::IntegerOverflow));
+        assert_eq!(decode_hex("c0").unwrap().as_u64(), Err(RlpError::ExpectedBytes));
+        assert_eq!(decode_hex("80").unwrap().as_list(), Err(RlpError::ExpectedList));
+        assert_eq!(decode_hex("82abcd").unwrap().as_address(), Err(RlpError::InvalidLength { expected: 20, got: 2 }));
+        assert_eq!(decode_hex("80").unwrap().as_optional_address(), Ok(None));
+    }
+
+    fn nested(depth: usize) -> Vec<u8> {
+        (0..depth).fold(encode_bytes(&[]), |item, _| encode_list(&[item]))
+    }
+
+    #[test]
+    fn rejects_lists_nested_past_the_limit() {
+        assert!(Rlp::decode(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(Rlp::decode(&nested(MAX_DEPTH + 1)), Err(RlpError::TooDeep));
+        // Built back to front: 200k list headers, each covering everything after it
+        let mut reversed = vec![0xc0];
+        for _ in 0..200_000 {
+            let header = encode_length(reversed.len(), 0xc0);
+            reversed.extend(header.iter().rev());
+        }
+        reversed.reverse();
+        assert_eq!(Rlp::decode(&reversed), Err(RlpError::TooDeep));
+    }
+}
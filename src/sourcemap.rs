@@ -0,0 +1,425 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SourceMapError {
+    #[error("Invalid source map entry {index}: {entry:?}")]
+    InvalidEntry { index: usize, entry: String },
+}
+
+// How an instruction moves between functions, solc's `i`, `o` and `-`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jump {
+    Into,
+    Out,
+    Regular,
+}
+
+// The bytes of a source file an instruction was compiled from. `file` is `None` for code the
+// compiler generated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceRange {
+    pub offset: usize,
+    pub length: usize,
+    pub file: Option<usize>,
+    pub jump: Jump,
+}
+
+// A contract, function or modifier within a source file, from its keyword to the end of its body
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Scope {
+    contract: bool,
+    name: String,
+    start: usize,
+    end: usize,
+}
+
+// A Solidity file with its line starts and the contracts and functions it defines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub name: String,
+    pub content: String,
+    lines: Vec<usize>,
+    scopes: Vec<Scope>,
+}
+
+// Where an instruction is in the source: 1-based line and byte column, the enclosing function as
+// `Contract.function` and the text of the line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub function: Option<String>,
+    pub text: String,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)?;
+        if let Some(function) = &self.function {
+            write!(f, " in {}", function)?;
+        }
+        Ok(())
+    }
+}
+
+// The source with comments and string literals blanked out, keeping every offset
+fn strip(content: &str) -> Vec<u8> {
+    let mut bytes = content.as_bytes().to_vec();
+    let mut index = 0;
+    while index < bytes.len() {
+        let end = match (bytes[index], bytes.get(index + 1)) {
+            (b'/', Some(b'/')) => bytes[index..].iter().position(|byte| *byte == b'\n').map_or(bytes.len(), |end| index + end),
+            (b'/', Some(b'*')) => content[index + 2..].find("*/").map_or(bytes.len(), |end| index + end + 4),
+            (quote @ (b'"' | b'\''), _) => {
+                let mut end = index + 1;
+                while end < bytes.len() && bytes[end] != quote && bytes[end] != b'\n' {
+                    end += if bytes[end] == b'\\' { 2 } else { 1 };
+                }
+                (end + 1).min(bytes.len())
+            }
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+        for byte in &mut bytes[index..end] {
+            if *byte != b'\n' {
+                *byte = b' ';
+            }
+        }
+        index = end;
+    }
+    bytes
+}
+
+fn is_identifier(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$'
+}
+
+// Contracts, functions and modifiers with a body, found by their keywords and matching braces
+fn scopes(code: &[u8]) -> Vec<Scope> {
+    let word = |start: usize| {
+        let end = code[start..].iter().position(|byte| !is_identifier(*byte)).map_or(code.len(), |end| start + end);
+        (end, String::from_utf8_lossy(&code[start..end]).to_string())
+    };
+    let skip_spaces = |start: usize| code[start..].iter().position(|byte| !byte.is_ascii_whitespace()).map_or(code.len(), |skip| start + skip);
+
+    let mut scopes = Vec::new();
+    let mut index = 0;
+    while index < code.len() {
+        if !is_identifier(code[index]) || (index > 0 && is_identifier(code[index - 1])) {
+            index += 1;
+            continue;
+        }
+        let keyword_start = index;
+        let (end, keyword) = word(index);
+        index = end;
+        let contract = matches!(keyword.as_str(), "contract" | "library" | "interface");
+        let name = match keyword.as_str() {
+            "contract" | "library" | "interface" | "function" | "modifier" => {
+                let start = skip_spaces(end);
+                match code.get(start) {
+                    Some(byte) if is_identifier(*byte) => word(start).1,
+                    _ => continue,
+                }
+            }
+            "constructor" | "fallback" | "receive" if code.get(skip_spaces(end)) == Some(&b'(') => keyword,
+            _ => continue,
+        };
+
+        // The body is the first brace outside parentheses, a semicolon first meaning there is none
+        let mut depth = 0usize;
+        let mut open = None;
+        for (offset, byte) in code[end..].iter().enumerate() {
+            match byte {
+                b'(' => depth += 1,
+                b')' => depth = depth.saturating_sub(1),
+                b'{' if depth == 0 => {
+                    open = Some(end + offset);
+                    break;
+                }
+                b';' if depth == 0 && !contract => break,
+                _ => {}
+            }
+        }
+        let Some(open) = open else {
+            continue;
+        };
+        let mut braces = 0usize;
+        let close = code[open..].iter().enumerate().find_map(|(offset, byte)| {
+            match byte {
+                b'{' => braces += 1,
+                b'}' => braces -= 1,
+                _ => {}
+            }
+            (braces == 0).then_some(open + offset)
+        });
+        scopes.push(Scope { contract, name, start: keyword_start, end: close.unwrap_or(code.len()) });
+    }
+    scopes
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, content: impl Into<String>) -> Self {
+        let content = content.into();
+        let lines = std::iter::once(0).chain(content.match_indices('\n').map(|(index, _)| index + 1)).collect();
+        let scopes = scopes(&strip(&content));
+        SourceFile { name: name.into(), content, lines, scopes }
+    }
+
+    // 1-based line and column of a byte offset
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = self.lines.partition_point(|start| *start <= offset);
+        (line, offset - self.lines[line - 1] + 1)
+    }
+
+    // The text of a 1-based line, without its line break
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let start = *self.lines.get(line.checked_sub(1)?)?;
+        let end = self.lines.get(line).map_or(self.content.len(), |next| next - 1);
+        Some(self.content[start..end].trim_end_matches('\r'))
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    // The innermost function or modifier around `offset`, with its contract
+    pub fn function_at(&self, offset: usize) -> Option<String> {
+        let around = |scope: &&Scope| scope.start <= offset && offset <= scope.end;
+        let innermost = |contract: bool| self.scopes.iter().filter(|scope| scope.contract == contract).filter(around).max_by_key(|scope| scope.start);
+        match (innermost(true), innermost(false)) {
+            (Some(contract), Some(function)) => Some(format!("{}.{}", contract.name, function.name)),
+            (None, Some(function)) => Some(function.name.clone()),
+            _ => None,
+        }
+    }
+}
+
+// solc's `srcmap-runtime` (or `srcmap`) for a contract's code, mapping every pc to the source it
+// was compiled from. Sources are added by their index in solc's source list:
+//
+//     let map = SourceMap::new(&code, &srcmap)?.with_source(0, "Token.sol", source);
+//     let location = map.location(pc);
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    code: Vec<u8>,
+    ranges: Vec<SourceRange>,
+    // Index into `ranges` of the instruction at each pc, `None` within PUSH data
+    instructions: Vec<Option<usize>>,
+    sources: BTreeMap<usize, SourceFile>,
+}
+
+impl SourceMap {
+    // Entries are `s:l:f:j:m` separated by semicolons, empty or missing fields repeating the
+    // previous entry's. The modifier depth `m` is not kept
+    pub fn new(code: &[u8], srcmap: &str) -> Result<SourceMap, SourceMapError> {
+        let mut ranges = Vec::new();
+        let mut previous = SourceRange { offset: 0, length: 0, file: None, jump: Jump::Regular };
+        let srcmap = srcmap.trim();
+        for (index, entry) in srcmap.split(';').enumerate().filter(|_| !srcmap.is_empty()) {
+            let invalid = || SourceMapError::InvalidEntry { index, entry: entry.to_string() };
+            let fields: Vec<&str> = entry.split(':').collect();
+            let field = |position: usize| fields.get(position).copied().filter(|field| !field.is_empty());
+            let mut range = previous;
+            if let Some(offset) = field(0) {
+                range.offset = offset.parse().map_err(|_| invalid())?;
+            }
+            if let Some(length) = field(1) {
+                range.length = length.parse().map_err(|_| invalid())?;
+            }
+            if let Some(file) = field(2) {
+                let file: i64 = file.parse().map_err(|_| invalid())?;
+                range.file = usize::try_from(file).ok();
+            }
+            if let Some(jump) = field(3) {
+                range.jump = match jump {
+                    "i" => Jump::Into,
+                    "o" => Jump::Out,
+                    "-" => Jump::Regular,
+                    _ => return Err(invalid()),
+                };
+            }
+            if let Some(depth) = field(4) {
+                depth.parse::<usize>().map_err(|_| invalid())?;
+            }
+            ranges.push(range);
+            previous = range;
+        }
+
+        let mut instructions = vec![None; code.len()];
+        let mut pc = 0;
+        let mut index = 0;
+        while pc < code.len() {
+            instructions[pc] = (index < ranges.len()).then_some(index);
+            pc += match code[pc] {
+                op @ 0x60..=0x7f => (op - 0x5e) as usize,
+                _ => 1,
+            };
+            index += 1;
+        }
+        Ok(SourceMap { code: code.to_vec(), ranges, instructions, sources: BTreeMap::new() })
+    }
+
+    pub fn with_source(mut self, index: usize, name: impl Into<String>, content: impl Into<String>) -> Self {
+        self.sources.insert(index, SourceFile::new(name, content));
+        self
+    }
+
+    // The code the map was made for
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    pub fn source(&self, index: usize) -> Option<&SourceFile> {
+        self.sources.get(&index)
+    }
+
+    pub fn range(&self, pc: usize) -> Option<&SourceRange> {
+        self.ranges.get((*self.instructions.get(pc)?)?)
+    }
+
+    // The file and range of the instruction at `pc`, if it maps into a known source
+    pub fn source_range(&self, pc: usize) -> Option<(&SourceFile, &SourceRange)> {
+        let range = self.range(pc)?;
+        let source = self.sources.get(&range.file?)?;
+        (range.offset <= source.content.len()).then_some((source, range))
+    }
+
+    pub fn location(&self, pc: usize) -> Option<SourceLocation> {
+        let (source, range) = self.source_range(pc)?;
+        let (line, column) = source.line_column(range.offset);
+        Some(SourceLocation {
+            file: source.name.clone(),
+            line,
+            column,
+            function: source.function_at(range.offset),
+            text: source.line(line).unwrap_or_default().trim().to_string(),
+        })
+    }
+
+    // The lines around the one of `pc`, numbered, with that one marked
+    pub fn listing(&self, pc: usize, context: usize) -> Option<String> {
+        let (source, range) = self.source_range(pc)?;
+        let (current, _) = source.line_column(range.offset);
+        let (last, _) = source.line_column(range.offset + range.length.saturating_sub(1));
+        let mut out = String::new();
+        for line in current.saturating_sub(context).max(1)..=(current + context).min(source.line_count()) {
+            let marker = if (current..=last).contains(&line) { "→" } else { " " };
+            out.push_str(&format!("{} {:>5}  {}\n", marker, line, source.line(line).unwrap_or_default()));
+        }
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    const COUNTER: &str = "// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+contract Counter {
+    uint256 public count;
+
+    function increment() public {
+        count += 1;
+    }
+}
+";
+
+    // A cut-down runtime of Counter: the callvalue check, a jump into `increment` at 0x1b and its
+    // body, with PUSH1 and PUSH4 data in between
+    const CODE: &str = "6080604052348015600e575f80fd5b5063d09de08a601b5600fefe5b60015f54015f5556";
+
+    // In solc's compressed `s:l:f:j:m` form, one entry per instruction: empty fields repeat the
+    // previous entry, -1 marks compiler-generated code, `i` and `o` jumps into and out of a function
+    const SRCMAP: &str = "58:107:0:-:0;;;;;;;;::-1;;;58:107:0;;108:55;;:::i;58:107::-;;;108:55;146:10;146:5;;146:10;146:5;146:10;108:55::o";
+
+    fn counter() -> SourceMap {
+        SourceMap::new(&hex::decode(CODE).unwrap(), SRCMAP).unwrap().with_source(0, "Counter.sol", COUNTER)
+    }
+
+    fn range(offset: usize, length: usize, file: Option<usize>, jump: Jump) -> Option<SourceRange> {
+        Some(SourceRange { offset, length, file, jump })
+    }
+
+    #[test]
+    fn empty_fields_repeat_the_previous_entry() {
+        let map = counter();
+        let contract = range(58, 107, Some(0), Jump::Regular);
+        for pc in [0, 2, 4, 5, 6, 7, 8, 10] {
+            assert_eq!(map.range(pc).copied(), contract, "pc {}", pc);
+        }
+        // Only the file changes to -1, and stays so until an entry sets it again
+        for pc in [11, 12, 13] {
+            assert_eq!(map.range(pc).copied(), range(58, 107, None, Jump::Regular), "pc {}", pc);
+        }
+        assert_eq!(map.range(14).copied(), contract);
+        assert_eq!(map.range(16).copied(), range(108, 55, Some(0), Jump::Regular));
+        assert_eq!(map.range(23).copied(), range(108, 55, Some(0), Jump::Into));
+        // A jump marker is repeated too, until `-` resets it
+        assert_eq!(map.range(24).copied(), contract);
+        assert_eq!(map.range(26).copied(), contract);
+        assert_eq!(map.range(35).copied(), range(108, 55, Some(0), Jump::Out));
+    }
+
+    #[test]
+    fn push_data_has_no_instruction() {
+        let map = counter();
+        for pc in [1, 3, 9, 17, 18, 19, 20, 22, 29] {
+            assert_eq!(map.range(pc), None, "pc {}", pc);
+        }
+        assert_eq!(map.range(21).copied(), range(108, 55, Some(0), Jump::Regular));
+        assert_eq!(map.range(36), None);
+
+        // Entries past the code are ignored, instructions past the entries have no range, and a
+        // PUSH cut short by the end of the code does not overrun it
+        let short = SourceMap::new(&hex::decode("6001610102").unwrap(), "0:1:0;1:1:0;2:1:0").unwrap();
+        assert_eq!(short.range(2).map(|range| range.offset), Some(1));
+        assert_eq!(short.range(3), None);
+        let long = SourceMap::new(&hex::decode("600160026003").unwrap(), "0:1:0;1:1:0").unwrap();
+        assert_eq!(long.range(4), None);
+    }
+
+    #[test]
+    fn maps_pcs_to_lines_and_functions() {
+        let map = counter();
+        let location = map.location(28).unwrap();
+        assert_eq!((location.file.as_str(), location.line, location.column), ("Counter.sol", 8, 9));
+        assert_eq!(location.function.as_deref(), Some("Counter.increment"));
+        assert_eq!(location.text, "count += 1;");
+        assert_eq!(location.to_string(), "Counter.sol:8:9 in Counter.increment");
+
+        let entry = map.location(27).unwrap();
+        assert_eq!((entry.line, entry.column), (7, 5));
+        assert_eq!(entry.function.as_deref(), Some("Counter.increment"));
+        let dispatcher = map.location(14).unwrap();
+        assert_eq!((dispatcher.line, dispatcher.column), (4, 1));
+        assert_eq!(dispatcher.function, None);
+        // Compiler-generated code and PUSH data have no location
+        assert_eq!(map.location(11), None);
+        assert_eq!(map.location(29), None);
+
+        assert_eq!(
+            map.listing(28, 1).unwrap(),
+            "      7      function increment() public {\n→     8          count += 1;\n      9      }\n"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_entries() {
+        let code = hex::decode("600100").unwrap();
+        assert_eq!(
+            SourceMap::new(&code, "0:1:0;x:1"),
+            Err(SourceMapError::InvalidEntry { index: 1, entry: "x:1".to_string() })
+        );
+        assert!(SourceMap::new(&code, "0:1:0:z").is_err());
+        assert!(SourceMap::new(&code, "0:1:0:-:m").is_err());
+        assert_eq!(SourceMap::new(&code, "  ").unwrap().range(0), None);
+    }
+}
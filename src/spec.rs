@@ -0,0 +1,26 @@
+// Hard forks that change execution rules, in activation order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Spec {
+    Frontier,
+    Homestead,
+    TangerineWhistle,
+    SpuriousDragon,
+    Byzantium,
+    Constantinople,
+    Petersburg,
+    Istanbul,
+    Berlin,
+    London,
+    Merge,
+    Shanghai,
+    #[default]
+    Cancun,
+    Prague,
+}
+
+impl Spec {
+    // true if the rules introduced in `fork` apply under this spec
+    pub fn is_enabled(&self, fork: Spec) -> bool {
+        *self >= fork
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use ethereum_types::U256;
 #[derive(Debug)]
 pub struct Stack {
@@ -6,7 +7,7 @@ pub struct Stack {
 
 
 // Maximum stack size for EVM is 1024
-const MAX_SIZE: usize = 1024;
+pub const MAX_SIZE: usize = 1024;
 
 const ERR_STACK_OVERFLOW: &str = "Stack overflow";
 const ERR_STACK_UNDERFLOW: &str = "Stack underflow";
@@ -20,7 +21,7 @@ impl Stack {
     }
 
     pub fn pop(&mut self) -> U256 {
-        if self.data.len() == 0 {
+        if self.data.is_empty() {
             panic!("{}", ERR_STACK_UNDERFLOW);
         }
         self.data.pop().unwrap()
@@ -46,15 +47,35 @@ impl Stack {
         self.data.push(self.data[index]);
     }
 
-    pub fn to_string(&self) -> String {
-        let mut s = String::new();
-        for i in 0..self.data.len() {
-            s.push_str(&format!("{} ", self.data[i]));
-        }
-        s
-    }
-
     pub fn new() -> Self {
         Self { data: Vec::new() }
+    }
+
+    // Bottom first, the top of the stack is the last item
+    pub fn data(&self) -> &[U256] {
+        &self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
     }   
-}
\ No newline at end of file
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for value in &self.data {
+            write!(f, "{} ", value)?;
+        }
+        Ok(())
+    }
+}
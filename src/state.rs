@@ -0,0 +1,617 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use ethereum_types::{H160, H256, U256};
+use crate::helper::Helper;
+use crate::rlp;
+use crate::trie::{secure_key, Trie};
+use crate::database::{AccountInfo, ChangeSet, Database, DatabaseError};
+use crate::Storage;
+
+mod diff;
+pub use diff::{AccountDiff, AccountStatus, Change, StateDiff};
+
+// keccak256 of empty code
+pub const EMPTY_CODE_HASH: H256 = H256([
+    0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0,
+    0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+]);
+
+// EIP-7702: code of an account delegating to another is 0xef0100 || address
+pub const DELEGATION_PREFIX: [u8; 3] = [0xef, 0x01, 0x00];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Account {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code: Vec<u8>,
+    pub storage: Storage,
+}
+
+impl Account {
+    pub fn new(balance: U256) -> Self {
+        Account {
+            balance,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_code(mut self, code: Vec<u8>) -> Self {
+        self.code = code;
+        self
+    }
+
+    // EIP-161 empty: no code, zero nonce and zero balance
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty() && self.nonce == 0 && self.balance.is_zero()
+    }
+
+    pub fn delegation(&self) -> Option<H160> {
+        parse_delegation(&self.code)
+    }
+
+    pub fn code_hash(&self) -> H256 {
+        H256(Helper::keccak256(&self.code))
+    }
+
+    pub fn storage_root(&self) -> H256 {
+        self.storage.root()
+    }
+
+    // Value stored in the state trie: rlp([nonce, balance, storage_root, code_hash])
+    pub fn encode(&self) -> Vec<u8> {
+        rlp::encode_list(&[
+            rlp::encode_u64(self.nonce),
+            rlp::encode_u256(self.balance),
+            rlp::encode_h256(&self.storage_root()),
+            rlp::encode_h256(&self.code_hash()),
+        ])
+    }
+}
+
+pub fn delegation_designator(target: H160) -> Vec<u8> {
+    let mut code = DELEGATION_PREFIX.to_vec();
+    code.extend_from_slice(target.as_bytes());
+    code
+}
+
+pub fn parse_delegation(code: &[u8]) -> Option<H160> {
+    if code.len() == 23 && code.starts_with(&DELEGATION_PREFIX) {
+        Some(H160::from_slice(&code[3..]))
+    } else {
+        None
+    }
+}
+
+// One undoable change to the state, recorded so a reverted call can be rolled back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalEntry {
+    AccountCreated { address: H160 },
+    AccountDestroyed { address: H160, previous: Account },
+    BalanceChanged { address: H160, previous: U256 },
+    NonceChanged { address: H160, previous: u64 },
+    CodeChanged { address: H160, previous: Vec<u8> },
+    StorageChanged { address: H160, key: U256, previous: U256 },
+    // EIP-2929 access lists and the EIP-3529 refund counter are rolled back with the frame too
+    AddressWarmed { address: H160 },
+    SlotWarmed { address: H160, key: U256 },
+    RefundChanged { previous: u64 },
+}
+
+impl JournalEntry {
+    // The account whose balance, nonce, code or storage the entry changed
+    pub fn account(&self) -> Option<H160> {
+        match self {
+            JournalEntry::AccountCreated { address }
+            | JournalEntry::AccountDestroyed { address, .. }
+            | JournalEntry::BalanceChanged { address, .. }
+            | JournalEntry::NonceChanged { address, .. }
+            | JournalEntry::CodeChanged { address, .. }
+            | JournalEntry::StorageChanged { address, .. } => Some(*address),
+            JournalEntry::AddressWarmed { .. } | JournalEntry::SlotWarmed { .. } | JournalEntry::RefundChanged { .. } => None,
+        }
+    }
+}
+
+// Accounts are loaded from `database`, if any, the first time they are used and kept in
+// `accounts` from then on, along with every change made to them
+#[derive(Default)]
+pub struct State {
+    accounts: HashMap<H160, Account>,
+    journal: Vec<JournalEntry>,
+    warm_addresses: HashSet<H160>,
+    warm_slots: HashSet<(H160, U256)>,
+    // Slot values before the transaction first wrote them (EIP-2200)
+    original_storage: HashMap<(H160, U256), U256>,
+    refund: u64,
+    database: Option<Box<dyn Database>>,
+    loaded_accounts: HashSet<H160>,
+    loaded_slots: HashSet<(H160, U256)>,
+    database_error: Option<DatabaseError>,
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("State")
+            .field("accounts", &self.accounts)
+            .field("journal", &self.journal)
+            .field("warm_addresses", &self.warm_addresses)
+            .field("database", &self.database.is_some())
+            .finish()
+    }
+}
+
+impl State {
+    pub fn new() -> State {
+        State::default()
+    }
+
+    pub fn with_database(database: impl Database + 'static) -> State {
+        State {
+            database: Some(Box::new(database)),
+            ..State::default()
+        }
+    }
+
+    pub fn has_database(&self) -> bool {
+        self.database.is_some()
+    }
+
+    // The first error the database returned since the last call. A failed load reads as an
+    // empty account or a zero slot, so results computed after an error must be discarded
+    pub fn take_database_error(&mut self) -> Option<DatabaseError> {
+        self.database_error.take()
+    }
+
+    pub fn block_hash(&mut self, number: u64) -> H256 {
+        let Some(database) = self.database.as_mut() else {
+            return H256::zero();
+        };
+        match database.block_hash(number) {
+            Ok(hash) => hash,
+            Err(error) => {
+                self.database_error.get_or_insert(error);
+                H256::zero()
+            }
+        }
+    }
+
+    // Sets up an account directly, without journaling
+    pub fn insert_account(&mut self, address: H160, account: Account) {
+        self.accounts.insert(address, account);
+    }
+
+    pub fn account(&mut self, address: &H160) -> Option<&Account> {
+        self.load_account(address);
+        self.accounts.get(address)
+    }
+
+    // The account if it is already loaded, without going to the database
+    pub fn cached_account(&self, address: &H160) -> Option<&Account> {
+        self.accounts.get(address)
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = (&H160, &Account)> {
+        self.accounts.iter()
+    }
+
+    // Root of the state trie: keccak256(address) -> account. `None` with a database, which only
+    // some of the accounts have been loaded from
+    pub fn state_root(&self) -> Option<H256> {
+        self.state_trie().map(|trie| trie.root())
+    }
+
+    pub fn state_trie(&self) -> Option<Trie> {
+        if self.has_database() {
+            return None;
+        }
+        let mut trie = Trie::new();
+        for (address, account) in &self.accounts {
+            trie.insert(&secure_key(address.as_bytes()), account.encode());
+        }
+        Some(trie)
+    }
+
+    pub fn exists(&mut self, address: &H160) -> bool {
+        self.account(address).is_some()
+    }
+
+    pub fn is_empty(&mut self, address: &H160) -> bool {
+        self.account(address).is_none_or(Account::is_empty)
+    }
+
+    pub fn balance(&mut self, address: &H160) -> U256 {
+        self.account(address).map(|account| account.balance).unwrap_or_default()
+    }
+
+    pub fn nonce(&mut self, address: &H160) -> u64 {
+        self.account(address).map(|account| account.nonce).unwrap_or_default()
+    }
+
+    pub fn code(&mut self, address: &H160) -> &[u8] {
+        self.account(address).map(|account| account.code.as_slice()).unwrap_or_default()
+    }
+
+    // Address the account's code delegates to, if it holds an EIP-7702 designator
+    pub fn delegation(&mut self, address: &H160) -> Option<H160> {
+        parse_delegation(self.code(address))
+    }
+
+    pub fn storage(&mut self, address: &H160, key: U256) -> U256 {
+        self.load_slot(*address, key);
+        self.accounts.get(address).map(|account| account.storage.get(key)).unwrap_or_default()
+    }
+
+    pub fn set_balance(&mut self, address: H160, balance: U256) {
+        let account = self.touch(address);
+        let previous = account.balance;
+        account.balance = balance;
+        self.journal.push(JournalEntry::BalanceChanged { address, previous });
+    }
+
+    // false, with nothing changed, if the balance would overflow. Adding zero creates no account
+    pub fn add_balance(&mut self, address: H160, amount: U256) -> bool {
+        if amount.is_zero() {
+            return true;
+        }
+        let Some(balance) = self.balance(&address).checked_add(amount) else {
+            return false;
+        };
+        self.set_balance(address, balance);
+        true
+    }
+
+    // false, with nothing changed, if the balance is too low
+    pub fn sub_balance(&mut self, address: H160, amount: U256) -> bool {
+        let balance = self.balance(&address);
+        if balance < amount {
+            return false;
+        }
+        if !amount.is_zero() {
+            self.set_balance(address, balance - amount);
+        }
+        true
+    }
+
+    // false, with nothing changed, if `from` cannot cover `value` or `to` cannot hold it
+    pub fn transfer(&mut self, from: H160, to: H160, value: U256) -> bool {
+        let checkpoint = self.checkpoint();
+        if !self.sub_balance(from, value) {
+            return false;
+        }
+        if !self.add_balance(to, value) {
+            self.revert_to(checkpoint);
+            return false;
+        }
+        true
+    }
+
+    pub fn set_nonce(&mut self, address: H160, nonce: u64) {
+        let account = self.touch(address);
+        let previous = account.nonce;
+        account.nonce = nonce;
+        self.journal.push(JournalEntry::NonceChanged { address, previous });
+    }
+
+    pub fn increment_nonce(&mut self, address: H160) {
+        let nonce = self.nonce(&address);
+        self.set_nonce(address, nonce + 1);
+    }
+
+    pub fn set_code(&mut self, address: H160, code: Vec<u8>) {
+        let account = self.touch(address);
+        let previous = std::mem::replace(&mut account.code, code);
+        self.journal.push(JournalEntry::CodeChanged { address, previous });
+    }
+
+    // Returns whether the slot was warm and its value, and marks it warm, whether or not the
+    // account exists
+    pub fn sload(&mut self, address: H160, key: U256) -> (bool, U256) {
+        let warm = self.warm_slot(address, key);
+        (warm, self.storage(&address, key))
+    }
+
+    pub fn sstore(&mut self, address: H160, key: U256, value: U256) {
+        self.load_slot(address, key);
+        self.warm_slot(address, key);
+        let account = self.touch(address);
+        let previous = account.storage.get(key);
+        account.storage.store(key, value);
+        self.original_storage.entry((address, key)).or_insert(previous);
+        self.journal.push(JournalEntry::StorageChanged { address, key, previous });
+    }
+
+    // EIP-2200: the slot's value at the start of the transaction
+    pub fn original_storage(&mut self, address: H160, key: U256) -> U256 {
+        match self.original_storage.get(&(address, key)) {
+            Some(value) => *value,
+            None => self.storage(&address, key),
+        }
+    }
+
+    // EIP-2929: marks `address` warm and returns whether it already was
+    pub fn warm_address(&mut self, address: H160) -> bool {
+        let warm = !self.warm_addresses.insert(address);
+        if !warm {
+            self.journal.push(JournalEntry::AddressWarmed { address });
+        }
+        warm
+    }
+
+    // Marks the slot warm and returns whether it already was
+    pub fn warm_slot(&mut self, address: H160, key: U256) -> bool {
+        let warm = !self.warm_slots.insert((address, key));
+        if !warm {
+            self.journal.push(JournalEntry::SlotWarmed { address, key });
+        }
+        warm
+    }
+
+    // Gas refunded at the end of the transaction, before the EIP-3529 cap
+    pub fn refund(&self) -> u64 {
+        self.refund
+    }
+
+    pub fn add_refund(&mut self, gas: u64) {
+        self.journal.push(JournalEntry::RefundChanged { previous: self.refund });
+        self.refund += gas;
+    }
+
+    pub fn sub_refund(&mut self, gas: u64) {
+        self.journal.push(JournalEntry::RefundChanged { previous: self.refund });
+        self.refund = self.refund.saturating_sub(gas);
+    }
+
+    // Forgets warm addresses and slots, original slot values and the refund counter, done at
+    // the start of every transaction
+    pub fn clear_access_lists(&mut self) {
+        self.warm_addresses.clear();
+        self.warm_slots.clear();
+        self.original_storage.clear();
+        self.refund = 0;
+    }
+
+    // EIP-161: removes the accounts changed since `checkpoint` that were left empty
+    pub fn remove_empty_accounts(&mut self, checkpoint: usize) {
+        let touched: BTreeSet<H160> = self.journal[checkpoint..].iter().filter_map(JournalEntry::account).collect();
+        for address in touched {
+            if self.accounts.get(&address).is_some_and(Account::is_empty) {
+                let previous = self.accounts.remove(&address).unwrap();
+                self.journal.push(JournalEntry::AccountDestroyed { address, previous });
+            }
+        }
+    }
+
+    // Marks a point the state can later be reverted to
+    pub fn checkpoint(&self) -> usize {
+        self.journal.len()
+    }
+
+    // Undoes every change made since `checkpoint`
+    pub fn revert_to(&mut self, checkpoint: usize) {
+        while self.journal.len() > checkpoint {
+            match self.journal.pop().unwrap() {
+                JournalEntry::AccountCreated { address } => {
+                    self.accounts.remove(&address);
+                }
+                JournalEntry::AccountDestroyed { address, previous } => {
+                    self.accounts.insert(address, previous);
+                }
+                JournalEntry::BalanceChanged { address, previous } => {
+                    if let Some(account) = self.accounts.get_mut(&address) {
+                        account.balance = previous;
+                    }
+                }
+                JournalEntry::NonceChanged { address, previous } => {
+                    if let Some(account) = self.accounts.get_mut(&address) {
+                        account.nonce = previous;
+                    }
+                }
+                JournalEntry::CodeChanged { address, previous } => {
+                    if let Some(account) = self.accounts.get_mut(&address) {
+                        account.code = previous;
+                    }
+                }
+                JournalEntry::StorageChanged { address, key, previous } => {
+                    if let Some(account) = self.accounts.get_mut(&address) {
+                        account.storage.store(key, previous);
+                    }
+                }
+                JournalEntry::AddressWarmed { address } => {
+                    self.warm_addresses.remove(&address);
+                }
+                JournalEntry::SlotWarmed { address, key } => {
+                    self.warm_slots.remove(&(address, key));
+                }
+                JournalEntry::RefundChanged { previous } => self.refund = previous,
+            }
+        }
+    }
+
+    pub fn journal(&self) -> &[JournalEntry] {
+        &self.journal
+    }
+
+    pub fn clear_journal(&mut self) {
+        self.journal.clear();
+    }
+
+    // Current values of every account and slot changed since the journal was last cleared
+    pub fn change_set(&self) -> ChangeSet {
+        let mut changes = ChangeSet::default();
+        let mut addresses = HashSet::new();
+        for entry in &self.journal {
+            match entry {
+                JournalEntry::AccountCreated { address }
+                | JournalEntry::AccountDestroyed { address, .. }
+                | JournalEntry::BalanceChanged { address, .. }
+                | JournalEntry::NonceChanged { address, .. }
+                | JournalEntry::CodeChanged { address, .. } => {
+                    addresses.insert(*address);
+                }
+                JournalEntry::StorageChanged { address, key, .. } => {
+                    addresses.insert(*address);
+                    let value = self.accounts.get(address).map(|account| account.storage.get(*key)).unwrap_or_default();
+                    changes.storage.insert((*address, *key), value);
+                }
+                JournalEntry::AddressWarmed { .. } | JournalEntry::SlotWarmed { .. } | JournalEntry::RefundChanged { .. } => {}
+            }
+        }
+        for address in addresses {
+            let Some(account) = self.accounts.get(&address) else {
+                changes.destroyed.insert(address);
+                continue;
+            };
+            let code_hash = account.code_hash();
+            if !account.code.is_empty() {
+                changes.code.insert(code_hash, account.code.clone());
+            }
+            changes.accounts.insert(address, AccountInfo { balance: account.balance, nonce: account.nonce, code_hash });
+        }
+        changes
+    }
+
+    // Writes the change set to the database and starts a new one
+    pub fn commit(&mut self) -> Result<(), DatabaseError> {
+        let changes = self.change_set();
+        let database = self.database.as_mut().ok_or(DatabaseError::ReadOnly)?;
+        database.commit(&changes)?;
+        self.journal.clear();
+        Ok(())
+    }
+
+    // Returns the account at `address`, creating (and journaling) it if missing
+    fn touch(&mut self, address: H160) -> &mut Account {
+        self.load_account(&address);
+        if !self.accounts.contains_key(&address) {
+            self.journal.push(JournalEntry::AccountCreated { address });
+        }
+        self.accounts.entry(address).or_default()
+    }
+
+    // Accounts set up with `insert_account` or created here are never looked up
+    fn load_account(&mut self, address: &H160) {
+        if self.accounts.contains_key(address) || !self.loaded_accounts.insert(*address) {
+            return;
+        }
+        let Some(database) = self.database.as_mut() else {
+            return;
+        };
+        let loaded = database.basic(*address).and_then(|info| match info {
+            Some(info) => {
+                let code = database.code_by_hash(info.code_hash)?;
+                Ok(Some(Account { balance: info.balance, nonce: info.nonce, code, storage: Storage::new() }))
+            }
+            None => Ok(None),
+        });
+        match loaded {
+            Ok(Some(account)) => {
+                self.accounts.insert(*address, account);
+            }
+            Ok(None) => {}
+            Err(error) => {
+                self.loaded_accounts.remove(address); // retried on next use
+                self.database_error.get_or_insert(error);
+            }
+        }
+    }
+
+    fn load_slot(&mut self, address: H160, key: U256) {
+        self.load_account(&address);
+        if self.database.is_none() || !self.loaded_slots.insert((address, key)) {
+            return;
+        }
+        // Only accounts that came from the database can have stored slots
+        let Some(account) = self.accounts.get_mut(&address) else {
+            return;
+        };
+        match self.database.as_mut().unwrap().storage(address, key) {
+            Ok(value) => account.storage.store(key, value),
+            Err(error) => {
+                self.loaded_slots.remove(&(address, key));
+                self.database_error.get_or_insert(error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revert_rolls_back_warm_addresses_slots_and_refunds() {
+        let (address, key) = (H160::repeat_byte(1), U256::from(7));
+        let mut state = State::new();
+        state.warm_address(address);
+        state.add_refund(100);
+
+        let checkpoint = state.checkpoint();
+        assert!(!state.warm_slot(address, key));
+        assert!(!state.warm_address(H160::repeat_byte(2)));
+        state.sstore(address, key, U256::one());
+        state.add_refund(4800);
+        assert!(state.sload(address, key).0);
+        state.revert_to(checkpoint);
+
+        assert!(state.warm_address(address));
+        assert!(!state.warm_address(H160::repeat_byte(2)));
+        assert_eq!(state.sload(address, key), (false, U256::zero()));
+        assert_eq!(state.refund(), 100);
+    }
+
+    #[test]
+    fn slots_of_missing_accounts_are_warmed() {
+        let mut state = State::new();
+        let address = H160::repeat_byte(1);
+        assert_eq!(state.sload(address, U256::one()), (false, U256::zero()));
+        assert_eq!(state.sload(address, U256::one()), (true, U256::zero()));
+        assert!(!state.exists(&address));
+    }
+
+    #[test]
+    fn zero_values_and_overflows_leave_accounts_alone() {
+        let (rich, missing) = (H160::repeat_byte(1), H160::repeat_byte(2));
+        let mut state = State::new();
+        state.insert_account(rich, Account::new(U256::MAX));
+        assert!(state.transfer(rich, missing, U256::zero()));
+        assert!(state.add_balance(missing, U256::zero()));
+        assert!(!state.exists(&missing));
+
+        state.insert_account(missing, Account::new(U256::one()));
+        assert!(!state.add_balance(rich, U256::one()));
+        assert!(!state.transfer(missing, rich, U256::one()));
+        assert_eq!((state.balance(&rich), state.balance(&missing)), (U256::MAX, U256::one()));
+        assert!(state.journal().is_empty());
+    }
+
+    #[test]
+    fn emptied_accounts_are_removed_and_restored_on_revert() {
+        let (emptied, funded) = (H160::repeat_byte(1), H160::repeat_byte(2));
+        let mut state = State::new();
+        state.insert_account(emptied, Account::new(U256::from(5)));
+        let start = state.checkpoint();
+        assert!(state.transfer(emptied, funded, U256::from(5)));
+        state.remove_empty_accounts(start);
+        assert!(!state.exists(&emptied));
+        assert_eq!(state.balance(&funded), U256::from(5));
+        assert_eq!(state.change_set().destroyed.into_iter().collect::<Vec<_>>(), [emptied]);
+
+        state.revert_to(start);
+        assert_eq!(state.balance(&emptied), U256::from(5));
+        assert!(!state.exists(&funded));
+    }
+
+    #[test]
+    fn original_storage_is_the_value_before_the_first_write() {
+        let (address, key) = (H160::repeat_byte(1), U256::from(7));
+        let mut state = State::new();
+        state.insert_account(address, Account::default());
+        state.sstore(address, key, U256::from(5));
+        state.clear_access_lists();
+        assert_eq!(state.original_storage(address, key), U256::from(5));
+        state.sstore(address, key, U256::from(6));
+        state.sstore(address, key, U256::from(8));
+        assert_eq!(state.original_storage(address, key), U256::from(5));
+        assert_eq!(state.storage(&address, key), U256::from(8));
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use ethereum_types::{BigEndianHash, H160, H256, U256};
+use serde::{Deserialize, Serialize};
+use crate::hex::{self, Bytes};
+use super::{Account, JournalEntry, State};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    Created,
+    Modified,
+    Destroyed,
+}
+
+// A value before and after
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change<T> {
+    pub from: T,
+    pub to: T,
+}
+
+impl<T: PartialEq> Change<T> {
+    // `None` if the value stayed the same
+    pub fn between(from: T, to: T) -> Option<Change<T>> {
+        (from != to).then_some(Change { from, to })
+    }
+}
+
+// The fields of an account that changed, unchanged ones being `None`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountDiff {
+    pub status: AccountStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<Change<U256>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<Change<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<Change<Bytes>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<H256, Change<H256>>,
+}
+
+// Every account changed since a checkpoint, serialized as a JSON object keyed by address
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StateDiff {
+    pub accounts: BTreeMap<H160, AccountDiff>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn created(&self) -> impl Iterator<Item = &H160> {
+        self.with_status(AccountStatus::Created)
+    }
+
+    pub fn destroyed(&self) -> impl Iterator<Item = &H160> {
+        self.with_status(AccountStatus::Destroyed)
+    }
+
+    fn with_status(&self, status: AccountStatus) -> impl Iterator<Item = &H160> {
+        self.accounts.iter().filter(move |(_, diff)| diff.status == status).map(|(address, _)| address)
+    }
+}
+
+impl State {
+    // What changed since `checkpoint`, rebuilt from the journal, so the state must not have been
+    // committed in between. Accounts created and left empty count as unchanged, and an account
+    // that was emptied counts as destroyed, as EIP-161 would remove it
+    pub fn diff(&self, checkpoint: usize) -> StateDiff {
+        let journal = &self.journal[checkpoint.min(self.journal.len())..];
+        let mut originals: BTreeMap<H160, Option<Account>> = BTreeMap::new();
+        let mut slots: BTreeMap<(H160, U256), U256> = BTreeMap::new();
+        for entry in journal.iter().rev() {
+            let Some(address) = entry.account() else {
+                continue;
+            };
+            let original = originals.entry(address).or_insert_with(|| {
+                self.accounts.get(&address).map(|account| Account { storage: Default::default(), ..account.clone() })
+            });
+            match entry {
+                JournalEntry::AccountCreated { .. } => *original = None,
+                JournalEntry::AccountDestroyed { previous, .. } => {
+                    *original = Some(Account { storage: Default::default(), ..previous.clone() });
+                }
+                JournalEntry::BalanceChanged { previous, .. } => {
+                    if let Some(account) = original {
+                        account.balance = *previous;
+                    }
+                }
+                JournalEntry::NonceChanged { previous, .. } => {
+                    if let Some(account) = original {
+                        account.nonce = *previous;
+                    }
+                }
+                JournalEntry::CodeChanged { previous, .. } => {
+                    if let Some(account) = original {
+                        account.code = previous.clone();
+                    }
+                }
+                JournalEntry::StorageChanged { key, previous, .. } => {
+                    slots.insert((address, *key), *previous);
+                }
+                JournalEntry::AddressWarmed { .. } | JournalEntry::SlotWarmed { .. } | JournalEntry::RefundChanged { .. } => {}
+            }
+        }
+
+        let mut diff = StateDiff::default();
+        for (address, original) in originals {
+            let current = self.accounts.get(&address);
+            let mut storage = BTreeMap::new();
+            for (&(_, key), &from) in slots.range((address, U256::zero())..=(address, U256::MAX)) {
+                let to = current.map(|account| account.storage.get(key)).unwrap_or_default();
+                if from != to {
+                    storage.insert(H256::from_uint(&key), Change { from: H256::from_uint(&from), to: H256::from_uint(&to) });
+                }
+            }
+            let live = |account: Option<&Account>| account.is_some_and(|account| !account.is_empty());
+            let stored_before = storage.values().any(|change| !change.from.is_zero());
+            let stored_after = storage.values().any(|change| !change.to.is_zero());
+            let status = match (live(original.as_ref()) || stored_before, live(current) || stored_after) {
+                (false, false) => continue,
+                (false, true) => AccountStatus::Created,
+                (true, true) => AccountStatus::Modified,
+                (true, false) => AccountStatus::Destroyed,
+            };
+
+            let empty = Account::default();
+            let (before, after) = (original.as_ref().unwrap_or(&empty), current.unwrap_or(&empty));
+            let account = AccountDiff {
+                status,
+                balance: Change::between(before.balance, after.balance),
+                nonce: Change::between(before.nonce, after.nonce),
+                code: Change::between(Bytes(before.code.clone()), Bytes(after.code.clone())),
+                storage,
+            };
+            if account.status == AccountStatus::Modified
+                && account.balance.is_none()
+                && account.nonce.is_none()
+                && account.code.is_none()
+                && account.storage.is_empty()
+            {
+                continue;
+            }
+            diff.accounts.insert(address, account);
+        }
+        diff
+    }
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (address, account) in &self.accounts {
+            let status = match account.status {
+                AccountStatus::Created => "created",
+                AccountStatus::Modified => "modified",
+                AccountStatus::Destroyed => "destroyed",
+            };
+            writeln!(f, "{} ({})", hex::checksum(address), status)?;
+            if let Some(Change { from, to }) = &account.balance {
+                writeln!(f, "  balance: {} → {}", from, to)?;
+            }
+            if let Some(Change { from, to }) = &account.nonce {
+                writeln!(f, "  nonce: {} → {}", from, to)?;
+            }
+            if let Some(Change { from, to }) = &account.code {
+                writeln!(f, "  code: {} bytes → {} bytes", from.len(), to.len())?;
+            }
+            for (key, Change { from, to }) in &account.storage {
+                writeln!(f, "  storage {:#x}: {:#x} → {:#x}", key.into_uint(), from.into_uint(), to.into_uint())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn funded_state() -> (State, H160) {
+        let funder = H160::repeat_byte(1);
+        let mut state = State::new();
+        state.insert_account(funder, Account::new(U256::from(100)));
+        (state, funder)
+    }
+
+    #[test]
+    fn created_accounts_show_every_field_they_set() {
+        let (mut state, funder) = funded_state();
+        let contract = H160::repeat_byte(2);
+        let start = state.checkpoint();
+        assert!(state.transfer(funder, contract, U256::from(40)));
+        state.set_nonce(contract, 1);
+        state.set_code(contract, vec![0x00]);
+        state.sstore(contract, U256::one(), U256::from(9));
+
+        let diff = state.diff(start);
+        assert_eq!(diff.created().collect::<Vec<_>>(), [&contract]);
+        let created = &diff.accounts[&contract];
+        assert_eq!(created.balance, Change::between(U256::zero(), U256::from(40)));
+        assert_eq!(created.nonce, Change::between(0, 1));
+        assert_eq!(created.code, Change::between(Bytes(vec![]), Bytes(vec![0x00])));
+        assert_eq!(created.storage[&H256::from_low_u64_be(1)].to, H256::from_low_u64_be(9));
+        let funder_diff = &diff.accounts[&funder];
+        assert_eq!(funder_diff.status, AccountStatus::Modified);
+        assert_eq!(funder_diff.balance, Change::between(U256::from(100), U256::from(60)));
+        assert_eq!(funder_diff.nonce, None);
+    }
+
+    #[test]
+    fn emptied_accounts_are_destroyed() {
+        let (mut state, funder) = funded_state();
+        let receiver = H160::repeat_byte(2);
+        let start = state.checkpoint();
+        assert!(state.transfer(funder, receiver, U256::from(100)));
+        state.remove_empty_accounts(start);
+
+        let diff = state.diff(start);
+        assert_eq!(diff.destroyed().collect::<Vec<_>>(), [&funder]);
+        assert_eq!(diff.accounts[&funder].balance, Change::between(U256::from(100), U256::zero()));
+        assert_eq!(diff.accounts[&receiver].status, AccountStatus::Created);
+        assert_eq!(
+            serde_json::to_value(&diff.accounts[&funder]).unwrap(),
+            serde_json::json!({"status": "destroyed", "balance": {"from": "0x64", "to": "0x0"}})
+        );
+    }
+
+    #[test]
+    fn restored_slots_and_untouched_accounts_are_left_out() {
+        let (mut state, funder) = funded_state();
+        let (key, other) = (U256::from(7), U256::from(8));
+        state.sstore(funder, key, U256::from(3));
+        let start = state.checkpoint();
+        state.sstore(funder, key, U256::from(4));
+        state.sstore(funder, key, U256::from(3));
+        state.sstore(funder, other, U256::from(5));
+        state.sstore(funder, other, U256::zero());
+        state.warm_address(H160::repeat_byte(3));
+        // touched but left empty, as EIP-161 would remove it
+        assert!(state.transfer(funder, H160::repeat_byte(4), U256::zero()));
+        state.set_nonce(H160::repeat_byte(5), 0);
+
+        assert!(state.diff(start).is_empty(), "{}", state.diff(start));
+    }
+
+    #[test]
+    fn reverted_inner_changes_are_left_out() {
+        let (mut state, funder) = funded_state();
+        let (outer, inner) = (H160::repeat_byte(2), H160::repeat_byte(3));
+        let start = state.checkpoint();
+        assert!(state.transfer(funder, outer, U256::from(10)));
+        state.sstore(outer, U256::one(), U256::one());
+
+        let call = state.checkpoint();
+        assert!(state.transfer(outer, inner, U256::from(10)));
+        state.sstore(outer, U256::one(), U256::from(2));
+        state.sstore(funder, U256::one(), U256::one());
+        state.set_code(inner, vec![0xfe]);
+        state.revert_to(call);
+
+        let diff = state.diff(start);
+        assert_eq!(diff.accounts.keys().collect::<Vec<_>>(), [&funder, &outer]);
+        assert!(diff.accounts[&funder].storage.is_empty());
+        let outer_diff = &diff.accounts[&outer];
+        assert_eq!(outer_diff.balance, Change::between(U256::zero(), U256::from(10)));
+        assert_eq!(outer_diff.storage[&H256::from_low_u64_be(1)], Change { from: H256::zero(), to: H256::from_low_u64_be(1) });
+        // the diff from inside the reverted call is empty
+        assert!(state.diff(call).is_empty());
+    }
+}
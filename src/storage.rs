@@ -1,10 +1,12 @@
 use std::collections::HashMap;
-use ethereum_types::U256;
+use ethereum_types::{H256, U256};
+use crate::rlp;
+use crate::trie::{secure_key, Trie};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Storage {
-    data: HashMap<i32, Vec<U256>>, //value can be up to 32 bytes
-    cache: Vec<i32>,
+    data: HashMap<U256, U256>, // slot -> 32-byte value
+    cache: Vec<U256>,
 }
 
 
@@ -16,19 +18,51 @@ impl Storage {
         }
     }
 
-    pub fn load(&mut self, key: i32) -> (bool, Vec<U256>) {
+    pub fn load(&mut self, key: U256) -> (bool, U256) {
         let warm_access = self.cache.contains(&key); // warm slot means it was accessed before and key's in cache
         if !warm_access {
             self.cache.push(key);
         }
-        match self.data.get(&key) {
-            Some(data) => (warm_access, data.clone()),
-            None => (false, vec![U256::zero()]),
+        (warm_access, self.get(key))
+    }
+
+    pub fn store(&mut self, key: U256, value: U256) {
+        if value.is_zero() {
+            self.data.remove(&key);
+        } else {
+            self.data.insert(key, value);
         }
-        
     }
 
-    pub fn store(&mut self, key: i32, value: &[U256]) {
-        self.data.insert(key, value.to_vec());
+    // Reads a slot without marking it warm
+    pub fn get(&self, key: U256) -> U256 {
+        self.data.get(&key).copied().unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&U256, &U256)> {
+        self.data.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    // Root of the storage trie: keccak256(slot) -> rlp(value)
+    pub fn root(&self) -> H256 {
+        self.trie().root()
     }
-}
\ No newline at end of file
+
+    pub fn trie(&self) -> Trie {
+        let mut trie = Trie::new();
+        for (key, value) in &self.data {
+            let mut slot = [0u8; 32];
+            key.to_big_endian(&mut slot);
+            trie.insert(&secure_key(&slot), rlp::encode_u256(*value));
+        }
+        trie
+    }
+}
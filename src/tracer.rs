@@ -0,0 +1,12 @@
+mod call;
+mod diff;
+mod eip3155;
+mod prestate;
+mod pretty;
+mod source;
+pub use call::{geth_error, revert_reason, CallFrame, CallLog, CallTracer, FrameType};
+pub use diff::{first_divergence, parse_trace, read_trace, render_divergence, Divergence, TraceError};
+pub use eip3155::{op_name, Eip3155Tracer, TraceStep, TraceSummary};
+pub use prestate::{Prestate, PrestateAccount, PrestateDiff, PrestateTracer};
+pub use pretty::TracePrinter;
+pub use source::{SourceFailure, SourceStep, SourceTracer};
@@ -0,0 +1,338 @@
+use ethereum_types::{H160, H256, U256, U64};
+use serde::{Deserialize, Serialize};
+use crate::evm::{EvmError, LogEntry};
+use crate::hex::Bytes;
+use crate::inspector::{CallInputs, CallKind, CallOutcome, CreateInputs, CreateKind, Inspector};
+use crate::precompiles::PrecompileError;
+use crate::EVM;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum FrameType {
+    Call,
+    CallCode,
+    DelegateCall,
+    StaticCall,
+    Create,
+    Create2,
+    Selfdestruct,
+}
+
+// A frame of geth's callTracer output. The outermost frame's gas is the transaction's gas limit
+// and its gasUsed the gas the transaction paid for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    #[serde(rename = "type")]
+    pub kind: FrameType,
+    pub from: H160,
+    pub gas: U64,
+    pub gas_used: U64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<H160>,
+    pub input: Bytes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Bytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<CallFrame>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub logs: Vec<CallLog>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<U256>,
+}
+
+// A log with the number of calls its frame had made before it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallLog {
+    pub address: H160,
+    pub topics: Vec<H256>,
+    pub data: Bytes,
+    pub position: U64,
+}
+
+// Builds geth's callTracer tree. `with_logs` and `only_top_call` are its `withLog` and
+// `onlyTopCall` options
+#[derive(Debug, Default)]
+pub struct CallTracer {
+    with_logs: bool,
+    only_top_call: bool,
+    frames: Vec<CallFrame>,
+    root: Option<CallFrame>,
+}
+
+impl CallTracer {
+    pub fn new() -> Self {
+        CallTracer::default()
+    }
+
+    pub fn with_logs(mut self, with_logs: bool) -> Self {
+        self.with_logs = with_logs;
+        self
+    }
+
+    pub fn only_top_call(mut self, only_top_call: bool) -> Self {
+        self.only_top_call = only_top_call;
+        self
+    }
+
+    // The outermost frame once it has finished
+    pub fn frame(&self) -> Option<&CallFrame> {
+        self.root.as_ref()
+    }
+
+    pub fn into_frame(self) -> Option<CallFrame> {
+        self.root
+    }
+
+    fn enter(&mut self, depth: usize, frame: CallFrame) {
+        if depth == 0 || !self.only_top_call {
+            self.frames.push(frame);
+        }
+    }
+
+    fn exit(&mut self, depth: usize, outcome: &CallOutcome) {
+        if depth > 0 && self.only_top_call {
+            return;
+        }
+        let Some(mut frame) = self.frames.pop() else {
+            return;
+        };
+        frame.gas_used = U64::from(frame.gas.as_u64().saturating_sub(outcome.gas_left as u64));
+        if outcome.is_revert() {
+            frame.error = Some("execution reverted".to_string());
+            frame.revert_reason = revert_reason(&outcome.output);
+        } else if let Some(error) = &outcome.error {
+            frame.error = Some(geth_error(error));
+        }
+        // geth leaves out empty output, and the address of a creation that failed
+        if (outcome.success || outcome.is_revert()) && !outcome.output.is_empty() {
+            frame.output = Some(Bytes(outcome.output.clone()));
+        }
+        if !outcome.success && matches!(frame.kind, FrameType::Create | FrameType::Create2) {
+            frame.to = None;
+        }
+        // As in geth, logs of failed frames are dropped along with those of the calls they made
+        if !outcome.success {
+            clear_logs(&mut frame);
+        }
+        match self.frames.last_mut() {
+            Some(parent) => parent.calls.push(frame),
+            None => self.root = Some(frame),
+        }
+    }
+}
+
+impl Inspector for CallTracer {
+    fn call(&mut self, inputs: &CallInputs) {
+        let kind = match inputs.kind {
+            CallKind::Call => FrameType::Call,
+            CallKind::CallCode => FrameType::CallCode,
+            CallKind::DelegateCall => FrameType::DelegateCall,
+            CallKind::StaticCall => FrameType::StaticCall,
+        };
+        let value = matches!(inputs.kind, CallKind::Call | CallKind::CallCode).then_some(inputs.value);
+        self.enter(inputs.depth, new_frame(kind, inputs.caller, inputs.target, inputs.gas, inputs.input.clone(), value));
+    }
+
+    fn call_end(&mut self, inputs: &CallInputs, outcome: &CallOutcome) {
+        self.exit(inputs.depth, outcome);
+    }
+
+    fn create(&mut self, inputs: &CreateInputs) {
+        let kind = match inputs.kind {
+            CreateKind::Create => FrameType::Create,
+            CreateKind::Create2 => FrameType::Create2,
+        };
+        let frame = new_frame(kind, inputs.caller, inputs.address, inputs.gas, inputs.init_code.clone(), Some(inputs.value));
+        self.enter(inputs.depth, frame);
+    }
+
+    fn create_end(&mut self, inputs: &CreateInputs, outcome: &CallOutcome) {
+        self.exit(inputs.depth, outcome);
+    }
+
+    fn log(&mut self, evm: &EVM, log: &LogEntry) {
+        if !self.with_logs || (self.only_top_call && evm.depth() > 0) {
+            return;
+        }
+        if let Some(frame) = self.frames.last_mut() {
+            frame.logs.push(CallLog {
+                address: log.address,
+                topics: log.topics.clone(),
+                data: Bytes(log.data.clone()),
+                position: U64::from(frame.calls.len()),
+            });
+        }
+    }
+
+    fn selfdestruct(&mut self, address: H160, target: H160, value: U256) {
+        if self.only_top_call {
+            return;
+        }
+        if let Some(frame) = self.frames.last_mut() {
+            let mut selfdestruct = new_frame(FrameType::Selfdestruct, address, target, 0, Vec::new(), Some(value));
+            selfdestruct.gas_used = U64::zero();
+            frame.calls.push(selfdestruct);
+        }
+    }
+}
+
+fn new_frame(kind: FrameType, from: H160, to: H160, gas: usize, input: Vec<u8>, value: Option<U256>) -> CallFrame {
+    CallFrame {
+        kind,
+        from,
+        gas: U64::from(gas),
+        gas_used: U64::zero(),
+        to: Some(to),
+        input: Bytes(input),
+        output: None,
+        error: None,
+        revert_reason: None,
+        calls: Vec::new(),
+        logs: Vec::new(),
+        value,
+    }
+}
+
+fn clear_logs(frame: &mut CallFrame) {
+    frame.logs.clear();
+    for call in &mut frame.calls {
+        clear_logs(call);
+    }
+}
+
+// The messages geth reports for the same failures
+pub fn geth_error(error: &EvmError) -> String {
+    match error {
+        EvmError::OutOfGas | EvmError::Precompile(PrecompileError::OutOfGas) => "out of gas".to_string(),
+        EvmError::StateChangeDuringStaticCall => "write protection".to_string(),
+        EvmError::CallDepthExceeded => "max call depth exceeded".to_string(),
+        EvmError::InsufficientBalance => "insufficient balance for transfer".to_string(),
+        EvmError::CodeSizeExceeded => "max code size exceeded".to_string(),
+        EvmError::InvalidCodePrefix => "invalid code: must not begin with 0xef".to_string(),
+        EvmError::ReturnDataOutOfBounds => "return data out of bounds".to_string(),
+        EvmError::CreateCollision => "contract address collision".to_string(),
+        EvmError::StackUnderflow { len, required } => format!("stack underflow ({len} <=> {required})"),
+        EvmError::StackOverflow { len, limit } => format!("stack limit reached {len} ({limit})"),
+        EvmError::InvalidJump => "invalid jump destination".to_string(),
+        EvmError::InitCodeSizeExceeded => "max initcode size exceeded".to_string(),
+        EvmError::NonceOverflow => "nonce uint64 overflow".to_string(),
+        EvmError::Precompile(error) => error.to_string(),
+    }
+}
+
+// Decodes revert data in the Solidity `Error(string)` or `Panic(uint256)` format
+pub fn revert_reason(output: &[u8]) -> Option<String> {
+    let (selector, data) = (output.get(..4)?, &output[4..]);
+    match selector {
+        [0x08, 0xc3, 0x79, 0xa0] => {
+            let offset = word_as_usize(data, 0)?;
+            let length = word_as_usize(data, offset)?;
+            let start = offset.checked_add(32)?;
+            let bytes = data.get(start..start.checked_add(length)?)?;
+            String::from_utf8(bytes.to_vec()).ok()
+        }
+        [0x4e, 0x48, 0x7b, 0x71] => {
+            let code = U256::from_big_endian(data.get(..32)?);
+            let reason = match code.low_u64() {
+                _ if code > U256::from(u64::MAX) => None,
+                0x00 => Some("generic panic"),
+                0x01 => Some("assert(false)"),
+                0x11 => Some("arithmetic underflow or overflow"),
+                0x12 => Some("division or modulo by zero"),
+                0x21 => Some("enum overflow"),
+                0x22 => Some("invalid encoded storage byte array accessed"),
+                0x31 => Some("out-of-bounds array access; popping on an empty array"),
+                0x32 => Some("out-of-bounds access of an array or bytesN"),
+                0x41 => Some("out of memory"),
+                0x51 => Some("uninitialized function"),
+                _ => None,
+            };
+            Some(reason.map(str::to_string).unwrap_or_else(|| format!("unknown panic code: {:#x}", code)))
+        }
+        _ => None,
+    }
+}
+
+fn word_as_usize(data: &[u8], offset: usize) -> Option<usize> {
+    let word = U256::from_big_endian(data.get(offset..offset.checked_add(32)?)?);
+    (word <= U256::from(u32::MAX)).then(|| word.as_usize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{inspect_transaction, BlockEnv, Transaction};
+    use crate::{Account, Precompiles, State};
+
+    // A calls B with 1 wei and 30000 gas, B logs, stores a slot and returns 42. A then calls C with
+    // 10000 gas, which reverts with Error("nope"). The expected output follows geth's callTracer
+    // with `withLog`, gas worked out by hand from the opcodes
+    #[test]
+    fn nested_calls_value_and_revert_match_geth() {
+        let (sender, a, b, c) = (H160([0x11; 20]), H160([0xaa; 20]), H160([0xbb; 20]), H160([0xcc; 20]));
+        let code_a = format!("60006000600060006001 73{b:x} 617530 f1 50 6000600060006000600073{c:x} 612710 f1 50 00");
+        let code_b = "6001 6000 6000 a1 6001 6000 55 602a 6000 52 6020 6000 f3";
+        let selector = format!("08c379a0{}", "00".repeat(28));
+        let nope = format!("6e6f7065{}", "00".repeat(28));
+        let code_c = format!("7f{selector} 6000 52 6020 6004 52 6004 6024 52 7f{nope} 6044 52 6064 6000 fd");
+        let code = |code: &str| crate::hex::decode(&code.replace(' ', "")).unwrap();
+
+        let mut state = State::new();
+        state.insert_account(sender, Account::new(U256::from(10).pow(U256::from(18))));
+        state.insert_account(a, Account::new(U256::from(1)).with_code(code(&code_a)));
+        state.insert_account(b, Account::default().with_code(code(code_b)));
+        state.insert_account(c, Account::default().with_code(code(&code_c)));
+        let tx = Transaction { caller: sender, to: Some(a), gas_limit: 200_000, gas_price: U256::one(), ..Default::default() };
+        let mut tracer = CallTracer::new().with_logs(true);
+        inspect_transaction(&tx, &BlockEnv::default(), &mut state, &mut Precompiles::default(), &mut tracer).unwrap();
+
+        let expected = r#"{
+            "from": "0x1111111111111111111111111111111111111111",
+            "gas": "0x30d40",
+            "gasUsed": "0xda4b",
+            "to": "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "input": "0x",
+            "calls": [
+                {
+                    "from": "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+                    "gas": "0x7e2c",
+                    "gasUsed": "0x5963",
+                    "to": "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb",
+                    "input": "0x",
+                    "output": "0x000000000000000000000000000000000000000000000000000000000000002a",
+                    "logs": [
+                        {
+                            "address": "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb",
+                            "topics": ["0x0000000000000000000000000000000000000000000000000000000000000001"],
+                            "data": "0x",
+                            "position": "0x0"
+                        }
+                    ],
+                    "value": "0x1",
+                    "type": "CALL"
+                },
+                {
+                    "from": "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+                    "gas": "0x2710",
+                    "gasUsed": "0x36",
+                    "to": "0xcccccccccccccccccccccccccccccccccccccccc",
+                    "input": "0x",
+                    "output": "0x08c379a0000000000000000000000000000000000000000000000000000000000000002000000000000000000000000000000000000000000000000000000000000000046e6f706500000000000000000000000000000000000000000000000000000000",
+                    "error": "execution reverted",
+                    "revertReason": "nope",
+                    "value": "0x0",
+                    "type": "CALL"
+                }
+            ],
+            "value": "0x0",
+            "type": "CALL"
+        }"#;
+        let frame = serde_json::to_value(tracer.frame().unwrap()).unwrap();
+        assert_eq!(frame, serde_json::from_str::<serde_json::Value>(expected).unwrap());
+    }
+}
@@ -0,0 +1,207 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+use crate::hex;
+use super::eip3155::TraceStep;
+
+#[derive(Error, Debug)]
+pub enum TraceError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Line {line}: {message}")]
+    Parse { line: usize, message: String },
+}
+
+// Reads the steps of an EIP-3155 trace, skipping the summary line and blank lines
+pub fn read_trace(path: impl AsRef<Path>) -> Result<Vec<TraceStep>, TraceError> {
+    parse_trace(&fs::read_to_string(path)?)
+}
+
+pub fn parse_trace(input: &str) -> Result<Vec<TraceStep>, TraceError> {
+    let mut steps = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let value: serde_json::Value = serde_json::from_str(line)
+            .map_err(|e| TraceError::Parse { line: index + 1, message: e.to_string() })?;
+        if value.get("pc").is_none() {
+            continue;
+        }
+        let step = serde_json::from_value(value).map_err(|e| TraceError::Parse { line: index + 1, message: e.to_string() })?;
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+// The first step at which two traces disagree, `None` on the side of a trace that ended earlier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub index: usize,
+    pub fields: Vec<&'static str>,
+    pub a: Option<TraceStep>,
+    pub b: Option<TraceStep>,
+}
+
+// Compares the traces step by step on pc, opcode, gas, stack and, when both traces have it, memory
+pub fn first_divergence(a: &[TraceStep], b: &[TraceStep]) -> Option<Divergence> {
+    for index in 0..a.len().max(b.len()) {
+        let fields = match (a.get(index), b.get(index)) {
+            (Some(step_a), Some(step_b)) => differing_fields(step_a, step_b),
+            _ => vec!["length"],
+        };
+        if !fields.is_empty() {
+            return Some(Divergence { index, fields, a: a.get(index).cloned(), b: b.get(index).cloned() });
+        }
+    }
+    None
+}
+
+fn differing_fields(a: &TraceStep, b: &TraceStep) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if a.pc != b.pc {
+        fields.push("pc");
+    }
+    if a.op != b.op {
+        fields.push("op");
+    }
+    if a.gas != b.gas {
+        fields.push("gas");
+    }
+    if a.stack != b.stack {
+        fields.push("stack");
+    }
+    if let (Some(memory_a), Some(memory_b)) = (&a.memory, &b.memory) {
+        if memory_a != memory_b {
+            fields.push("memory");
+        }
+    }
+    fields
+}
+
+// Describes the divergence with up to `context` steps before it, which both traces share
+pub fn render_divergence(a: &[TraceStep], b: &[TraceStep], divergence: &Divergence, context: usize) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "first divergence at step {} ({})", divergence.index, divergence.fields.join(", "));
+    let _ = writeln!(out, "{:>8} {:>5} {:>6} {:<14} {:>10} {:>8}", "step", "depth", "pc", "op", "gas", "cost");
+    let start = divergence.index.saturating_sub(context);
+    for (index, step) in a.iter().enumerate().take(divergence.index).skip(start) {
+        let _ = writeln!(out, "  {}", step_row(index, step));
+    }
+    for (label, step) in [("a", &divergence.a), ("b", &divergence.b)] {
+        match step {
+            Some(step) => {
+                let _ = writeln!(out, "{} {}", label, step_row(divergence.index, step));
+            }
+            None => {
+                let _ = writeln!(out, "{} {:>6} trace ended after {} steps", label, divergence.index, if label == "a" { a.len() } else { b.len() });
+            }
+        }
+    }
+
+    if let (Some(step_a), Some(step_b)) = (&divergence.a, &divergence.b) {
+        if divergence.fields.contains(&"stack") {
+            let _ = writeln!(out, "stack (top first):");
+            let depth = step_a.stack.len().max(step_b.stack.len());
+            for position in 0..depth {
+                let item_a = step_a.stack.iter().rev().nth(position);
+                let item_b = step_b.stack.iter().rev().nth(position);
+                let marker = if item_a == item_b { ' ' } else { '*' };
+                let show = |item: Option<&ethereum_types::U256>| item.map(|value| format!("{:#x}", value)).unwrap_or("-".to_string());
+                let _ = writeln!(out, "{} {:>4} a {}", marker, position, show(item_a));
+                let _ = writeln!(out, "{} {:>4} b {}", marker, position, show(item_b));
+            }
+        }
+        if let (Some(memory_a), Some(memory_b)) = (&step_a.memory, &step_b.memory) {
+            if memory_a != memory_b {
+                let offset = memory_a.iter().zip(memory_b.iter()).position(|(x, y)| x != y).unwrap_or(memory_a.len().min(memory_b.len()));
+                let word = offset / 32 * 32;
+                let slice = |memory: &[u8]| hex::encode(&memory[word.min(memory.len())..(word + 32).min(memory.len())]);
+                let _ = writeln!(out, "memory differs at offset {:#x} (sizes {} and {}):", offset, memory_a.len(), memory_b.len());
+                let _ = writeln!(out, "  a [{:#06x}] {}", word, slice(memory_a));
+                let _ = writeln!(out, "  b [{:#06x}] {}", word, slice(memory_b));
+            }
+        }
+    }
+    out
+}
+
+fn step_row(index: usize, step: &TraceStep) -> String {
+    let name = if step.op_name.is_empty() { super::op_name(step.op) } else { step.op_name.clone() };
+    format!("{:>6} {:>5} {:>6} {:<14} {:>10} {:>8}", index, step.depth, step.pc, name, step.gas.as_u64(), step.gas_cost.as_u64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // PUSH1 1 PUSH1 2 ADD, as geth prints it
+    const TRACE: &str = r#"{"pc":0,"op":96,"gas":"0x2710","gasCost":"0x3","memSize":0,"stack":[],"depth":1,"refund":0,"opName":"PUSH1"}
+{"pc":2,"op":96,"gas":"0x270d","gasCost":"0x3","memSize":0,"stack":["0x1"],"depth":1,"refund":0,"opName":"PUSH1"}
+
+{"pc":4,"op":1,"gas":"0x270a","gasCost":"0x3","memSize":0,"stack":["0x1","0x2"],"depth":1,"refund":0,"opName":"ADD"}
+{"output":"","gasUsed":"0x9","pass":true}
+"#;
+
+    #[test]
+    fn parses_steps_and_skips_the_summary() {
+        let steps = parse_trace(TRACE).unwrap();
+        assert_eq!(steps.iter().map(|step| step.pc).collect::<Vec<_>>(), [0, 2, 4]);
+        assert_eq!(steps[2].stack.len(), 2);
+        assert_eq!(first_divergence(&steps, &steps), None);
+
+        let error = parse_trace("{\"pc\":0}\nnot json").unwrap_err();
+        assert!(matches!(error, TraceError::Parse { line: 1, .. }));
+        assert!(matches!(parse_trace("{}\nnot json"), Err(TraceError::Parse { line: 2, .. })));
+    }
+
+    #[test]
+    fn finds_the_first_differing_step() {
+        let a = parse_trace(TRACE).unwrap();
+        let mut b = a.clone();
+        b[2].stack[1] = 3.into();
+        b[2].gas = 0x2709.into();
+        let divergence = first_divergence(&a, &b).unwrap();
+        assert_eq!(divergence.index, 2);
+        assert_eq!(divergence.fields, ["gas", "stack"]);
+
+        // Memory counts only when both traces include it
+        let mut with_memory = a.clone();
+        with_memory[1].memory = Some(crate::hex::Bytes(vec![1]));
+        assert_eq!(first_divergence(&a, &with_memory), None);
+        let mut other_memory = with_memory.clone();
+        other_memory[1].memory = Some(crate::hex::Bytes(vec![2]));
+        assert_eq!(first_divergence(&with_memory, &other_memory).unwrap().fields, ["memory"]);
+
+        // A trace that stops early diverges where it ends
+        let divergence = first_divergence(&a, &a[..2]).unwrap();
+        assert_eq!((divergence.index, divergence.fields.clone(), divergence.b), (2, vec!["length"], None));
+    }
+
+    #[test]
+    fn renders_aligned_context_and_stacks() {
+        let a = parse_trace(TRACE).unwrap();
+        let mut b = a.clone();
+        b[2].stack[1] = 3.into();
+        let divergence = first_divergence(&a, &b).unwrap();
+        let expected = "\
+first divergence at step 2 (stack)
+    step depth     pc op                    gas     cost
+       1     1      2 PUSH1                9997        3
+a      2     1      4 ADD                  9994        3
+b      2     1      4 ADD                  9994        3
+stack (top first):
+*    0 a 0x2
+*    0 b 0x3
+     1 a 0x1
+     1 b 0x1
+";
+        assert_eq!(render_divergence(&a, &b, &divergence, 1), expected);
+
+        let divergence = first_divergence(&a, &a[..1]).unwrap();
+        let rendered = render_divergence(&a, &a[..1], &divergence, 0);
+        assert!(rendered.ends_with("b      1 trace ended after 1 steps\n"), "{}", rendered);
+    }
+}
@@ -0,0 +1,228 @@
+use std::io::{self, Write};
+use ethereum_types::{H256, U256, U64};
+use serde::{Deserialize, Serialize};
+use crate::hex::Bytes;
+use crate::inspector::{CallInputs, CallOutcome, CreateInputs, Inspector};
+use crate::opcodes::Opcode;
+use crate::EVM;
+
+// One line of an EIP-3155 trace, the machine state before the opcode ran. `depth` starts at 1
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceStep {
+    pub pc: u64,
+    pub op: u8,
+    pub gas: U64,
+    pub gas_cost: U64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Bytes>,
+    pub mem_size: u64,
+    pub stack: Vec<U256>,
+    pub depth: u64,
+    #[serde(default)]
+    pub return_data: Bytes,
+    #[serde(default)]
+    pub refund: u64,
+    #[serde(default)]
+    pub op_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Last line of a trace
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceSummary {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_root: Option<H256>,
+    pub output: Bytes,
+    pub gas_used: U64,
+    pub pass: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TraceSummary {
+    // Summary of a finished run of `evm` that started with `gas_limit`
+    pub fn new(evm: &EVM, gas_limit: usize) -> Self {
+        let error = match evm.error() {
+            Some(error) => Some(error.to_string()),
+            None if !evm.is_success() => Some("execution reverted".to_string()),
+            None => None,
+        };
+        TraceSummary {
+            state_root: evm.state().state_root(),
+            output: Bytes(evm.output().to_vec()),
+            gas_used: U64::from(gas_limit - evm.gas_left()),
+            pass: evm.is_success(),
+            error,
+        }
+    }
+}
+
+// geth's name for opcodes it does not define
+pub fn op_name(op: u8) -> String {
+    match Opcode::try_from_u8(op) {
+        Some(opcode) => format!("{:?}", opcode),
+        None => format!("opcode {:#x} not defined", op),
+    }
+}
+
+// A step whose line waits for the call it made to return, with the lines of that call
+struct PendingCall {
+    step: TraceStep,
+    gas_returned: usize,
+    lines: Vec<String>,
+}
+
+// Writes an EIP-3155 trace, one JSON object per line. A line is written once its opcode has run,
+// but a call's line still comes before the lines of the frame it entered. Write errors stop the
+// trace and are returned by `finish`
+pub struct Eip3155Tracer<W: Write> {
+    writer: W,
+    memory: bool,
+    current: Option<TraceStep>,
+    calls: Vec<PendingCall>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Eip3155Tracer<W> {
+    pub fn new(writer: W) -> Self {
+        Eip3155Tracer { writer, memory: false, current: None, calls: Vec::new(), error: None }
+    }
+
+    // Adds the full memory to every line
+    pub fn with_memory(mut self, memory: bool) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    pub fn write_summary(&mut self, summary: &TraceSummary) -> io::Result<()> {
+        let line = serde_json::to_string(summary)?;
+        self.write_line(&line);
+        self.finish()
+    }
+
+    // Flushes the writer, failing with the first write error of the trace
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn enter(&mut self) {
+        if let Some(step) = self.current.take() {
+            self.calls.push(PendingCall { step, gas_returned: 0, lines: Vec::new() });
+        }
+    }
+
+    fn exit(&mut self, depth: usize, outcome: &CallOutcome) {
+        if depth > 0 {
+            if let Some(call) = self.calls.last_mut() {
+                call.gas_returned = outcome.gas_left;
+            }
+        }
+    }
+
+    // Lines inside a pending call wait for its line
+    fn emit(&mut self, line: String) {
+        match self.calls.last_mut() {
+            Some(call) => call.lines.push(line),
+            None => self.write_line(&line),
+        }
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.writer, "{}", line) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+impl<W: Write> Inspector for Eip3155Tracer<W> {
+    fn step(&mut self, evm: &EVM) {
+        self.current = Some(TraceStep {
+            pc: evm.pc() as u64,
+            op: evm.opcode(),
+            gas: U64::from(evm.gas_left()),
+            gas_cost: U64::zero(),
+            memory: self.memory.then(|| Bytes(evm.memory().data().to_vec())),
+            mem_size: evm.memory().len() as u64,
+            stack: evm.stack().data().to_vec(),
+            depth: evm.depth() as u64 + 1,
+            return_data: Bytes(evm.return_data().to_vec()),
+            refund: evm.state().refund(),
+            op_name: op_name(evm.opcode()),
+            error: None,
+        });
+    }
+
+    fn step_end(&mut self, evm: &EVM) {
+        // Without a current step the opcode made a call, which has returned by now
+        let (mut step, gas_returned, lines) = match self.current.take() {
+            Some(step) => (step, 0, Vec::new()),
+            None => match self.calls.pop() {
+                Some(call) => (call.step, call.gas_returned, call.lines),
+                None => return,
+            },
+        };
+        // What a call gave back is not part of its cost
+        let gas_after = evm.gas_left().saturating_sub(gas_returned) as u64;
+        step.gas_cost = U64::from(step.gas.as_u64().saturating_sub(gas_after));
+        step.error = evm.error().map(|error| error.to_string());
+        self.emit(serde_json::to_string(&step).expect("trace steps serialize"));
+        for line in lines {
+            self.emit(line);
+        }
+    }
+
+    fn call(&mut self, _inputs: &CallInputs) {
+        self.enter();
+    }
+
+    fn call_end(&mut self, inputs: &CallInputs, outcome: &CallOutcome) {
+        self.exit(inputs.depth, outcome);
+    }
+
+    fn create(&mut self, _inputs: &CreateInputs) {
+        self.enter();
+    }
+
+    fn create_end(&mut self, inputs: &CreateInputs, outcome: &CallOutcome) {
+        self.exit(inputs.depth, outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{inspect_transaction, BlockEnv, Transaction};
+    use crate::{Account, Precompiles, State};
+    use ethereum_types::H160;
+
+    #[test]
+    fn steps_report_the_refund_counter() {
+        let (sender, contract) = (H160([0x11; 20]), H160([0x22; 20]));
+        let mut state = State::new();
+        state.insert_account(sender, Account::new(U256::from(10).pow(U256::from(18))));
+        // PUSH1 0 PUSH1 0 SSTORE STOP, clearing a set slot
+        let mut account = Account::default().with_code(vec![0x60, 0x00, 0x60, 0x00, 0x55, 0x00]);
+        account.storage.store(U256::zero(), U256::one());
+        state.insert_account(contract, account);
+        let tx = Transaction { caller: sender, to: Some(contract), gas_limit: 100_000, gas_price: U256::one(), ..Default::default() };
+
+        let block = BlockEnv::default();
+        let mut tracer = Eip3155Tracer::new(Vec::new());
+        inspect_transaction(&tx, &block, &mut state, &mut Precompiles::new(block.spec), &mut tracer).unwrap();
+        let output = String::from_utf8(tracer.into_inner()).unwrap();
+        let refunds: Vec<u64> = output.lines().map(|line| serde_json::from_str::<TraceStep>(line).unwrap().refund).collect();
+        assert_eq!(refunds, [0, 0, 0, 4800]);
+    }
+}
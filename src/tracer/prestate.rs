@@ -0,0 +1,305 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use ethereum_types::{BigEndianHash, H160, H256, U256};
+use serde::{Deserialize, Serialize};
+use crate::hex::Bytes;
+use crate::inspector::{CallInputs, CreateInputs, Inspector};
+use crate::opcodes::Opcode;
+use crate::state::JournalEntry;
+use crate::{State, EVM};
+
+// An account in geth's prestateTracer output. Zero nonces, empty code and empty storage are left out
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrestateAccount {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<Bytes>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<H256, H256>,
+}
+
+pub type Prestate = BTreeMap<H160, PrestateAccount>;
+
+// Output of the prestateTracer in diff mode: what changed, before and after
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrestateDiff {
+    pub pre: Prestate,
+    pub post: Prestate,
+}
+
+// Records the accounts and storage slots a transaction touches. Their values before it are
+// rebuilt from the state's journal, so the state must not be committed in between. Like geth,
+// the block's coinbase is reported whether it was touched or not:
+//
+//     let mut tracer = PrestateTracer::new().with_coinbase(block.coinbase);
+//     let checkpoint = state.checkpoint();
+//     inspect_transaction(&tx, &block, &mut state, &mut precompiles, &mut tracer)?;
+//     let prestate = tracer.prestate(&mut state, checkpoint);
+#[derive(Debug, Default)]
+pub struct PrestateTracer {
+    accounts: BTreeSet<H160>,
+    slots: BTreeSet<(H160, U256)>,
+    created: BTreeSet<H160>,
+}
+
+// The values an account had before the transaction, which may have created it
+struct Original {
+    exists: bool,
+    balance: U256,
+    nonce: u64,
+    code: Vec<u8>,
+    storage: HashMap<U256, U256>,
+}
+
+impl Original {
+    fn is_empty(&self) -> bool {
+        self.balance.is_zero() && self.nonce == 0 && self.code.is_empty() && self.storage.values().all(U256::is_zero)
+    }
+}
+
+impl PrestateTracer {
+    pub fn new() -> Self {
+        PrestateTracer::default()
+    }
+
+    pub fn with_coinbase(mut self, coinbase: H160) -> Self {
+        self.accounts.insert(coinbase);
+        self
+    }
+
+    // Every touched account as it was before the transaction, with the slots it read or wrote.
+    // Contracts the transaction created are left out unless something was there before
+    pub fn prestate(&self, state: &mut State, checkpoint: usize) -> Prestate {
+        let originals = self.originals(state, checkpoint);
+        let mut prestate = Prestate::new();
+        for (address, original) in &originals {
+            if self.created.contains(address) && original.is_empty() {
+                continue;
+            }
+            let storage = self.slots_of(*address).map(|key| (key, original.storage[&key])).collect();
+            prestate.insert(*address, account(original.balance, original.nonce, &original.code, storage));
+        }
+        prestate
+    }
+
+    // The accounts the transaction changed. `pre` has their balance, nonce and code before it with
+    // the slots that changed, `post` only the fields and non-zero slots that changed. Accounts
+    // created by the transaction are only in `post`
+    pub fn diff(&self, state: &mut State, checkpoint: usize) -> PrestateDiff {
+        let originals = self.originals(state, checkpoint);
+        let mut diff = PrestateDiff::default();
+        for (address, original) in &originals {
+            let balance = state.balance(address);
+            let nonce = state.nonce(address);
+            let code = state.code(address).to_vec();
+            let mut pre_storage = BTreeMap::new();
+            let mut post_storage = BTreeMap::new();
+            for key in self.slots_of(*address) {
+                let before = original.storage[&key];
+                let after = state.storage(address, key);
+                if before != after {
+                    if !before.is_zero() {
+                        pre_storage.insert(key, before);
+                    }
+                    if !after.is_zero() {
+                        post_storage.insert(key, after);
+                    }
+                }
+            }
+            // Touching a missing account, say by paying it a zero fee, leaves it missing for geth
+            let empty = balance.is_zero() && nonce == 0 && code.is_empty() && post_storage.is_empty();
+            let created = !original.exists && !empty;
+            let changed = created
+                || balance != original.balance
+                || nonce != original.nonce
+                || code != original.code
+                || !post_storage.is_empty()
+                || !pre_storage.is_empty();
+            if !changed {
+                continue;
+            }
+
+            if !self.created.contains(address) || !original.is_empty() {
+                diff.pre.insert(*address, account(original.balance, original.nonce, &original.code, pre_storage));
+            }
+            let post = PrestateAccount {
+                balance: (balance != original.balance).then_some(balance),
+                nonce: (nonce != original.nonce).then_some(nonce),
+                code: (code != original.code && !code.is_empty()).then_some(Bytes(code)),
+                storage: post_storage.into_iter().map(|(key, value)| (H256::from_uint(&key), H256::from_uint(&value))).collect(),
+            };
+            diff.post.insert(*address, post);
+        }
+        diff
+    }
+
+    fn slots_of(&self, address: H160) -> impl Iterator<Item = U256> + '_ {
+        self.slots.range((address, U256::zero())..=(address, U256::MAX)).map(|(_, key)| *key)
+    }
+
+    // Starts from the current values and takes back every change journaled since `checkpoint`.
+    // Accounts only the transaction itself changed, like the coinbase, are found in the journal
+    fn originals(&self, state: &mut State, checkpoint: usize) -> BTreeMap<H160, Original> {
+        let journal = state.journal();
+        let journal = &journal[checkpoint.min(journal.len())..];
+        let mut accounts = self.accounts.clone();
+        accounts.extend(journal.iter().filter_map(JournalEntry::account));
+
+        let mut originals = BTreeMap::new();
+        for address in &accounts {
+            let storage = self.slots_of(*address).map(|key| (key, state.storage(address, key))).collect();
+            let original = Original {
+                exists: true,
+                balance: state.balance(address),
+                nonce: state.nonce(address),
+                code: state.code(address).to_vec(),
+                storage,
+            };
+            originals.insert(*address, original);
+        }
+        let journal = state.journal();
+        for entry in journal[checkpoint.min(journal.len())..].iter().rev() {
+            let Some(address) = entry.account() else {
+                continue;
+            };
+            let original = originals.get_mut(&address).expect("journaled accounts are collected");
+            match entry {
+                JournalEntry::AccountCreated { .. } => original.exists = false,
+                JournalEntry::AccountDestroyed { previous, .. } => {
+                    original.exists = true;
+                    original.balance = previous.balance;
+                    original.nonce = previous.nonce;
+                    original.code = previous.code.clone();
+                    for (key, value) in original.storage.iter_mut() {
+                        *value = previous.storage.get(*key);
+                    }
+                }
+                JournalEntry::BalanceChanged { previous, .. } => original.balance = *previous,
+                JournalEntry::NonceChanged { previous, .. } => original.nonce = *previous,
+                JournalEntry::CodeChanged { previous, .. } => original.code = previous.clone(),
+                JournalEntry::StorageChanged { key, previous, .. } => {
+                    original.storage.insert(*key, *previous);
+                }
+                JournalEntry::AddressWarmed { .. } | JournalEntry::SlotWarmed { .. } | JournalEntry::RefundChanged { .. } => {}
+            }
+        }
+        originals
+    }
+}
+
+fn account(balance: U256, nonce: u64, code: &[u8], storage: BTreeMap<U256, U256>) -> PrestateAccount {
+    PrestateAccount {
+        balance: Some(balance),
+        nonce: (nonce != 0).then_some(nonce),
+        code: (!code.is_empty()).then(|| Bytes(code.to_vec())),
+        storage: storage.into_iter().map(|(key, value)| (H256::from_uint(&key), H256::from_uint(&value))).collect(),
+    }
+}
+
+fn stack_address(evm: &EVM, position: usize) -> Option<H160> {
+    let stack = evm.stack().data();
+    let value = stack.get(stack.len().checked_sub(position + 1)?)?;
+    Some(H160::from(H256::from_uint(value)))
+}
+
+impl Inspector for PrestateTracer {
+    fn step(&mut self, evm: &EVM) {
+        let top = evm.stack().data().last().copied();
+        match Opcode::try_from_u8(evm.opcode()) {
+            Some(Opcode::SLOAD | Opcode::SSTORE) => {
+                self.accounts.insert(evm.address());
+                if let Some(key) = top {
+                    self.slots.insert((evm.address(), key));
+                }
+            }
+            Some(Opcode::BALANCE | Opcode::EXTCODESIZE | Opcode::EXTCODECOPY | Opcode::EXTCODEHASH | Opcode::SELFDESTRUCT) => {
+                self.accounts.extend(stack_address(evm, 0));
+            }
+            Some(Opcode::CALL | Opcode::CALLCODE | Opcode::DELEGATECALL | Opcode::STATICCALL) => {
+                self.accounts.extend(stack_address(evm, 1));
+            }
+            _ => {}
+        }
+    }
+
+    fn call(&mut self, inputs: &CallInputs) {
+        self.accounts.extend([inputs.caller, inputs.target, inputs.code_address]);
+    }
+
+    fn create(&mut self, inputs: &CreateInputs) {
+        self.accounts.extend([inputs.caller, inputs.address]);
+        self.created.insert(inputs.address);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{create_address, inspect_transaction, BlockEnv, Transaction};
+    use crate::{Account, Precompiles};
+
+    // F reads slot 1, sets slot 0 from 7 to 9 and back, sets slot 2 and creates a contract whose
+    // code is a single STOP. Gas is free so only the nonces and storage change. The expected
+    // outputs follow geth's prestateTracer, with and without `diffMode`
+    fn run(tracer: &mut PrestateTracer) -> (State, usize) {
+        let (sender, factory) = (H160([0x11; 20]), H160([0xf0; 20]));
+        let mut state = State::new();
+        state.insert_account(sender, Account::new(U256::from(10).pow(U256::from(18))));
+        let mut account = Account::default().with_code(crate::hex::decode(CODE).unwrap());
+        account.nonce = 1;
+        account.storage.store(U256::zero(), U256::from(7));
+        account.storage.store(U256::one(), U256::from(3));
+        state.insert_account(factory, account);
+        state.clear_journal();
+
+        let checkpoint = state.checkpoint();
+        let tx = Transaction { caller: sender, to: Some(factory), gas_limit: 1_000_000, ..Default::default() };
+        let receipt = inspect_transaction(&tx, &BlockEnv::default(), &mut state, &mut Precompiles::default(), tracer).unwrap();
+        assert!(receipt.status);
+        assert_eq!(state.code(&create_address(factory, 1)), [0x00]);
+        (state, checkpoint)
+    }
+
+    const CODE: &str = "0x600154506009600055600760005560056002556460016000f36000526005601b6000f05000";
+
+    fn slot(n: u8) -> String {
+        format!("0x{:064x}", n)
+    }
+
+    #[test]
+    fn prestate_matches_geth() {
+        let mut tracer = PrestateTracer::new().with_coinbase(H160::zero());
+        let (mut state, checkpoint) = run(&mut tracer);
+        let expected = serde_json::json!({
+            "0x0000000000000000000000000000000000000000": { "balance": "0x0" },
+            "0x1111111111111111111111111111111111111111": { "balance": "0xde0b6b3a7640000" },
+            "0xf0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0": {
+                "balance": "0x0",
+                "nonce": 1,
+                "code": CODE,
+                "storage": { slot(0): slot(7), slot(1): slot(3), slot(2): slot(0) }
+            }
+        });
+        assert_eq!(serde_json::to_value(tracer.prestate(&mut state, checkpoint)).unwrap(), expected);
+    }
+
+    #[test]
+    fn prestate_diff_matches_geth() {
+        let mut tracer = PrestateTracer::new().with_coinbase(H160::zero());
+        let (mut state, checkpoint) = run(&mut tracer);
+        let expected = serde_json::json!({
+            "pre": {
+                "0x1111111111111111111111111111111111111111": { "balance": "0xde0b6b3a7640000" },
+                "0xf0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0": { "balance": "0x0", "nonce": 1, "code": CODE }
+            },
+            "post": {
+                "0x1111111111111111111111111111111111111111": { "nonce": 1 },
+                format!("{:?}", create_address(H160([0xf0; 20]), 1)): { "nonce": 1, "code": "0x00" },
+                "0xf0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0": { "nonce": 2, "storage": { slot(2): slot(5) } }
+            }
+        });
+        assert_eq!(serde_json::to_value(tracer.diff(&mut state, checkpoint)).unwrap(), expected);
+    }
+}
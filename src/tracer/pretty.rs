@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use ethereum_types::H160;
+use crate::abi::{Abi, AbiValue, Event, Function, Param};
+use crate::hex;
+use super::call::{CallFrame, CallLog, FrameType};
+
+// Renders a callTracer tree the way Foundry prints traces at -vvvv:
+//
+//     [30512] Token::transfer(Bob, 100)
+//       ├─ emit Transfer(from: Alice, to: Bob, value: 100)
+//       └─ ← [Return] true
+//
+// Calls, events, return values and custom errors are decoded with the ABI registered for the
+// address, falling back to any registered ABI that knows the selector or topic
+#[derive(Debug, Default)]
+pub struct TracePrinter {
+    labels: BTreeMap<H160, String>,
+    abis: BTreeMap<H160, Abi>,
+}
+
+// What a frame shows under its header, in the order it happened
+enum Item<'a> {
+    Call(&'a CallFrame),
+    Log(&'a CallLog),
+    Return,
+}
+
+impl TracePrinter {
+    pub fn new() -> Self {
+        TracePrinter::default()
+    }
+
+    pub fn with_label(mut self, address: H160, label: impl Into<String>) -> Self {
+        self.labels.insert(address, label.into());
+        self
+    }
+
+    pub fn with_abi(mut self, address: H160, abi: Abi) -> Self {
+        self.abis.insert(address, abi);
+        self
+    }
+
+    pub fn render(&self, frame: &CallFrame) -> String {
+        let mut out = String::new();
+        self.write_frame(&mut out, frame, "", "  ");
+        out
+    }
+
+    fn write_frame(&self, out: &mut String, frame: &CallFrame, head: &str, body: &str) {
+        let _ = writeln!(out, "{}{}", head, self.header(frame));
+        if frame.kind == FrameType::Selfdestruct {
+            return;
+        }
+
+        // A log's position is the number of calls its frame had made before it
+        let mut items = Vec::new();
+        for (index, call) in frame.calls.iter().enumerate() {
+            items.extend(frame.logs.iter().filter(|log| log.position.as_usize() == index).map(Item::Log));
+            items.push(Item::Call(call));
+        }
+        items.extend(frame.logs.iter().filter(|log| log.position.as_usize() >= frame.calls.len()).map(Item::Log));
+        items.push(Item::Return);
+
+        let count = items.len();
+        for (index, item) in items.into_iter().enumerate() {
+            let (branch, indent) = if index + 1 == count { ("└─ ", "   ") } else { ("├─ ", "│  ") };
+            match item {
+                Item::Call(call) => self.write_frame(out, call, &format!("{}{}", body, branch), &format!("{}{}", body, indent)),
+                Item::Log(log) => {
+                    let _ = writeln!(out, "{}{}{}", body, branch, self.event(log));
+                }
+                Item::Return => {
+                    let _ = writeln!(out, "{}{}{}", body, branch, self.outcome(frame));
+                }
+            }
+        }
+    }
+
+    pub fn label(&self, address: &H160) -> String {
+        self.labels.get(address).cloned().unwrap_or_else(|| hex::checksum(address))
+    }
+
+    fn format(&self, values: &[AbiValue]) -> String {
+        values.iter().map(|value| value.format_with(&|address| self.label(address))).collect::<Vec<_>>().join(", ")
+    }
+
+    fn format_named(&self, params: &[Param], values: &[AbiValue]) -> String {
+        let mut fields = Vec::new();
+        for (param, value) in params.iter().zip(values) {
+            let value = value.format_with(&|address| self.label(address));
+            fields.push(if param.name.is_empty() { value } else { format!("{}: {}", param.name, value) });
+        }
+        fields.join(", ")
+    }
+
+    // The ABIs to try for an address, its own first
+    fn abis_for<'a>(&'a self, address: Option<&H160>) -> impl Iterator<Item = &'a Abi> + 'a {
+        let own = address.and_then(|address| self.abis.get(address));
+        let own_address = address.copied();
+        own.into_iter().chain(self.abis.iter().filter(move |(address, _)| Some(**address) != own_address).map(|(_, abi)| abi))
+    }
+
+    fn function(&self, frame: &CallFrame) -> Option<&Function> {
+        let selector = frame.input.get(..4)?;
+        self.abis_for(frame.to.as_ref()).find_map(|abi| abi.function_by_selector(selector))
+    }
+
+    fn header(&self, frame: &CallFrame) -> String {
+        let to = frame.to.unwrap_or_default();
+        let value = frame.value.filter(|value| !value.is_zero());
+        match frame.kind {
+            FrameType::Create | FrameType::Create2 => {
+                let label = self.labels.get(&to).map(String::as_str).unwrap_or("<unknown>");
+                let value = value.map(|value| format!("{{value: {}}}", value)).unwrap_or_default();
+                format!("[{}] → new {}@{}{}", frame.gas_used, label, hex::checksum(&to), value)
+            }
+            FrameType::Selfdestruct => {
+                format!("selfdestruct {} → {} ({} wei)", self.label(&frame.from), self.label(&to), frame.value.unwrap_or_default())
+            }
+            _ => {
+                let call = match (self.function(frame), frame.input.get(..4)) {
+                    (Some(function), _) => match function.decode_input(&frame.input[4..]) {
+                        Ok(values) => format!("{}({})", function.name, self.format(&values)),
+                        Err(_) => format!("{}({})", function.name, hex::encode(&frame.input[4..])),
+                    },
+                    (None, Some(selector)) => format!("{}({})", hex::encode(selector), hex::encode(&frame.input[4..])),
+                    (None, None) if frame.input.is_empty() && value.is_some() => "receive()".to_string(),
+                    (None, None) => format!("fallback({})", if frame.input.is_empty() { String::new() } else { hex::encode(&frame.input) }),
+                };
+                let (name, arguments) = call.split_at(call.find('(').unwrap_or(call.len()));
+                let value = value.map(|value| format!("{{value: {}}}", value)).unwrap_or_default();
+                let kind = match frame.kind {
+                    FrameType::StaticCall => " [staticcall]",
+                    FrameType::DelegateCall => " [delegatecall]",
+                    FrameType::CallCode => " [callcode]",
+                    _ => "",
+                };
+                format!("[{}] {}::{}{}{}{}", frame.gas_used, self.label(&to), name, value, arguments, kind)
+            }
+        }
+    }
+
+    fn event(&self, log: &CallLog) -> String {
+        let known = log.topics.first().and_then(|topic| self.abis_for(Some(&log.address)).find_map(|abi| abi.event_by_topic(topic)));
+        let decoded = known.and_then(|event: &Event| Some((event, event.decode_log(&log.topics, &log.data).ok()?)));
+        match decoded {
+            Some((event, values)) => format!("emit {}({})", event.name, self.format_named(&event.inputs, &values)),
+            None => {
+                let mut fields: Vec<String> = log.topics.iter().enumerate().map(|(index, topic)| format!("topic {}: {:?}", index, topic)).collect();
+                fields.push(format!("data: {}", hex::encode(&log.data)));
+                format!("emit {}", fields.join(", "))
+            }
+        }
+    }
+
+    fn outcome(&self, frame: &CallFrame) -> String {
+        let output = frame.output.as_deref().unwrap_or_default();
+        match &frame.error {
+            None if matches!(frame.kind, FrameType::Create | FrameType::Create2) => format!("← [Return] {} bytes of code", output.len()),
+            None if output.is_empty() => "← [Stop]".to_string(),
+            None => {
+                let decoded = self.function(frame).and_then(|function| Some((function, function.decode_output(output).ok()?)));
+                match decoded {
+                    Some((function, values)) => format!("← [Return] {}", self.format_named(&function.outputs, &values)),
+                    None => format!("← [Return] {}", hex::encode(output)),
+                }
+            }
+            Some(_) if frame.revert_reason.is_some() => format!("← [Revert] {}", frame.revert_reason.as_deref().unwrap_or_default()),
+            Some(error) if error == "execution reverted" => {
+                let decoded = output.get(..4).and_then(|selector| {
+                    let error = self.abis_for(frame.to.as_ref()).find_map(|abi| abi.error_by_selector(selector))?;
+                    Some((error, error.decode(&output[4..]).ok()?))
+                });
+                match decoded {
+                    Some((error, values)) => format!("← [Revert] {}({})", error.name, self.format_named(&error.inputs, &values)),
+                    None if output.is_empty() => "← [Revert] EvmError: Revert".to_string(),
+                    None => format!("← [Revert] {}", hex::encode(output)),
+                }
+            }
+            Some(error) => format!("← [Error] {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABI: &str = r#"[
+        {"type":"function","name":"transfer","inputs":[{"name":"to","type":"address"},{"name":"amount","type":"uint256"}],"outputs":[{"name":"","type":"bool"}]},
+        {"type":"event","name":"Transfer","inputs":[{"name":"from","type":"address","indexed":true},{"name":"to","type":"address","indexed":true},{"name":"value","type":"uint256","indexed":false}]},
+        {"type":"error","name":"InsufficientBalance","inputs":[{"name":"available","type":"uint256"}]}
+    ]"#;
+
+    // Alice calls Token::transfer(Bob, 100), which logs Transfer, makes a call with calldata too
+    // short for transfer and one that reverts with a custom error
+    const FRAME: &str = r#"{
+        "type":"CALL","from":"0x1111111111111111111111111111111111111111","to":"0x3333333333333333333333333333333333333333",
+        "gas":"0x10000","gasUsed":"0x7530",
+        "input":"0xa9059cbb00000000000000000000000022222222222222222222222222222222222222220000000000000000000000000000000000000000000000000000000000000064",
+        "output":"0x0000000000000000000000000000000000000000000000000000000000000001",
+        "logs":[{"address":"0x3333333333333333333333333333333333333333","position":"0x0","data":"0x0000000000000000000000000000000000000000000000000000000000000064",
+            "topics":["0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef","0x0000000000000000000000001111111111111111111111111111111111111111","0x0000000000000000000000002222222222222222222222222222222222222222"]}],
+        "calls":[
+            {"type":"STATICCALL","from":"0x3333333333333333333333333333333333333333","to":"0x2222222222222222222222222222222222222222","gas":"0x100","gasUsed":"0x64","input":"0xa9059cbb0102"},
+            {"type":"CALL","from":"0x3333333333333333333333333333333333333333","to":"0x2222222222222222222222222222222222222222","gas":"0x100","gasUsed":"0xc8","input":"0x",
+                "value":"0x5","error":"execution reverted","output":"0x926653510000000000000000000000000000000000000000000000000000000000000003"}
+        ]
+    }"#;
+
+    #[test]
+    fn renders_a_decoded_call_tree() {
+        let frame: CallFrame = serde_json::from_str(FRAME).unwrap();
+        let token = H160::repeat_byte(0x33);
+        let printer = TracePrinter::new()
+            .with_label(H160::repeat_byte(0x11), "Alice")
+            .with_label(H160::repeat_byte(0x22), "Bob")
+            .with_label(token, "Token")
+            .with_abi(token, Abi::from_json(ABI).unwrap());
+        let expected = "\
+[30000] Token::transfer(Bob, 100)
+  ├─ emit Transfer(from: Alice, to: Bob, value: 100)
+  ├─ [100] Bob::transfer(0x0102) [staticcall]
+  │  └─ ← [Stop]
+  ├─ [200] Bob::receive{value: 5}()
+  │  └─ ← [Revert] InsufficientBalance(available: 3)
+  └─ ← [Return] true
+";
+        assert_eq!(printer.render(&frame), expected);
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt::Write as _;
+use crate::inspector::{CallInputs, CallOutcome, CreateInputs, Inspector};
+use crate::sourcemap::{SourceLocation, SourceMap};
+use crate::EVM;
+use super::call::{geth_error, revert_reason};
+
+// Execution moving onto another Solidity line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceStep {
+    pub depth: usize,
+    pub pc: usize,
+    pub location: SourceLocation,
+}
+
+// A frame that reverted or failed, with the last Solidity line it ran
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFailure {
+    pub depth: usize,
+    pub reverted: bool,
+    // The decoded revert reason or the error
+    pub reason: Option<String>,
+    pub location: Option<SourceLocation>,
+}
+
+// Follows execution line by line through code that has a source map, and records where every
+// failed frame stopped, innermost first. Maps are matched to frames by their code
+#[derive(Debug, Default)]
+pub struct SourceTracer {
+    maps: Vec<SourceMap>,
+    // Source map and last location of each running frame
+    frames: Vec<(Option<usize>, Option<SourceLocation>)>,
+    steps: Vec<SourceStep>,
+    failures: Vec<SourceFailure>,
+}
+
+impl SourceTracer {
+    pub fn new() -> Self {
+        SourceTracer::default()
+    }
+
+    pub fn with_source_map(mut self, map: SourceMap) -> Self {
+        self.maps.push(map);
+        self
+    }
+
+    pub fn steps(&self) -> &[SourceStep] {
+        &self.steps
+    }
+
+    pub fn failures(&self) -> &[SourceFailure] {
+        &self.failures
+    }
+
+    // Every line run, indented by call depth
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for step in &self.steps {
+            let _ = writeln!(out, "{}{}: {}", "  ".repeat(step.depth), step.location, step.location.text);
+        }
+        out
+    }
+
+    // Where each failed frame stopped, with its revert reason or error
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        for failure in &self.failures {
+            let kind = if failure.reverted { "revert" } else { "error" };
+            let reason = failure.reason.as_ref().map(|reason| format!(" ({})", reason)).unwrap_or_default();
+            match &failure.location {
+                Some(location) => {
+                    let _ = writeln!(out, "{}{} at {}", kind, reason, location);
+                    let _ = writeln!(out, "    {}", location.text);
+                }
+                None => {
+                    let _ = writeln!(out, "{}{} at depth {}, in code without a source map", kind, reason, failure.depth);
+                }
+            }
+        }
+        out
+    }
+
+    fn exit(&mut self, depth: usize, outcome: &CallOutcome) {
+        if !outcome.success {
+            let reason = match &outcome.error {
+                Some(error) => Some(geth_error(error)),
+                None => revert_reason(&outcome.output),
+            };
+            let location = self.frames.get(depth).and_then(|(_, location)| location.clone());
+            self.failures.push(SourceFailure { depth, reverted: outcome.is_revert(), reason, location });
+        }
+        self.frames.truncate(depth);
+    }
+}
+
+impl Inspector for SourceTracer {
+    fn step(&mut self, evm: &EVM) {
+        let depth = evm.depth();
+        self.frames.truncate(depth + 1);
+        while self.frames.len() < depth + 1 {
+            let map = self.maps.iter().position(|map| map.code() == evm.code());
+            self.frames.push((map, None));
+        }
+        let Some(location) = self.frames[depth].0.and_then(|map| self.maps[map].location(evm.pc())) else {
+            return;
+        };
+        let moved = self.steps.last().is_none_or(|last| {
+            last.depth != depth || last.location.file != location.file || last.location.line != location.line
+        });
+        if moved {
+            self.steps.push(SourceStep { depth, pc: evm.pc(), location: location.clone() });
+        }
+        self.frames[depth].1 = Some(location);
+    }
+
+    fn call_end(&mut self, inputs: &CallInputs, outcome: &CallOutcome) {
+        self.exit(inputs.depth, outcome);
+    }
+
+    fn create_end(&mut self, inputs: &CreateInputs, outcome: &CallOutcome) {
+        self.exit(inputs.depth, outcome);
+    }
+}
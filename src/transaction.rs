@@ -0,0 +1,1065 @@
+use ethereum_types::{Bloom, H160, H256, U256};
+use thiserror::Error;
+use crate::helper::Helper;
+use crate::database::DatabaseError;
+use crate::receipt::{logs_bloom, receipts_root, Receipt};
+use crate::evm::EvmError;
+use crate::inspector::{CallInputs, CallKind, CallOutcome, CreateInputs, CreateKind, Inspector, NoopInspector};
+use crate::precompiles::PrecompileContext;
+use crate::{Precompiles, Spec, State, Transient, EVM};
+use crate::rlp;
+use crate::state::delegation_designator;
+
+mod envelope;
+mod recovery;
+pub use envelope::{transactions_root, Authorization, Signature, SignedTransaction, TxDecodeError, TxType};
+pub use recovery::{recover_signer, SignatureError};
+
+const TX_BASE_GAS: u64 = 21000;
+const TX_CREATE_GAS: u64 = 32000;
+const TX_DATA_ZERO_GAS: u64 = 4;
+const TX_DATA_NON_ZERO_GAS: u64 = 16; // 68 before Istanbul (EIP-2028)
+const ACCESS_LIST_ADDRESS_GAS: u64 = 2400;
+const ACCESS_LIST_STORAGE_KEY_GAS: u64 = 1900;
+const INITCODE_WORD_GAS: u64 = 2;
+const PER_EMPTY_ACCOUNT_GAS: u64 = 25000;
+const PER_AUTH_BASE_GAS: u64 = 12500;
+const GAS_PER_BLOB: u64 = 131072;
+const MIN_BLOB_GASPRICE: u64 = 1;
+const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+const TOTAL_COST_FLOOR_PER_TOKEN: u64 = 10;
+const CODE_DEPOSIT_GAS: usize = 200;
+const MAX_CODE_SIZE: usize = 24576;
+pub(crate) const MAX_INITCODE_SIZE: usize = 2 * MAX_CODE_SIZE;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessListItem {
+    pub address: H160,
+    pub storage_keys: Vec<U256>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transaction {
+    pub tx_type: TxType, // only used to encode the receipt
+    pub caller: H160,
+    pub to: Option<H160>, // None creates a contract from `data`
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub nonce: u64,
+    pub gas_limit: u64,
+    pub gas_price: U256, // max fee per gas for EIP-1559 transactions
+    pub max_priority_fee_per_gas: Option<U256>, // None for legacy and EIP-2930 transactions
+    pub access_list: Vec<AccessListItem>,
+    pub chain_id: Option<u64>,
+    pub authorization_list: Vec<Authorization>, // EIP-7702, only in set-code transactions
+    pub max_fee_per_blob_gas: Option<U256>, // Some for EIP-4844 blob transactions
+    pub blob_versioned_hashes: Vec<H256>,
+}
+
+impl Transaction {
+    // Price paid per unit of gas given the block's base fee
+    pub fn effective_gas_price(&self, base_fee: U256) -> U256 {
+        match self.max_priority_fee_per_gas {
+            Some(priority_fee) => self.gas_price.min(base_fee.saturating_add(priority_fee)),
+            None => self.gas_price,
+        }
+    }
+
+    pub fn blob_gas_used(&self) -> u64 {
+        GAS_PER_BLOB * self.blob_versioned_hashes.len() as u64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockEnv {
+    pub spec: Spec,
+    pub chain_id: u64,
+    pub number: u64,
+    pub timestamp: u64,
+    pub coinbase: H160,
+    pub gas_limit: u64,
+    pub base_fee: U256,
+    pub excess_blob_gas: u64, // EIP-4844, sets the blob gas price
+}
+
+impl BlockEnv {
+    // EIP-4844: MIN_BLOB_GASPRICE * e ** (excess_blob_gas / update_fraction)
+    pub fn blob_gas_price(&self) -> U256 {
+        // EIP-7691 raised the blob target in Prague, slowing the price's response
+        let update_fraction = if self.spec.is_enabled(Spec::Prague) { 5007716 } else { 3338477 };
+        fake_exponential(U256::from(MIN_BLOB_GASPRICE), U256::from(self.excess_blob_gas), U256::from(update_fraction))
+    }
+
+    pub fn max_blobs_per_block(&self) -> usize {
+        if self.spec.is_enabled(Spec::Prague) { 9 } else { 6 }
+    }
+}
+
+impl Default for BlockEnv {
+    fn default() -> Self {
+        BlockEnv {
+            spec: Spec::default(),
+            chain_id: 1,
+            number: 0,
+            timestamp: 0,
+            coinbase: H160::zero(),
+            gas_limit: 30_000_000,
+            base_fee: U256::zero(),
+            excess_blob_gas: 0,
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TransactionError {
+    #[error("Nonce too low: expected {expected}, got {got}")]
+    NonceTooLow { expected: u64, got: u64 },
+    #[error("Nonce too high: expected {expected}, got {got}")]
+    NonceTooHigh { expected: u64, got: u64 },
+    #[error("Insufficient funds: balance {balance}, need {cost}")]
+    InsufficientFunds { balance: U256, cost: U256 },
+    #[error("Intrinsic gas too low: need {intrinsic_gas}, gas limit {gas_limit}")]
+    IntrinsicGasTooLow { intrinsic_gas: u64, gas_limit: u64 },
+    #[error("Gas limit exceeds the block gas limit")]
+    GasLimitExceedsBlock,
+    #[error("Max fee per gas is below the block base fee")]
+    FeeCapTooLow,
+    #[error("Max priority fee per gas is higher than max fee per gas")]
+    PriorityFeeTooHigh,
+    #[error("Sender has deployed code")]
+    SenderNotEoa,
+    #[error("Initcode exceeds the maximum size")]
+    InitcodeTooLarge,
+    #[error("Blob transactions need Cancun, a call target and at least one blob")]
+    InvalidBlobTransaction,
+    #[error("Blob versioned hash does not have the KZG version byte")]
+    InvalidVersionedHash,
+    #[error("Too many blobs: {count}, at most {max}")]
+    TooManyBlobs { count: usize, max: usize },
+    #[error("Max fee per blob gas is below the blob gas price")]
+    BlobFeeCapTooLow,
+    #[error("Authorization lists need Prague and a call target")]
+    InvalidAuthorizationList,
+    #[error("State database error: {0}")]
+    Database(#[from] DatabaseError),
+    #[error("Transaction is for chain {got}, expected {expected}")]
+    InvalidChainId { expected: u64, got: u64 },
+    #[error("Balance of {0:?} would overflow")]
+    BalanceOverflow(H160),
+}
+
+// Gas charged before any code runs: the base cost, calldata, contract creation and access list
+pub fn intrinsic_gas(tx: &Transaction, spec: Spec) -> u64 {
+    let zero_bytes = tx.data.iter().filter(|&&b| b == 0).count() as u64;
+    let non_zero_bytes = tx.data.len() as u64 - zero_bytes;
+    let non_zero_gas = if spec.is_enabled(Spec::Istanbul) { TX_DATA_NON_ZERO_GAS } else { 68 };
+
+    let mut gas = TX_BASE_GAS + zero_bytes * TX_DATA_ZERO_GAS + non_zero_bytes * non_zero_gas;
+
+    if tx.to.is_none() {
+        if spec.is_enabled(Spec::Homestead) {
+            gas += TX_CREATE_GAS;
+        }
+        // EIP-3860
+        if spec.is_enabled(Spec::Shanghai) {
+            gas += INITCODE_WORD_GAS * Helper::to_word_size(tx.data.len()) as u64;
+        }
+    }
+
+    for item in &tx.access_list {
+        gas += ACCESS_LIST_ADDRESS_GAS + ACCESS_LIST_STORAGE_KEY_GAS * item.storage_keys.len() as u64;
+    }
+    gas + PER_EMPTY_ACCOUNT_GAS * tx.authorization_list.len() as u64
+}
+
+// EIP-7623: from Prague a transaction pays at least this much, however little it executes
+pub fn floor_data_gas(tx: &Transaction) -> u64 {
+    let zero_bytes = tx.data.iter().filter(|&&b| b == 0).count() as u64;
+    let tokens = zero_bytes + 4 * (tx.data.len() as u64 - zero_bytes);
+    TX_BASE_GAS + TOTAL_COST_FLOOR_PER_TOKEN * tokens
+}
+
+// gas_limit * gas_price + blob gas * max_fee_per_blob_gas + value, None if it overflows
+fn max_cost(tx: &Transaction) -> Option<U256> {
+    let max_blob_fee = U256::from(tx.blob_gas_used()).checked_mul(tx.max_fee_per_blob_gas.unwrap_or_default())?;
+    U256::from(tx.gas_limit).checked_mul(tx.gas_price)?.checked_add(max_blob_fee)?.checked_add(tx.value)
+}
+
+// Address of a contract created by `sender` with `nonce`: keccak256(rlp([sender, nonce]))[12..]
+pub fn create_address(sender: H160, nonce: u64) -> H160 {
+    let encoded = rlp::encode_list(&[rlp::encode_address(&sender), rlp::encode_u64(nonce)]);
+    H160::from_slice(&Helper::keccak256(&encoded)[12..])
+}
+
+// Address of a contract created by `sender` with CREATE2 (EIP-1014):
+// keccak256(0xff ++ sender ++ salt ++ keccak256(init_code))[12..]
+pub fn create2_address(sender: H160, salt: H256, init_code: &[u8]) -> H160 {
+    let mut preimage = vec![0xff];
+    preimage.extend_from_slice(sender.as_bytes());
+    preimage.extend_from_slice(salt.as_bytes());
+    preimage.extend_from_slice(&Helper::keccak256(init_code));
+    H160::from_slice(&Helper::keccak256(&preimage)[12..])
+}
+
+// Stores the code returned by init code as the code of `address`, paying for every byte of it.
+// Returns the gas left
+pub(crate) fn deposit_code(state: &mut State, spec: Spec, address: H160, code: Vec<u8>, gas_left: usize) -> Result<usize, EvmError> {
+    let deposit_gas = CODE_DEPOSIT_GAS * code.len();
+    if deposit_gas > gas_left {
+        return Err(EvmError::OutOfGas);
+    }
+    if spec.is_enabled(Spec::SpuriousDragon) && code.len() > MAX_CODE_SIZE {
+        return Err(EvmError::CodeSizeExceeded);
+    }
+    if spec.is_enabled(Spec::London) && code.first() == Some(&0xef) { // EIP-3541
+        return Err(EvmError::InvalidCodePrefix);
+    }
+    state.set_code(address, code);
+    Ok(gas_left - deposit_gas)
+}
+
+pub fn execute_transaction(tx: &Transaction, block: &BlockEnv, state: &mut State) -> Result<Receipt, TransactionError> {
+    let mut precompiles = Precompiles::new(block.spec);
+    execute_transaction_with_precompiles(tx, block, state, &mut precompiles)
+}
+
+// Validates `tx` against `state`, runs it and settles fees. An invalid transaction leaves the
+// state untouched, a valid one that reverts still pays for its gas and bumps the nonce. The
+// built-ins of `precompiles` are switched to the block's fork, registered ones are kept
+pub fn execute_transaction_with_precompiles(
+    tx: &Transaction,
+    block: &BlockEnv,
+    state: &mut State,
+    precompiles: &mut Precompiles,
+) -> Result<Receipt, TransactionError> {
+    inspect_transaction(tx, block, state, precompiles, &mut NoopInspector)
+}
+
+// Like `execute_transaction_with_precompiles`, reporting the execution to `inspector`. The
+// outermost frame is reported as a call or creation too, given the transaction's gas limit and
+// leaving what the transaction did not pay for
+pub fn inspect_transaction(
+    tx: &Transaction,
+    block: &BlockEnv,
+    state: &mut State,
+    precompiles: &mut Precompiles,
+    inspector: &mut dyn Inspector,
+) -> Result<Receipt, TransactionError> {
+    // Nothing computed after a database error can be trusted, so the whole transaction is undone,
+    // as it is when a balance would overflow
+    let start = state.checkpoint();
+    let result = execute_unchecked(tx, block, state, precompiles, inspector);
+    if let Some(error) = state.take_database_error() {
+        state.revert_to(start);
+        return Err(TransactionError::Database(error));
+    }
+    if result.is_err() {
+        state.revert_to(start);
+    }
+    result
+}
+
+fn execute_unchecked(
+    tx: &Transaction,
+    block: &BlockEnv,
+    state: &mut State,
+    precompiles: &mut Precompiles,
+    inspector: &mut dyn Inspector,
+) -> Result<Receipt, TransactionError> {
+    let spec = block.spec;
+    let base_fee = if spec.is_enabled(Spec::London) { block.base_fee } else { U256::zero() };
+    precompiles.set_spec(spec);
+
+    // Validation
+    // EIP-155: a signature for one chain cannot be replayed on another
+    if let Some(chain_id) = tx.chain_id {
+        if spec.is_enabled(Spec::SpuriousDragon) && chain_id != block.chain_id {
+            return Err(TransactionError::InvalidChainId { expected: block.chain_id, got: chain_id });
+        }
+    }
+    let expected_nonce = state.nonce(&tx.caller);
+    if tx.nonce < expected_nonce {
+        return Err(TransactionError::NonceTooLow { expected: expected_nonce, got: tx.nonce });
+    }
+    if tx.nonce > expected_nonce {
+        return Err(TransactionError::NonceTooHigh { expected: expected_nonce, got: tx.nonce });
+    }
+    // EIP-3607, EOAs that delegated their code (EIP-7702) can still send
+    let delegated = spec.is_enabled(Spec::Prague) && state.delegation(&tx.caller).is_some();
+    if !state.code(&tx.caller).is_empty() && !delegated {
+        return Err(TransactionError::SenderNotEoa);
+    }
+    if tx.gas_limit > block.gas_limit {
+        return Err(TransactionError::GasLimitExceedsBlock);
+    }
+    if tx.gas_price < base_fee {
+        return Err(TransactionError::FeeCapTooLow);
+    }
+    if tx.max_priority_fee_per_gas.is_some_and(|priority_fee| priority_fee > tx.gas_price) {
+        return Err(TransactionError::PriorityFeeTooHigh);
+    }
+    if tx.to.is_none() && spec.is_enabled(Spec::Shanghai) && tx.data.len() > MAX_INITCODE_SIZE {
+        return Err(TransactionError::InitcodeTooLarge);
+    }
+    if !tx.authorization_list.is_empty() && (!spec.is_enabled(Spec::Prague) || tx.to.is_none()) {
+        return Err(TransactionError::InvalidAuthorizationList);
+    }
+
+    // EIP-4844
+    let blob_gas_price = block.blob_gas_price();
+    if tx.max_fee_per_blob_gas.is_none() && !tx.blob_versioned_hashes.is_empty() {
+        return Err(TransactionError::InvalidBlobTransaction);
+    }
+    if let Some(max_fee_per_blob_gas) = tx.max_fee_per_blob_gas {
+        if !spec.is_enabled(Spec::Cancun) || tx.to.is_none() || tx.blob_versioned_hashes.is_empty() {
+            return Err(TransactionError::InvalidBlobTransaction);
+        }
+        if tx.blob_versioned_hashes.iter().any(|hash| hash[0] != VERSIONED_HASH_VERSION_KZG) {
+            return Err(TransactionError::InvalidVersionedHash);
+        }
+        let count = tx.blob_versioned_hashes.len();
+        if count > block.max_blobs_per_block() {
+            return Err(TransactionError::TooManyBlobs { count, max: block.max_blobs_per_block() });
+        }
+        if max_fee_per_blob_gas < blob_gas_price {
+            return Err(TransactionError::BlobFeeCapTooLow);
+        }
+    }
+
+    let initial_gas = intrinsic_gas(tx, spec);
+    let required_gas = if spec.is_enabled(Spec::Prague) { initial_gas.max(floor_data_gas(tx)) } else { initial_gas };
+    if tx.gas_limit < required_gas {
+        return Err(TransactionError::IntrinsicGasTooLow { intrinsic_gas: required_gas, gas_limit: tx.gas_limit });
+    }
+
+    // The sender must be able to pay the worst case up front. A cost past U256 is more than any
+    // balance, even U256::MAX
+    let balance = state.balance(&tx.caller);
+    let insufficient = |cost: Option<U256>| TransactionError::InsufficientFunds { balance, cost: cost.unwrap_or(U256::MAX) };
+    let max_cost = max_cost(tx);
+    if max_cost.is_none_or(|cost| balance < cost) {
+        return Err(insufficient(max_cost));
+    }
+
+    // Buy gas and bump the nonce, neither is undone if execution fails. Blob gas is not refunded
+    let start = state.checkpoint();
+    let effective_gas_price = tx.effective_gas_price(base_fee);
+    let upfront_cost = U256::from(tx.blob_gas_used())
+        .checked_mul(blob_gas_price)
+        .and_then(|blob_fee| U256::from(tx.gas_limit).checked_mul(effective_gas_price)?.checked_add(blob_fee));
+    if upfront_cost.is_none_or(|cost| !state.sub_balance(tx.caller, cost)) {
+        return Err(insufficient(upfront_cost));
+    }
+    state.increment_nonce(tx.caller);
+
+    state.clear_access_lists();
+    state.warm_address(tx.caller);
+    if spec.is_enabled(Spec::Shanghai) {
+        state.warm_address(block.coinbase); // EIP-3651
+    }
+    for item in &tx.access_list {
+        state.warm_address(item.address);
+        for key in &item.storage_keys {
+            state.warm_slot(item.address, *key);
+        }
+    }
+    // Delegations are set before execution and stay even if it fails
+    let refund = apply_authorization_list(tx, block, state);
+    state.add_refund(refund);
+
+    let checkpoint = state.checkpoint();
+    let mut collision = false;
+    let (target, code, delegate) = match tx.to {
+        Some(to) => match state.delegation(&to).filter(|_| spec.is_enabled(Spec::Prague)) {
+            Some(delegate) => {
+                state.warm_address(delegate);
+                let code = if precompiles.contains(&delegate) { Vec::new() } else { state.code(&delegate).to_vec() };
+                (to, code, Some(delegate))
+            }
+            None => (to, state.code(&to).to_vec(), None),
+        },
+        None => {
+            let address = create_address(tx.caller, tx.nonce);
+            // EIP-684: never deploy over an account that already has a nonce or code
+            collision = state.nonce(&address) != 0 || !state.code(&address).is_empty();
+            if spec.is_enabled(Spec::SpuriousDragon) && !collision {
+                state.set_nonce(address, 1); // EIP-161
+            }
+            (address, tx.data.clone(), None)
+        }
+    };
+    let contract_address = tx.to.is_none().then_some(target);
+    state.warm_address(target);
+    if !state.transfer(tx.caller, target, tx.value) {
+        return Err(TransactionError::BalanceOverflow(target));
+    }
+
+    let execution_gas = (tx.gas_limit - initial_gas) as usize;
+    let call_data = if tx.to.is_some() { tx.data.clone() } else { Vec::new() };
+    let call_inputs = tx.to.is_some().then(|| CallInputs {
+        kind: CallKind::Call,
+        caller: tx.caller,
+        target,
+        code_address: delegate.unwrap_or(target),
+        value: tx.value,
+        input: tx.data.clone(),
+        gas: tx.gas_limit as usize,
+        is_static: false,
+        depth: 0,
+    });
+    let create_inputs = tx.to.is_none().then(|| CreateInputs {
+        kind: CreateKind::Create,
+        caller: tx.caller,
+        address: target,
+        value: tx.value,
+        init_code: tx.data.clone(),
+        gas: tx.gas_limit as usize,
+        depth: 0,
+    });
+    if let Some(inputs) = &call_inputs {
+        inspector.call(inputs);
+    }
+    if let Some(inputs) = &create_inputs {
+        inspector.create(inputs);
+    }
+
+    // A call straight to a precompile runs it natively, as the CALL opcode does
+    let (outcome, mut logs) = if collision {
+        (CallOutcome { success: false, gas_left: 0, output: Vec::new(), error: Some(EvmError::CreateCollision) }, Vec::new())
+    } else if tx.to.is_some() && delegate.is_none() && precompiles.contains(&target) {
+        let mut transient = Transient::new();
+        let mut context = PrecompileContext {
+            caller: tx.caller,
+            value: tx.value,
+            is_static: false,
+            state: &mut *state,
+            transient: &mut transient,
+        };
+        let outcome = match precompiles.call(&target, &tx.data, execution_gas, &mut context).unwrap() {
+            Ok(result) => CallOutcome { success: true, gas_left: execution_gas - result.gas_used, output: result.output, error: None },
+            Err(error) => CallOutcome { success: false, gas_left: 0, output: Vec::new(), error: Some(EvmError::Precompile(error)) },
+        };
+        (outcome, Vec::new())
+    } else {
+        let mut evm = EVM::new(tx.caller, execution_gas, 0, code, call_data)
+            .with_state(std::mem::take(state))
+            .with_precompiles(std::mem::take(precompiles))
+            .with_spec(spec)
+            .with_address(target)
+            .with_value(tx.value)
+            .with_gas_price(effective_gas_price)
+            .with_blob_hashes(tx.blob_versioned_hashes.clone())
+            .with_block_number(block.number)
+            .with_coinbase(block.coinbase)
+            .with_inspector(&mut *inspector);
+        evm.run();
+
+        let outcome = CallOutcome {
+            success: evm.is_success(),
+            gas_left: evm.gas_left(),
+            output: evm.output().to_vec(),
+            error: evm.error().cloned(),
+        };
+        let logs = evm.logs().to_vec();
+        let (new_state, new_precompiles) = evm.take_state();
+        *state = new_state;
+        *precompiles = new_precompiles;
+        (outcome, logs)
+    };
+    let CallOutcome { mut success, mut gas_left, output, mut error } = outcome;
+
+    if success && tx.to.is_none() {
+        match deposit_code(state, spec, target, output.clone(), gas_left) {
+            Ok(left) => gas_left = left,
+            Err(deposit_error) => {
+                success = false;
+                gas_left = 0;
+                error = Some(deposit_error);
+            }
+        }
+    }
+
+    if !success {
+        state.revert_to(checkpoint);
+        logs.clear();
+    }
+
+    // EIP-3529 caps the refund at a fifth of the gas used, half before London
+    let mut gas_used = tx.gas_limit - gas_left as u64;
+    let max_refund_quotient = if spec.is_enabled(Spec::London) { 5 } else { 2 };
+    gas_used -= state.refund().min(gas_used / max_refund_quotient);
+    if spec.is_enabled(Spec::Prague) {
+        gas_used = gas_used.max(floor_data_gas(tx));
+    }
+
+    let outcome = CallOutcome { success, gas_left: (tx.gas_limit - gas_used) as usize, output: output.clone(), error };
+    if let Some(inputs) = &call_inputs {
+        inspector.call_end(inputs, &outcome);
+    }
+    if let Some(inputs) = &create_inputs {
+        inspector.create_end(inputs, &outcome);
+    }
+
+    // Refund unused gas, pay the tip to the coinbase. The base fee part is burnt
+    if !state.add_balance(tx.caller, U256::from(tx.gas_limit - gas_used) * effective_gas_price) {
+        return Err(TransactionError::BalanceOverflow(tx.caller));
+    }
+    let priority_fee = U256::from(gas_used) * (effective_gas_price - base_fee);
+    if !state.add_balance(block.coinbase, priority_fee) {
+        return Err(TransactionError::BalanceOverflow(block.coinbase));
+    }
+    let burnt_fee = U256::from(gas_used) * base_fee;
+    if spec.is_enabled(Spec::SpuriousDragon) {
+        state.remove_empty_accounts(start);
+    }
+
+    Ok(Receipt {
+        tx_type: tx.tx_type,
+        status: success,
+        gas_used,
+        cumulative_gas_used: gas_used,
+        effective_gas_price,
+        priority_fee,
+        burnt_fee,
+        blob_gas_used: tx.blob_gas_used(),
+        blob_gas_price: if tx.max_fee_per_blob_gas.is_some() { blob_gas_price } else { U256::zero() },
+        contract_address: if success { contract_address } else { None },
+        output,
+        logs_bloom: logs_bloom(&logs),
+        logs,
+    })
+}
+
+// Executes the transactions of a block one after the other, keeping their receipts
+#[derive(Debug)]
+pub struct BlockExecutor {
+    block: BlockEnv,
+    precompiles: Precompiles,
+    receipts: Vec<Receipt>,
+}
+
+impl BlockExecutor {
+    pub fn new(block: BlockEnv) -> Self {
+        let precompiles = Precompiles::new(block.spec);
+        BlockExecutor { block, precompiles, receipts: Vec::new() }
+    }
+
+    // Registered precompiles of `precompiles` are added to the built-ins of the block's fork
+    pub fn with_precompiles(mut self, mut precompiles: Precompiles) -> Self {
+        precompiles.set_spec(self.block.spec);
+        self.precompiles = precompiles;
+        self
+    }
+
+    // The transaction may only use the gas left in the block. An invalid transaction is not
+    // included and leaves the executor as it was
+    pub fn execute(&mut self, tx: &Transaction, state: &mut State) -> Result<&Receipt, TransactionError> {
+        let cumulative_gas_used = self.cumulative_gas_used();
+        let block = BlockEnv { gas_limit: self.block.gas_limit - cumulative_gas_used, ..self.block.clone() };
+        let mut receipt = execute_transaction_with_precompiles(tx, &block, state, &mut self.precompiles)?;
+        receipt.cumulative_gas_used += cumulative_gas_used;
+        self.receipts.push(receipt);
+        Ok(self.receipts.last().unwrap())
+    }
+
+    pub fn receipts(&self) -> &[Receipt] {
+        &self.receipts
+    }
+
+    pub fn cumulative_gas_used(&self) -> u64 {
+        self.receipts.last().map(|receipt| receipt.cumulative_gas_used).unwrap_or_default()
+    }
+
+    pub fn receipts_root(&self) -> H256 {
+        receipts_root(&self.receipts)
+    }
+
+    // Bloom of the block header, the union of the receipts' blooms
+    pub fn logs_bloom(&self) -> Bloom {
+        let mut bloom = Bloom::zero();
+        for receipt in &self.receipts {
+            bloom.accrue_bloom(&receipt.logs_bloom);
+        }
+        bloom
+    }
+}
+
+// Approximates factor * e ** (numerator / denominator) with integer math, as in EIP-4844
+fn fake_exponential(factor: U256, numerator: U256, denominator: U256) -> U256 {
+    let mut output = U256::zero();
+    let mut accumulator = factor * denominator;
+    let mut i = U256::one();
+    while !accumulator.is_zero() {
+        // Past U256 the price is more than any fee cap
+        let Some(next) = accumulator.checked_mul(numerator) else {
+            return U256::MAX;
+        };
+        output = output.saturating_add(accumulator);
+        accumulator = next / (denominator * i);
+        i += U256::one();
+    }
+    output / denominator
+}
+
+// EIP-7702: points each valid authority's code at the authorized address and bumps its nonce.
+// Invalid tuples are skipped. Returns the gas refunded for authorities that already existed
+fn apply_authorization_list(tx: &Transaction, block: &BlockEnv, state: &mut State) -> u64 {
+    let mut refund = 0;
+    for authorization in &tx.authorization_list {
+        if !authorization.chain_id.is_zero() && authorization.chain_id != U256::from(block.chain_id) {
+            continue;
+        }
+        if authorization.nonce == u64::MAX {
+            continue;
+        }
+        let Ok(authority) = authorization.recover_authority() else {
+            continue;
+        };
+        state.warm_address(authority);
+        if !state.code(&authority).is_empty() && state.delegation(&authority).is_none() {
+            continue;
+        }
+        if state.nonce(&authority) != authorization.nonce {
+            continue;
+        }
+        if state.exists(&authority) {
+            refund += PER_EMPTY_ACCOUNT_GAS - PER_AUTH_BASE_GAS;
+        }
+        // Delegating to the zero address clears the designator
+        let code = if authorization.address.is_zero() { Vec::new() } else { delegation_designator(authorization.address) };
+        state.set_code(authority, code);
+        state.increment_nonce(authority);
+    }
+    refund
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Account;
+
+    const SENDER: H160 = H160([0x11; 20]);
+    const RECIPIENT: H160 = H160([0x22; 20]);
+
+    fn funded_state() -> State {
+        let mut state = State::new();
+        state.insert_account(SENDER, Account::new(U256::from(10).pow(U256::from(24))));
+        state
+    }
+
+    fn transfer() -> Transaction {
+        Transaction {
+            caller: SENDER,
+            to: Some(RECIPIENT),
+            gas_limit: 100_000,
+            gas_price: U256::one(),
+            ..Default::default()
+        }
+    }
+
+    fn versioned_hash() -> H256 {
+        let mut hash = H256::repeat_byte(0xaa);
+        hash.0[0] = VERSIONED_HASH_VERSION_KZG;
+        hash
+    }
+
+    #[test]
+    fn rejects_costs_past_u256_without_touching_the_state() {
+        let block = BlockEnv::default();
+        let mut state = funded_state();
+        let cases = [
+            Transaction { gas_price: U256::MAX, ..transfer() },
+            Transaction { value: U256::MAX, ..transfer() },
+            Transaction {
+                max_fee_per_blob_gas: Some(U256::MAX),
+                blob_versioned_hashes: vec![versioned_hash()],
+                ..transfer()
+            },
+        ];
+        // Not even the largest balance covers them
+        let mut rich = State::new();
+        rich.insert_account(SENDER, Account::new(U256::MAX));
+        for state in [&mut state, &mut rich] {
+            for tx in &cases {
+                let error = execute_transaction(tx, &block, state).unwrap_err();
+                assert!(matches!(error, TransactionError::InsufficientFunds { cost, .. } if cost == U256::MAX), "{:?}", error);
+            }
+            assert_eq!(state.nonce(&SENDER), 0);
+        }
+
+        let tx = Transaction { blob_versioned_hashes: vec![versioned_hash()], ..transfer() };
+        assert_eq!(execute_transaction(&tx, &block, &mut state), Err(TransactionError::InvalidBlobTransaction));
+    }
+
+    #[test]
+    fn runs_a_precompile_called_by_the_transaction() {
+        let block = BlockEnv::default();
+        let mut state = funded_state();
+        let tx = Transaction { to: Some(H160::from_low_u64_be(2)), data: b"abc".to_vec(), ..transfer() };
+        let receipt = execute_transaction(&tx, &block, &mut state).unwrap();
+        assert!(receipt.status);
+        assert_eq!(
+            receipt.output,
+            crate::hex::decode("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad").unwrap()
+        );
+        // Intrinsic gas for three non-zero bytes, then 60 + 12 per word
+        assert_eq!(receipt.gas_used, 21000 + 3 * 16 + 72);
+
+        // Out of gas in the precompile consumes everything
+        let tx = Transaction { nonce: 1, gas_limit: 21000 + 3 * 16 + 71, ..tx };
+        let receipt = execute_transaction(&tx, &block, &mut state).unwrap();
+        assert!(!receipt.status);
+        assert_eq!(receipt.gas_used, tx.gas_limit);
+    }
+
+    #[test]
+    fn coinbase_opcode_reads_the_block_beneficiary() {
+        let coinbase = H160([0xcb; 20]);
+        let block = BlockEnv { coinbase, ..BlockEnv::default() };
+        let mut state = funded_state();
+        // COINBASE PUSH1 0 SSTORE
+        state.insert_account(RECIPIENT, Account::default().with_code(vec![0x41, 0x60, 0x00, 0x55]));
+        let receipt = execute_transaction(&transfer(), &block, &mut state).unwrap();
+        assert!(receipt.status);
+        assert_eq!(state.storage(&RECIPIENT, U256::zero()), U256::from_big_endian(coinbase.as_bytes()));
+    }
+
+    fn run_code(code: &[u8], storage: &[(u64, u64)]) -> (Receipt, State) {
+        let mut state = funded_state();
+        let mut account = Account::default().with_code(code.to_vec());
+        for (key, value) in storage {
+            account.storage.store(U256::from(*key), U256::from(*value));
+        }
+        state.insert_account(RECIPIENT, account);
+        let receipt = execute_transaction(&transfer(), &BlockEnv::default(), &mut state).unwrap();
+        (receipt, state)
+    }
+
+    #[test]
+    fn sstore_is_priced_by_original_and_current_value() {
+        // PUSH1 1 PUSH1 0 SSTORE: cold slot set from zero
+        let (receipt, _) = run_code(&[0x60, 0x01, 0x60, 0x00, 0x55], &[]);
+        assert_eq!(receipt.gas_used, 21000 + 6 + 2100 + 20000);
+
+        // Writing the value already stored only pays for the access
+        let (receipt, _) = run_code(&[0x60, 0x01, 0x60, 0x00, 0x55], &[(0, 1)]);
+        assert_eq!(receipt.gas_used, 21000 + 6 + 2100 + 100);
+
+        // Clearing a slot refunds 4800, under the cap of a fifth of the gas used
+        let (receipt, state) = run_code(&[0x60, 0x00, 0x60, 0x00, 0x55], &[(0, 1)]);
+        assert_eq!(receipt.gas_used, 21000 + 6 + 2100 + 2900 - 4800);
+        assert_eq!(state.refund(), 4800);
+
+        // Set and cleared again in one transaction: 19900 back, capped at a fifth
+        let (receipt, _) = run_code(&[0x60, 0x01, 0x60, 0x00, 0x55, 0x60, 0x00, 0x60, 0x00, 0x55], &[]);
+        let gas = 21000 + 12 + 2100 + 20000 + 100;
+        assert_eq!(receipt.gas_used, gas - gas / 5);
+    }
+
+    #[test]
+    fn sstore_needs_more_than_the_call_stipend() {
+        let mut state = funded_state();
+        state.insert_account(RECIPIENT, Account::default().with_code(vec![0x60, 0x01, 0x60, 0x00, 0x55]));
+        let tx = Transaction { gas_limit: 21000 + 6 + 2300, ..transfer() };
+        let receipt = execute_transaction(&tx, &BlockEnv::default(), &mut state).unwrap();
+        assert!(!receipt.status);
+        assert_eq!(state.storage(&RECIPIENT, U256::zero()), U256::zero());
+    }
+
+    #[test]
+    fn creation_fails_over_an_existing_account() {
+        let address = create_address(SENDER, 0);
+        // PUSH1 1 PUSH1 0 MSTORE8 PUSH1 1 PUSH1 0 RETURN, deploys 0x01
+        let tx = Transaction { to: None, data: vec![0x60, 0x01, 0x60, 0x00, 0x53, 0x60, 0x01, 0x60, 0x00, 0xf3], ..transfer() };
+
+        let mut state = funded_state();
+        state.insert_account(address, Account::default().with_code(vec![0x00]));
+        let receipt = execute_transaction(&tx, &BlockEnv::default(), &mut state).unwrap();
+        assert!(!receipt.status);
+        assert_eq!(receipt.gas_used, tx.gas_limit);
+        assert_eq!(receipt.contract_address, None);
+        assert_eq!(state.code(&address), &[0x00]);
+
+        let mut state = funded_state();
+        state.set_nonce(address, 1);
+        let receipt = execute_transaction(&tx, &BlockEnv::default(), &mut state).unwrap();
+        assert!(!receipt.status);
+        assert!(state.code(&address).is_empty());
+
+        let mut state = funded_state();
+        let receipt = execute_transaction(&tx, &BlockEnv::default(), &mut state).unwrap();
+        assert!(receipt.status);
+        assert_eq!(receipt.contract_address, Some(address));
+        assert_eq!(state.code(&address), &[0x01]);
+    }
+
+    #[test]
+    fn reverted_frames_roll_back_transient_storage() {
+        let child = H160([0x33; 20]);
+        // PUSH1 2 PUSH1 0 TSTORE PUSH1 0 PUSH1 0 REVERT
+        let child_code = vec![0x60, 0x02, 0x60, 0x00, 0x5d, 0x60, 0x00, 0x60, 0x00, 0xfd];
+        // TSTORE(0, 1), DELEGATECALL the child with 100000 gas, then SSTORE(0, TLOAD(0))
+        let mut code = vec![0x60, 0x01, 0x60, 0x00, 0x5d, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73];
+        code.extend_from_slice(child.as_bytes());
+        code.extend_from_slice(&[0x62, 0x01, 0x86, 0xa0, 0xf4, 0x50, 0x60, 0x00, 0x5c, 0x60, 0x00, 0x55, 0x00]);
+
+        let mut state = funded_state();
+        state.insert_account(child, Account::default().with_code(child_code));
+        state.insert_account(RECIPIENT, Account::default().with_code(code));
+        let receipt = execute_transaction(&transfer(), &BlockEnv::default(), &mut state).unwrap();
+        assert!(receipt.status);
+        assert_eq!(state.storage(&RECIPIENT, U256::zero()), U256::one());
+    }
+
+    #[test]
+    fn zero_value_transfers_and_tips_create_no_accounts() {
+        // No tip at a zero gas price, and nothing sent to the recipient
+        let block = BlockEnv { coinbase: H160([0xcb; 20]), ..BlockEnv::default() };
+        let mut state = funded_state();
+        let root = state.state_root();
+        let tx = Transaction { gas_price: U256::zero(), ..transfer() };
+        let receipt = execute_transaction(&tx, &block, &mut state).unwrap();
+        assert!(receipt.status);
+        assert!(!state.exists(&block.coinbase));
+        assert!(!state.exists(&RECIPIENT));
+        assert_ne!(state.state_root(), root);
+        state.set_nonce(SENDER, 0);
+        assert_eq!(state.state_root(), root);
+    }
+
+    #[test]
+    fn credits_past_u256_make_the_transaction_invalid() {
+        let mut state = funded_state();
+        state.insert_account(RECIPIENT, Account::new(U256::MAX));
+        let tx = Transaction { value: U256::one(), ..transfer() };
+        let balance = state.balance(&SENDER);
+        assert_eq!(execute_transaction(&tx, &BlockEnv::default(), &mut state), Err(TransactionError::BalanceOverflow(RECIPIENT)));
+        assert_eq!((state.balance(&SENDER), state.nonce(&SENDER)), (balance, 0));
+    }
+
+    #[test]
+    fn precompiles_of_another_fork_run_under_the_block_spec() {
+        let custom = H160::from_low_u64_be(0x100);
+        let mut precompiles = Precompiles::new(Spec::Berlin);
+        precompiles.register(custom, |_: &[u8], _: usize| Ok(crate::precompiles::PrecompileOutput::new(0, vec![1])));
+        let mut executor = BlockExecutor::new(BlockEnv::default()).with_precompiles(precompiles);
+
+        // Clearing a slot refunds 4800 under Cancun, 15000 under Berlin
+        let mut state = funded_state();
+        let mut account = Account::default().with_code(vec![0x60, 0x00, 0x60, 0x00, 0x55]);
+        account.storage.store(U256::zero(), U256::one());
+        state.insert_account(RECIPIENT, account);
+        let receipt = executor.execute(&transfer(), &mut state).unwrap();
+        assert_eq!(receipt.gas_used, 21000 + 6 + 2100 + 2900 - 4800);
+
+        // The Cancun point evaluation precompile is there, next to the registered one
+        let tx = Transaction { nonce: 1, to: Some(H160::from_low_u64_be(10)), ..transfer() };
+        assert!(!executor.execute(&tx, &mut state).unwrap().status);
+        let tx = Transaction { nonce: 2, to: Some(custom), ..transfer() };
+        assert_eq!(executor.execute(&tx, &mut state).unwrap().output, [1]);
+    }
+
+    #[test]
+    fn create2_addresses_match_eip_1014() {
+        let address = create2_address(H160::zero(), H256::zero(), &[0x00]);
+        assert_eq!(address, H160::from_slice(&crate::hex::decode("4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38").unwrap()));
+        let mut salt = H256::zero();
+        salt.0[28..].copy_from_slice(&[0xca, 0xfe, 0xba, 0xbe]);
+        let sender = H160::from_low_u64_be(0xdeadbeef);
+        let address = create2_address(sender, salt, &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(address, H160::from_slice(&crate::hex::decode("60f3f640a8508fc6a86d45df051962668e1e8ac7").unwrap()));
+    }
+
+    #[test]
+    fn create_opcodes_deploy_the_returned_code() {
+        // Init code returning ADDRESS SELFDESTRUCT, and one reverting with a byte of data
+        let init_code = "6130ff6000526002601ef3";
+        let reverting = "60016000fd";
+        let factory = [
+            format!("6a{init_code}600052"),
+            "600b60156000f0600055".to_string(), // slot 0: CREATE
+            "602a600b60156000f5600155".to_string(), // slot 1: CREATE2 with salt 42
+            format!("64{reverting}600052"),
+            "6005601b6000f0156002553d600355".to_string(), // slots 2 and 3: failed, 1 byte returned
+        ]
+        .concat();
+        let mut state = funded_state();
+        state.insert_account(RECIPIENT, Account::default().with_code(crate::hex::decode(&factory).unwrap()));
+        let receipt = execute_transaction(&Transaction { gas_limit: 1_000_000, ..transfer() }, &BlockEnv::default(), &mut state).unwrap();
+        assert!(receipt.status);
+
+        let created = create_address(RECIPIENT, 0);
+        let mut salt = H256::zero();
+        salt.0[31] = 42;
+        let created2 = create2_address(RECIPIENT, salt, &crate::hex::decode(init_code).unwrap());
+        assert_eq!(state.storage(&RECIPIENT, U256::zero()), U256::from_big_endian(created.as_bytes()));
+        assert_eq!(state.storage(&RECIPIENT, U256::one()), U256::from_big_endian(created2.as_bytes()));
+        assert_eq!(state.storage(&RECIPIENT, U256::from(2)), U256::one());
+        assert_eq!(state.storage(&RECIPIENT, U256::from(3)), U256::one());
+        for address in [created, created2] {
+            assert_eq!(state.code(&address), [0x30, 0xff]);
+            assert_eq!(state.nonce(&address), 1);
+        }
+        // The failed creation still used up a nonce
+        assert_eq!(state.nonce(&RECIPIENT), 3);
+        assert!(!state.exists(&create_address(RECIPIENT, 2)));
+    }
+
+    #[test]
+    fn effective_gas_price_saturates_instead_of_overflowing() {
+        let tx = Transaction { gas_price: U256::MAX, max_priority_fee_per_gas: Some(U256::MAX), ..transfer() };
+        assert_eq!(tx.effective_gas_price(U256::MAX), U256::MAX);
+    }
+
+    // Signed with the private key 0x4646...46, whose address is AUTHORITY
+    fn authorize(chain_id: u64, address: H160, nonce: u64) -> Authorization {
+        let mut authorization = Authorization { chain_id: U256::from(chain_id), address, nonce, ..Default::default() };
+        let key = k256::ecdsa::SigningKey::from_slice(&[0x46; 32]).unwrap();
+        let (signature, recovery_id) = key.sign_prehash_recoverable(authorization.signing_hash().as_bytes()).unwrap();
+        let bytes = signature.to_bytes();
+        authorization.y_parity = recovery_id.is_y_odd() as u8;
+        authorization.r = U256::from_big_endian(&bytes[..32]);
+        authorization.s = U256::from_big_endian(&bytes[32..]);
+        authorization
+    }
+
+    const AUTHORITY: H160 = H160([
+        0x9d, 0x8a, 0x62, 0xf6, 0x56, 0xa8, 0xd1, 0x61, 0x5c, 0x12, 0x94, 0xfd, 0x71, 0xe9, 0xcf, 0xb3, 0xe4, 0x85, 0x5a, 0x4f,
+    ]);
+    const DELEGATE: H160 = H160([0x33; 20]);
+
+    fn prague() -> BlockEnv {
+        BlockEnv { spec: Spec::Prague, ..BlockEnv::default() }
+    }
+
+    #[test]
+    fn delegated_accounts_run_the_delegate_code_in_their_own_context() {
+        let mut state = funded_state();
+        // SSTORE(0, ADDRESS)
+        state.insert_account(DELEGATE, Account::default().with_code(vec![0x30, 0x60, 0x00, 0x55]));
+        let tx = Transaction {
+            to: Some(AUTHORITY),
+            authorization_list: vec![authorize(1, DELEGATE, 0)],
+            ..transfer()
+        };
+        assert_eq!(authorize(1, DELEGATE, 0).recover_authority(), Ok(AUTHORITY));
+        let receipt = execute_transaction(&tx, &prague(), &mut state).unwrap();
+        assert!(receipt.status);
+        assert_eq!(state.code(&AUTHORITY), delegation_designator(DELEGATE));
+        assert_eq!(state.nonce(&AUTHORITY), 1);
+        assert_eq!(state.storage(&AUTHORITY, U256::zero()), U256::from_big_endian(AUTHORITY.as_bytes()));
+        assert_eq!(state.storage(&DELEGATE, U256::zero()), U256::zero());
+
+        // Delegating to the zero address clears the code again
+        let tx = Transaction { nonce: 1, authorization_list: vec![authorize(0, H160::zero(), 1)], ..transfer() };
+        assert!(execute_transaction(&tx, &prague(), &mut state).unwrap().status);
+        assert!(state.code(&AUTHORITY).is_empty());
+        assert_eq!(state.nonce(&AUTHORITY), 2);
+
+        let tx = Transaction { nonce: 2, authorization_list: vec![authorize(1, DELEGATE, 2)], ..transfer() };
+        let block = BlockEnv { spec: Spec::Cancun, ..prague() };
+        assert_eq!(execute_transaction(&tx, &block, &mut state), Err(TransactionError::InvalidAuthorizationList));
+    }
+
+    #[test]
+    fn authorizations_for_another_chain_or_nonce_are_skipped() {
+        let mut state = funded_state();
+        let tx = Transaction {
+            authorization_list: vec![authorize(5, DELEGATE, 0), authorize(1, DELEGATE, 7)],
+            ..transfer()
+        };
+        let receipt = execute_transaction(&tx, &prague(), &mut state).unwrap();
+        assert!(receipt.status);
+        // Every tuple is paid for, valid or not
+        assert_eq!(receipt.gas_used, 21000 + 2 * 25000);
+        assert!(!state.exists(&AUTHORITY));
+
+        // A chain id of zero is valid on any chain
+        let tx = Transaction { nonce: 1, authorization_list: vec![authorize(0, DELEGATE, 0)], ..transfer() };
+        assert!(execute_transaction(&tx, &prague(), &mut state).unwrap().status);
+        assert_eq!(state.code(&AUTHORITY), delegation_designator(DELEGATE));
+    }
+
+    #[test]
+    fn existing_authorities_refund_the_new_account_cost() {
+        let tx = Transaction { authorization_list: vec![authorize(1, DELEGATE, 0)], ..transfer() };
+        let mut state = funded_state();
+        assert_eq!(execute_transaction(&tx, &prague(), &mut state).unwrap().gas_used, 46000);
+
+        let mut state = funded_state();
+        state.insert_account(AUTHORITY, Account::new(U256::one()));
+        let receipt = execute_transaction(&tx, &prague(), &mut state).unwrap();
+        // 25000 - 12500 refunded, capped at a fifth of the gas used
+        assert_eq!(receipt.gas_used, 46000 - 46000 / 5);
+        assert_eq!(state.nonce(&AUTHORITY), 1);
+    }
+
+    fn blob_transaction(count: usize) -> Transaction {
+        let hashes = (0..count)
+            .map(|i| {
+                let mut hash = versioned_hash();
+                hash.0[31] = i as u8;
+                hash
+            })
+            .collect();
+        Transaction { max_fee_per_blob_gas: Some(U256::one()), blob_versioned_hashes: hashes, ..transfer() }
+    }
+
+    #[test]
+    fn blob_gas_price_follows_the_fork_update_fraction() {
+        // Values of the EIP-4844 fake_exponential reference code
+        let cases = [(0, 1, 1), (3_338_477, 2, 1), (5_007_716, 4, 2), (33_384_770, 22026, 785), (39_321_600, 130_392, 2571)];
+        for (excess_blob_gas, cancun, prague) in cases {
+            let block = BlockEnv { excess_blob_gas, ..BlockEnv::default() };
+            assert_eq!(block.blob_gas_price(), U256::from(cancun), "cancun, excess {}", excess_blob_gas);
+            let block = BlockEnv { spec: Spec::Prague, ..block };
+            assert_eq!(block.blob_gas_price(), U256::from(prague), "prague, excess {}", excess_blob_gas);
+        }
+    }
+
+    #[test]
+    fn rejects_too_many_blobs_and_low_blob_fee_caps() {
+        let block = BlockEnv { excess_blob_gas: 33_384_770, ..BlockEnv::default() };
+        let mut state = funded_state();
+        assert_eq!(
+            execute_transaction(&blob_transaction(7), &block, &mut state),
+            Err(TransactionError::TooManyBlobs { count: 7, max: 6 })
+        );
+        let prague = BlockEnv { spec: Spec::Prague, ..block.clone() };
+        assert_eq!(
+            execute_transaction(&blob_transaction(10), &prague, &mut state),
+            Err(TransactionError::TooManyBlobs { count: 10, max: 9 })
+        );
+        let tx = Transaction { max_fee_per_blob_gas: Some(U256::from(22025)), ..blob_transaction(6) };
+        assert_eq!(execute_transaction(&tx, &block, &mut state), Err(TransactionError::BlobFeeCapTooLow));
+        assert_eq!(state.nonce(&SENDER), 0);
+
+        let balance = state.balance(&SENDER);
+        let tx = Transaction { max_fee_per_blob_gas: Some(U256::from(22026)), ..blob_transaction(6) };
+        let receipt = execute_transaction(&tx, &block, &mut state).unwrap();
+        assert_eq!(receipt.blob_gas_used, 6 * GAS_PER_BLOB);
+        assert_eq!(receipt.blob_gas_price, U256::from(22026));
+        assert_eq!(balance - state.balance(&SENDER), U256::from(21000 + 6 * GAS_PER_BLOB * 22026));
+    }
+
+    #[test]
+    fn blobhash_returns_the_versioned_hashes_by_index() {
+        // SSTORE(i, BLOBHASH(i)) for i in 0..3
+        let code = crate::hex::decode("600049600055600149600155600249600255").unwrap();
+        let mut state = funded_state();
+        state.insert_account(RECIPIENT, Account::default().with_code(code));
+        let tx = blob_transaction(2);
+        assert!(execute_transaction(&tx, &BlockEnv::default(), &mut state).unwrap().status);
+        for (i, hash) in tx.blob_versioned_hashes.iter().enumerate() {
+            assert_eq!(state.storage(&RECIPIENT, U256::from(i)), U256::from_big_endian(hash.as_bytes()));
+        }
+        assert_eq!(state.storage(&RECIPIENT, U256::from(2)), U256::zero());
+    }
+
+    #[test]
+    fn blob_gas_price_saturates_for_huge_excess_blob_gas() {
+        let block = BlockEnv { excess_blob_gas: u64::MAX, ..BlockEnv::default() };
+        assert_eq!(block.blob_gas_price(), U256::MAX);
+        assert_eq!(BlockEnv::default().blob_gas_price(), U256::one());
+    }
+}
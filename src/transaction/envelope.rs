@@ -0,0 +1,470 @@
+use ethereum_types::{H160, H256, U256};
+use thiserror::Error;
+use crate::helper::Helper;
+use crate::rlp::{self, Rlp, RlpError};
+use crate::trie::ordered_trie_root;
+use super::{AccessListItem, Transaction};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TxDecodeError {
+    #[error("Invalid RLP: {0}")]
+    Rlp(#[from] RlpError),
+    #[error("Empty transaction")]
+    Empty,
+    #[error("Unsupported transaction type {0:#04x}")]
+    UnsupportedType(u8),
+    #[error("Expected {expected} fields, got {got}")]
+    InvalidFieldCount { expected: usize, got: usize },
+    #[error("Transaction type {0:#04x} cannot create a contract")]
+    MissingTo(u8),
+    #[error("Invalid signature value v = {0}")]
+    InvalidV(u64),
+    #[error("Set-code transaction with an empty authorization list")]
+    EmptyAuthorizationList,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TxType {
+    #[default]
+    Legacy,
+    AccessList, // EIP-2930
+    DynamicFee, // EIP-1559
+    Blob, // EIP-4844
+    SetCode, // EIP-7702
+}
+
+impl TxType {
+    pub fn from_u8(value: u8) -> Option<TxType> {
+        match value {
+            0x00 => Some(TxType::Legacy),
+            0x01 => Some(TxType::AccessList),
+            0x02 => Some(TxType::DynamicFee),
+            0x03 => Some(TxType::Blob),
+            0x04 => Some(TxType::SetCode),
+            _ => None,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            TxType::Legacy => 0x00,
+            TxType::AccessList => 0x01,
+            TxType::DynamicFee => 0x02,
+            TxType::Blob => 0x03,
+            TxType::SetCode => 0x04,
+        }
+    }
+}
+
+// `v` is as encoded: 27/28 or 35 + 2 * chain_id + parity for legacy transactions,
+// the y parity (0 or 1) for typed ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Signature {
+    pub v: u64,
+    pub r: U256,
+    pub s: U256,
+}
+
+// EIP-7702 authorization tuple
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Authorization {
+    pub chain_id: U256,
+    pub address: H160,
+    pub nonce: u64,
+    pub y_parity: u8,
+    pub r: U256,
+    pub s: U256,
+}
+
+impl Authorization {
+    // keccak256(0x05 || rlp([chain_id, address, nonce]))
+    pub fn signing_hash(&self) -> H256 {
+        let mut payload = vec![0x05];
+        payload.extend(rlp::encode_list(&[
+            rlp::encode_u256(self.chain_id),
+            rlp::encode_address(&self.address),
+            rlp::encode_u64(self.nonce),
+        ]));
+        H256(Helper::keccak256(&payload))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        rlp::encode_list(&[
+            rlp::encode_u256(self.chain_id),
+            rlp::encode_address(&self.address),
+            rlp::encode_u64(self.nonce),
+            rlp::encode_u64(self.y_parity as u64),
+            rlp::encode_u256(self.r),
+            rlp::encode_u256(self.s),
+        ])
+    }
+
+    fn decode(item: &Rlp) -> Result<Authorization, TxDecodeError> {
+        let fields = expect_fields(item, 6)?;
+        let y_parity = fields[3].as_u64()?;
+        Ok(Authorization {
+            chain_id: fields[0].as_u256()?,
+            address: fields[1].as_address()?,
+            nonce: fields[2].as_u64()?,
+            y_parity: u8::try_from(y_parity).map_err(|_| TxDecodeError::InvalidV(y_parity))?,
+            r: fields[4].as_u256()?,
+            s: fields[5].as_u256()?,
+        })
+    }
+}
+
+// A signed transaction of any type as it appears on the wire. Fields a type does not
+// have are left at their defaults
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SignedTransaction {
+    pub tx_type: TxType,
+    pub chain_id: Option<u64>, // None for pre-EIP-155 legacy transactions
+    pub nonce: u64,
+    pub gas_price: U256, // max fee per gas from EIP-1559 on
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub gas_limit: u64,
+    pub to: Option<H160>,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub access_list: Vec<AccessListItem>,
+    pub max_fee_per_blob_gas: Option<U256>,
+    pub blob_versioned_hashes: Vec<H256>,
+    pub authorization_list: Vec<Authorization>,
+    pub signature: Signature,
+}
+
+impl SignedTransaction {
+    // Decodes a legacy RLP list or an EIP-2718 typed envelope (type byte || rlp payload)
+    pub fn decode(raw: &[u8]) -> Result<SignedTransaction, TxDecodeError> {
+        let first = *raw.first().ok_or(TxDecodeError::Empty)?;
+        if first >= 0xc0 {
+            return Self::decode_legacy(&Rlp::decode(raw)?);
+        }
+        let tx_type = match TxType::from_u8(first) {
+            Some(TxType::Legacy) | None => return Err(TxDecodeError::UnsupportedType(first)),
+            Some(tx_type) => tx_type,
+        };
+        let item = Rlp::decode(&raw[1..])?;
+        let expected = match tx_type {
+            TxType::AccessList => 11,
+            TxType::DynamicFee => 12,
+            TxType::Blob => 14,
+            _ => 13,
+        };
+        let fields = expect_fields(&item, expected)?;
+
+        let mut tx = SignedTransaction {
+            tx_type,
+            chain_id: Some(fields[0].as_u64()?),
+            nonce: fields[1].as_u64()?,
+            ..Default::default()
+        };
+        let mut rest = if tx_type == TxType::AccessList {
+            tx.gas_price = fields[2].as_u256()?;
+            &fields[3..]
+        } else {
+            tx.max_priority_fee_per_gas = Some(fields[2].as_u256()?);
+            tx.gas_price = fields[3].as_u256()?;
+            &fields[4..]
+        };
+        tx.gas_limit = rest[0].as_u64()?;
+        tx.to = rest[1].as_optional_address()?;
+        tx.value = rest[2].as_u256()?;
+        tx.data = rest[3].as_bytes()?.to_vec();
+        tx.access_list = decode_access_list(&rest[4])?;
+        rest = &rest[5..];
+
+        match tx_type {
+            TxType::Blob => {
+                tx.max_fee_per_blob_gas = Some(rest[0].as_u256()?);
+                tx.blob_versioned_hashes = rest[1].as_list()?.iter().map(Rlp::as_h256).collect::<Result<_, _>>()?;
+                rest = &rest[2..];
+            }
+            TxType::SetCode => {
+                tx.authorization_list = rest[0].as_list()?.iter().map(Authorization::decode).collect::<Result<_, _>>()?;
+                if tx.authorization_list.is_empty() {
+                    return Err(TxDecodeError::EmptyAuthorizationList);
+                }
+                rest = &rest[1..];
+            }
+            _ => {}
+        }
+        if tx.to.is_none() && matches!(tx_type, TxType::Blob | TxType::SetCode) {
+            return Err(TxDecodeError::MissingTo(tx_type.as_u8()));
+        }
+
+        let y_parity = rest[0].as_u64()?;
+        if y_parity > 1 {
+            return Err(TxDecodeError::InvalidV(y_parity));
+        }
+        tx.signature = Signature { v: y_parity, r: rest[1].as_u256()?, s: rest[2].as_u256()? };
+        Ok(tx)
+    }
+
+    fn decode_legacy(item: &Rlp) -> Result<SignedTransaction, TxDecodeError> {
+        let fields = expect_fields(item, 9)?;
+        let v = fields[6].as_u64()?;
+        // EIP-155 folds the chain id into v
+        let chain_id = match v {
+            27 | 28 => None,
+            v if v >= 35 => Some((v - 35) / 2),
+            v => return Err(TxDecodeError::InvalidV(v)),
+        };
+        Ok(SignedTransaction {
+            tx_type: TxType::Legacy,
+            chain_id,
+            nonce: fields[0].as_u64()?,
+            gas_price: fields[1].as_u256()?,
+            gas_limit: fields[2].as_u64()?,
+            to: fields[3].as_optional_address()?,
+            value: fields[4].as_u256()?,
+            data: fields[5].as_bytes()?.to_vec(),
+            signature: Signature { v, r: fields[7].as_u256()?, s: fields[8].as_u256()? },
+            ..Default::default()
+        })
+    }
+
+    // The raw signed transaction, as hashed for the transaction hash and the transactions root
+    pub fn encode(&self) -> Vec<u8> {
+        let mut fields = self.unsigned_fields();
+        if self.tx_type == TxType::Legacy {
+            fields.truncate(6);
+        }
+        fields.push(rlp::encode_u64(self.signature.v));
+        fields.push(rlp::encode_u256(self.signature.r));
+        fields.push(rlp::encode_u256(self.signature.s));
+        self.envelope(rlp::encode_list(&fields))
+    }
+
+    pub fn hash(&self) -> H256 {
+        H256(Helper::keccak256(&self.encode()))
+    }
+
+    // The hash the sender signed
+    pub fn signing_hash(&self) -> H256 {
+        let fields = self.unsigned_fields();
+        H256(Helper::keccak256(&self.envelope(rlp::encode_list(&fields))))
+    }
+
+    // Execution environment for this transaction sent by `caller`
+    pub fn to_transaction(&self, caller: H160) -> Transaction {
+        Transaction {
+            tx_type: self.tx_type,
+            caller,
+            to: self.to,
+            value: self.value,
+            data: self.data.clone(),
+            nonce: self.nonce,
+            gas_limit: self.gas_limit,
+            gas_price: self.gas_price,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+            access_list: self.access_list.clone(),
+            chain_id: self.chain_id,
+            authorization_list: self.authorization_list.clone(),
+            max_fee_per_blob_gas: self.max_fee_per_blob_gas,
+            blob_versioned_hashes: self.blob_versioned_hashes.clone(),
+        }
+    }
+
+    // Encoded fields covered by the signature. For EIP-155 legacy transactions these end
+    // with chain_id, 0, 0
+    fn unsigned_fields(&self) -> Vec<Vec<u8>> {
+        let mut fields = Vec::new();
+        if self.tx_type == TxType::Legacy {
+            fields.push(rlp::encode_u64(self.nonce));
+            fields.push(rlp::encode_u256(self.gas_price));
+            fields.push(rlp::encode_u64(self.gas_limit));
+            fields.push(rlp::encode_optional_address(&self.to));
+            fields.push(rlp::encode_u256(self.value));
+            fields.push(rlp::encode_bytes(&self.data));
+            if let Some(chain_id) = self.chain_id {
+                fields.push(rlp::encode_u64(chain_id));
+                fields.push(rlp::encode_u64(0));
+                fields.push(rlp::encode_u64(0));
+            }
+            return fields;
+        }
+
+        fields.push(rlp::encode_u64(self.chain_id.unwrap_or_default()));
+        fields.push(rlp::encode_u64(self.nonce));
+        if self.tx_type == TxType::AccessList {
+            fields.push(rlp::encode_u256(self.gas_price));
+        } else {
+            fields.push(rlp::encode_u256(self.max_priority_fee_per_gas.unwrap_or_default()));
+            fields.push(rlp::encode_u256(self.gas_price));
+        }
+        fields.push(rlp::encode_u64(self.gas_limit));
+        fields.push(rlp::encode_optional_address(&self.to));
+        fields.push(rlp::encode_u256(self.value));
+        fields.push(rlp::encode_bytes(&self.data));
+        fields.push(encode_access_list(&self.access_list));
+        match self.tx_type {
+            TxType::Blob => {
+                fields.push(rlp::encode_u256(self.max_fee_per_blob_gas.unwrap_or_default()));
+                fields.push(rlp::encode_list(&self.blob_versioned_hashes.iter().map(rlp::encode_h256).collect::<Vec<_>>()));
+            }
+            TxType::SetCode => {
+                fields.push(rlp::encode_list(&self.authorization_list.iter().map(Authorization::encode).collect::<Vec<_>>()));
+            }
+            _ => {}
+        }
+        fields
+    }
+
+    fn envelope(&self, payload: Vec<u8>) -> Vec<u8> {
+        if self.tx_type == TxType::Legacy {
+            return payload;
+        }
+        let mut out = vec![self.tx_type.as_u8()];
+        out.extend(payload);
+        out
+    }
+}
+
+pub fn transactions_root(transactions: &[SignedTransaction]) -> H256 {
+    ordered_trie_root(&transactions.iter().map(SignedTransaction::encode).collect::<Vec<_>>())
+}
+
+fn expect_fields(item: &Rlp, expected: usize) -> Result<&[Rlp], TxDecodeError> {
+    let fields = item.as_list()?;
+    if fields.len() != expected {
+        return Err(TxDecodeError::InvalidFieldCount { expected, got: fields.len() });
+    }
+    Ok(fields)
+}
+
+fn decode_access_list(item: &Rlp) -> Result<Vec<AccessListItem>, TxDecodeError> {
+    item.as_list()?
+        .iter()
+        .map(|entry| {
+            let fields = expect_fields(entry, 2)?;
+            let storage_keys = fields[1]
+                .as_list()?
+                .iter()
+                .map(|key| key.as_h256().map(|key| U256::from_big_endian(key.as_bytes())))
+                .collect::<Result<_, _>>()?;
+            Ok(AccessListItem { address: fields[0].as_address()?, storage_keys })
+        })
+        .collect()
+}
+
+fn encode_access_list(access_list: &[AccessListItem]) -> Vec<u8> {
+    let items: Vec<Vec<u8>> = access_list
+        .iter()
+        .map(|item| {
+            let keys: Vec<Vec<u8>> = item
+                .storage_keys
+                .iter()
+                .map(|key| {
+                    let mut bytes = [0u8; 32];
+                    key.to_big_endian(&mut bytes);
+                    rlp::encode_bytes(&bytes)
+                })
+                .collect();
+            rlp::encode_list(&[rlp::encode_address(&item.address), rlp::encode_list(&keys)])
+        })
+        .collect();
+    rlp::encode_list(&items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    // EIP-155's example: nonce 9, 20 gwei, 21000 gas, 1 ether to 0x3535...35 on chain 1
+    const EIP155_RAW: &str = "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+
+    fn h256(hex: &str) -> H256 {
+        H256::from_slice(&hex::decode(hex).unwrap())
+    }
+
+    #[test]
+    fn eip155_example() {
+        let raw = hex::decode(EIP155_RAW).unwrap();
+        let tx = SignedTransaction::decode(&raw).unwrap();
+        assert_eq!(tx.tx_type, TxType::Legacy);
+        assert_eq!(tx.chain_id, Some(1));
+        assert_eq!(tx.nonce, 9);
+        assert_eq!(tx.gas_price, U256::from(20_000_000_000u64));
+        assert_eq!(tx.gas_limit, 21000);
+        assert_eq!(tx.to, Some(H160::repeat_byte(0x35)));
+        assert_eq!(tx.value, U256::exp10(18));
+        assert_eq!(tx.signature.v, 37);
+        assert_eq!(
+            tx.signature.r,
+            U256::from_dec_str("18515461264373351373200002665853028612451056578545711640558177340181847433846").unwrap()
+        );
+        // The signing data is given in the EIP as well
+        let signing_data = hex::decode("ec098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a764000080018080").unwrap();
+        assert_eq!(tx.signing_hash(), H256(Helper::keccak256(&signing_data)));
+        assert_eq!(tx.signing_hash(), h256("daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"));
+        assert_eq!(tx.encode(), raw);
+        assert_eq!(tx.hash(), H256(Helper::keccak256(&raw)));
+    }
+
+    fn typed(tx_type: TxType) -> SignedTransaction {
+        SignedTransaction {
+            tx_type,
+            chain_id: Some(1),
+            nonce: 7,
+            gas_price: U256::from(30_000_000_000u64),
+            max_priority_fee_per_gas: (tx_type != TxType::AccessList).then(|| U256::from(1_000_000_000u64)),
+            gas_limit: 100_000,
+            to: Some(H160::repeat_byte(0x35)),
+            value: U256::from(1000),
+            data: vec![0xde, 0xad, 0xbe, 0xef],
+            access_list: vec![AccessListItem { address: H160::repeat_byte(0x36), storage_keys: vec![U256::one()] }],
+            max_fee_per_blob_gas: (tx_type == TxType::Blob).then(U256::one),
+            blob_versioned_hashes: if tx_type == TxType::Blob { vec![H256::repeat_byte(0x01)] } else { Vec::new() },
+            authorization_list: if tx_type == TxType::SetCode {
+                vec![Authorization { chain_id: U256::one(), address: H160::repeat_byte(0x37), nonce: 1, y_parity: 1, r: U256::from(2), s: U256::from(3) }]
+            } else {
+                Vec::new()
+            },
+            signature: Signature { v: 1, r: U256::from(4), s: U256::from(5) },
+        }
+    }
+
+    #[test]
+    fn typed_transactions_round_trip() {
+        for tx_type in [TxType::AccessList, TxType::DynamicFee, TxType::Blob, TxType::SetCode] {
+            let tx = typed(tx_type);
+            let raw = tx.encode();
+            assert_eq!(raw[0], tx_type.as_u8());
+            assert_eq!(SignedTransaction::decode(&raw), Ok(tx.clone()));
+            // The signature is not part of what was signed
+            let mut resigned = tx.clone();
+            resigned.signature.r = U256::from(6);
+            assert_eq!(resigned.signing_hash(), tx.signing_hash());
+            assert_ne!(resigned.hash(), tx.hash());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_transactions() {
+        assert_eq!(SignedTransaction::decode(&[]), Err(TxDecodeError::Empty));
+        assert_eq!(SignedTransaction::decode(&[0x05, 0xc0]), Err(TxDecodeError::UnsupportedType(0x05)));
+        assert_eq!(SignedTransaction::decode(&[0x00, 0xc0]), Err(TxDecodeError::UnsupportedType(0x00)));
+        assert_eq!(SignedTransaction::decode(&[0x02, 0xc0]), Err(TxDecodeError::InvalidFieldCount { expected: 12, got: 0 }));
+
+        let mut raw = hex::decode(EIP155_RAW).unwrap();
+        raw.push(0x00);
+        assert_eq!(SignedTransaction::decode(&raw), Err(TxDecodeError::Rlp(RlpError::TrailingBytes)));
+
+        let mut legacy = SignedTransaction::decode(&hex::decode(EIP155_RAW).unwrap()).unwrap();
+        legacy.signature.v = 30;
+        assert_eq!(SignedTransaction::decode(&legacy.encode()), Err(TxDecodeError::InvalidV(30)));
+
+        let mut dynamic = typed(TxType::DynamicFee);
+        dynamic.signature.v = 2;
+        assert_eq!(SignedTransaction::decode(&dynamic.encode()), Err(TxDecodeError::InvalidV(2)));
+
+        for tx_type in [TxType::Blob, TxType::SetCode] {
+            let tx = SignedTransaction { to: None, ..typed(tx_type) };
+            assert_eq!(SignedTransaction::decode(&tx.encode()), Err(TxDecodeError::MissingTo(tx_type.as_u8())));
+        }
+        let tx = SignedTransaction { authorization_list: Vec::new(), ..typed(TxType::SetCode) };
+        assert_eq!(SignedTransaction::decode(&tx.encode()), Err(TxDecodeError::EmptyAuthorizationList));
+    }
+}
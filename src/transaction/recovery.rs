@@ -0,0 +1,148 @@
+use ethereum_types::{H160, H256, U256};
+use k256::ecdsa::{RecoveryId, Signature as EcdsaSignature, VerifyingKey};
+use thiserror::Error;
+use crate::helper::Helper;
+use super::{Authorization, SignedTransaction, Transaction, TxType};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    #[error("Invalid signature value v = {0}")]
+    InvalidV(u64),
+    #[error("r or s is zero or not below the curve order")]
+    InvalidScalar,
+    #[error("s is in the upper half of the curve order")]
+    HighS,
+    #[error("No public key recovers from the signature")]
+    RecoveryFailed,
+}
+
+// Signer of `hash`. Only low-s signatures are accepted (EIP-2), so each (hash, signer) pair
+// has a single valid encoding
+pub fn recover_signer(hash: H256, y_parity: bool, r: U256, s: U256) -> Result<H160, SignatureError> {
+    let mut r_bytes = [0u8; 32];
+    let mut s_bytes = [0u8; 32];
+    r.to_big_endian(&mut r_bytes);
+    s.to_big_endian(&mut s_bytes);
+
+    let signature = EcdsaSignature::from_scalars(r_bytes, s_bytes).map_err(|_| SignatureError::InvalidScalar)?;
+    if signature.normalize_s().is_some() {
+        return Err(SignatureError::HighS);
+    }
+    let recovery_id = RecoveryId::new(y_parity, false);
+    let key = VerifyingKey::recover_from_prehash(hash.as_bytes(), &signature, recovery_id)
+        .map_err(|_| SignatureError::RecoveryFailed)?;
+
+    let public_key = key.to_encoded_point(false);
+    let address_hash = Helper::keccak256(&public_key.as_bytes()[1..]);
+    Ok(H160::from_slice(&address_hash[12..]))
+}
+
+impl SignedTransaction {
+    // Parity of the signature's R point, from 27/28, the EIP-155 v or a typed y parity
+    pub fn y_parity(&self) -> Result<bool, SignatureError> {
+        let v = self.signature.v;
+        match (self.tx_type, self.chain_id) {
+            (TxType::Legacy, None) if v == 27 || v == 28 => Ok(v == 28),
+            (TxType::Legacy, Some(chain_id)) if v == 35 + 2 * chain_id || v == 36 + 2 * chain_id => Ok(v == 36 + 2 * chain_id),
+            (TxType::Legacy, _) => Err(SignatureError::InvalidV(v)),
+            _ if v <= 1 => Ok(v == 1),
+            _ => Err(SignatureError::InvalidV(v)),
+        }
+    }
+
+    pub fn recover_sender(&self) -> Result<H160, SignatureError> {
+        let signature = self.signature;
+        recover_signer(self.signing_hash(), self.y_parity()?, signature.r, signature.s)
+    }
+
+    // Execution environment with the sender taken from the signature
+    pub fn recover_transaction(&self) -> Result<Transaction, SignatureError> {
+        Ok(self.to_transaction(self.recover_sender()?))
+    }
+}
+
+impl Authorization {
+    // The account granting the delegation
+    pub fn recover_authority(&self) -> Result<H160, SignatureError> {
+        if self.y_parity > 1 {
+            return Err(SignatureError::InvalidV(self.y_parity as u64));
+        }
+        recover_signer(self.signing_hash(), self.y_parity == 1, self.r, self.s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+    use crate::hex;
+    use crate::transaction::Signature;
+
+    // EIP-155's example, signed with the private key 0x4646...46
+    const EIP155_RAW: &str = "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+    const SIGNER: &str = "9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f";
+
+    fn signer() -> H160 {
+        H160::from_slice(&hex::decode(SIGNER).unwrap())
+    }
+
+    // Signs `hash` with the example's key, returning the y parity, r and s
+    fn sign(hash: H256) -> (bool, U256, U256) {
+        let key = SigningKey::from_slice(&[0x46; 32]).unwrap();
+        let (signature, recovery_id) = key.sign_prehash_recoverable(hash.as_bytes()).unwrap();
+        let bytes = signature.to_bytes();
+        (recovery_id.is_y_odd(), U256::from_big_endian(&bytes[..32]), U256::from_big_endian(&bytes[32..]))
+    }
+
+    #[test]
+    fn recovers_the_eip155_example_sender() {
+        let tx = SignedTransaction::decode(&hex::decode(EIP155_RAW).unwrap()).unwrap();
+        assert_eq!(tx.y_parity(), Ok(false));
+        assert_eq!(tx.recover_sender(), Ok(signer()));
+        assert_eq!(tx.recover_transaction().unwrap().caller, signer());
+    }
+
+    #[test]
+    fn recovers_typed_senders_and_authorities() {
+        let mut tx = SignedTransaction {
+            tx_type: TxType::DynamicFee,
+            chain_id: Some(1),
+            gas_limit: 21000,
+            to: Some(H160::repeat_byte(0x35)),
+            ..Default::default()
+        };
+        let (y_parity, r, s) = sign(tx.signing_hash());
+        tx.signature = Signature { v: y_parity as u64, r, s };
+        assert_eq!(tx.recover_sender(), Ok(signer()));
+
+        let mut authorization = Authorization { chain_id: U256::one(), address: H160::repeat_byte(0x37), nonce: 3, ..Default::default() };
+        let (y_parity, r, s) = sign(authorization.signing_hash());
+        authorization.y_parity = y_parity as u8;
+        authorization.r = r;
+        authorization.s = s;
+        assert_eq!(authorization.recover_authority(), Ok(signer()));
+        authorization.y_parity = 2;
+        assert_eq!(authorization.recover_authority(), Err(SignatureError::InvalidV(2)));
+    }
+
+    #[test]
+    fn rejects_malleable_and_invalid_signatures() {
+        let tx = SignedTransaction::decode(&hex::decode(EIP155_RAW).unwrap()).unwrap();
+        let hash = tx.signing_hash();
+        let (r, s) = (tx.signature.r, tx.signature.s);
+        let order = U256::from_big_endian(&hex::decode("fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141").unwrap());
+
+        // The same signature with s mirrored is valid ECDSA, but not accepted (EIP-2)
+        assert_eq!(recover_signer(hash, true, r, order - s), Err(SignatureError::HighS));
+        assert_eq!(recover_signer(hash, false, U256::zero(), s), Err(SignatureError::InvalidScalar));
+        assert_eq!(recover_signer(hash, false, order, s), Err(SignatureError::InvalidScalar));
+        // The other parity recovers someone else
+        assert_ne!(recover_signer(hash, true, r, s), Ok(signer()));
+
+        // v must match the chain id
+        let other_chain = SignedTransaction { chain_id: Some(2), ..tx.clone() };
+        assert_eq!(other_chain.y_parity(), Err(SignatureError::InvalidV(37)));
+        let typed = SignedTransaction { tx_type: TxType::AccessList, ..tx };
+        assert_eq!(typed.y_parity(), Err(SignatureError::InvalidV(37)));
+    }
+}
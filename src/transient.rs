@@ -1,30 +1,94 @@
 use std::collections::HashMap;
-use ethereum_types::U256;
+use ethereum_types::{H160, U256};
 
+// EIP-1153 storage, kept per account for one transaction. Writes are journaled so a reverted
+// frame undoes its TSTOREs like its SSTOREs
 #[derive(Debug)]
 pub struct Transient {
-    data: HashMap<i32, Vec<U256>>, //value can be up to 32 bytes
+    data: HashMap<(H160, U256), U256>, // (account, slot) -> 32-byte value
+    journal: Vec<((H160, U256), U256)>, // slot and the value it held before each write
 }
 
 impl Transient {
     pub fn new() -> Transient {
         Transient {
-            data: HashMap::new()
+            data: HashMap::new(),
+            journal: Vec::new(),
         }
     }
 
-    pub fn load(&mut self, key: i32) -> Vec<U256> {
-        match self.data.get(&key) {
-            Some(data) => data.clone(),
-            None => vec![U256::zero()],
-        }
+    pub fn load(&self, address: H160, key: U256) -> U256 {
+        self.data.get(&(address, key)).copied().unwrap_or_default()
+    }
+
+    pub fn store(&mut self, address: H160, key: U256, value: U256) {
+        let previous = self.load(address, key);
+        self.journal.push(((address, key), previous));
+        self.set(address, key, value);
     }
 
-    pub fn store(&mut self, key: i32, value: &[U256]) {
-        self.data.insert(key, value.to_vec());
+    pub fn iter(&self) -> impl Iterator<Item = (&(H160, U256), &U256)> {
+        self.data.iter()
+    }
+
+    pub fn checkpoint(&self) -> usize {
+        self.journal.len()
+    }
+
+    // Undoes every write made since `checkpoint`
+    pub fn revert_to(&mut self, checkpoint: usize) {
+        while self.journal.len() > checkpoint {
+            let ((address, key), previous) = self.journal.pop().unwrap();
+            self.set(address, key, previous);
+        }
     }
 
     pub fn clear(&mut self) {
         self.data = HashMap::new();
+        self.journal = Vec::new();
+    }
+
+    fn set(&mut self, address: H160, key: U256, value: U256) {
+        if value.is_zero() {
+            self.data.remove(&(address, key));
+        } else {
+            self.data.insert((address, key), value);
+        }
     }
-}
\ No newline at end of file
+}
+
+impl Default for Transient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_are_kept_per_account() {
+        let (a, b) = (H160::repeat_byte(0xaa), H160::repeat_byte(0xbb));
+        let mut transient = Transient::new();
+        transient.store(a, U256::one(), U256::from(7));
+        assert_eq!(transient.load(a, U256::one()), U256::from(7));
+        assert_eq!(transient.load(b, U256::one()), U256::zero());
+        // Keys differing only above the low 64 bits are different slots
+        assert_eq!(transient.load(a, U256::one() + (U256::one() << 64)), U256::zero());
+    }
+
+    #[test]
+    fn revert_restores_previous_values() {
+        let address = H160::repeat_byte(0xaa);
+        let mut transient = Transient::new();
+        transient.store(address, U256::zero(), U256::from(1));
+        let checkpoint = transient.checkpoint();
+        transient.store(address, U256::zero(), U256::from(2));
+        transient.store(address, U256::one(), U256::from(3));
+        transient.revert_to(checkpoint);
+        assert_eq!(transient.load(address, U256::zero()), U256::from(1));
+        assert_eq!(transient.load(address, U256::one()), U256::zero());
+        assert_eq!(transient.iter().count(), 1);
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+use ethereum_types::H256;
+use thiserror::Error;
+use crate::helper::Helper;
+use crate::rlp::{self, Rlp, RlpError};
+
+// keccak256(rlp("")), the root of a trie with no entries
+pub const EMPTY_ROOT: H256 = H256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+]);
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ProofError {
+    #[error("Proof is missing a node")]
+    MissingNode,
+    #[error("Proof node does not match the hash referencing it")]
+    HashMismatch,
+    #[error("Malformed trie node")]
+    InvalidNode,
+    #[error("Invalid RLP in proof node: {0}")]
+    Rlp(#[from] RlpError),
+    #[error("Proven value does not match the claimed one")]
+    ValueMismatch,
+}
+
+// Hexary Merkle Patricia Trie. Entries are kept sorted and the nodes are built when the root
+// is asked for, so inserting and removing are cheap and any shape of trie hashes the same
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trie {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Trie {
+    pub fn new() -> Trie {
+        Trie::default()
+    }
+
+    // An empty value removes the key, as tries never hold empty values
+    pub fn insert(&mut self, key: &[u8], value: Vec<u8>) {
+        if value.is_empty() {
+            self.entries.remove(key);
+        } else {
+            self.entries.insert(key.to_vec(), value);
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        self.entries.remove(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Vec<u8>)> {
+        self.entries.iter()
+    }
+
+    pub fn root(&self) -> H256 {
+        H256(Helper::keccak256(&encode_node(&self.nibble_entries(), 0, None, &mut Vec::new())))
+    }
+
+    // Nodes on the path to `key`, root first. Proves the value of `key` or that it is absent
+    pub fn proof(&self, key: &[u8]) -> Vec<Vec<u8>> {
+        let mut proof = Vec::new();
+        encode_node(&self.nibble_entries(), 0, Some(&to_nibbles(key)), &mut proof);
+        proof.reverse();
+        proof
+    }
+
+    fn nibble_entries(&self) -> Vec<(Vec<u8>, &[u8])> {
+        self.entries.iter().map(|(key, value)| (to_nibbles(key), value.as_slice())).collect()
+    }
+}
+
+// Root of a trie keyed by rlp(index), as used for the transactions and receipts roots
+pub fn ordered_trie_root(values: &[Vec<u8>]) -> H256 {
+    let mut trie = Trie::new();
+    for (index, value) in values.iter().enumerate() {
+        trie.insert(&rlp::encode_u64(index as u64), value.clone());
+    }
+    trie.root()
+}
+
+// Walks `proof` from `root` along `key`. Returns the value stored under `key`, or None if the
+// proof shows the key is not in the trie
+pub fn verify_proof(root: H256, key: &[u8], proof: &[Vec<u8>]) -> Result<Option<Vec<u8>>, ProofError> {
+    let path = to_nibbles(key);
+    let mut depth = 0;
+    let mut proof_nodes = proof.iter();
+    let mut next = Rlp::Bytes(root.as_bytes().to_vec());
+    loop {
+        // A child is referenced by its hash, or embedded whole when shorter than one
+        let node = match next {
+            Rlp::Bytes(ref hash) if hash.is_empty() => return Ok(None),
+            Rlp::Bytes(ref hash) if hash.len() == 32 => {
+                let encoded = proof_nodes.next().ok_or(ProofError::MissingNode)?;
+                if Helper::keccak256(encoded)[..] != hash[..] {
+                    return Err(ProofError::HashMismatch);
+                }
+                Rlp::decode(encoded)?
+            }
+            Rlp::Bytes(_) => return Err(ProofError::InvalidNode),
+            list => list,
+        };
+        let items = match node {
+            Rlp::Bytes(ref empty) if empty.is_empty() => return Ok(None), // the empty trie
+            Rlp::List(items) => items,
+            _ => return Err(ProofError::InvalidNode),
+        };
+        match items.len() {
+            17 => {
+                if depth == path.len() {
+                    let value = items[16].as_bytes()?;
+                    return Ok(if value.is_empty() { None } else { Some(value.to_vec()) });
+                }
+                next = items[path[depth] as usize].clone();
+                depth += 1;
+            }
+            2 => {
+                let (nibbles, is_leaf) = decode_hex_prefix(items[0].as_bytes()?)?;
+                let rest = &path[depth..];
+                if is_leaf {
+                    return Ok(if rest == nibbles.as_slice() { Some(items[1].as_bytes()?.to_vec()) } else { None });
+                }
+                if !rest.starts_with(&nibbles) {
+                    return Ok(None);
+                }
+                depth += nibbles.len();
+                next = items[1].clone();
+            }
+            _ => return Err(ProofError::InvalidNode),
+        }
+    }
+}
+
+// State and storage tries are keyed by the hash of the address or slot
+pub fn secure_key(key: &[u8]) -> [u8; 32] {
+    Helper::keccak256(key)
+}
+
+fn to_nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|byte| [byte >> 4, byte & 0x0f]).collect()
+}
+
+// Compact encoding of a nibble path, flagging leaves and odd lengths in the first nibble
+fn hex_prefix(nibbles: &[u8], is_leaf: bool) -> Vec<u8> {
+    let flag = if is_leaf { 2 } else { 0 };
+    let mut out = Vec::with_capacity(nibbles.len() / 2 + 1);
+    let rest = if nibbles.len() % 2 == 1 {
+        out.push(((flag + 1) << 4) | nibbles[0]);
+        &nibbles[1..]
+    } else {
+        out.push(flag << 4);
+        nibbles
+    };
+    out.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    out
+}
+
+fn decode_hex_prefix(encoded: &[u8]) -> Result<(Vec<u8>, bool), ProofError> {
+    let first = *encoded.first().ok_or(ProofError::InvalidNode)?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return Err(ProofError::InvalidNode);
+    }
+    let mut nibbles = if flag % 2 == 1 { vec![first & 0x0f] } else { Vec::new() };
+    nibbles.extend(to_nibbles(&encoded[1..]));
+    Ok((nibbles, flag >= 2))
+}
+
+// RLP of the node holding `items`, sorted and all sharing their first `depth` nibbles. Nodes
+// on the way to `path` that are stored by hash are collected into `proof`, deepest first
+fn encode_node(items: &[(Vec<u8>, &[u8])], depth: usize, path: Option<&[u8]>, proof: &mut Vec<Vec<u8>>) -> Vec<u8> {
+    let node = build_node(items, depth, path, proof);
+    if path.is_some() && (depth == 0 || node.len() >= 32) {
+        proof.push(node.clone());
+    }
+    node
+}
+
+fn build_node(items: &[(Vec<u8>, &[u8])], depth: usize, path: Option<&[u8]>, proof: &mut Vec<Vec<u8>>) -> Vec<u8> {
+    match items {
+        [] => rlp::encode_bytes(&[]),
+        [(key, value)] => rlp::encode_list(&[rlp::encode_bytes(&hex_prefix(&key[depth..], true)), rlp::encode_bytes(value)]),
+        _ => {
+            // Sorted, so the first and last keys bound the prefix all of them share
+            let (first, last) = (&items[0].0, &items[items.len() - 1].0);
+            let shared = first[depth..].iter().zip(&last[depth..]).take_while(|(a, b)| a == b).count();
+            if shared > 0 {
+                let prefix = &first[depth..depth + shared];
+                let path = path.filter(|path| path.get(depth..depth + shared) == Some(prefix));
+                let child = encode_node(items, depth + shared, path, proof);
+                return rlp::encode_list(&[
+                    rlp::encode_bytes(&hex_prefix(prefix, false)),
+                    node_reference(child),
+                ]);
+            }
+
+            // A key ending here sorts first and becomes the branch value
+            let (value, mut rest) = match items[0].0.len() == depth {
+                true => (items[0].1, &items[1..]),
+                false => (&[][..], items),
+            };
+            let mut branch = Vec::with_capacity(17);
+            for nibble in 0..16 {
+                let end = rest.partition_point(|(key, _)| key[depth] <= nibble);
+                let (children, remaining) = rest.split_at(end);
+                let path = path.filter(|path| path.get(depth) == Some(&nibble));
+                branch.push(if children.is_empty() { rlp::encode_bytes(&[]) } else { node_reference(encode_node(children, depth + 1, path, proof)) });
+                rest = remaining;
+            }
+            branch.push(rlp::encode_bytes(value));
+            rlp::encode_list(&branch)
+        }
+    }
+}
+
+// Nodes shorter than a hash are embedded in their parent
+fn node_reference(node: Vec<u8>) -> Vec<u8> {
+    if node.len() < 32 {
+        node
+    } else {
+        rlp::encode_bytes(&Helper::keccak256(&node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    fn trie(entries: &[(&str, &str)]) -> Trie {
+        let mut trie = Trie::new();
+        for (key, value) in entries {
+            trie.insert(key.as_bytes(), value.as_bytes().to_vec());
+        }
+        trie
+    }
+
+    fn h256(hex: &str) -> H256 {
+        H256::from_slice(&hex::decode(hex).unwrap())
+    }
+
+    // Cases from ethereum/tests TrieTests
+    #[test]
+    fn known_roots() {
+        assert_eq!(Trie::new().root(), EMPTY_ROOT);
+        assert_eq!(EMPTY_ROOT, H256(Helper::keccak256(&rlp::encode_bytes(&[]))));
+        assert_eq!(ordered_trie_root(&[]), EMPTY_ROOT);
+
+        let cases = [
+            (vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")], "8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"),
+            (vec![("do", "verb"), ("horse", "stallion"), ("doge", "coin"), ("dog", "puppy")], "5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84"),
+            (vec![("foo", "bar"), ("food", "bass")], "17beaa1648bafa633cda809c90c04af50fc8aed3cb40d16efbddee6fdf63c4c3"),
+            (vec![("be", "e"), ("dog", "puppy"), ("bed", "d")], "3f67c7a47520f79faa29255d2d3c084a7a6df0453116ed7232ff10277a8be68b"),
+            (vec![("test", "test"), ("te", "testy")], "8452568af70d8d140f58d941338542f645fcca50094b20f3c3d8c3df49337928"),
+        ];
+        for (entries, root) in cases {
+            assert_eq!(trie(&entries).root(), h256(root));
+            // Any insertion order gives the same trie
+            let reversed: Vec<_> = entries.iter().rev().copied().collect();
+            assert_eq!(trie(&reversed).root(), h256(root));
+        }
+
+        let mut hex_keys = Trie::new();
+        hex_keys.insert(&[0x00, 0x45], vec![0x01, 0x23, 0x45, 0x67, 0x89]);
+        hex_keys.insert(&[0x45, 0x00], vec![0x98, 0x76, 0x54, 0x32, 0x10]);
+        assert_eq!(hex_keys.root(), h256("285505fcabe84badc8aa310e2aae17eddc7d120aabec8a476902c8184b3a3503"));
+    }
+
+    #[test]
+    fn removing_restores_the_previous_root() {
+        let mut dogs = trie(&[("doe", "reindeer"), ("dog", "puppy")]);
+        let root = dogs.root();
+        dogs.insert(b"dogglesworth", b"cat".to_vec());
+        assert_eq!(dogs.remove(b"dogglesworth"), Some(b"cat".to_vec()));
+        assert_eq!(dogs.root(), root);
+        assert_eq!(dogs.remove(b"dogglesworth"), None);
+    }
+
+    #[test]
+    fn proofs_verify_present_and_absent_keys() {
+        let dogs = trie(&[("do", "verb"), ("horse", "stallion"), ("doge", "coin"), ("dog", "puppy")]);
+        let root = dogs.root();
+        for (key, value) in dogs.iter() {
+            assert_eq!(verify_proof(root, key, &dogs.proof(key)), Ok(Some(value.clone())));
+        }
+        for key in [&b"d"[..], b"dogs", b"cat", b"horses", b""] {
+            assert_eq!(verify_proof(root, key, &dogs.proof(key)), Ok(None));
+        }
+        assert_eq!(verify_proof(EMPTY_ROOT, b"dog", &Trie::new().proof(b"dog")), Ok(None));
+
+        let proof = dogs.proof(b"horse");
+        assert_eq!(verify_proof(root, b"horse", &proof[..proof.len() - 1]), Err(ProofError::MissingNode));
+        assert_eq!(verify_proof(H256::repeat_byte(1), b"horse", &proof), Err(ProofError::HashMismatch));
+    }
+}